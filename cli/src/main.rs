@@ -1,5 +1,11 @@
-use clap::{Parser, Subcommand};
-use flern::model::{CrudRepository, DatabaseError, DbConnection, ModelManager};
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use flern::config::Database;
+use flern::error::AppError;
+use flern::model::{CourseImport, CrudRepository, DatabaseError, DbConnection, ModelManager};
 use flern::model::entity::{
     Answer,
     AnswerCreate,
@@ -12,7 +18,7 @@ use flern::model::entity::{
     UserEntity,
     UserEntityCreateUpdate,
 };
-use flern::web::AuthenticatedUser;
+use flern::web::{AuthenticatedUser, UserRole};
 
 #[derive(Parser, Debug)]
 #[command(about = "CLI tool for filling the learning DB", long_about = None)]
@@ -46,6 +52,12 @@ pub enum Commands {
         #[command(subcommand)]
         action: TaskCommands,
     },
+
+    /// Manage whole courses
+    Course {
+        #[command(subcommand)]
+        action: CourseCommands,
+    },
 }
 
 /// User management
@@ -56,8 +68,17 @@ pub enum UserCommands {
         username: String,
         #[arg(long)]
         password: String,
-        #[arg(long, default_value = "user")]
-        role: String,
+        /// One of `admin`, `instructor` or `user`
+        #[arg(long, default_value = "user", value_parser = UserRole::from_str)]
+        role: UserRole,
+    },
+    List {
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    Delete {
+        #[arg(long)]
+        username: String,
     },
 }
 
@@ -72,6 +93,15 @@ pub enum ModuleCommands {
         #[arg(long, default_value_t = 0)]
         order_index: i32,
     },
+    List {
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Deletes the module with its lessons, tasks and answers
+    Delete {
+        #[arg(long)]
+        title: String,
+    },
 }
 
 /// Lesson management
@@ -89,6 +119,15 @@ pub enum LessonCommands {
         #[arg(long, default_value_t = 0)]
         order_index: i32,
     },
+    List {
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Deletes the lesson with its tasks and answers
+    Delete {
+        #[arg(long)]
+        title: String,
+    },
 }
 
 /// Task management
@@ -116,32 +155,172 @@ pub enum TaskCommands {
         #[arg(long, default_value_t = false)]
         is_correct: bool,
     },
+    List {
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Deletes the task with its answers and attempts
+    Delete {
+        #[arg(long)]
+        question: String,
+    },
+}
+
+/// Window of a `list` command
+#[derive(Args, Debug)]
+pub struct PageArgs {
+    #[arg(long, default_value_t = 20)]
+    limit: i64,
+    #[arg(long, default_value_t = 0)]
+    offset: i64,
+}
+
+/// Course management
+#[derive(Subcommand, Debug)]
+pub enum CourseCommands {
+    /// Creates modules, lessons, tasks and answers from one file in a single transaction
+    Import {
+        /// Path to a `.yaml`, `.yml` or `.json` course file
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Writes every module with its lessons, tasks and answers as a JSON file `import` accepts
+    Export {
+        #[arg(long)]
+        out: PathBuf,
+    },
 }
 
+/// Errors `main` prints before exiting with a failure
+#[derive(Debug)]
+enum CliError {
+    App(AppError),
+    NotFound { what: &'static str, key: String },
+    Ambiguous { what: &'static str, key: String, count: usize },
+    MissingEnv(&'static str),
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::App(e) => write!(f, "{e}"),
+            Self::NotFound { what, key } => write!(f, "{what} '{key}' not found"),
+            Self::Ambiguous { what, key, count } => write!(f, "{what} '{key}' is ambiguous, {count} match"),
+            Self::MissingEnv(name) => write!(f, "{name} is not set, add it to the environment or .env"),
+        }
+    }
+}
+
+impl<E: Into<AppError>> From<E> for CliError {
+    fn from(error: E) -> Self {
+        Self::App(error.into())
+    }
+}
+
+/// Resolves a human-readable key (title, question) to a row id. Nothing or more than one
+/// matching row is a readable `CliError` instead of a raw sqlx error.
+async fn lookup_id(
+    mm: &ModelManager,
+    query: &str,
+    key: &str,
+    what: &'static str,
+) -> Result<uuid::Uuid, CliError> {
+    let ids: Vec<uuid::Uuid> = sqlx::query_scalar(query)
+        .bind(key)
+        .fetch_all(mm.executor())
+        .await
+        .map_err(DatabaseError::from)?;
+
+    match ids[..] {
+        [id] => Ok(id),
+        [] => Err(CliError::NotFound { what, key: key.to_string() }),
+        _ => Err(CliError::Ambiguous { what, key: key.to_string(), count: ids.len() }),
+    }
+}
+
+/// Prints `rows` as space-aligned columns under `header`, followed by the window of `total`
+fn print_table<const N: usize>(header: [&str; N], rows: Vec<[String; N]>, offset: i64, total: i64) {
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let print_row = |cells: [&str; N]| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    };
+    print_row(header);
+    for row in &rows {
+        print_row(row.each_ref().map(String::as_str));
+    }
+
+    match rows.len() {
+        0 => println!("{total} in total"),
+        n => println!("{}-{} of {total}", offset + 1, offset + n as i64),
+    }
+}
 
 #[tokio::main]
-async fn main() -> flern::error::AppResult<()> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), CliError> {
     let _ = dotenvy::dotenv();
     let args = Cli::parse();
 
-    let db_con = DbConnection::connect(&std::env::var("DATABASE_URL").unwrap())?;
+    let database_url = std::env::var("DATABASE_URL").map_err(|_| CliError::MissingEnv("DATABASE_URL"))?;
+    let db_con = DbConnection::connect(&database_url, &Database::default())?;
     let mm = ModelManager::new(db_con);
-    let actor = AuthenticatedUser::admin();
+    let actor = AuthenticatedUser::system();
 
     match args.command {
         Commands::User { action } => match action {
-            UserCommands::Add { username, password, .. } => {
-                let user = UserEntity::create(
+            UserCommands::Add { username, password, role } => {
+                let user = UserEntity::create_with_role(
                     &mm,
                     &actor,
                     UserEntityCreateUpdate {
                         username,
-                        password_hash: flern::auth::hash_password(&password).unwrap(),
+                        password_hash: flern::auth::hash_password(&password)?,
                     },
+                    &role,
                 )
                 .await?;
                 println!("User created: {:?}", user);
             }
+
+            UserCommands::List { page } => {
+                let users = UserEntity::list(&mm, &actor, page.limit, page.offset, None).await?;
+                let total = UserEntity::count(&mm, &actor).await?;
+                let rows = users
+                    .iter()
+                    .map(|u| [u.id().to_string(), u.username().to_string(), u.role().to_string()])
+                    .collect();
+                print_table(["ID", "USERNAME", "ROLE"], rows, page.offset, total);
+            }
+
+            UserCommands::Delete { username } => {
+                let user = UserEntity::find_by_username(&mm, &actor, &username).await?;
+                let Some(user) = user else {
+                    return Err(CliError::NotFound { what: "User", key: username });
+                };
+                let id = user.id();
+                user.delete(&mm, &actor).await?;
+                println!("User deleted: {} ({})", username, id);
+            }
         },
 
         Commands::Module { action } => match action {
@@ -158,15 +337,29 @@ async fn main() -> flern::error::AppResult<()> {
                 .await?;
                 println!("Module created: {:?}", module);
             }
+
+            ModuleCommands::List { page } => {
+                let modules = Module::list(&mm, &actor, page.limit, page.offset, None).await?;
+                let total = Module::count(&mm, &actor).await?;
+                let rows = modules
+                    .iter()
+                    .map(|m| [m.id().to_string(), m.order_index().to_string(), m.title().to_string()])
+                    .collect();
+                print_table(["ID", "ORDER", "TITLE"], rows, page.offset, total);
+            }
+
+            ModuleCommands::Delete { title } => {
+                let id = lookup_id(&mm, "SELECT id FROM modules WHERE title = $1", &title, "Module").await?;
+                if let Some(module) = Module::find_by_id(&mm, &actor, id).await? {
+                    module.delete_cascade(&mm, &actor).await?;
+                }
+                println!("Module deleted: {} ({})", title, id);
+            }
         },
 
         Commands::Lesson { action } => match action {
             LessonCommands::Add { module_title, title, file, order_index } => {
-                let module_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM modules WHERE title = $1")
-                    .bind(&module_title)
-                    .fetch_one(mm.executor())
-                    .await
-                    .map_err(|e| DatabaseError::SqlxError(e))?;
+                let module_id = lookup_id(&mm, "SELECT id FROM modules WHERE title = $1", &module_title, "Module").await?;
 
                 let content = std::fs::read_to_string(file)?;
                 let lesson = Lesson::create(
@@ -182,15 +375,36 @@ async fn main() -> flern::error::AppResult<()> {
                 .await?;
                 println!("Lesson created: {:?}", lesson);
             }
+
+            LessonCommands::List { page } => {
+                let lessons = Lesson::list(&mm, &actor, page.limit, page.offset, None).await?;
+                let total = Lesson::count(&mm, &actor).await?;
+                let rows = lessons
+                    .iter()
+                    .map(|l| {
+                        [
+                            l.id().to_string(),
+                            l.module_id().to_string(),
+                            l.order_index().to_string(),
+                            l.title().to_string(),
+                        ]
+                    })
+                    .collect();
+                print_table(["ID", "MODULE", "ORDER", "TITLE"], rows, page.offset, total);
+            }
+
+            LessonCommands::Delete { title } => {
+                let id = lookup_id(&mm, "SELECT id FROM lessons WHERE title = $1", &title, "Lesson").await?;
+                if let Some(lesson) = Lesson::find_by_id(&mm, &actor, id).await? {
+                    lesson.delete_cascade(&mm, &actor).await?;
+                }
+                println!("Lesson deleted: {} ({})", title, id);
+            }
         },
 
         Commands::Task { action } => match action {
             TaskCommands::Add { lesson_title, task_type, question, explanation } => {
-                let lesson_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM lessons WHERE title = $1")
-                    .bind(&lesson_title)
-                    .fetch_one(mm.executor())
-                    .await
-                    .map_err(|e| DatabaseError::SqlxError(e))?;
+                let lesson_id = lookup_id(&mm, "SELECT id FROM lessons WHERE title = $1", &lesson_title, "Lesson").await?;
 
                 let task = LessonTask::create(
                     &mm,
@@ -200,6 +414,10 @@ async fn main() -> flern::error::AppResult<()> {
                         task_type,
                         question,
                         explanation,
+                        case_insensitive: false,
+                        normalize_whitespace: false,
+                        min_seconds_before_check: 0,
+                        reveal_on_fail: true,
                     },
                 )
                 .await?;
@@ -207,11 +425,7 @@ async fn main() -> flern::error::AppResult<()> {
             }
 
             TaskCommands::AddAnswer { task_question, answer_text, image, is_correct } => {
-                let task_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM tasks WHERE question = $1")
-                    .bind(&task_question)
-                    .fetch_one(mm.executor())
-                    .await
-                    .map_err(|e| DatabaseError::SqlxError(e))?;
+                let task_id = lookup_id(&mm, "SELECT id FROM tasks WHERE question = $1", &task_question, "Task").await?;
 
                 let answer = Answer::create(
                     &mm,
@@ -226,6 +440,48 @@ async fn main() -> flern::error::AppResult<()> {
                 .await?;
                 println!("Answer created: {:?}", answer);
             }
+
+            TaskCommands::List { page } => {
+                let tasks = LessonTask::list(&mm, &actor, page.limit, page.offset, None).await?;
+                let total = LessonTask::count(&mm, &actor).await?;
+                let rows = tasks
+                    .iter()
+                    .map(|t| {
+                        [
+                            t.id().to_string(),
+                            t.lesson_id().to_string(),
+                            t.task_type().to_string(),
+                            t.question().to_string(),
+                        ]
+                    })
+                    .collect();
+                print_table(["ID", "LESSON", "TYPE", "QUESTION"], rows, page.offset, total);
+            }
+
+            TaskCommands::Delete { question } => {
+                let id = lookup_id(&mm, "SELECT id FROM tasks WHERE question = $1", &question, "Task").await?;
+                if let Some(task) = LessonTask::find_by_id(&mm, &actor, id).await? {
+                    task.delete(&mm, &actor).await?;
+                }
+                println!("Task deleted: {} ({})", question, id);
+            }
+        },
+
+        Commands::Course { action } => match action {
+            CourseCommands::Import { file } => {
+                let summary = CourseImport::from_path(&file)?.import(&mm, &actor).await?;
+                println!(
+                    "Course imported: {} modules, {} lessons, {} tasks, {} answers",
+                    summary.modules, summary.lessons, summary.tasks, summary.answers
+                );
+            }
+
+            CourseCommands::Export { out } => {
+                let course = CourseImport::from_database(&mm, &actor).await?;
+                let json = serde_json::to_string_pretty(&course).expect("course serializes to JSON");
+                std::fs::write(&out, json)?;
+                println!("Course exported to {}: {} modules", out.display(), course.modules.len());
+            }
         },
     }
 
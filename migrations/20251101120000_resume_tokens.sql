@@ -0,0 +1,9 @@
+-- Shareable links pointing at a user's current lesson
+CREATE TABLE resume_tokens (
+	id UUID PRIMARY KEY,
+	token TEXT NOT NULL UNIQUE,
+	user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	lesson_id UUID NOT NULL REFERENCES lessons(id) ON DELETE CASCADE,
+	expires_at TIMESTAMPTZ NOT NULL,
+	created_at TIMESTAMPTZ DEFAULT now()
+);
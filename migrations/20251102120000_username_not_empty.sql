@@ -0,0 +1,2 @@
+-- Usernames are required, an empty string shouldn't count as one
+ALTER TABLE users ADD CONSTRAINT users_username_not_empty CHECK (btrim(username) <> '');
@@ -0,0 +1,10 @@
+-- Long-lived, single-use tokens exchanged for fresh session tokens
+CREATE TABLE refresh_tokens (
+	id UUID PRIMARY KEY,
+	token TEXT NOT NULL UNIQUE,
+	user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	expires_at TIMESTAMPTZ NOT NULL,
+	created_at TIMESTAMPTZ DEFAULT now()
+);
+
+CREATE INDEX idx_refresh_tokens_user_id ON refresh_tokens(user_id);
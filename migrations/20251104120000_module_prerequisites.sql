@@ -0,0 +1,4 @@
+-- Share of the previous module's lessons a user must complete before this module opens
+ALTER TABLE modules ADD COLUMN min_prerequisite_percent INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE modules ADD CONSTRAINT modules_min_prerequisite_percent_range
+    CHECK (min_prerequisite_percent BETWEEN 0 AND 100);
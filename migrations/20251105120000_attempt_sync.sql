@@ -0,0 +1,5 @@
+-- Offline clients send their own attempt ids, so replaying a batch is harmless
+ALTER TABLE user_task_attempts ADD COLUMN client_id UUID;
+ALTER TABLE user_task_attempts ADD COLUMN attempted_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE UNIQUE INDEX idx_user_task_attempts_user_client ON user_task_attempts(user_id, client_id);
@@ -0,0 +1,19 @@
+-- Per user per module lesson counts, kept in sync with user_progress so dashboards skip the aggregation
+CREATE TABLE user_module_progress (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    module_id UUID NOT NULL REFERENCES modules(id) ON DELETE CASCADE,
+    completed INTEGER NOT NULL DEFAULT 0,
+    total INTEGER NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, module_id)
+);
+
+INSERT INTO user_module_progress (user_id, module_id, completed, total)
+SELECT
+    up.user_id,
+    l.module_id,
+    COUNT(*) FILTER (WHERE up.status),
+    (SELECT COUNT(*) FROM lessons ml WHERE ml.module_id = l.module_id)
+FROM user_progress up
+JOIN lessons l ON l.id = up.lesson_id
+GROUP BY up.user_id, l.module_id;
@@ -0,0 +1,7 @@
+-- Insertion time of an attempt, keyset pagination walks attempts by (created_at, id)
+ALTER TABLE user_task_attempts ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+-- rows added before this column know nothing better than when they were attempted
+UPDATE user_task_attempts SET created_at = attempted_at;
+
+CREATE INDEX idx_user_task_attempts_created_at_id ON user_task_attempts(created_at, id);
@@ -0,0 +1,3 @@
+-- Lets instances list the catalog by the time content was added
+ALTER TABLE modules ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE lessons ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,15 @@
+-- Creation and last update time of the core entities, for auditing and "recently added" views.
+-- modules and lessons already have created_at
+ALTER TABLE users ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE tasks ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE task_answers ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+ALTER TABLE users ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE modules ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE lessons ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE tasks ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE task_answers ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+-- existing rows haven't changed since they were added as far as anyone knows
+UPDATE modules SET updated_at = created_at;
+UPDATE lessons SET updated_at = created_at;
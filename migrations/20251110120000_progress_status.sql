@@ -0,0 +1,8 @@
+-- Lesson progress as a state instead of a done flag, every query compares against 'done'
+CREATE TYPE progress_status AS ENUM ('not_started', 'in_progress', 'done');
+
+ALTER TABLE user_progress ALTER COLUMN status DROP DEFAULT;
+-- a row that isn't done still means the user got to the lesson
+ALTER TABLE user_progress ALTER COLUMN status TYPE progress_status
+    USING (CASE WHEN status THEN 'done' ELSE 'in_progress' END)::progress_status;
+ALTER TABLE user_progress ALTER COLUMN status SET DEFAULT 'not_started';
@@ -0,0 +1,10 @@
+ALTER TABLE tasks DROP CONSTRAINT tasks_task_type_check;
+ALTER TABLE tasks ADD CONSTRAINT tasks_task_type_check
+    CHECK (task_type IN ('fill_code', 'choice', 'string_cmp', 'multi_select'));
+
+-- Answers picked in a multi_select attempt, selected_answer_id only holds one of them
+CREATE TABLE user_task_attempt_answers (
+    attempt_id UUID NOT NULL REFERENCES user_task_attempts(id) ON DELETE CASCADE,
+    answer_id UUID NOT NULL REFERENCES task_answers(id) ON DELETE CASCADE,
+    PRIMARY KEY (attempt_id, answer_id)
+);
@@ -0,0 +1,3 @@
+-- How string_cmp answers are compared, both off keeps the exact comparison
+ALTER TABLE tasks ADD COLUMN case_insensitive BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE tasks ADD COLUMN normalize_whitespace BOOLEAN NOT NULL DEFAULT FALSE;
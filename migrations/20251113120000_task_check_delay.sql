@@ -0,0 +1,3 @@
+-- Seconds a learner has to spend on a task before checking it, 0 turns the delay off
+ALTER TABLE tasks ADD COLUMN min_seconds_before_check INT NOT NULL DEFAULT 0
+    CHECK (min_seconds_before_check >= 0);
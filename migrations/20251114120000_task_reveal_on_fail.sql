@@ -0,0 +1,2 @@
+-- Whether a wrong check tells which answers were right, string_cmp tasks never do
+ALTER TABLE tasks ADD COLUMN reveal_on_fail BOOLEAN NOT NULL DEFAULT TRUE;
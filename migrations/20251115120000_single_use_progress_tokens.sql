@@ -0,0 +1,2 @@
+-- Single-use share tokens are deleted after their first successful read
+ALTER TABLE progress_tokens ADD COLUMN single_use BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Instructors author course content but don't manage users, existing accounts keep their role
+ALTER TABLE users DROP CONSTRAINT users_role_check;
+ALTER TABLE users ADD CONSTRAINT users_role_check CHECK (role IN ('admin', 'instructor', 'user'));
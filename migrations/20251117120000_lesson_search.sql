@@ -0,0 +1,3 @@
+-- Full-text search over lesson titles and content, queries have to use the same expression
+CREATE INDEX lessons_search_idx ON lessons
+    USING GIN (to_tsvector('english', title || ' ' || content));
@@ -0,0 +1,8 @@
+-- multi_select attempts keep their picks in user_task_attempt_answers only,
+-- the one answer id stored with them so far was an arbitrary answer of the task
+ALTER TABLE user_task_attempts ALTER COLUMN selected_answer_id DROP NOT NULL;
+
+UPDATE user_task_attempts uta
+SET selected_answer_id = NULL
+FROM tasks t
+WHERE t.id = uta.task_id AND t.task_type = 'multi_select';
@@ -9,3 +9,11 @@ pub enum CryptError {
     #[error("jwt error: {0}")]
     JwtError(#[from] jsonwebtoken::errors::Error),
 }
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PasswordError {
+    #[error("password must be at least {min_len} characters long")]
+    TooShort { min_len: usize },
+    #[error("password can't be only whitespace")]
+    Blank,
+}
@@ -1,10 +1,18 @@
+use chrono::{DateTime, Duration, Utc};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, TokenData, Validation};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserClaims {
     pub sub: String,
     pub exp: i64,
+    /// Role of the user at sign in, missing from tokens issued before it was added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// Unix time in milliseconds, `0` in tokens issued before it was added
+    #[serde(default)]
+    pub issued_at: i64,
 }
 
 pub fn generate_token<K: AsRef<[u8]>>(
@@ -28,3 +36,75 @@ pub fn process_token<K: AsRef<[u8]>>(
     let claims = jsonwebtoken::decode::<UserClaims>(token, &key, &validation)?;
     Ok(claims)
 }
+
+/// Issued with every task that has a check delay, proves when the task was served and to whom
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskServedClaims {
+    pub sub: String,
+    pub task_id: String,
+    /// Unix time in milliseconds
+    pub served_at: i64,
+    pub exp: i64,
+}
+
+impl TaskServedClaims {
+    /// Valid for an hour, long enough to work through a lesson's tasks
+    pub fn new(user_id: Uuid, task_id: Uuid, served_at: DateTime<Utc>) -> Self {
+        Self {
+            sub: user_id.to_string(),
+            task_id: task_id.to_string(),
+            served_at: served_at.timestamp_millis(),
+            exp: (served_at + Duration::hours(1)).timestamp(),
+        }
+    }
+}
+
+/// Secret task tokens are signed with, derived from the session secret. Its own key keeps a
+/// served token from passing as a session token, both carry the user id as `sub`
+fn task_key(key: &[u8]) -> Vec<u8> {
+    [b"flern-task-token\0".as_slice(), key].concat()
+}
+
+/// Takes the session secret, see `task_key`
+pub fn generate_task_token<K: AsRef<[u8]>>(
+    claims: TaskServedClaims,
+    key: K,
+) -> jsonwebtoken::errors::Result<String> {
+    let key = EncodingKey::from_secret(&task_key(key.as_ref()));
+    jsonwebtoken::encode(&Header::default(), &claims, &key)
+}
+
+/// Takes the session secret, see `task_key`
+pub fn process_task_token<K: AsRef<[u8]>>(
+    token: &str,
+    key: K,
+) -> jsonwebtoken::errors::Result<TokenData<TaskServedClaims>> {
+    let key = DecodingKey::from_secret(&task_key(key.as_ref()));
+    jsonwebtoken::decode::<TaskServedClaims>(token, &key, &Validation::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_token_session_test() {
+        let secret = "secret";
+        let user_id = Uuid::new_v4();
+
+        let task = TaskServedClaims::new(user_id, Uuid::new_v4(), Utc::now());
+        let task = generate_task_token(task, secret).unwrap();
+        assert!(process_task_token(&task, secret).is_ok());
+        assert!(process_token(&task, secret).is_err());
+
+        let session = UserClaims {
+            sub: user_id.to_string(),
+            exp: (Utc::now() + Duration::minutes(5)).timestamp(),
+            role: None,
+            issued_at: 0,
+        };
+        let session = generate_token(session, secret).unwrap();
+        assert!(process_token(&session, secret).is_ok());
+        assert!(process_task_token(&session, secret).is_err());
+    }
+}
@@ -1,11 +1,14 @@
 mod password;
-pub use password::{hash_password, verify_password};
+pub use password::{hash_password, validate_password, verify_password};
 
 mod jwt;
-pub use jwt::{UserClaims, generate_token, process_token};
+pub use jwt::{
+    TaskServedClaims, UserClaims, generate_task_token, generate_token, process_task_token,
+    process_token,
+};
 
 mod error;
-pub use error::{CryptError, CryptResult};
+pub use error::{CryptError, CryptResult, PasswordError};
 
 pub mod token;
 
@@ -3,7 +3,7 @@ use argon2::{
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
 
-use crate::auth::error::CryptResult;
+use crate::auth::error::{CryptResult, PasswordError};
 
 pub fn hash_password(password: &str) -> CryptResult<String> {
     let salt = SaltString::generate(&mut OsRng);
@@ -19,3 +19,38 @@ pub fn verify_password(hash: &str, password: &str) -> CryptResult<bool> {
         .verify_password(password.as_bytes(), &parsed_hash)
         .is_ok())
 }
+
+/// Checks a new password against the strength rules, call it before `hash_password`
+pub fn validate_password(password: &str, min_len: usize) -> Result<(), PasswordError> {
+    if password.chars().count() < min_len {
+        return Err(PasswordError::TooShort { min_len });
+    }
+    if password.trim().is_empty() {
+        return Err(PasswordError::Blank);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate_password_length_test() {
+        assert_eq!(validate_password("1234567", 8), Err(PasswordError::TooShort { min_len: 8 }));
+        assert_eq!(validate_password("12345678", 8), Ok(()));
+        assert_eq!(validate_password("123456789", 8), Ok(()));
+        assert_eq!(validate_password("", 8), Err(PasswordError::TooShort { min_len: 8 }));
+        // characters, not bytes
+        assert_eq!(validate_password("пароль12", 8), Ok(()));
+    }
+
+    #[test]
+    fn validate_password_whitespace_test() {
+        assert_eq!(validate_password("        ", 8), Err(PasswordError::Blank));
+        assert_eq!(validate_password(" \t\n  \t  ", 8), Err(PasswordError::Blank));
+        assert_eq!(validate_password("", 0), Err(PasswordError::Blank));
+        assert_eq!(validate_password("  pass  word  ", 8), Ok(()));
+    }
+}
@@ -0,0 +1,118 @@
+//! Typed HTTP client for the flern API, built with the `client` feature.
+//!
+//! Requests and responses use the same DTO and entity types as the server, so both sides stay
+//! in sync. The session cookie set by `signin` is kept and sent with every later request.
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, de::DeserializeOwned};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+    model::entity::UserEntity,
+    web::{
+        dto::{
+            lessons::LessonResponse,
+            modules::ModuleWithLessons,
+            tasks::{TaskCheckRequest, TaskCheckResponse},
+        },
+        routes::user::UserCreateUpdateBody,
+    },
+};
+
+pub type ClientResult<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The API answered with an error status, `message` is the one of its `ErrorResponse`
+    #[error("api error {status}: {message}")]
+    Api { status: StatusCode, message: String },
+}
+
+impl ClientError {
+    /// Status the API answered with, none if the request didn't get that far
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Request(e) => e.status(),
+            Self::Api { status, .. } => Some(*status),
+        }
+    }
+}
+
+/// Only the part of `ErrorResponse` the client needs
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct FlernClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl FlernClient {
+    /// Client for the API served at `base_url`, e.g. `https://flern.example.com`
+    pub fn new<S: Into<String>>(base_url: S) -> ClientResult<Self> {
+        let http = reqwest::Client::builder().cookie_store(true).build()?;
+        Ok(Self::with_http_client(http, base_url))
+    }
+
+    /// Same as `new` with a preconfigured `reqwest::Client`, it needs a cookie store to stay
+    /// signed in
+    pub fn with_http_client<S: Into<String>>(http: reqwest::Client, base_url: S) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { http, base_url }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Signs in and keeps the session for the following requests
+    pub async fn signin(&self, username: &str, password: &str) -> ClientResult<UserEntity> {
+        let body = UserCreateUpdateBody {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        self.send(self.request(Method::POST, "/api/v1/account/signin").json(&body))
+            .await
+    }
+
+    /// Module catalog with the lessons of each module
+    pub async fn list_modules(&self) -> ClientResult<Vec<ModuleWithLessons>> {
+        self.send(self.request(Method::GET, "/api/v1/modules/")).await
+    }
+
+    pub async fn get_lesson(&self, id: Uuid) -> ClientResult<LessonResponse> {
+        self.send(self.request(Method::GET, &format!("/api/v1/lessons/{id}")))
+            .await
+    }
+
+    /// Checks an answer, a correct one also marks its lesson as done
+    pub async fn check_task(&self, req: &TaskCheckRequest) -> ClientResult<TaskCheckResponse> {
+        self.send(self.request(Method::POST, "/api/v1/tasks/check").json(req))
+            .await
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, format!("{}{path}", self.base_url))
+    }
+
+    async fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> ClientResult<T> {
+        let response = req.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        // the body of errors that didn't come from the API itself may not be JSON
+        let message = match response.json::<ErrorBody>().await {
+            Ok(body) => body.message,
+            Err(_) => status.canonical_reason().unwrap_or_default().to_string(),
+        };
+        Err(ClientError::Api { status, message })
+    }
+}
@@ -1,4 +1,8 @@
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use tracing::debug;
 
@@ -35,8 +39,11 @@ pub fn find_config_file(use_local: bool) -> PathBuf {
 }
 
 pub fn read_config(use_local: bool) -> ConfigResult<Vec<u8>> {
-    let filename = find_config_file(use_local);
+    read_config_file(&find_config_file(use_local))
+}
 
+/// Reads the config at `filename`, `ConfigNotFound` when there's no file
+pub fn read_config_file(filename: &Path) -> ConfigResult<Vec<u8>> {
     tracing::trace!("looking for config at: {}", filename.display());
     if !filename.exists() {
         return Err(crate::config::error::ConfigError::ConfigNotFound);
@@ -6,10 +6,13 @@ pub type ConfigResult<T> = std::result::Result<T, ConfigError>;
 pub enum ConfigError {
     #[error("io error: {0}")]
     IoError(#[from] std::io::Error),
-    #[error("de error: {0}")]
-    TomlDeError(#[from] toml::de::Error),
+    /// Config isn't valid TOML or doesn't match the expected shape, the message points at the spot
+    #[error("toml error: {0}")]
+    Toml(#[from] toml::de::Error),
     #[error("se error: {0}")]
     TomlSeError(#[from] toml::ser::Error),
     #[error("config not found")]
     ConfigNotFound,
+    #[error("invalid `{field}`: {reason}")]
+    Invalid { field: &'static str, reason: String },
 }
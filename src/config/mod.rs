@@ -1,9 +1,16 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
 use serde::Deserialize;
 
+use crate::model::ContentSort;
+
 static CONFIG: OnceCell<Config> = OnceCell::const_new();
 
 mod config_dir;
-pub use config_dir::{find_config_file, read_config};
+pub use config_dir::{find_config_file, read_config, read_config_file};
 
 mod error;
 pub use error::{ConfigError, ConfigResult};
@@ -13,6 +20,9 @@ use tokio::sync::OnceCell;
 pub struct Config {
     host: Host,
     app: App,
+    #[serde(default)]
+    database: Database,
+    cors: Option<Cors>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,41 +30,400 @@ pub struct Host {
     bindto: String,
 }
 
+/// Connection pool settings, the whole `[database]` section is optional
+#[derive(Debug, Deserialize)]
+pub struct Database {
+    #[serde(default = "default_max_connections")]
+    max_connections: u32,
+    #[serde(default = "default_acquire_timeout_secs")]
+    acquire_timeout_secs: u64,
+    #[serde(default = "default_idle_timeout_secs")]
+    idle_timeout_secs: u64,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self {
+            max_connections: default_max_connections(),
+            acquire_timeout_secs: default_acquire_timeout_secs(),
+            idle_timeout_secs: default_idle_timeout_secs(),
+        }
+    }
+}
+
+/// Cross-origin access, see `Config::cors` for what happens without the section
+#[derive(Debug, Deserialize)]
+pub struct Cors {
+    #[serde(default)]
+    allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    allowed_methods: Vec<String>,
+    #[serde(default)]
+    allow_credentials: bool,
+}
+
+fn default_cors_methods() -> Vec<String> {
+    ["GET", "POST", "PUT", "PATCH", "DELETE"].map(String::from).to_vec()
+}
+
+fn default_max_connections() -> u32 {
+    10
+}
+
+fn default_acquire_timeout_secs() -> u64 {
+    30
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    60 * 10
+}
+
 #[derive(Debug, Deserialize)]
 pub struct App {
     jwt: String,
     database_uri: String,
     host_url: String,
+    #[serde(default)]
     docs: bool,
+    #[serde(default = "default_modules_cache_ttl")]
+    modules_cache_ttl: u64,
+    #[serde(default)]
+    public_catalog: bool,
+    #[serde(default = "default_compression")]
+    compression: bool,
+    #[serde(default = "default_token_ttl_secs")]
+    token_ttl_secs: i64,
+    #[serde(default = "default_refresh_token_ttl_secs")]
+    refresh_token_ttl_secs: i64,
+    #[serde(default = "default_min_password_len")]
+    min_password_len: usize,
+    #[serde(default = "default_max_active_share_tokens")]
+    max_active_share_tokens: i64,
+    #[serde(default = "default_max_share_ttl_minutes")]
+    max_share_ttl_minutes: u32,
+    #[serde(default)]
+    cookie_secure: bool,
+    #[serde(default)]
+    cookie_same_site: CookieSameSite,
+    #[serde(default)]
+    log_exclude_paths: Vec<String>,
+    #[serde(default = "default_token_cleanup_secs")]
+    token_cleanup_secs: u64,
+    #[serde(default = "default_uploads_gc_grace_secs")]
+    uploads_gc_grace_secs: u64,
+    #[serde(default = "default_max_upload_bytes")]
+    max_upload_bytes: usize,
+    #[serde(default = "default_uploads_dir")]
+    uploads_dir: PathBuf,
+    #[serde(default = "default_uploads_max_age_secs")]
+    uploads_max_age_secs: u64,
+    #[serde(default = "default_rate_limit_requests")]
+    rate_limit_requests: u32,
+    #[serde(default = "default_rate_limit_window_secs")]
+    rate_limit_window_secs: u64,
+    #[serde(default = "default_auth_rate_limit_requests")]
+    auth_rate_limit_requests: u32,
+    #[serde(default = "default_auth_rate_limit_window_secs")]
+    auth_rate_limit_window_secs: u64,
+    #[serde(default)]
+    default_content_sort: ContentSort,
+    #[serde(default)]
+    migrations_dir: Option<PathBuf>,
+    #[serde(default = "default_admin_username")]
+    admin_username: String,
+    #[serde(default)]
+    admin_password: Option<String>,
+}
+
+/// `SameSite` attribute of the auth cookies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    #[default]
+    Lax,
+    Strict,
+    None,
+}
+
+fn default_modules_cache_ttl() -> u64 {
+    60
 }
 
+fn default_token_ttl_secs() -> i64 {
+    60 * 60 * 24
+}
+
+fn default_refresh_token_ttl_secs() -> i64 {
+    60 * 60 * 24 * 30
+}
+
+fn default_admin_username() -> String {
+    String::from("admin")
+}
+
+fn default_min_password_len() -> usize {
+    8
+}
+
+fn default_max_active_share_tokens() -> i64 {
+    5
+}
+
+fn default_max_share_ttl_minutes() -> u32 {
+    60 * 24
+}
+
+fn default_compression() -> bool {
+    true
+}
+
+fn default_token_cleanup_secs() -> u64 {
+    60 * 5
+}
+
+fn default_uploads_gc_grace_secs() -> u64 {
+    60 * 60 * 24
+}
+
+fn default_max_upload_bytes() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_uploads_dir() -> PathBuf {
+    PathBuf::from("uploads")
+}
+
+fn default_uploads_max_age_secs() -> u64 {
+    60 * 60 * 24
+}
+
+fn default_rate_limit_requests() -> u32 {
+    300
+}
+
+fn default_rate_limit_window_secs() -> u64 {
+    60
+}
+
+fn default_auth_rate_limit_requests() -> u32 {
+    20
+}
+
+fn default_auth_rate_limit_window_secs() -> u64 {
+    60
+}
+
+/// Read `./config.toml` instead of `~/.config/flern/config.toml` (`%APPDATA%` on Windows).
+/// Local lookup suits dev checkouts, tests and containers, which all run from the project dir
 static USE_LOCAL: bool = true;
 
+/// Environment variables that override config keys, as `(variable, section, key)`
+const ENV_OVERRIDES: [(&str, &str, &str); 4] = [
+    ("FLERN_BINDTO", "host", "bindto"),
+    ("FLERN_JWT", "app", "jwt"),
+    ("FLERN_DATABASE_URI", "app", "database_uri"),
+    ("FLERN_ADMIN_PASSWORD", "app", "admin_password"),
+];
+
 impl Config {
+    /// Loads the global config on first use, see [`USE_LOCAL`] for where it's looked up. Errors
+    /// aren't cached, a later call reads the file again
     #[tracing::instrument]
-    pub async fn get_or_init() -> &'static Config {
-        CONFIG
-            .get_or_init(|| async {
-                let read_cfg = |use_local| -> ConfigResult<Self> {
-                    let bytes = read_config(use_local)?;
-                    let config: Self = toml::from_slice(&bytes)?;
-                    Ok(config)
+    pub async fn get_or_init() -> ConfigResult<&'static Config> {
+        Self::init_cell(&CONFIG, &find_config_file(USE_LOCAL)).await
+    }
+
+    async fn init_cell(cell: &'static OnceCell<Config>, path: &Path) -> ConfigResult<&'static Config> {
+        cell.get_or_try_init(|| async { Self::load(path) }).await
+    }
+
+    /// Reads, parses and validates the config at `path`. `FLERN_*` environment variables win
+    /// over the file
+    pub fn load(path: &Path) -> ConfigResult<Self> {
+        let bytes = read_config_file(path)?;
+        Self::from_slice_with_env(&bytes, |var| std::env::var(var).ok())
+    }
+
+    fn from_slice_with_env(bytes: &[u8], env: impl Fn(&str) -> Option<String>) -> ConfigResult<Self> {
+        let overrides: Vec<_> = ENV_OVERRIDES
+            .iter()
+            .filter_map(|&(var, section, key)| env(var).map(|value| (section, key, value)))
+            .collect();
+
+        // going through a table loses the spans in error messages, so only do it when needed
+        let config: Self = if overrides.is_empty() {
+            toml::from_slice(bytes)?
+        } else {
+            let mut table: toml::Table = toml::from_slice(bytes)?;
+            for (section, key, value) in overrides {
+                let Some(section_table) = table
+                    .entry(section)
+                    .or_insert_with(|| toml::Table::new().into())
+                    .as_table_mut()
+                else {
+                    return Err(ConfigError::Invalid {
+                        field: section,
+                        reason: String::from("must be a table"),
+                    });
                 };
+                section_table.insert(key.to_string(), value.into());
+            }
+            table.try_into()?
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses a config without touching the global one, e.g. for tests
+    pub fn from_toml(source: &str) -> ConfigResult<Self> {
+        let config: Self = toml::from_str(source)?;
+        config.validate()?;
+        Ok(config)
+    }
 
-                let config = match read_cfg(USE_LOCAL) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        if !matches!(e, error::ConfigError::ConfigNotFound) {
-                            crate::error::log_error(&e);
-                        }
-                        tracing::error!("Config not found.");
-                        std::process::exit(1);
+    /// Checks values serde can't, so a bad config fails at startup instead of at request time
+    fn validate(&self) -> ConfigResult<()> {
+        // otherwise these only fail once the listener binds or the first query runs
+        if let Err(e) = self.host.bindto.parse::<std::net::SocketAddr>() {
+            return Err(ConfigError::Invalid {
+                field: "host.bindto",
+                reason: format!("expected an address like 127.0.0.1:5000, got {:?}: {e}", self.host.bindto),
+            });
+        }
+
+        match url::Url::parse(&self.app.host_url) {
+            Ok(url) if !url.cannot_be_a_base() => {}
+            Ok(_) => {
+                return Err(ConfigError::Invalid {
+                    field: "app.host_url",
+                    reason: format!("expected a base URL like http://example.com/, got {:?}", self.app.host_url),
+                });
+            }
+            Err(e) => {
+                return Err(ConfigError::Invalid {
+                    field: "app.host_url",
+                    reason: format!("expected an absolute URL, got {:?}: {e}", self.app.host_url),
+                });
+            }
+        }
+
+        if !["postgres://", "postgresql://"]
+            .iter()
+            .any(|scheme| self.app.database_uri.starts_with(scheme))
+        {
+            return Err(ConfigError::Invalid {
+                field: "app.database_uri",
+                reason: String::from("must start with postgres:// or postgresql://"),
+            });
+        }
+
+        let positive = [
+            ("app.token_ttl_secs", self.app.token_ttl_secs),
+            ("app.refresh_token_ttl_secs", self.app.refresh_token_ttl_secs),
+            ("app.max_active_share_tokens", self.app.max_active_share_tokens),
+        ];
+        for (field, value) in positive {
+            if value <= 0 {
+                return Err(ConfigError::Invalid {
+                    field,
+                    reason: format!("must be positive, got {value}"),
+                });
+            }
+        }
+
+        if self.app.max_upload_bytes == 0 {
+            return Err(ConfigError::Invalid {
+                field: "app.max_upload_bytes",
+                reason: String::from("must be positive, got 0"),
+            });
+        }
+
+        if let Some(cors) = &self.cors {
+            for origin in &cors.allowed_origins {
+                if origin == "*" {
+                    // browsers refuse credentialed responses for `*`
+                    if cors.allow_credentials {
+                        return Err(ConfigError::Invalid {
+                            field: "cors.allowed_origins",
+                            reason: String::from("\"*\" can't be combined with allow_credentials, list the origins"),
+                        });
                     }
-                };
+                    continue;
+                }
+                let valid = url::Url::parse(origin)
+                    .is_ok_and(|url| url.origin().is_tuple() && url.origin().ascii_serialization() == *origin);
+                if !valid {
+                    return Err(ConfigError::Invalid {
+                        field: "cors.allowed_origins",
+                        reason: format!("expected an origin like https://example.com, got {origin:?}"),
+                    });
+                }
+            }
+            for method in &cors.allowed_methods {
+                if method.parse::<axum::http::Method>().is_err() {
+                    return Err(ConfigError::Invalid {
+                        field: "cors.allowed_methods",
+                        reason: format!("not an HTTP method: {method:?}"),
+                    });
+                }
+            }
+        }
+
+        let database = [
+            ("database.max_connections", u64::from(self.database.max_connections)),
+            ("database.acquire_timeout_secs", self.database.acquire_timeout_secs),
+            ("database.idle_timeout_secs", self.database.idle_timeout_secs),
+        ];
+        for (field, value) in database {
+            if value == 0 {
+                return Err(ConfigError::Invalid {
+                    field,
+                    reason: String::from("must be positive, got 0"),
+                });
+            }
+        }
 
-                config
-            })
-            .await
+        if self.app.token_cleanup_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field: "app.token_cleanup_secs",
+                reason: String::from("must be positive, got 0"),
+            });
+        }
+
+        if self.app.max_share_ttl_minutes == 0 {
+            return Err(ConfigError::Invalid {
+                field: "app.max_share_ttl_minutes",
+                reason: String::from("must be positive, got 0"),
+            });
+        }
+
+        if self.app.admin_username.trim().is_empty() {
+            return Err(ConfigError::Invalid {
+                field: "app.admin_username",
+                reason: String::from("must not be empty"),
+            });
+        }
+
+        // the seeded admin signs in like everyone else, so hold it to the signup rules
+        if let Some(password) = &self.app.admin_password
+            && let Err(e) = crate::auth::validate_password(password, self.app.min_password_len)
+        {
+            return Err(ConfigError::Invalid {
+                field: "app.admin_password",
+                reason: e.to_string(),
+            });
+        }
+
+        // browsers drop `SameSite=None` cookies without `Secure`
+        if self.app.cookie_same_site == CookieSameSite::None && !self.app.cookie_secure {
+            return Err(ConfigError::Invalid {
+                field: "app.cookie_same_site",
+                reason: String::from("`none` requires `cookie_secure = true`"),
+            });
+        }
+
+        Ok(())
     }
 
     #[inline]
@@ -66,6 +435,57 @@ impl Config {
     pub fn app(&self) -> &App {
         &self.app
     }
+
+    #[inline]
+    pub fn database(&self) -> &Database {
+        &self.database
+    }
+
+    /// `None` without a `[cors]` section. Debug builds then allow every origin, release builds
+    /// none
+    #[inline]
+    pub fn cors(&self) -> Option<&Cors> {
+        self.cors.as_ref()
+    }
+}
+
+impl Cors {
+    /// Origins like `https://example.com` that may call the API, `*` for any without credentials
+    #[inline]
+    pub fn allowed_origins(&self) -> &[String] {
+        &self.allowed_origins
+    }
+
+    #[inline]
+    pub fn allowed_methods(&self) -> &[String] {
+        &self.allowed_methods
+    }
+
+    /// Whether browsers may send cookies along with cross-origin requests
+    #[inline]
+    pub fn allow_credentials(&self) -> bool {
+        self.allow_credentials
+    }
+}
+
+impl Database {
+    /// Most connections the pool opens at once
+    #[inline]
+    pub fn max_connections(&self) -> u32 {
+        self.max_connections
+    }
+
+    /// How long a query waits for a free connection before failing
+    #[inline]
+    pub fn acquire_timeout(&self) -> Duration {
+        Duration::from_secs(self.acquire_timeout_secs)
+    }
+
+    /// How long an unused connection stays open
+    #[inline]
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
 }
 
 impl Host {
@@ -86,15 +506,159 @@ impl App {
         &self.database_uri
     }
 
+    /// Absolute base URL uploaded files are linked from
     #[inline]
     pub fn host_url(&self) -> &str {
-        &self.host_url 
+        &self.host_url
     }
 
+    /// Whether the Swagger UI is served at `/api/v1/docs`
     #[inline]
     pub fn docs(&self) -> bool {
         self.docs
     }
+
+    /// Lifetime of cached module listings, `0` disables the cache
+    #[inline]
+    pub fn modules_cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.modules_cache_ttl)
+    }
+
+    /// Lifetime of session tokens and their cookies, in seconds
+    #[inline]
+    pub fn token_ttl_secs(&self) -> i64 {
+        self.token_ttl_secs
+    }
+
+    /// Lifetime of refresh tokens and their cookies, in seconds
+    #[inline]
+    pub fn refresh_token_ttl_secs(&self) -> i64 {
+        self.refresh_token_ttl_secs
+    }
+
+    /// Shortest password accepted for new accounts, in characters
+    #[inline]
+    pub fn min_password_len(&self) -> usize {
+        self.min_password_len
+    }
+
+    /// How many unexpired progress share tokens a user may hold at once
+    #[inline]
+    pub fn max_active_share_tokens(&self) -> i64 {
+        self.max_active_share_tokens
+    }
+
+    /// Longest lifetime a progress share token may be requested with, in minutes
+    #[inline]
+    pub fn max_share_ttl_minutes(&self) -> u32 {
+        self.max_share_ttl_minutes
+    }
+
+    /// Whether auth cookies are only sent over HTTPS
+    #[inline]
+    pub fn cookie_secure(&self) -> bool {
+        self.cookie_secure
+    }
+
+    #[inline]
+    pub fn cookie_same_site(&self) -> CookieSameSite {
+        self.cookie_same_site
+    }
+
+    /// Request paths, with everything below them, that the request log skips
+    #[inline]
+    pub fn log_exclude_paths(&self) -> &[String] {
+        &self.log_exclude_paths
+    }
+
+    /// How often expired progress, resume and refresh tokens are deleted
+    #[inline]
+    pub fn token_cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.token_cleanup_secs)
+    }
+
+    /// How long an unreferenced upload is kept before the uploads GC may remove it
+    #[inline]
+    pub fn uploads_gc_grace(&self) -> Duration {
+        Duration::from_secs(self.uploads_gc_grace_secs)
+    }
+
+    /// Largest accepted image upload, in bytes
+    #[inline]
+    pub fn max_upload_bytes(&self) -> usize {
+        self.max_upload_bytes
+    }
+
+    /// Directory answer images are stored in and served from, relative ones start at the working directory
+    #[inline]
+    pub fn uploads_dir(&self) -> &Path {
+        &self.uploads_dir
+    }
+
+    /// How long clients may cache files served from `uploads_dir`
+    #[inline]
+    pub fn uploads_max_age(&self) -> Duration {
+        Duration::from_secs(self.uploads_max_age_secs)
+    }
+
+    /// Requests a client may make to the API per `rate_limit_window`, `0` disables the limit
+    #[inline]
+    pub fn rate_limit_requests(&self) -> u32 {
+        self.rate_limit_requests
+    }
+
+    #[inline]
+    pub fn rate_limit_window(&self) -> Duration {
+        Duration::from_secs(self.rate_limit_window_secs)
+    }
+
+    /// Sign-ins and sign-ups a client may make per `auth_rate_limit_window`, `0` disables the limit
+    #[inline]
+    pub fn auth_rate_limit_requests(&self) -> u32 {
+        self.auth_rate_limit_requests
+    }
+
+    #[inline]
+    pub fn auth_rate_limit_window(&self) -> Duration {
+        Duration::from_secs(self.auth_rate_limit_window_secs)
+    }
+
+    /// Order of the module catalog and of module lesson lists
+    #[inline]
+    pub fn default_content_sort(&self) -> ContentSort {
+        self.default_content_sort
+    }
+
+    /// Directory to read migrations from instead of the ones built into the binary
+    #[inline]
+    pub fn migrations_dir(&self) -> Option<&Path> {
+        self.migrations_dir.as_deref()
+    }
+
+    /// Username of the admin account created at startup, see `admin_password`
+    #[inline]
+    pub fn admin_username(&self) -> &str {
+        &self.admin_username
+    }
+
+    /// Password of the admin account created at startup when no admin exists yet, `None`
+    /// disables the seeding
+    #[inline]
+    pub fn admin_password(&self) -> Option<&str> {
+        self.admin_password.as_deref()
+    }
+
+    /// Whether the module catalog can be browsed without signing in
+    #[inline]
+    pub fn public_catalog(&self) -> bool {
+        self.public_catalog
+    }
+
+    /// Whether responses are gzip or brotli compressed for clients that accept it
+    #[inline]
+    pub fn compression(&self) -> bool {
+        self.compression
+    }
 }
 
 #[cfg(test)]
@@ -103,7 +667,362 @@ mod test {
 
     #[tokio::test]
     async fn config_test() {
-        let config = Config::get_or_init().await;
+        let config = Config::get_or_init().await.unwrap();
         assert_eq!(config.host().bindto(), "127.0.0.1:5000"); // defaults
     }
+
+    #[tokio::test]
+    async fn config_not_found_test() {
+        static CELL: OnceCell<Config> = OnceCell::const_new();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        let err = Config::init_cell(&CELL, &path).await.unwrap_err();
+        assert!(matches!(err, ConfigError::ConfigNotFound), "got {err:?}");
+        assert!(CELL.get().is_none());
+
+        // a failed load isn't cached, fixing the config lets the next call succeed
+        std::fs::write(
+            &path,
+            r#"
+            [host]
+            bindto = "0.0.0.0:8080"
+
+            [app]
+            jwt = "secret"
+            database_uri = "postgres://localhost/flern"
+            host_url = "http://localhost"
+            docs = false
+            "#,
+        )
+        .unwrap();
+        let config = Config::init_cell(&CELL, &path).await.unwrap();
+        assert_eq!(config.host().bindto(), "0.0.0.0:8080");
+    }
+
+    #[test]
+    fn config_from_toml_test() {
+        let config = Config::from_toml(
+            r#"
+            [host]
+            bindto = "0.0.0.0:8080"
+
+            [app]
+            jwt = "secret"
+            database_uri = "postgres://localhost/flern"
+            host_url = "http://localhost"
+            docs = false
+            public_catalog = true
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.host().bindto(), "0.0.0.0:8080");
+        assert!(config.app().public_catalog());
+        assert_eq!(config.app().modules_cache_ttl(), Duration::from_secs(60));
+        assert_eq!(config.app().token_ttl_secs(), 86400);
+        assert_eq!(config.app().refresh_token_ttl_secs(), 60 * 60 * 24 * 30);
+        assert_eq!(config.app().min_password_len(), 8);
+        assert_eq!(config.app().max_active_share_tokens(), 5);
+        assert!(!config.app().cookie_secure());
+        assert_eq!(config.app().cookie_same_site(), CookieSameSite::Lax);
+        assert!(config.app().log_exclude_paths().is_empty());
+        assert_eq!(config.app().rate_limit_requests(), 300);
+        assert_eq!(config.app().rate_limit_window(), Duration::from_secs(60));
+        assert_eq!(config.app().auth_rate_limit_requests(), 20);
+        assert_eq!(config.app().auth_rate_limit_window(), Duration::from_secs(60));
+        assert_eq!(config.app().default_content_sort(), ContentSort::OrderIndex);
+        assert!(config.app().migrations_dir().is_none());
+        assert_eq!(config.app().admin_username(), "admin");
+        assert!(config.app().admin_password().is_none());
+        assert!(config.cors().is_none());
+        assert!(config.app().compression());
+        assert_eq!(config.database().max_connections(), 10);
+        assert_eq!(config.database().acquire_timeout(), Duration::from_secs(30));
+        assert_eq!(config.database().idle_timeout(), Duration::from_secs(600));
+    }
+
+    #[test]
+    fn config_token_ttl_test() {
+        let source = |ttl: i64| {
+            format!(
+                r#"
+                [host]
+                bindto = "127.0.0.1:5000"
+
+                [app]
+                jwt = "secret"
+                database_uri = "postgres://localhost/flern"
+                host_url = "http://localhost"
+                docs = false
+                token_ttl_secs = {ttl}
+                "#
+            )
+        };
+
+        let config = Config::from_toml(&source(3600)).unwrap();
+        assert_eq!(config.app().token_ttl_secs(), 3600);
+
+        for ttl in [0, -5] {
+            let err = Config::from_toml(&source(ttl)).unwrap_err();
+            assert!(matches!(err, ConfigError::Invalid { field: "app.token_ttl_secs", .. }));
+        }
+    }
+
+    #[test]
+    fn config_admin_password_test() {
+        let source = |password: &str| {
+            format!(
+                r#"
+                [host]
+                bindto = "127.0.0.1:5000"
+
+                [app]
+                jwt = "secret"
+                database_uri = "postgres://localhost/flern"
+                host_url = "http://localhost"
+                admin_username = "root"
+                admin_password = "{password}"
+                "#
+            )
+        };
+
+        let config = Config::from_toml(&source("long enough")).unwrap();
+        assert_eq!(config.app().admin_username(), "root");
+        assert_eq!(config.app().admin_password(), Some("long enough"));
+
+        for password in ["short", "          "] {
+            let err = Config::from_toml(&source(password)).unwrap_err();
+            assert!(matches!(err, ConfigError::Invalid { field: "app.admin_password", .. }));
+        }
+    }
+
+    #[test]
+    fn config_env_override_test() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |var: &str| vars.iter().find(|(k, _)| *k == var).map(|(_, v)| v.to_string())
+        };
+        let file = br#"
+            [host]
+            bindto = "127.0.0.1:5000"
+
+            [app]
+            jwt = "file-secret"
+            host_url = "http://localhost"
+            docs = false
+            "#;
+
+        // database_uri may be left out of the file when the environment has it
+        let config = Config::from_slice_with_env(
+            file,
+            env(&[
+                ("FLERN_JWT", "env-secret"),
+                ("FLERN_DATABASE_URI", "postgres://env/flern"),
+                ("FLERN_BINDTO", "0.0.0.0:8080"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(config.app().jwt(), "env-secret");
+        assert_eq!(config.app().database_uri(), "postgres://env/flern");
+        assert_eq!(config.host().bindto(), "0.0.0.0:8080");
+
+        let config =
+            Config::from_slice_with_env(file, env(&[("FLERN_DATABASE_URI", "postgres://env/flern")])).unwrap();
+        assert_eq!(config.app().jwt(), "file-secret");
+        assert_eq!(config.host().bindto(), "127.0.0.1:5000");
+
+        // missing in both
+        let err = Config::from_slice_with_env(file, env(&[])).unwrap_err();
+        assert!(err.to_string().contains("database_uri"), "got {err}");
+    }
+
+    #[test]
+    fn config_env_load_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [host]
+            bindto = "127.0.0.1:5000"
+
+            [app]
+            host_url = "http://localhost"
+            docs = false
+            "#,
+        )
+        .unwrap();
+
+        // only keys nothing else asserts on, tests share the process environment
+        unsafe {
+            std::env::set_var("FLERN_JWT", "env-secret");
+            std::env::set_var("FLERN_DATABASE_URI", "postgres://env/flern");
+        }
+        let config = Config::load(&path);
+        unsafe {
+            std::env::remove_var("FLERN_JWT");
+            std::env::remove_var("FLERN_DATABASE_URI");
+        }
+
+        let config = config.unwrap();
+        assert_eq!(config.app().jwt(), "env-secret");
+        assert_eq!(config.app().database_uri(), "postgres://env/flern");
+    }
+
+    #[test]
+    fn config_address_test() {
+        let source = |bindto: &str, database_uri: &str| {
+            format!(
+                r#"
+                [host]
+                bindto = "{bindto}"
+
+                [app]
+                jwt = "secret"
+                database_uri = "{database_uri}"
+                host_url = "http://localhost"
+                docs = false
+                "#
+            )
+        };
+
+        assert!(Config::from_toml(&source("[::1]:5000", "postgresql://localhost/flern")).is_ok());
+
+        for bindto in ["localhost:5000", "127.0.0.1", "127.0.0.1:port", ""] {
+            let err = Config::from_toml(&source(bindto, "postgres://localhost/flern")).unwrap_err();
+            assert!(
+                matches!(err, ConfigError::Invalid { field: "host.bindto", .. }),
+                "{bindto:?}: got {err:?}"
+            );
+        }
+
+        for uri in ["mysql://localhost/flern", "localhost/flern", "postgres:/localhost"] {
+            let err = Config::from_toml(&source("127.0.0.1:5000", uri)).unwrap_err();
+            assert!(
+                matches!(err, ConfigError::Invalid { field: "app.database_uri", .. }),
+                "{uri:?}: got {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_docs_host_url_test() {
+        let source = |app: &str| {
+            format!(
+                r#"
+                [host]
+                bindto = "127.0.0.1:5000"
+
+                [app]
+                jwt = "secret"
+                database_uri = "postgres://localhost/flern"
+                {app}
+                "#
+            )
+        };
+
+        let config = Config::from_toml(&source("host_url = \"https://flern.example/\"\ndocs = true")).unwrap();
+        assert_eq!(config.app().host_url(), "https://flern.example/");
+        assert!(config.app().docs());
+
+        // docs are off unless asked for
+        let config = Config::from_toml(&source("host_url = \"http://localhost:5000\"")).unwrap();
+        assert!(!config.app().docs());
+
+        for host_url in ["flern.example", "/uploads", "mailto:admin@flern.example", ""] {
+            let err = Config::from_toml(&source(&format!("host_url = {host_url:?}"))).unwrap_err();
+            assert!(
+                matches!(err, ConfigError::Invalid { field: "app.host_url", .. }),
+                "{host_url:?}: got {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_cors_test() {
+        let source = |cors: &str| {
+            format!(
+                r#"
+                [host]
+                bindto = "127.0.0.1:5000"
+
+                [app]
+                jwt = "secret"
+                database_uri = "postgres://localhost/flern"
+                host_url = "http://localhost"
+
+                [cors]
+                {cors}
+                "#
+            )
+        };
+
+        let config = Config::from_toml(&source(
+            r#"allowed_origins = ["https://app.example", "http://localhost:3000"]
+            allow_credentials = true"#,
+        ))
+        .unwrap();
+        let cors = config.cors().unwrap();
+        assert_eq!(cors.allowed_origins(), ["https://app.example", "http://localhost:3000"]);
+        assert_eq!(cors.allowed_methods(), ["GET", "POST", "PUT", "PATCH", "DELETE"]);
+        assert!(cors.allow_credentials());
+        assert!(Config::from_toml(&source(r#"allowed_origins = ["*"]"#)).is_ok());
+
+        let invalid = [
+            r#"allowed_origins = ["*"]
+            allow_credentials = true"#,
+            r#"allowed_origins = ["app.example"]"#,
+            r#"allowed_origins = ["https://app.example/"]"#,
+            r#"allowed_methods = ["GET", "NOT A METHOD"]"#,
+        ];
+        for cors in invalid {
+            let err = Config::from_toml(&source(cors)).unwrap_err();
+            assert!(matches!(err, ConfigError::Invalid { .. }), "{cors}: got {err:?}");
+        }
+    }
+
+    #[test]
+    fn config_malformed_test() {
+        let err = Config::from_toml("[host\nbindto = 1").unwrap_err();
+        assert!(matches!(err, ConfigError::Toml(_)));
+        assert!(err.to_string().starts_with("toml error: "));
+
+        // valid toml, wrong shape
+        let err = Config::from_toml("[host]\nbindto = 1").unwrap_err();
+        assert!(matches!(err, ConfigError::Toml(_)));
+        assert!(err.to_string().contains("bindto"));
+    }
+
+    #[test]
+    fn config_cookie_test() {
+        let source = |extra: &str| {
+            format!(
+                r#"
+                [host]
+                bindto = "127.0.0.1:5000"
+
+                [app]
+                jwt = "secret"
+                database_uri = "postgres://localhost/flern"
+                host_url = "http://localhost"
+                docs = false
+                {extra}
+                "#
+            )
+        };
+
+        let config = Config::from_toml(&source("cookie_same_site = \"strict\"")).unwrap();
+        assert_eq!(config.app().cookie_same_site(), CookieSameSite::Strict);
+
+        let config =
+            Config::from_toml(&source("cookie_same_site = \"none\"\ncookie_secure = true")).unwrap();
+        assert_eq!(config.app().cookie_same_site(), CookieSameSite::None);
+        assert!(config.app().cookie_secure());
+
+        let err = Config::from_toml(&source("cookie_same_site = \"none\"")).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { field: "app.cookie_same_site", .. }));
+
+        let err = Config::from_toml(&source("cookie_same_site = \"sometimes\"")).unwrap_err();
+        assert!(matches!(err, ConfigError::Toml(_)));
+    }
 }
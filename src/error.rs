@@ -10,6 +10,10 @@ pub enum AppError {
     ConfigError(#[from] crate::config::ConfigError),
     #[error("database error: {0}")]
     DatabaseError(#[from] crate::model::DatabaseError),
+    #[error("crypt error: {0}")]
+    CryptError(#[from] crate::auth::CryptError),
+    #[error("course import error: {0}")]
+    CourseImportError(#[from] crate::model::CourseImportError),
 }
 
 pub type AppResult<T> = std::result::Result<T, AppError>;
@@ -34,7 +38,11 @@ fn default_error_handler(error: AppError) {
     error!("{}\n{}", error, span);
 }
 
+/// Logs the error with its span trace, tagged with the id of the request it happened in
 pub fn log_error<E: std::error::Error + std::fmt::Display>(error: &E) {
     let span = SpanTrace::capture();
-    error!("{}\n{}", error, span);
+    match crate::web::middlewares::current_request_id() {
+        Some(request_id) => error!(request_id, "{}\n{}", error, span),
+        None => error!("{}\n{}", error, span),
+    }
 }
@@ -1,18 +1,21 @@
 #![allow(dead_code)] // FIXME: Dev only
 
-use std::path::{Path, PathBuf};
+use std::net::SocketAddr;
 
-use crate::model::{DbConnection, ModelManager};
+use crate::model::{DbConnection, ModelManager, entity::UserEntity};
 use crate::utils::signal::shutdown_signal;
+use crate::web::AuthenticatedUser;
 use crate::{error::AppResult, web::AppState};
 use axum::Router;
-use sqlx::migrate::Migrator;
 use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
 
 pub mod config;
 pub use config::{Config, ConfigError, ConfigResult};
 
 pub mod auth;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod error;
 pub mod model;
 pub mod utils;
@@ -21,41 +24,84 @@ pub mod web;
 static APPLICATION_NAME: &str = "flern";
 
 pub async fn build_server() -> AppResult<(AppState, Router)> {
-    config::Config::get_or_init().await;
+    let config = config::Config::get_or_init().await?;
+    let db = DbConnection::connect(config.app().database_uri(), config.database())?;
 
-    let config = config::Config::get_or_init().await;
-    let db = DbConnection::connect(config.app().database_uri())?;
-
-    let migrator = Migrator::new(Path::new("./migrations")).await.unwrap();
     tracing::debug!("applying migrations...");
-    migrator.run(db.pool()).await.unwrap();
+    db.migrate(config.app().migrations_dir()).await?;
 
-    let mm = ModelManager::new(db);
-    let state = AppState::new(mm);
-    let app = web::routes::build_app(state.clone(), config);
-    Ok((state, app))
+    build_server_with_config(db, config).await
 }
 
 pub async fn build_server_with_pool(db: DbConnection) -> AppResult<(AppState, Router)> {
-    let config = config::Config::get_or_init().await;
+    let config = config::Config::get_or_init().await?;
+    build_server_with_config(db, config).await
+}
 
+/// Builds the app around an explicit config instead of the global one
+pub async fn build_server_with_config(
+    db: DbConnection,
+    config: &'static Config,
+) -> AppResult<(AppState, Router)> {
     let mm = ModelManager::new(db);
-    let state = AppState::new(mm);
+    seed_admin(&mm, config).await?;
+    let state = AppState::new(mm, config);
     let app = web::routes::build_app(state.clone(), config);
     Ok((state, app))
 }
 
+/// Creates the `[app] admin_username` account when `admin_password` is set and there's no
+/// admin yet, so a fresh install can be signed into. Does nothing on later starts
+async fn seed_admin(mm: &ModelManager, config: &Config) -> AppResult<()> {
+    let Some(password) = config.app().admin_password() else {
+        return Ok(());
+    };
+    let actor = AuthenticatedUser::system();
+    // hashing is slow on purpose, skip it when there's nothing to do
+    if UserEntity::admin_exists(mm, &actor).await? {
+        return Ok(());
+    }
+
+    let username = config.app().admin_username();
+    let hash = auth::hash_password(password)?;
+    if UserEntity::seed_admin(mm, &actor, username, &hash).await? {
+        tracing::info!("created admin account {username:?}");
+    } else if !UserEntity::admin_exists(mm, &actor).await? {
+        tracing::warn!("no admin was created, user {username:?} exists already without the admin role");
+    }
+    Ok(())
+}
+
 #[tracing::instrument]
 pub async fn setup_workers() -> AppResult<()> {
-    let (_, app) = build_server().await?;
-    let config = Config::get_or_init().await;
+    let (state, app) = build_server().await?;
+    let config = Config::get_or_init().await?;
+
+    // background jobs stop together with the server
+    let shutdown = CancellationToken::new();
+    let cleanup = tokio::spawn(utils::cleanup::progress_token_cleanup(
+        state.pool().clone(),
+        config.app().token_cleanup_interval(),
+        shutdown.clone(),
+    ));
+
     let listener = TcpListener::bind(config.host().bindto()).await?;
 
     tracing::info!("axum is starting at: {}", config.host().bindto());
-    let axum_handle = axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal());
-
-    axum_handle.await?;
+    // the rate limiter keys clients by their address
+    let axum_handle = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown({
+            let shutdown = shutdown.clone();
+            async move {
+                shutdown_signal().await;
+                shutdown.cancel();
+            }
+        });
+
+    let served = axum_handle.await;
+    shutdown.cancel();
+    let _ = cleanup.await;
+    served?;
     Ok(())
 }
 
@@ -77,7 +123,7 @@ fn setup_trace() {
 
 fn log_runtime() {
     let cwd = std::env::current_dir()
-        .unwrap_or(PathBuf::new());
+        .unwrap_or_default();
     tracing::info!("cwd: {}", cwd.display());
 }
 
@@ -1,3 +1,5 @@
+use uuid::Uuid;
+
 use crate::{
     model::{
         ModelManager,
@@ -6,32 +8,36 @@ use crate::{
     web::{AuthenticatedUser, UserRole},
 };
 
+/// Who may change a resource besides admins.
+///
+/// User-owned: the account itself and what it produced, i.e. `UserEntity`, `UserProgress`,
+/// `UserTaskAttempt` and the progress, resume and refresh tokens.
+/// Admin-owned: course content shared by everyone, i.e. `Module`, `Lesson`, `LessonTask` and `Answer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+    User(Uuid),
+    Admin,
+}
+
 #[async_trait::async_trait]
 pub trait HasOwner {
-    type OwnerId: PartialEq + Send + Sync;
-    async fn get_owner_id(
-        &self,
-        mm: &ModelManager,
-        ctx: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId>;
+    async fn get_owner(&self, mm: &ModelManager, ctx: &AuthenticatedUser) -> DatabaseResult<Owner>;
 }
 
-pub async fn check_access<T: HasOwner<OwnerId = O>, O: PartialEq + Send + Sync>(
+/// Lets admins through, instructors to course content, and users only to resources they own
+pub async fn check_access<T: HasOwner + Sync>(
     mm: &ModelManager,
     ctx: &AuthenticatedUser,
     resource: &T,
-    expected: O,
 ) -> DatabaseResult<()> {
-    let actual_owner = resource.get_owner_id(mm, ctx).await?;
-
     // admin can get all resources
     if ctx.user_role() == UserRole::Admin {
         return Ok(());
     }
 
-    if actual_owner == expected {
-        Ok(())
-    } else {
-        Err(DatabaseError::Forbidden)
+    match resource.get_owner(mm, ctx).await? {
+        Owner::User(id) if id == ctx.user_id() => Ok(()),
+        Owner::Admin if ctx.user_role().can_author() => Ok(()),
+        _ => Err(DatabaseError::Forbidden),
     }
 }
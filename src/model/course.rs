@@ -0,0 +1,360 @@
+//! Whole courses described in one YAML or JSON file, used by `flern-cli course import` and `export`
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::entity::{
+    Answer, AnswerCreate, Lesson, LessonCreate, LessonTask, LessonTaskCreate, Module, ModuleCreate,
+};
+use crate::model::{DatabaseError, DatabaseResult, ModelManager};
+use uuid::Uuid;
+use crate::web::AuthenticatedUser;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CourseImportError {
+    #[error("can't read `{path}`: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("can't parse `{path}`: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("invalid course, {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+}
+
+pub type CourseImportResult<T> = Result<T, CourseImportError>;
+
+/// Entries without an `order_index` continue after the previous one, the first module after the
+/// existing ones and the first lesson of a module at 1
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CourseImport {
+    pub modules: Vec<ModuleImport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleImport {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_index: Option<i32>,
+    #[serde(default)]
+    pub lessons: Vec<LessonImport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LessonImport {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_index: Option<i32>,
+    /// Markdown, either inline or read from `file`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Markdown file, relative to the course file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(default)]
+    pub tasks: Vec<TaskImport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskImport {
+    pub task_type: String,
+    pub question: String,
+    #[serde(default)]
+    pub explanation: String,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    #[serde(default)]
+    pub normalize_whitespace: bool,
+    #[serde(default)]
+    pub min_seconds_before_check: i32,
+    #[serde(default = "default_reveal_on_fail")]
+    pub reveal_on_fail: bool,
+    #[serde(default)]
+    pub answers: Vec<AnswerImport>,
+}
+
+fn default_reveal_on_fail() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnswerImport {
+    pub answer_text: String,
+    #[serde(default)]
+    pub image: String,
+    #[serde(default)]
+    pub is_correct: bool,
+}
+
+/// Rows created by [`CourseImport::import`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ImportSummary {
+    pub modules: usize,
+    pub lessons: usize,
+    pub tasks: usize,
+    pub answers: usize,
+}
+
+impl CourseImport {
+    /// Reads a `.yaml`, `.yml` or `.json` course file along with the lesson files it points to
+    pub fn from_path(path: &Path) -> CourseImportResult<Self> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path).map_err(|source| CourseImportError::Read {
+                path: path.to_path_buf(),
+                source,
+            })
+        };
+        let parse_error = |message: String| CourseImportError::Parse {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        let text = read(path)?;
+        let mut course: Self = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_norway::from_str(&text).map_err(|e| parse_error(e.to_string()))?,
+            Some("json") => serde_json::from_str(&text).map_err(|e| parse_error(e.to_string()))?,
+            _ => return Err(parse_error("expected a .yaml, .yml or .json file".to_string())),
+        };
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        for lesson in course.modules.iter_mut().flat_map(|m| m.lessons.iter_mut()) {
+            if lesson.content.is_none()
+                && let Some(file) = lesson.file.take()
+            {
+                lesson.content = Some(read(&base.join(file))?);
+            }
+        }
+        Ok(course)
+    }
+
+    /// Checks everything that doesn't need the database, the error names the offending entry
+    pub fn validate(&self) -> CourseImportResult<()> {
+        let invalid = |at: String, message: &str| Err(CourseImportError::Invalid(format!("{at}: {message}")));
+
+        if self.modules.is_empty() {
+            return Err(CourseImportError::Invalid("no modules to import".to_string()));
+        }
+
+        let mut titles = HashSet::new();
+        for (m, module) in self.modules.iter().enumerate() {
+            let at = format!("modules[{m}]");
+            if module.title.trim().is_empty() {
+                return invalid(at, "title is empty");
+            }
+            if !titles.insert(module.title.as_str()) {
+                return invalid(at, "title is used by another module of the course");
+            }
+
+            for (l, lesson) in module.lessons.iter().enumerate() {
+                let at = format!("{at}.lessons[{l}]");
+                if lesson.title.trim().is_empty() {
+                    return invalid(at, "title is empty");
+                }
+                match (&lesson.content, &lesson.file) {
+                    (Some(_), Some(_)) => return invalid(at, "set either content or file, not both"),
+                    (None, Some(_)) => return invalid(at, "file was not read, load the course with `from_path`"),
+                    (None, None) => return invalid(at, "content or file is required"),
+                    (Some(_), None) => {}
+                }
+
+                for (t, task) in lesson.tasks.iter().enumerate() {
+                    let at = format!("{at}.tasks[{t}]");
+                    if !LessonTask::is_supported_type(&task.task_type) {
+                        return invalid(
+                            at,
+                            &format!(
+                                "unsupported task_type `{}`, expected one of {}",
+                                task.task_type,
+                                LessonTask::TASK_TYPES.join(", ")
+                            ),
+                        );
+                    }
+                    if task.question.trim().is_empty() {
+                        return invalid(at, "question is empty");
+                    }
+                    if task.min_seconds_before_check < 0 {
+                        return invalid(at, "min_seconds_before_check can't be negative");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the course and inserts it in one transaction, nothing is stored when any part fails.
+    /// Module titles already in the database are refused, the CLI looks modules up by title.
+    pub async fn import(self, mm: &ModelManager, _actor: &AuthenticatedUser) -> CourseImportResult<ImportSummary> {
+        self.validate()?;
+
+        let mut tx = mm.begin().await?;
+
+        let titles: Vec<&str> = self.modules.iter().map(|m| m.title.as_str()).collect();
+        let taken: Option<String> = sqlx::query_scalar("SELECT title FROM modules WHERE title = ANY($1) LIMIT 1")
+            .bind(&titles)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(DatabaseError::from)?;
+        if let Some(title) = taken {
+            return Err(CourseImportError::Invalid(format!("module `{title}` already exists")));
+        }
+
+        let last_index: i32 = sqlx::query_scalar("SELECT COALESCE(MAX(order_index), 0) FROM modules")
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(DatabaseError::from)?;
+
+        // new modules have no cached progress yet, so nothing needs a refresh
+        let mut summary = ImportSummary::default();
+        let mut module_index = last_index;
+        for module in self.modules {
+            module_index = module.order_index.unwrap_or(module_index + 1);
+            let created = Module::insert(
+                &mut *tx,
+                ModuleCreate {
+                    title: module.title,
+                    description: module.description,
+                    order_index: Some(module_index),
+                },
+            )
+            .await?;
+            summary.modules += 1;
+
+            let mut lesson_index = 0;
+            for lesson in module.lessons {
+                lesson_index = lesson.order_index.unwrap_or(lesson_index + 1);
+                let created = Lesson::insert(
+                    &mut *tx,
+                    LessonCreate {
+                        module_id: created.id(),
+                        title: lesson.title,
+                        content: lesson.content.unwrap_or_default(),
+                        order_index: Some(lesson_index),
+                    },
+                )
+                .await?;
+                summary.lessons += 1;
+
+                for task in lesson.tasks {
+                    let created = LessonTask::insert(
+                        &mut *tx,
+                        LessonTaskCreate {
+                            lesson_id: created.id(),
+                            task_type: task.task_type,
+                            question: task.question,
+                            explanation: task.explanation,
+                            case_insensitive: task.case_insensitive,
+                            normalize_whitespace: task.normalize_whitespace,
+                            min_seconds_before_check: task.min_seconds_before_check,
+                            reveal_on_fail: task.reveal_on_fail,
+                        },
+                    )
+                    .await?;
+                    summary.tasks += 1;
+
+                    for answer in task.answers {
+                        Answer::insert(
+                            &mut *tx,
+                            AnswerCreate {
+                                task_id: created.id(),
+                                answer_text: answer.answer_text,
+                                image: answer.image,
+                                is_correct: Some(answer.is_correct),
+                            },
+                        )
+                        .await?;
+                        summary.answers += 1;
+                    }
+                }
+            }
+        }
+
+        tx.commit().await.map_err(DatabaseError::from)?;
+        Ok(summary)
+    }
+}
+
+impl CourseImport {
+    /// Every module with its lessons, tasks and answers, read from one snapshot. Importing the
+    /// result recreates the course with the same positions. Tasks and answers have no position
+    /// of their own and are listed by id like everywhere else, user progress is left out.
+    pub async fn from_database(mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<Self> {
+        let mut tx = mm.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+
+        let modules: Vec<Module> = sqlx::query_as("SELECT * FROM modules ORDER BY order_index, id")
+            .fetch_all(&mut *tx)
+            .await?;
+        let lessons: Vec<Lesson> = sqlx::query_as("SELECT * FROM lessons ORDER BY order_index, id")
+            .fetch_all(&mut *tx)
+            .await?;
+        let tasks: Vec<LessonTask> = sqlx::query_as("SELECT * FROM tasks ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await?;
+        let answers: Vec<Answer> = sqlx::query_as("SELECT * FROM task_answers ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        let mut answers_by_task: HashMap<Uuid, Vec<AnswerImport>> = HashMap::new();
+        for answer in answers {
+            answers_by_task.entry(answer.task_id()).or_default().push(AnswerImport {
+                answer_text: answer.answer_text().to_string(),
+                image: answer.image().to_string(),
+                is_correct: answer.is_correct(),
+            });
+        }
+
+        let mut tasks_by_lesson: HashMap<Uuid, Vec<TaskImport>> = HashMap::new();
+        for task in tasks {
+            tasks_by_lesson.entry(task.lesson_id()).or_default().push(TaskImport {
+                task_type: task.task_type().to_string(),
+                question: task.question().to_string(),
+                explanation: task.explanation().to_string(),
+                case_insensitive: task.case_insensitive(),
+                normalize_whitespace: task.normalize_whitespace(),
+                min_seconds_before_check: task.min_seconds_before_check(),
+                reveal_on_fail: task.reveal_on_fail(),
+                answers: answers_by_task.remove(&task.id()).unwrap_or_default(),
+            });
+        }
+
+        let mut lessons_by_module: HashMap<Uuid, Vec<LessonImport>> = HashMap::new();
+        for lesson in lessons {
+            lessons_by_module.entry(lesson.module_id()).or_default().push(LessonImport {
+                title: lesson.title().to_string(),
+                order_index: Some(lesson.order_index()),
+                content: Some(lesson.content().to_string()),
+                file: None,
+                tasks: tasks_by_lesson.remove(&lesson.id()).unwrap_or_default(),
+            });
+        }
+
+        let modules = modules
+            .into_iter()
+            .map(|module| ModuleImport {
+                lessons: lessons_by_module.remove(&module.id()).unwrap_or_default(),
+                title: module.title().to_string(),
+                description: module.description().to_string(),
+                order_index: Some(module.order_index()),
+            })
+            .collect();
+
+        Ok(Self { modules })
+    }
+}
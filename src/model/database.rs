@@ -1,5 +1,10 @@
-use sqlx::PgPool;
-use crate::model::error::DatabaseResult;
+use std::path::Path;
+
+use sqlx::{PgPool, migrate::Migrator, postgres::PgPoolOptions};
+use crate::{
+    config::Database,
+    model::error::{DatabaseError, DatabaseResult},
+};
 
 #[derive(Debug, Clone)]
 pub struct DbConnection {
@@ -7,11 +12,21 @@ pub struct DbConnection {
 }
 
 impl DbConnection {
-    pub fn connect(connection_str: &str) -> DatabaseResult<Self> {
-        let pool = PgPool::connect_lazy(connection_str)?;
+    /// Builds the pool without connecting, the first query opens the first connection
+    pub fn connect(connection_str: &str, options: &Database) -> DatabaseResult<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(options.max_connections())
+            .acquire_timeout(options.acquire_timeout())
+            .idle_timeout(options.idle_timeout())
+            .connect_lazy(connection_str)?;
         Ok(Self { pool })
     }
 
+    /// Most connections the pool opens at once
+    pub fn max_connections(&self) -> u32 {
+        self.pool.options().get_max_connections()
+    }
+
     pub fn pool(&self) -> &PgPool {
         &self.pool
     }
@@ -19,4 +34,19 @@ impl DbConnection {
     pub fn from_pool(pool: PgPool) -> Self {
         Self { pool }
     }
+
+    /// Applies the migrations from `dir`, or the ones built into the binary when it's `None`,
+    /// so the server doesn't depend on the directory it's started from
+    pub async fn migrate(&self, dir: Option<&Path>) -> DatabaseResult<()> {
+        match dir {
+            Some(dir) => {
+                if !dir.is_dir() {
+                    return Err(DatabaseError::MigrationsDirNotFound(dir.to_path_buf()));
+                }
+                Migrator::new(dir).await?.run(&self.pool).await?;
+            }
+            None => sqlx::migrate!().run(&self.pool).await?,
+        }
+        Ok(())
+    }
 }
@@ -1,10 +1,12 @@
 use crate::impl_paginatable_for;
-use crate::model::access::HasOwner;
+use crate::model::access::{HasOwner, Owner};
 use crate::model::repo::ResourceTyped;
-use crate::model::{ModelManager, error::DatabaseResult, repo::CrudRepository};
+use crate::model::{ModelManager, Sort, Unsorted, error::DatabaseResult, repo::CrudRepository};
 use crate::web::AuthenticatedUser;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
 use sqlx::prelude::FromRow;
 use sqlx::prelude::Row;
 use uuid::Uuid;
@@ -16,6 +18,8 @@ pub struct Answer {
     answer_text: String,
     image: String,
     is_correct: bool,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 impl ResourceTyped for Answer {
@@ -44,6 +48,14 @@ impl Answer {
     pub fn is_correct(&self) -> bool {
         self.is_correct
     }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn updated_at(&self) -> &DateTime<Utc> {
+        &self.updated_at
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
@@ -56,28 +68,14 @@ pub struct AnswerCreate {
 
 #[async_trait]
 impl CrudRepository<Answer, AnswerCreate, uuid::Uuid> for Answer {
+    type SortField = Unsorted;
+
     async fn create(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
         data: AnswerCreate,
     ) -> DatabaseResult<Self> {
-        let result = sqlx::query("INSERT INTO task_answers (id, task_id, answer_text, image, is_correct) VALUES ($1,$2,$3,$4,$5) RETURNING id")
-            .bind(Uuid::new_v4())
-            .bind(data.task_id)
-            .bind(&data.answer_text)
-            .bind(&data.image)
-            .bind(data.is_correct.unwrap_or(false))
-            .fetch_one(mm.executor())
-            .await?;
-
-        let id = result.try_get("id")?;
-        Ok(Answer {
-            id,
-            task_id: data.task_id,
-            answer_text: data.answer_text,
-            image: data.image,
-            is_correct: data.is_correct.unwrap_or(false),
-        })
+        Self::insert(mm.executor(), data).await
     }
 
     async fn update(
@@ -86,19 +84,20 @@ impl CrudRepository<Answer, AnswerCreate, uuid::Uuid> for Answer {
         _actor: &AuthenticatedUser,
         data: AnswerCreate,
     ) -> DatabaseResult<Self> {
-        sqlx::query("UPDATE task_answers SET task_id = $1, answer_text = $2, image = $3, is_correct = $4 WHERE id = $5")
+        let updated_at = sqlx::query_scalar("UPDATE task_answers SET task_id = $1, answer_text = $2, image = $3, is_correct = $4, updated_at = now() WHERE id = $5 RETURNING updated_at")
             .bind(data.task_id)
             .bind(&data.answer_text)
             .bind(&data.image)
             .bind(data.is_correct.unwrap_or(false))
             .bind(self.id)
-            .execute(mm.executor())
+            .fetch_one(mm.executor())
             .await?;
 
         self.task_id = data.task_id;
         self.answer_text = data.answer_text;
         self.image = data.image;
         self.is_correct = data.is_correct.unwrap_or(false);
+        self.updated_at = updated_at;
         Ok(self)
     }
 
@@ -117,13 +116,9 @@ impl CrudRepository<Answer, AnswerCreate, uuid::Uuid> for Answer {
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM task_answers WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -131,6 +126,7 @@ impl CrudRepository<Answer, AnswerCreate, uuid::Uuid> for Answer {
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        _sort: Option<Sort<Unsorted>>,
     ) -> DatabaseResult<Vec<Self>> {
         let result = sqlx::query_as("SELECT * FROM task_answers LIMIT $1 OFFSET $2")
             .bind(limit)
@@ -153,20 +149,64 @@ impl_paginatable_for!(Answer, AnswerCreate, Uuid);
 
 #[async_trait]
 impl HasOwner for Answer {
-    type OwnerId = uuid::Uuid;
-
-    async fn get_owner_id(
+    async fn get_owner(
         &self,
         _mm: &ModelManager,
         _actor: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId> {
-        Ok(self.task_id)
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::Admin)
     }
 }
 
 // Utils
 
 impl Answer {
+    /// Same as `create`, but runs on any executor so it can be part of a transaction
+    pub async fn insert<'e>(
+        executor: impl PgExecutor<'e>,
+        data: AnswerCreate,
+    ) -> DatabaseResult<Self> {
+        let result = sqlx::query("INSERT INTO task_answers (id, task_id, answer_text, image, is_correct) VALUES ($1,$2,$3,$4,$5) RETURNING id, created_at, updated_at")
+            .bind(Uuid::new_v4())
+            .bind(data.task_id)
+            .bind(&data.answer_text)
+            .bind(&data.image)
+            .bind(data.is_correct.unwrap_or(false))
+            .fetch_one(executor)
+            .await?;
+
+        let id = result.try_get("id")?;
+        let created_at = result.try_get("created_at")?;
+        let updated_at = result.try_get("updated_at")?;
+        Ok(Answer {
+            id,
+            task_id: data.task_id,
+            answer_text: data.answer_text,
+            image: data.image,
+            is_correct: data.is_correct.unwrap_or(false),
+            created_at,
+            updated_at,
+        })
+    }
+
+    /// Points the answer at a new image, a path relative to the uploads directory
+    pub async fn set_image(
+        mut self,
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        image: String,
+    ) -> DatabaseResult<Self> {
+        let updated_at = sqlx::query_scalar("UPDATE task_answers SET image = $1, updated_at = now() WHERE id = $2 RETURNING updated_at")
+            .bind(&image)
+            .bind(self.id)
+            .fetch_one(mm.executor())
+            .await?;
+
+        self.image = image;
+        self.updated_at = updated_at;
+        Ok(self)
+    }
+
     pub async fn find_all_by_task(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
@@ -185,4 +225,58 @@ impl Answer {
 
         Ok(rows)
     }
+
+    /// Answers of all given tasks in one query, ordered by task and then by id
+    pub async fn find_all_by_tasks(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        task_ids: &[Uuid],
+    ) -> DatabaseResult<Vec<Self>> {
+        let rows: Vec<Self> = sqlx::query_as(
+            r#"
+            SELECT *
+            FROM task_answers ta
+            WHERE ta.task_id = ANY($1)
+            ORDER BY ta.task_id, ta.id
+            "#
+        )
+        .bind(task_ids)
+        .fetch_all(mm.executor())
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Correct answer of the task, the first one if the task has several
+    pub async fn find_correct_by_task(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        task_id: Uuid,
+    ) -> DatabaseResult<Option<Self>> {
+        let row = sqlx::query_as(
+            r#"
+            SELECT *
+            FROM task_answers ta
+            WHERE ta.task_id = $1 AND ta.is_correct
+            ORDER BY ta.id
+            LIMIT 1
+            "#
+        )
+        .bind(task_id)
+        .fetch_optional(mm.executor())
+        .await?;
+
+        Ok(row)
+    }
+    /// Every distinct image path stored on an answer, empty ones left out
+    pub async fn referenced_images(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+    ) -> DatabaseResult<Vec<String>> {
+        let images = sqlx::query_scalar("SELECT DISTINCT image FROM task_answers WHERE image <> ''")
+            .fetch_all(mm.executor())
+            .await?;
+
+        Ok(images)
+    }
 }
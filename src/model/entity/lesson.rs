@@ -1,11 +1,14 @@
-use crate::impl_paginatable_for;
-use crate::model::access::HasOwner;
+use crate::{impl_order_paginatable_for, impl_paginatable_for};
+use crate::model::access::{HasOwner, Owner};
+use crate::model::entity::UserModuleProgress;
 use crate::model::repo::ResourceTyped;
-use crate::model::{ModelManager, error::DatabaseResult, repo::CrudRepository};
+use crate::model::{ContentSort, ModelManager, Page, Sort, Unsorted, error::DatabaseResult, repo::CrudRepository};
 use crate::web::AuthenticatedUser;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
+use sqlx::{PgConnection, PgExecutor};
 use sqlx::prelude::Row;
 use uuid::Uuid;
 
@@ -16,6 +19,8 @@ pub struct Lesson {
     title: String,
     content: String,
     order_index: i32,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 impl ResourceTyped for Lesson {
@@ -44,6 +49,14 @@ impl Lesson {
     pub fn order_index(&self) -> i32 {
         self.order_index
     }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn updated_at(&self) -> &DateTime<Utc> {
+        &self.updated_at
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
@@ -56,28 +69,20 @@ pub struct LessonCreate {
 
 #[async_trait]
 impl CrudRepository<Lesson, LessonCreate, uuid::Uuid> for Lesson {
+    type SortField = Unsorted;
+
     async fn create(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
         data: LessonCreate,
     ) -> DatabaseResult<Self> {
-        let result = sqlx::query("INSERT INTO lessons (id, module_id, title, content, order_index) VALUES ($1,$2,$3,$4,$5) RETURNING id")
-            .bind(Uuid::new_v4())
-            .bind(data.module_id)
-            .bind(&data.title)
-            .bind(&data.content)
-            .bind(data.order_index.unwrap_or(0))
-            .fetch_one(mm.executor())
-            .await?;
+        let mut tx = mm.begin().await?;
+        let module_id = data.module_id;
+        let lesson = Self::insert(&mut *tx, data).await?;
 
-        let id = result.try_get("id")?;
-        Ok(Lesson {
-            id,
-            module_id: data.module_id,
-            title: data.title,
-            content: data.content,
-            order_index: data.order_index.unwrap_or(0),
-        })
+        UserModuleProgress::refresh_module(&mut *tx, module_id).await?;
+        tx.commit().await?;
+        Ok(lesson)
     }
 
     async fn update(
@@ -86,27 +91,39 @@ impl CrudRepository<Lesson, LessonCreate, uuid::Uuid> for Lesson {
         _actor: &AuthenticatedUser,
         data: LessonCreate,
     ) -> DatabaseResult<Self> {
-        sqlx::query("UPDATE lessons SET module_id = $1, title = $2, content = $3, order_index = $4 WHERE id = $5")
+        let mut tx = mm.begin().await?;
+        let updated_at = sqlx::query_scalar("UPDATE lessons SET module_id = $1, title = $2, content = $3, order_index = $4, updated_at = now() WHERE id = $5 RETURNING updated_at")
             .bind(data.module_id)
             .bind(&data.title)
             .bind(&data.content)
-            .bind(&data.order_index.unwrap_or(0))
+            .bind(data.order_index.unwrap_or(0))
             .bind(self.id)
-            .execute(mm.executor())
+            .fetch_one(&mut *tx)
             .await?;
 
+        if data.module_id != self.module_id {
+            UserModuleProgress::refresh_module(&mut *tx, self.module_id).await?;
+            UserModuleProgress::refresh_module(&mut *tx, data.module_id).await?;
+        }
+        tx.commit().await?;
+
         self.module_id = data.module_id;
         self.title = data.title;
         self.content = data.content;
         self.order_index = data.order_index.unwrap_or(0);
+        self.updated_at = updated_at;
         Ok(self)
     }
 
     async fn delete(self, mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<()> {
+        let mut tx = mm.begin().await?;
         sqlx::query("DELETE FROM lessons WHERE id = $1")
             .bind(self.id)
-            .execute(mm.executor())
+            .execute(&mut *tx)
             .await?;
+
+        UserModuleProgress::refresh_module(&mut *tx, self.module_id).await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -117,13 +134,9 @@ impl CrudRepository<Lesson, LessonCreate, uuid::Uuid> for Lesson {
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM lessons WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -131,8 +144,9 @@ impl CrudRepository<Lesson, LessonCreate, uuid::Uuid> for Lesson {
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        _sort: Option<Sort<Unsorted>>,
     ) -> DatabaseResult<Vec<Self>> {
-        let result = sqlx::query_as("SELECT * FROM lessons LIMIT $1 OFFSET $2")
+        let result = sqlx::query_as("SELECT * FROM lessons ORDER BY order_index, id LIMIT $1 OFFSET $2")
             .bind(limit)
             .bind(offset)
             .fetch_all(mm.executor())
@@ -150,6 +164,35 @@ impl CrudRepository<Lesson, LessonCreate, uuid::Uuid> for Lesson {
 }
 
 impl Lesson {
+    /// Same as `create` without the progress cache refresh, runs on any executor so it can be
+    /// part of a transaction
+    pub async fn insert<'e>(
+        executor: impl PgExecutor<'e>,
+        data: LessonCreate,
+    ) -> DatabaseResult<Self> {
+        let result = sqlx::query("INSERT INTO lessons (id, module_id, title, content, order_index) VALUES ($1,$2,$3,$4,$5) RETURNING id, created_at, updated_at")
+            .bind(Uuid::new_v4())
+            .bind(data.module_id)
+            .bind(&data.title)
+            .bind(&data.content)
+            .bind(data.order_index.unwrap_or(0))
+            .fetch_one(executor)
+            .await?;
+
+        let id = result.try_get("id")?;
+        let created_at = result.try_get("created_at")?;
+        let updated_at = result.try_get("updated_at")?;
+        Ok(Lesson {
+            id,
+            module_id: data.module_id,
+            title: data.title,
+            content: data.content,
+            order_index: data.order_index.unwrap_or(0),
+            created_at,
+            updated_at,
+        })
+    }
+
     pub async fn all_by_module(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
@@ -161,20 +204,107 @@ impl Lesson {
             .await?;
         Ok(result)
     }
+
+    /// Gives the module's lessons the order of `lesson_ids`, starting from 1. Nothing changes and
+    /// `false` is returned unless `lesson_ids` holds every lesson of the module exactly once
+    pub async fn reorder(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        module_id: Uuid,
+        lesson_ids: &[Uuid],
+    ) -> DatabaseResult<bool> {
+        let mut tx = mm.begin().await?;
+        let mut current: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM lessons WHERE module_id = $1 FOR UPDATE")
+            .bind(module_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        let mut requested = lesson_ids.to_vec();
+        current.sort();
+        requested.sort();
+        if current != requested {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE lessons l
+            SET order_index = o.position, updated_at = now()
+            FROM unnest($1::UUID[]) WITH ORDINALITY AS o(id, position)
+            WHERE l.id = o.id
+            "#,
+        )
+        .bind(lesson_ids)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Deletes the lesson with its tasks, answers, attempts, progress and resume tokens in one transaction.
+    /// The database would cascade too, but spelling it out keeps the order explicit and the caches in sync.
+    pub async fn delete_cascade(self, mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<()> {
+        let mut tx = mm.begin().await?;
+        Self::delete_rows(&mut tx, &[self.id]).await?;
+        UserModuleProgress::refresh_module(&mut *tx, self.module_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Removes the given lessons and every row hanging off them, children first
+    pub(crate) async fn delete_rows(conn: &mut PgConnection, lesson_ids: &[Uuid]) -> DatabaseResult<()> {
+        let statements = [
+            "DELETE FROM user_task_attempts WHERE task_id IN (SELECT id FROM tasks WHERE lesson_id = ANY($1))",
+            "DELETE FROM task_answers WHERE task_id IN (SELECT id FROM tasks WHERE lesson_id = ANY($1))",
+            "DELETE FROM tasks WHERE lesson_id = ANY($1)",
+            "DELETE FROM user_progress WHERE lesson_id = ANY($1)",
+            "DELETE FROM resume_tokens WHERE lesson_id = ANY($1)",
+            "DELETE FROM lessons WHERE id = ANY($1)",
+        ];
+        for statement in statements {
+            sqlx::query(statement)
+                .bind(lesson_ids)
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Whether `actor` completed the lesson, none if the lesson doesn't exist
+    pub async fn is_completed_by(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        lesson_id: Uuid,
+    ) -> DatabaseResult<Option<bool>> {
+        let result = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(up.status = 'done', FALSE)
+            FROM lessons l
+            LEFT JOIN user_progress up
+                ON l.id = up.lesson_id AND up.user_id = $2
+            WHERE l.id = $1
+            "#
+        )
+        .bind(lesson_id)
+        .bind(actor.user_id())
+        .fetch_optional(mm.executor())
+        .await?;
+        Ok(result)
+    }
 }
 
 impl_paginatable_for!(Lesson, LessonCreate, Uuid);
+impl_order_paginatable_for!(Lesson, "lessons");
 
 #[async_trait]
 impl HasOwner for Lesson {
-    type OwnerId = uuid::Uuid;
-
-    async fn get_owner_id(
+    async fn get_owner(
         &self,
         _mm: &ModelManager,
         _actor: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId> {
-        Ok(self.module_id)
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::Admin)
     }
 }
 
@@ -195,8 +325,8 @@ impl LessonWithStatusRow {
         mm: &ModelManager,
         actor: &AuthenticatedUser,
         lesson_id: Uuid,
-    ) -> DatabaseResult<Self> {
-        let row: LessonWithStatusRow = sqlx::query_as(
+    ) -> DatabaseResult<Option<Self>> {
+        let row = sqlx::query_as(
             r#"
             SELECT 
                 l.id, 
@@ -204,7 +334,7 @@ impl LessonWithStatusRow {
                 l.title, 
                 l.content, 
                 l.order_index,
-                COALESCE(up.status, false) AS status
+                COALESCE(up.status = 'done', false) AS status
             FROM lessons l
             LEFT JOIN user_progress up
                 ON l.id = up.lesson_id AND up.user_id = $2
@@ -212,8 +342,39 @@ impl LessonWithStatusRow {
             "#
         )
         .bind(lesson_id)
-        .bind(&actor.user_id())
-        .fetch_one(mm.executor())
+        .bind(actor.user_id())
+        .fetch_optional(mm.executor())
+        .await?;
+
+        Ok(row)
+    }
+
+    /// First uncompleted lesson of the user in course order, i.e. where they'd continue
+    pub async fn find_current(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+    ) -> DatabaseResult<Option<Self>> {
+        let row = sqlx::query_as(
+            r#"
+            SELECT
+                l.id,
+                l.module_id,
+                l.title,
+                l.content,
+                l.order_index,
+                COALESCE(up.status = 'done', FALSE) AS status
+            FROM lessons l
+            JOIN modules m ON m.id = l.module_id
+            LEFT JOIN user_progress up
+                ON up.lesson_id = l.id
+                AND up.user_id = $1
+            WHERE COALESCE(up.status = 'done', FALSE) = FALSE
+            ORDER BY m.order_index, l.order_index, l.id
+            LIMIT 1
+            "#
+        )
+        .bind(actor.user_id())
+        .fetch_optional(mm.executor())
         .await?;
 
         Ok(row)
@@ -232,7 +393,7 @@ impl LessonWithStatusRow {
                 l.title,
                 l.content,
                 l.order_index,
-                COALESCE(up.status, FALSE) AS status
+                COALESCE(up.status = 'done', FALSE) AS status
             FROM lessons l
             JOIN modules m ON m.id = l.module_id
             LEFT JOIN user_progress up
@@ -244,7 +405,7 @@ impl LessonWithStatusRow {
             AND l.order_index > (
                 SELECT order_index FROM lessons WHERE id = $1
             )
-            AND COALESCE (up.status, FALSE) = FALSE
+            AND COALESCE(up.status = 'done', FALSE) = FALSE
             ORDER BY l.order_index ASC
             LIMIT 1;
             "#
@@ -257,3 +418,149 @@ impl LessonWithStatusRow {
         Ok(row)
     }
 }
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LessonTocRow {
+    pub id: Uuid,
+    pub title: String,
+    pub order_index: i32,
+}
+
+impl LessonTocRow {
+    pub async fn fetch_by_module(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        module_id: Uuid,
+        sort: ContentSort,
+    ) -> DatabaseResult<Vec<Self>> {
+        let sql = format!(
+            "SELECT l.id, l.title, l.order_index FROM lessons l WHERE l.module_id = $1 ORDER BY {}",
+            sort.order_by("l")
+        );
+        let rows = sqlx::query_as(&sql)
+            .bind(module_id)
+            .fetch_all(mm.executor())
+            .await?;
+
+        Ok(rows)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct LessonWithModuleRow {
+    pub id: Uuid,
+    pub module_id: Uuid,
+    pub module_title: String,
+    pub title: String,
+    pub order_index: i32,
+    pub status: bool,
+}
+
+impl LessonWithModuleRow {
+    /// Lessons of every module in course order with the user's completion,
+    /// `completed` keeps only the lessons with that status
+    pub async fn page_by_user(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        user_id: Uuid,
+        completed: Option<bool>,
+        limit: i64,
+        offset: i64,
+    ) -> DatabaseResult<Page<Self>> {
+        let items = sqlx::query_as(
+            r#"
+            SELECT
+                l.id,
+                l.module_id,
+                m.title AS module_title,
+                l.title,
+                l.order_index,
+                COALESCE(up.status = 'done', FALSE) AS status
+            FROM lessons l
+            JOIN modules m ON m.id = l.module_id
+            LEFT JOIN user_progress up
+                ON up.lesson_id = l.id
+                AND up.user_id = $1
+            WHERE $2::BOOLEAN IS NULL OR COALESCE(up.status = 'done', FALSE) = $2
+            ORDER BY m.order_index, l.order_index, l.id
+            LIMIT $3 OFFSET $4
+            "#
+        )
+        .bind(user_id)
+        .bind(completed)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(mm.executor())
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM lessons l
+            LEFT JOIN user_progress up
+                ON up.lesson_id = l.id
+                AND up.user_id = $1
+            WHERE $2::BOOLEAN IS NULL OR COALESCE(up.status = 'done', FALSE) = $2
+            "#
+        )
+        .bind(user_id)
+        .bind(completed)
+        .fetch_one(mm.executor())
+        .await?;
+
+        Ok(Page::new(items, total, limit, offset))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LessonSearchRow {
+    pub id: Uuid,
+    pub module_id: Uuid,
+    pub title: String,
+    /// Piece of the content around the matched words, still in markdown
+    pub headline: String,
+}
+
+impl LessonSearchRow {
+    /// Lessons matching `query` by full-text search over title and content, the most relevant
+    /// first. The expression matches the `lessons_search_idx` index.
+    pub async fn search(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> DatabaseResult<Page<Self>> {
+        let items = sqlx::query_as(
+            r#"
+            SELECT
+                l.id,
+                l.module_id,
+                l.title,
+                ts_headline('english', l.content, q, 'StartSel="", StopSel="", MinWords=15, MaxWords=35') AS headline
+            FROM lessons l, plainto_tsquery('english', $1) q
+            WHERE to_tsvector('english', l.title || ' ' || l.content) @@ q
+            ORDER BY ts_rank(to_tsvector('english', l.title || ' ' || l.content), q) DESC, l.id
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(query)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(mm.executor())
+        .await?;
+
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*)
+            FROM lessons l
+            WHERE to_tsvector('english', l.title || ' ' || l.content) @@ plainto_tsquery('english', $1)
+            "#
+        )
+        .bind(query)
+        .fetch_one(mm.executor())
+        .await?;
+
+        Ok(Page::new(items, total, limit, offset))
+    }
+}
@@ -1,23 +1,40 @@
 mod user;
-pub use user::{UserEntity, UserEntityCreateUpdate};
+pub use user::{UserEntity, UserEntityCreateUpdate, UserSortField};
 
 mod module;
-pub use module::{Module, ModuleCreate, ModuleWithLessonsRow};
+pub use module::{
+    Module, ModuleCompletionRow, ModuleCreate, ModuleGateRow, ModuleListOptions, ModuleProgressRow, ModuleSortField,
+    ModuleWithLessonsRow,
+};
 
 mod lesson;
-pub use lesson::{Lesson, LessonCreate, LessonWithStatusRow};
+pub use lesson::{Lesson, LessonCreate, LessonSearchRow, LessonTocRow, LessonWithModuleRow, LessonWithStatusRow};
 
 mod task;
-pub use task::{LessonTask, LessonTaskCreate};
+pub use task::{LessonTask, LessonTaskCreate, TaskTypeCountRow};
 
 mod answer;
 pub use answer::{Answer, AnswerCreate};
 
 mod user_progress;
-pub use user_progress::{UserProgress, UserProgressCreate};
+pub use user_progress::{
+    ProgressStatus, UserProgress, UserProgressCreate, UserProgressWithLessonRow,
+};
+
+mod user_module_progress;
+pub use user_module_progress::UserModuleProgress;
 
 mod user_task_attempt;
-pub use user_task_attempt::{UserTaskAttempt, UserTaskAttemptCreate};
+pub use user_task_attempt::{
+    AnswerDistributionRow, LeaderboardEntry, UserTaskAttempt, UserTaskAttemptCreate,
+    UserTaskAttemptSync,
+};
 
 mod progress_token;
 pub use progress_token::{ProgressToken, ProgressTokenCreate};
+
+mod resume_token;
+pub use resume_token::{ResumeToken, ResumeTokenCreate};
+
+mod refresh_token;
+pub use refresh_token::{RefreshToken, RefreshTokenCreate};
@@ -1,11 +1,16 @@
-use crate::impl_paginatable_for;
-use crate::model::access::HasOwner;
+use crate::{impl_order_paginatable_for, impl_paginatable_for};
+use crate::model::access::{HasOwner, Owner};
+use crate::model::entity::Lesson;
 use crate::model::repo::ResourceTyped;
-use crate::model::{ModelManager, error::DatabaseResult, repo::CrudRepository};
+use crate::model::{
+    ContentSort, ModelManager, Sort, SortField, error::DatabaseResult, repo::CrudRepository,
+};
 use crate::web::AuthenticatedUser;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
+use sqlx::PgExecutor;
 use sqlx::prelude::Row;
 use uuid::Uuid;
 
@@ -15,6 +20,10 @@ pub struct Module {
     title: String,
     description: String,
     order_index: i32,
+    /// Percent of the previous module's lessons to complete before this one opens
+    min_prerequisite_percent: i32,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
@@ -24,6 +33,24 @@ pub struct ModuleCreate {
     pub order_index: Option<i32>,
 }
 
+/// Columns modules can be listed by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleSortField {
+    OrderIndex,
+    Title,
+}
+
+impl SortField for ModuleSortField {
+    const ALL: &'static [Self] = &[Self::OrderIndex, Self::Title];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::OrderIndex => "order_index",
+            Self::Title => "title",
+        }
+    }
+}
+
 impl ResourceTyped for Module {
     fn get_resource_type() -> crate::model::ResourceType {
         crate::model::ResourceType::Module
@@ -37,6 +64,9 @@ impl Module {
             title,
             description,
             order_index,
+            min_prerequisite_percent: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
         }
     }
 
@@ -55,30 +85,30 @@ impl Module {
     pub fn order_index(&self) -> i32 {
         self.order_index
     }
+
+    pub fn min_prerequisite_percent(&self) -> i32 {
+        self.min_prerequisite_percent
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn updated_at(&self) -> &DateTime<Utc> {
+        &self.updated_at
+    }
 }
 
 #[async_trait]
 impl CrudRepository<Module, ModuleCreate, uuid::Uuid> for Module {
+    type SortField = ModuleSortField;
+
     async fn create(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
         data: ModuleCreate,
     ) -> DatabaseResult<Self> {
-        let result = sqlx::query("INSERT INTO modules (id, title, description, order_index) VALUES ($1,$2,$3,$4) RETURNING id")
-            .bind(Uuid::new_v4())
-            .bind(&data.title)
-            .bind(&data.description)
-            .bind(data.order_index.unwrap_or(0))
-            .fetch_one(mm.executor())
-            .await?;
-
-        let id = result.try_get("id")?;
-        Ok(Module {
-            id,
-            title: data.title,
-            description: data.description,
-            order_index: data.order_index.unwrap_or(0),
-        })
+        Self::insert(mm.executor(), data).await
     }
 
     async fn update(
@@ -87,19 +117,20 @@ impl CrudRepository<Module, ModuleCreate, uuid::Uuid> for Module {
         _actor: &AuthenticatedUser,
         data: ModuleCreate,
     ) -> DatabaseResult<Self> {
-        sqlx::query(
-            "UPDATE modules SET title = $1, description = $2, order_index = $3 WHERE id = $4",
+        let updated_at = sqlx::query_scalar(
+            "UPDATE modules SET title = $1, description = $2, order_index = $3, updated_at = now() WHERE id = $4 RETURNING updated_at",
         )
         .bind(&data.title)
         .bind(&data.description)
-        .bind(&data.order_index.unwrap_or(0))
+        .bind(data.order_index.unwrap_or(0))
         .bind(self.id)
-        .execute(mm.executor())
+        .fetch_one(mm.executor())
         .await?;
 
         self.title = data.title;
         self.description = data.description;
         self.order_index = data.order_index.unwrap_or(0);
+        self.updated_at = updated_at;
         Ok(self)
     }
 
@@ -118,13 +149,9 @@ impl CrudRepository<Module, ModuleCreate, uuid::Uuid> for Module {
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM modules WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -132,8 +159,13 @@ impl CrudRepository<Module, ModuleCreate, uuid::Uuid> for Module {
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        sort: Option<Sort<ModuleSortField>>,
     ) -> DatabaseResult<Vec<Self>> {
-        let result = sqlx::query_as("SELECT * FROM modules LIMIT $1 OFFSET $2")
+        let sql = format!(
+            "SELECT * FROM modules ORDER BY {} LIMIT $1 OFFSET $2",
+            Sort::order_by_or(sort, "order_index, id"),
+        );
+        let result = sqlx::query_as(&sql)
             .bind(limit)
             .bind(offset)
             .fetch_all(mm.executor())
@@ -151,6 +183,75 @@ impl CrudRepository<Module, ModuleCreate, uuid::Uuid> for Module {
 }
 
 impl Module {
+    /// Same as `create`, but runs on any executor so it can be part of a transaction
+    pub async fn insert<'e>(
+        executor: impl PgExecutor<'e>,
+        data: ModuleCreate,
+    ) -> DatabaseResult<Self> {
+        let result = sqlx::query("INSERT INTO modules (id, title, description, order_index) VALUES ($1,$2,$3,$4) RETURNING id, created_at, updated_at")
+            .bind(Uuid::new_v4())
+            .bind(&data.title)
+            .bind(&data.description)
+            .bind(data.order_index.unwrap_or(0))
+            .fetch_one(executor)
+            .await?;
+
+        let id = result.try_get("id")?;
+        let created_at = result.try_get("created_at")?;
+        let updated_at = result.try_get("updated_at")?;
+        Ok(Module {
+            id,
+            title: data.title,
+            description: data.description,
+            order_index: data.order_index.unwrap_or(0),
+            min_prerequisite_percent: 0,
+            created_at,
+            updated_at,
+        })
+    }
+
+    /// Deletes the module with its lessons and everything below them in one transaction,
+    /// see [`Lesson::delete_cascade`]
+    pub async fn delete_cascade(self, mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<()> {
+        let mut tx = mm.begin().await?;
+        let lesson_ids: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM lessons WHERE module_id = $1")
+            .bind(self.id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        Lesson::delete_rows(&mut tx, &lesson_ids).await?;
+        sqlx::query("DELETE FROM user_module_progress WHERE module_id = $1")
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM modules WHERE id = $1")
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn set_min_prerequisite_percent(
+        mut self,
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        percent: i32,
+    ) -> DatabaseResult<Self> {
+        let updated_at = sqlx::query_scalar(
+            "UPDATE modules SET min_prerequisite_percent = $1, updated_at = now() WHERE id = $2 RETURNING updated_at",
+        )
+        .bind(percent)
+        .bind(self.id)
+        .fetch_one(mm.executor())
+        .await?;
+
+        self.min_prerequisite_percent = percent;
+        self.updated_at = updated_at;
+        Ok(self)
+    }
+
     pub async fn all(
         mm: &ModelManager,
         _actor: &AuthenticatedUser
@@ -163,29 +264,40 @@ impl Module {
 }
 
 impl_paginatable_for!(Module, ModuleCreate, Uuid);
+impl_order_paginatable_for!(Module, "modules");
 
 #[async_trait]
 impl HasOwner for Module {
-    type OwnerId = uuid::Uuid;
-
-    async fn get_owner_id(
+    async fn get_owner(
         &self,
         _mm: &ModelManager,
         _actor: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId> {
-        Ok(self.id) // owners of modules are themselves
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::Admin)
     }
 }
 
 
 // Utils
 
+/// Narrows the modules listing, the default lists everything
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModuleListOptions {
+    /// Leaves out lessons the user completed, modules stay even when none of their lessons are left
+    pub only_incomplete: bool,
+    /// Modules to return, all when none
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
 #[derive(sqlx::FromRow)]
 pub struct ModuleWithLessonsRow {
     pub id: Uuid,
     pub title: String,
     pub description: String,
     pub order_index: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub lessons: serde_json::Value,
 }
 
@@ -193,23 +305,47 @@ impl ModuleWithLessonsRow {
     pub async fn fetch_all(
         mm: &ModelManager,
         actor: &AuthenticatedUser,
+        sort: ContentSort,
+        options: ModuleListOptions,
     ) -> DatabaseResult<Vec<Self>> {
-        let rows: Vec<ModuleWithLessonsRow> = sqlx::query_as(
+        Self::fetch_all_for(mm, Some(actor.user_id()), sort, options).await
+    }
+
+    /// Catalog for visitors without an account, every lesson is uncompleted
+    pub async fn fetch_all_anonymous(
+        mm: &ModelManager,
+        sort: ContentSort,
+        options: ModuleListOptions,
+    ) -> DatabaseResult<Vec<Self>> {
+        Self::fetch_all_for(mm, None, sort, options).await
+    }
+
+    /// Modules and the lessons inside each of them are both ordered by `sort`
+    async fn fetch_all_for(
+        mm: &ModelManager,
+        user_id: Option<Uuid>,
+        sort: ContentSort,
+        options: ModuleListOptions,
+    ) -> DatabaseResult<Vec<Self>> {
+        let sql = format!(
             r#"
             SELECT
             m.id,
             m.title,
             m.description,
             m.order_index,
+            m.created_at,
+            m.updated_at,
             COALESCE(
                 json_agg(
                     json_build_object(
                         'id', l.id,
                         'title', l.title,
-                        'completed', COALESCE(up.status = true, false),
+                        'completed', COALESCE(up.status = 'done', false),
                         'order_index', l.order_index
                     )
-                ) FILTER (WHERE l.id IS NOT NULL),
+                    ORDER BY {lesson_order}
+                ) FILTER (WHERE l.id IS NOT NULL AND NOT ($2 AND COALESCE(up.status = 'done', false))),
                 '[]'
             ) AS lessons
             FROM modules m
@@ -218,14 +354,224 @@ impl ModuleWithLessonsRow {
             ON up.lesson_id = l.id
             AND up.user_id = $1
             GROUP BY m.id
-            ORDER BY m.order_index;
-        "#
+            ORDER BY {module_order}
+            LIMIT $3 OFFSET $4;
+        "#,
+            lesson_order = sort.order_by("l"),
+            module_order = sort.order_by("m"),
+        );
+        let rows: Vec<ModuleWithLessonsRow> = sqlx::query_as(&sql)
+            .bind(user_id)
+            .bind(options.only_incomplete)
+            .bind(options.limit)
+            .bind(options.offset)
+            .fetch_all(mm.executor())
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Module to take next: the first one the user hasn't started, otherwise the least
+    /// completed one. None when every lesson is done
+    pub async fn fetch_recommended(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        sort: ContentSort,
+    ) -> DatabaseResult<Option<Self>> {
+        let sql = format!(
+            r#"
+            WITH stats AS (
+                SELECT
+                    l.module_id,
+                    COUNT(l.id) AS total,
+                    COUNT(up.id) FILTER (WHERE up.status = 'done') AS done
+                FROM lessons l
+                LEFT JOIN user_progress up
+                ON up.lesson_id = l.id
+                AND up.user_id = $1
+                GROUP BY l.module_id
+            ),
+            pick AS (
+                SELECT m.id
+                FROM modules m
+                JOIN stats s ON s.module_id = m.id
+                WHERE s.done < s.total
+                ORDER BY s.done > 0, s.done::FLOAT8 / s.total, m.order_index, m.id
+                LIMIT 1
             )
+            SELECT
+            m.id,
+            m.title,
+            m.description,
+            m.order_index,
+            m.created_at,
+            m.updated_at,
+            json_agg(
+                json_build_object(
+                    'id', l.id,
+                    'title', l.title,
+                    'completed', COALESCE(up.status = 'done', false),
+                    'order_index', l.order_index
+                )
+                ORDER BY {lesson_order}
+            ) AS lessons
+            FROM pick p
+            JOIN modules m ON m.id = p.id
+            JOIN lessons l ON l.module_id = m.id
+            LEFT JOIN user_progress up
+            ON up.lesson_id = l.id
+            AND up.user_id = $1
+            GROUP BY m.id;
+        "#,
+            lesson_order = sort.order_by("l"),
+        );
+        let row = sqlx::query_as(&sql)
+            .bind(actor.user_id())
+            .fetch_optional(mm.executor())
+            .await?;
+
+        Ok(row)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ModuleGateRow {
+    /// Module right before the lesson's module, none for the first one
+    pub prerequisite_id: Option<Uuid>,
+    pub required_percent: i32,
+    pub completed_percent: f64,
+}
+
+impl ModuleGateRow {
+    /// Gate in front of the module containing `lesson_id`, as seen by `actor`
+    pub async fn find_by_lesson(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        lesson_id: Uuid,
+    ) -> DatabaseResult<Option<Self>> {
+        let row = sqlx::query_as(
+            r#"
+            SELECT
+                prev.id AS prerequisite_id,
+                m.min_prerequisite_percent AS required_percent,
+                COALESCE(
+                    100.0 * COALESCE(ump.completed, 0) / NULLIF(COALESCE(
+                        ump.total,
+                        (SELECT COUNT(*) FROM lessons pl WHERE pl.module_id = prev.id)
+                    ), 0),
+                    100
+                )::FLOAT8 AS completed_percent
+            FROM lessons l
+            JOIN modules m ON m.id = l.module_id
+            LEFT JOIN LATERAL (
+                SELECT p.id FROM modules p
+                WHERE (p.order_index, p.id) < (m.order_index, m.id)
+                ORDER BY p.order_index DESC, p.id DESC
+                LIMIT 1
+            ) prev ON true
+            LEFT JOIN user_module_progress ump ON ump.module_id = prev.id AND ump.user_id = $2
+            WHERE l.id = $1
+            "#,
+        )
+        .bind(lesson_id)
+        .bind(actor.user_id())
+        .fetch_optional(mm.executor())
+        .await?;
+
+        Ok(row)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.prerequisite_id.is_some() && self.completed_percent < f64::from(self.required_percent)
+    }
+}
+
+/// Lesson counts of a module as seen by one user, without the lessons themselves
+#[derive(Debug, sqlx::FromRow)]
+pub struct ModuleCompletionRow {
+    pub module_id: Uuid,
+    pub title: String,
+    pub completed: i64,
+    pub total: i64,
+}
+
+impl ModuleCompletionRow {
+    /// Every module counted live with the same joins as `ModuleWithLessonsRow`, ordered by `sort`
+    pub async fn fetch_all(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        sort: ContentSort,
+    ) -> DatabaseResult<Vec<Self>> {
+        let sql = format!(
+            r#"
+            SELECT
+            m.id AS module_id,
+            m.title,
+            COUNT(up.id) FILTER (WHERE up.status = 'done') AS completed,
+            COUNT(l.id) AS total
+            FROM modules m
+            LEFT JOIN lessons l ON l.module_id = m.id
+            LEFT JOIN user_progress up
+            ON up.lesson_id = l.id
+            AND up.user_id = $1
+            GROUP BY m.id
+            ORDER BY {module_order};
+        "#,
+            module_order = sort.order_by("m"),
+        );
+        let rows = sqlx::query_as(&sql)
             .bind(actor.user_id())
             .fetch_all(mm.executor())
             .await?;
 
-        Ok(rows) 
+        Ok(rows)
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct ModuleProgressRow {
+    pub module_id: Uuid,
+    /// Lessons of the module the user completed
+    pub completed: i64,
+    pub total: i64,
+    /// Completed share of the lessons, 0 to 100
+    pub percent: f64,
+}
+
+impl ModuleProgressRow {
+    /// Progress of `actor` in each of the given modules, unknown ids are skipped.
+    /// Read from the `user_module_progress` cache rather than counted per request.
+    pub async fn fetch_by_ids(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        ids: &[Uuid],
+    ) -> DatabaseResult<Vec<Self>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT
+                m.id AS module_id,
+                c.completed,
+                c.total,
+                COALESCE(100.0 * c.completed / NULLIF(c.total, 0), 0)::FLOAT8 AS percent
+            FROM modules m
+            LEFT JOIN user_module_progress ump ON ump.module_id = m.id AND ump.user_id = $2
+            CROSS JOIN LATERAL (
+                SELECT
+                    COALESCE(ump.completed, 0)::INT8 AS completed,
+                    COALESCE(
+                        ump.total,
+                        (SELECT COUNT(*) FROM lessons l WHERE l.module_id = m.id)
+                    )::INT8 AS total
+            ) c
+            WHERE m.id = ANY($1)
+            ORDER BY m.order_index, m.id
+            "#,
+        )
+        .bind(ids)
+        .bind(actor.user_id())
+        .fetch_all(mm.executor())
+        .await?;
+
+        Ok(rows)
+    }
+}
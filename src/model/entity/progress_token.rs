@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use uuid::Uuid;
 
-use crate::{model::{CrudRepository, DatabaseResult, ModelManager, ResourceTyped}, web::AuthenticatedUser};
+use crate::{model::{CrudRepository, DatabaseResult, HasOwner, ModelManager, Owner, ResourceTyped, Sort, Unsorted}, web::AuthenticatedUser};
 
 
 #[derive(Debug, Serialize, Deserialize, FromRow, utoipa::ToSchema)]
@@ -13,6 +13,7 @@ pub struct ProgressToken {
     token: String,
     user_id: Uuid,
     expires_at: DateTime<Utc>,
+    single_use: bool,
     created_at: DateTime<Utc>,
 }
 
@@ -21,6 +22,7 @@ pub struct ProgressTokenCreate {
     pub token: String,
     pub user_id: Uuid,
     pub expires_at: DateTime<Utc>,
+    pub single_use: bool,
 }
 
 impl ResourceTyped for ProgressToken {
@@ -46,6 +48,10 @@ impl ProgressToken {
         &self.expires_at
     }
 
+    pub fn single_use(&self) -> bool {
+        self.single_use
+    }
+
     pub fn created_at(&self) -> &DateTime<Utc> {
         &self.created_at
     }
@@ -53,16 +59,19 @@ impl ProgressToken {
 
 #[async_trait]
 impl CrudRepository<ProgressToken, ProgressTokenCreate, uuid::Uuid> for ProgressToken {
+    type SortField = Unsorted;
+
     async fn create(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
         data: ProgressTokenCreate,
     ) -> DatabaseResult<Self> {
-        let result = sqlx::query_as("INSERT INTO progress_tokens (id, token, user_id, expires_at) VALUES ($1,$2,$3,$4) RETURNING id, token, user_id, expires_at, created_at")
+        let result = sqlx::query_as("INSERT INTO progress_tokens (id, token, user_id, expires_at, single_use) VALUES ($1,$2,$3,$4,$5) RETURNING id, token, user_id, expires_at, single_use, created_at")
             .bind(Uuid::new_v4())
             .bind(data.token)
             .bind(data.user_id)
             .bind(data.expires_at)
+            .bind(data.single_use)
             .fetch_one(mm.executor())
             .await?;
 
@@ -93,13 +102,9 @@ impl CrudRepository<ProgressToken, ProgressTokenCreate, uuid::Uuid> for Progress
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM progress_tokens WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -107,6 +112,7 @@ impl CrudRepository<ProgressToken, ProgressTokenCreate, uuid::Uuid> for Progress
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        _sort: Option<Sort<Unsorted>>,
     ) -> DatabaseResult<Vec<Self>> {
         let result = sqlx::query_as("SELECT * FROM progress_tokens LIMIT $1 OFFSET $2")
             .bind(limit)
@@ -125,6 +131,17 @@ impl CrudRepository<ProgressToken, ProgressTokenCreate, uuid::Uuid> for Progress
     }
 }
 
+#[async_trait]
+impl HasOwner for ProgressToken {
+    async fn get_owner(
+        &self,
+        _mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::User(self.user_id))
+    }
+}
+
 impl ProgressToken {
     pub async fn find_by_token(
         mm: &ModelManager,
@@ -139,6 +156,31 @@ impl ProgressToken {
         Ok(result)
     }
 
+    /// Tokens of the actor that haven't expired yet
+    pub async fn count_active(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+    ) -> DatabaseResult<i64> {
+        let result: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM progress_tokens WHERE user_id = $1 AND expires_at > now()",
+        )
+        .bind(actor.user_id())
+        .fetch_one(mm.executor())
+        .await?;
+
+        Ok(result)
+    }
+
+    /// Deletes the token, `false` when it was already gone, e.g. a concurrent read consumed it
+    pub async fn consume(&self, mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<bool> {
+        let result = sqlx::query("DELETE FROM progress_tokens WHERE id = $1")
+            .bind(self.id)
+            .execute(mm.executor())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn cleanup_expired(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
@@ -0,0 +1,191 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+use crate::{model::{CrudRepository, DatabaseError, DatabaseResult, HasOwner, ModelManager, Owner, ResourceTyped, Sort, Unsorted}, web::AuthenticatedUser};
+
+
+#[derive(Debug, Serialize, Deserialize, FromRow, utoipa::ToSchema)]
+pub struct RefreshToken {
+    id: Uuid,
+    token: String,
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokenCreate {
+    pub token: String,
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ResourceTyped for RefreshToken {
+    fn get_resource_type() -> crate::model::ResourceType {
+        crate::model::ResourceType::RefreshToken
+    }
+}
+
+impl RefreshToken {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+
+    pub fn expires_at(&self) -> &DateTime<Utc> {
+        &self.expires_at
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+}
+
+#[async_trait]
+impl CrudRepository<RefreshToken, RefreshTokenCreate, uuid::Uuid> for RefreshToken {
+    type SortField = Unsorted;
+
+    async fn create(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        data: RefreshTokenCreate,
+    ) -> DatabaseResult<Self> {
+        let result = sqlx::query_as("INSERT INTO refresh_tokens (id, token, user_id, expires_at) VALUES ($1,$2,$3,$4) RETURNING id, token, user_id, expires_at, created_at")
+            .bind(Uuid::new_v4())
+            .bind(data.token)
+            .bind(data.user_id)
+            .bind(data.expires_at)
+            .fetch_one(mm.executor())
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn update(
+        self,
+        _mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        _data: RefreshTokenCreate,
+    ) -> DatabaseResult<Self> {
+        Err(DatabaseError::Unsupported("refresh tokens are never updated"))
+    }
+
+    async fn delete(self, mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<()> {
+        sqlx::query("DELETE FROM refresh_tokens WHERE id = $1")
+            .bind(self.id)
+            .execute(mm.executor())
+            .await?;
+        Ok(())
+    }
+
+    async fn find_by_id(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        id: uuid::Uuid,
+    ) -> DatabaseResult<Option<Self>> {
+        let result = sqlx::query_as("SELECT * FROM refresh_tokens WHERE id = $1")
+            .bind(id)
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
+    }
+
+    async fn list(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        limit: i64,
+        offset: i64,
+        _sort: Option<Sort<Unsorted>>,
+    ) -> DatabaseResult<Vec<Self>> {
+        let result = sqlx::query_as("SELECT * FROM refresh_tokens LIMIT $1 OFFSET $2")
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(mm.executor())
+            .await?;
+        Ok(result)
+    }
+
+    async fn count(mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<i64> {
+        let result: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM refresh_tokens")
+            .fetch_one(mm.executor())
+            .await?;
+
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl HasOwner for RefreshToken {
+    async fn get_owner(
+        &self,
+        _mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::User(self.user_id))
+    }
+}
+
+impl RefreshToken {
+    pub async fn find_by_token(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        token: &str
+    ) -> DatabaseResult<Option<Self>> {
+        let result = sqlx::query_as("SELECT * FROM refresh_tokens WHERE token = $1")
+            .bind(token)
+            .fetch_optional(mm.executor())
+            .await?;
+
+        Ok(result)
+    }
+
+    pub async fn cleanup_expired(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+    ) -> DatabaseResult<u64> {
+        let result = sqlx::query(r#"DELETE FROM refresh_tokens WHERE expires_at < now()"#)
+            .execute(mm.executor())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Deletes the token and returns it if it was still valid, so each token refreshes only once
+    pub async fn consume(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        token: &str,
+    ) -> DatabaseResult<Option<Self>> {
+        let result = sqlx::query_as(
+            "DELETE FROM refresh_tokens WHERE token = $1 AND expires_at > now() RETURNING *",
+        )
+        .bind(token)
+        .fetch_optional(mm.executor())
+        .await?;
+
+        Ok(result)
+    }
+
+    pub async fn delete_by_token(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        token: &str,
+    ) -> DatabaseResult<u64> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE token = $1")
+            .bind(token)
+            .execute(mm.executor())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
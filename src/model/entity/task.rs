@@ -1,9 +1,12 @@
-use crate::model::access::HasOwner;
+use crate::model::access::{HasOwner, Owner};
+use crate::model::entity::{Answer, AnswerCreate};
 use crate::model::repo::ResourceTyped;
-use crate::model::{ModelManager, error::DatabaseResult, repo::CrudRepository};
+use crate::model::{ModelManager, Sort, Unsorted, error::DatabaseResult, repo::CrudRepository};
 use crate::web::AuthenticatedUser;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
 use sqlx::prelude::FromRow;
 use sqlx::prelude::Row;
 use uuid::Uuid;
@@ -15,6 +18,16 @@ pub struct LessonTask {
     task_type: String,
     question: String,
     explanation: String,
+    /// `string_cmp` only, ignore case when comparing
+    case_insensitive: bool,
+    /// `string_cmp` only, trim and collapse runs of whitespace when comparing
+    normalize_whitespace: bool,
+    /// Seconds between serving the task and checking it, 0 when there's no delay
+    min_seconds_before_check: i32,
+    /// Wrong checks get the correct answers back, `string_cmp` tasks ignore it
+    reveal_on_fail: bool,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 impl ResourceTyped for LessonTask {
@@ -24,6 +37,13 @@ impl ResourceTyped for LessonTask {
 }
 
 impl LessonTask {
+    /// Values accepted in `task_type`, mirrors the check constraint on `tasks`
+    pub const TASK_TYPES: [&'static str; 4] = ["fill_code", "choice", "string_cmp", "multi_select"];
+
+    pub fn is_supported_type(task_type: &str) -> bool {
+        Self::TASK_TYPES.contains(&task_type)
+    }
+
     pub fn id(&self) -> uuid::Uuid {
         self.id
     }
@@ -43,6 +63,44 @@ impl LessonTask {
     pub fn explanation(&self) -> &str {
         &self.explanation
     }
+
+    pub fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    pub fn normalize_whitespace(&self) -> bool {
+        self.normalize_whitespace
+    }
+
+    pub fn min_seconds_before_check(&self) -> i32 {
+        self.min_seconds_before_check
+    }
+
+    pub fn reveal_on_fail(&self) -> bool {
+        self.reveal_on_fail
+    }
+
+    /// Whether the text typed for a `string_cmp` task matches the stored answer.
+    /// Without flags only the typed text is trimmed, the flags apply to both sides
+    pub fn text_matches(&self, expected: &str, given: &str) -> bool {
+        let normalize = |text: &str| {
+            let text = if self.normalize_whitespace {
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            } else {
+                text.to_string()
+            };
+            if self.case_insensitive { text.to_lowercase() } else { text }
+        };
+        normalize(expected) == normalize(given.trim())
+    }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn updated_at(&self) -> &DateTime<Utc> {
+        &self.updated_at
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
@@ -51,32 +109,30 @@ pub struct LessonTaskCreate {
     pub task_type: String,
     pub question: String,
     pub explanation: String,
+    #[serde(default)]
+    pub case_insensitive: bool,
+    #[serde(default)]
+    pub normalize_whitespace: bool,
+    #[serde(default)]
+    pub min_seconds_before_check: i32,
+    #[serde(default = "default_reveal_on_fail")]
+    pub reveal_on_fail: bool,
+}
+
+fn default_reveal_on_fail() -> bool {
+    true
 }
 
 #[async_trait]
 impl CrudRepository<LessonTask, LessonTaskCreate, uuid::Uuid> for LessonTask {
+    type SortField = Unsorted;
+
     async fn create(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
         data: LessonTaskCreate,
     ) -> DatabaseResult<Self> {
-        let result = sqlx::query("INSERT INTO tasks (id, lesson_id, task_type, question, explanation) VALUES ($1,$2,$3,$4,$5) RETURNING id")
-            .bind(Uuid::new_v4())
-            .bind(data.lesson_id)
-            .bind(&data.task_type)
-            .bind(&data.question)
-            .bind(&data.explanation)
-            .fetch_one(mm.executor())
-            .await?;
-
-        let id = result.try_get("id")?;
-        Ok(LessonTask {
-            id,
-            lesson_id: data.lesson_id,
-            task_type: data.task_type,
-            question: data.question,
-            explanation: data.explanation,
-        })
+        Self::insert(mm.executor(), data).await
     }
 
     async fn update(
@@ -85,19 +141,28 @@ impl CrudRepository<LessonTask, LessonTaskCreate, uuid::Uuid> for LessonTask {
         _actor: &AuthenticatedUser,
         data: LessonTaskCreate,
     ) -> DatabaseResult<Self> {
-        sqlx::query("UPDATE tasks SET lesson_id = $1, task_type = $2, question = $3, explanation = $4 WHERE id = $5")
+        let updated_at = sqlx::query_scalar("UPDATE tasks SET lesson_id = $1, task_type = $2, question = $3, explanation = $4, case_insensitive = $5, normalize_whitespace = $6, min_seconds_before_check = $7, reveal_on_fail = $8, updated_at = now() WHERE id = $9 RETURNING updated_at")
             .bind(data.lesson_id)
             .bind(&data.task_type)
             .bind(&data.question)
             .bind(&data.explanation)
+            .bind(data.case_insensitive)
+            .bind(data.normalize_whitespace)
+            .bind(data.min_seconds_before_check)
+            .bind(data.reveal_on_fail)
             .bind(self.id)
-            .execute(mm.executor())
+            .fetch_one(mm.executor())
             .await?;
 
         self.lesson_id = data.lesson_id;
         self.task_type = data.task_type;
         self.question = data.question;
         self.explanation = data.explanation;
+        self.case_insensitive = data.case_insensitive;
+        self.normalize_whitespace = data.normalize_whitespace;
+        self.min_seconds_before_check = data.min_seconds_before_check;
+        self.reveal_on_fail = data.reveal_on_fail;
+        self.updated_at = updated_at;
         Ok(self)
     }
 
@@ -116,13 +181,9 @@ impl CrudRepository<LessonTask, LessonTaskCreate, uuid::Uuid> for LessonTask {
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM tasks WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -130,8 +191,9 @@ impl CrudRepository<LessonTask, LessonTaskCreate, uuid::Uuid> for LessonTask {
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        _sort: Option<Sort<Unsorted>>,
     ) -> DatabaseResult<Vec<Self>> {
-        let result = sqlx::query_as("SELECT * FROM tasks LIMIT $1 OFFSET $2")
+        let result = sqlx::query_as("SELECT * FROM tasks ORDER BY id LIMIT $1 OFFSET $2")
             .bind(limit)
             .bind(offset)
             .fetch_all(mm.executor())
@@ -150,19 +212,77 @@ impl CrudRepository<LessonTask, LessonTaskCreate, uuid::Uuid> for LessonTask {
 
 #[async_trait]
 impl HasOwner for LessonTask {
-    type OwnerId = uuid::Uuid;
-
-    async fn get_owner_id(
+    async fn get_owner(
         &self,
         _mm: &ModelManager,
         _actor: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId> {
-        Ok(self.lesson_id)
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::Admin)
     }
 }
 
 // Utils
 impl LessonTask {
+    /// Same as `create`, but runs on any executor so it can be part of a transaction
+    pub async fn insert<'e>(
+        executor: impl PgExecutor<'e>,
+        data: LessonTaskCreate,
+    ) -> DatabaseResult<Self> {
+        let result = sqlx::query("INSERT INTO tasks (id, lesson_id, task_type, question, explanation, case_insensitive, normalize_whitespace, min_seconds_before_check, reveal_on_fail) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9) RETURNING id, created_at, updated_at")
+            .bind(Uuid::new_v4())
+            .bind(data.lesson_id)
+            .bind(&data.task_type)
+            .bind(&data.question)
+            .bind(&data.explanation)
+            .bind(data.case_insensitive)
+            .bind(data.normalize_whitespace)
+            .bind(data.min_seconds_before_check)
+            .bind(data.reveal_on_fail)
+            .fetch_one(executor)
+            .await?;
+
+        let id = result.try_get("id")?;
+        let created_at = result.try_get("created_at")?;
+        let updated_at = result.try_get("updated_at")?;
+        Ok(LessonTask {
+            id,
+            lesson_id: data.lesson_id,
+            task_type: data.task_type,
+            question: data.question,
+            explanation: data.explanation,
+            case_insensitive: data.case_insensitive,
+            normalize_whitespace: data.normalize_whitespace,
+            min_seconds_before_check: data.min_seconds_before_check,
+            reveal_on_fail: data.reveal_on_fail,
+            created_at,
+            updated_at,
+        })
+    }
+
+    /// Creates the task together with its answers, nothing is stored if any insert fails.
+    /// The `task_id` of the answers is ignored, they all belong to the new task.
+    pub async fn create_with_answers(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        data: LessonTaskCreate,
+        answers: Vec<AnswerCreate>,
+    ) -> DatabaseResult<(Self, Vec<Answer>)> {
+        let mut tx = mm.begin().await?;
+
+        let task = Self::insert(&mut *tx, data).await?;
+        let mut created = Vec::with_capacity(answers.len());
+        for answer in answers {
+            let answer = AnswerCreate {
+                task_id: task.id,
+                ..answer
+            };
+            created.push(Answer::insert(&mut *tx, answer).await?);
+        }
+
+        tx.commit().await?;
+        Ok((task, created))
+    }
+
     pub async fn find_all_by_lesson(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
@@ -181,4 +301,101 @@ impl LessonTask {
 
         Ok(rows)
     }
+
+    /// Tasks of several lessons in one query, ordered by lesson
+    pub async fn find_all_by_lessons(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        lesson_ids: &[Uuid],
+    ) -> DatabaseResult<Vec<Self>> {
+        let rows: Vec<Self> = sqlx::query_as(
+            r#"
+            SELECT *
+            FROM tasks t
+            WHERE t.lesson_id = ANY($1)
+            ORDER BY t.lesson_id
+            "#
+        )
+        .bind(lesson_ids)
+        .fetch_all(mm.executor())
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct TaskTypeCountRow {
+    pub task_type: String,
+    pub count: i64,
+}
+
+impl TaskTypeCountRow {
+    pub async fn fetch_all(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+    ) -> DatabaseResult<Vec<Self>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT task_type, COUNT(*) AS count
+            FROM tasks
+            GROUP BY task_type
+            ORDER BY count DESC, task_type
+            "#
+        )
+        .fetch_all(mm.executor())
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn string_cmp_task(case_insensitive: bool, normalize_whitespace: bool) -> LessonTask {
+        LessonTask {
+            id: Uuid::new_v4(),
+            lesson_id: Uuid::new_v4(),
+            task_type: "string_cmp".to_string(),
+            question: String::new(),
+            explanation: String::new(),
+            case_insensitive,
+            normalize_whitespace,
+            min_seconds_before_check: 0,
+            reveal_on_fail: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn text_matches_flags_test() {
+        // (case_insensitive, normalize_whitespace, stored "hello", stored "  Hello ")
+        let cases = [
+            (false, false, false, false),
+            (true, false, true, false),
+            (false, true, false, false),
+            (true, true, true, true),
+        ];
+        for (case_insensitive, normalize_whitespace, typed_padded, stored_padded) in cases {
+            let task = string_cmp_task(case_insensitive, normalize_whitespace);
+            assert_eq!(task.text_matches("hello", "  Hello "), typed_padded, "{case_insensitive} {normalize_whitespace}");
+            assert_eq!(task.text_matches("  Hello ", "hello"), stored_padded, "{case_insensitive} {normalize_whitespace}");
+        }
+    }
+
+    #[test]
+    fn text_matches_whitespace_test() {
+        let exact = string_cmp_task(false, false);
+        // the typed text was always trimmed
+        assert!(exact.text_matches("let x", " let x\n"));
+        assert!(!exact.text_matches("let x", "let  x"));
+
+        let normalized = string_cmp_task(false, true);
+        assert!(normalized.text_matches("let x", "let \t x"));
+        assert!(normalized.text_matches(" let\nx ", "let x"));
+        assert!(!normalized.text_matches("let x", "letx"));
+    }
 }
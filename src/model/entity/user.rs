@@ -1,15 +1,16 @@
 use crate::impl_paginatable_for;
-use crate::model::access::HasOwner;
+use crate::model::access::{HasOwner, Owner};
 use crate::model::repo::ResourceTyped;
 use crate::web::AuthenticatedUser;
 use crate::web::UserRole;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use sqlx::prelude::Row;
 use uuid::Uuid;
 
-use crate::model::{ModelManager, error::DatabaseResult, repo::CrudRepository};
+use crate::model::{ModelManager, Sort, SortField, error::DatabaseResult, repo::CrudRepository};
 
 #[derive(Debug, Serialize, Deserialize, FromRow, utoipa::ToSchema)]
 pub struct UserEntity {
@@ -18,14 +19,35 @@ pub struct UserEntity {
     #[serde(skip)]
     password_hash: String,
     role: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
 pub struct UserEntityCreateUpdate {
     pub username: String,
+    /// Left empty on update to keep the current password
     pub password_hash: String,
 }
 
+/// Columns users can be listed by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserSortField {
+    Username,
+    Role,
+}
+
+impl SortField for UserSortField {
+    const ALL: &'static [Self] = &[Self::Username, Self::Role];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Username => "username",
+            Self::Role => "role",
+        }
+    }
+}
+
 impl ResourceTyped for UserEntity {
     fn get_resource_type() -> crate::model::repo::ResourceType {
         crate::model::repo::ResourceType::User
@@ -48,31 +70,26 @@ impl UserEntity {
     pub fn role(&self) -> UserRole {
         UserRole::from(self.role.as_str())
     }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
+
+    pub fn updated_at(&self) -> &DateTime<Utc> {
+        &self.updated_at
+    }
 }
 
 #[async_trait::async_trait]
 impl CrudRepository<UserEntity, UserEntityCreateUpdate, uuid::Uuid> for UserEntity {
+    type SortField = UserSortField;
+
     async fn create(
         mm: &ModelManager,
-        _actor: &AuthenticatedUser,
+        actor: &AuthenticatedUser,
         data: UserEntityCreateUpdate,
     ) -> DatabaseResult<Self> {
-        let role = UserRole::User.to_string();
-        let result = sqlx::query("INSERT INTO users (id, username, password_hash, role) VALUES ($1,$2,$3,$4) RETURNING id")
-            .bind(Uuid::new_v4())
-            .bind(&data.username)
-            .bind(&data.password_hash)
-            .bind(&role)
-            .fetch_one(mm.executor())
-            .await?;
-
-        let id = result.try_get("id")?;
-        Ok(UserEntity {
-            id,
-            username: data.username,
-            password_hash: data.password_hash,
-            role,
-        })
+        Self::create_with_role(mm, actor, data, &UserRole::User).await
     }
 
     async fn update(
@@ -81,14 +98,21 @@ impl CrudRepository<UserEntity, UserEntityCreateUpdate, uuid::Uuid> for UserEnti
         _actor: &AuthenticatedUser,
         data: UserEntityCreateUpdate,
     ) -> DatabaseResult<Self> {
-        sqlx::query("UPDATE users SET username = $1 WHERE id = $2")
-            .bind(&data.username)
-            .bind(&self.id)
-            .execute(mm.executor())
-            .await?;
+        // an empty hash leaves the password as it is
+        let updated_at = sqlx::query_scalar(
+            "UPDATE users SET username = $1, password_hash = COALESCE(NULLIF($2, ''), password_hash), updated_at = now() WHERE id = $3 RETURNING updated_at",
+        )
+        .bind(&data.username)
+        .bind(&data.password_hash)
+        .bind(self.id)
+        .fetch_one(mm.executor())
+        .await?;
 
-        self.password_hash = data.password_hash;
+        if !data.password_hash.is_empty() {
+            self.password_hash = data.password_hash;
+        }
         self.username = data.username;
+        self.updated_at = updated_at;
         Ok(self)
     }
 
@@ -107,13 +131,9 @@ impl CrudRepository<UserEntity, UserEntityCreateUpdate, uuid::Uuid> for UserEnti
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM users WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -121,8 +141,13 @@ impl CrudRepository<UserEntity, UserEntityCreateUpdate, uuid::Uuid> for UserEnti
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        sort: Option<Sort<UserSortField>>,
     ) -> DatabaseResult<Vec<Self>> {
-        let result = sqlx::query_as("SELECT * FROM users LIMIT $1 OFFSET $2")
+        let sql = format!(
+            "SELECT * FROM users ORDER BY {} LIMIT $1 OFFSET $2",
+            Sort::order_by_or(sort, "id"),
+        );
+        let result = sqlx::query_as(&sql)
             .bind(limit)
             .bind(offset)
             .fetch_all(mm.executor())
@@ -143,18 +168,77 @@ impl_paginatable_for!(UserEntity, UserEntityCreateUpdate, Uuid);
 
 #[async_trait]
 impl HasOwner for UserEntity {
-    type OwnerId = uuid::Uuid;
-
-    async fn get_owner_id(
+    async fn get_owner(
         &self,
         _mm: &ModelManager,
         _actor: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId> {
-        Ok(self.id) // owners of users are themselves
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::User(self.id)) // owners of users are themselves
     }
 }
 
 impl UserEntity {
+    /// Same as `create` with a role other than `user`, e.g. for accounts seeded by the CLI
+    pub async fn create_with_role(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        data: UserEntityCreateUpdate,
+        role: &UserRole,
+    ) -> DatabaseResult<Self> {
+        let role = role.to_string();
+        let result = sqlx::query("INSERT INTO users (id, username, password_hash, role) VALUES ($1,$2,$3,$4) RETURNING id, created_at, updated_at")
+            .bind(Uuid::new_v4())
+            .bind(&data.username)
+            .bind(&data.password_hash)
+            .bind(&role)
+            .fetch_one(mm.executor())
+            .await?;
+
+        let id = result.try_get("id")?;
+        let created_at = result.try_get("created_at")?;
+        let updated_at = result.try_get("updated_at")?;
+        Ok(UserEntity {
+            id,
+            username: data.username,
+            password_hash: data.password_hash,
+            role,
+            created_at,
+            updated_at,
+        })
+    }
+
+    /// Creates an admin unless one exists already, returns whether a row was inserted. Safe to
+    /// run from several instances at once, an existing user with the same name is left alone
+    pub async fn seed_admin(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        username: &str,
+        password_hash: &str,
+    ) -> DatabaseResult<bool> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO users (id, username, password_hash, role)
+            SELECT $1, $2, $3, 'admin'
+            WHERE NOT EXISTS (SELECT 1 FROM users WHERE role = 'admin')
+            ON CONFLICT (username) DO NOTHING
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(username)
+        .bind(password_hash)
+        .execute(mm.executor())
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Whether at least one user has the admin role
+    pub async fn admin_exists(mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<bool> {
+        let result = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users WHERE role = 'admin')")
+            .fetch_one(mm.executor())
+            .await?;
+        Ok(result)
+    }
+
     pub async fn find_by_username(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
@@ -162,11 +246,118 @@ impl UserEntity {
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM users WHERE username = $1")
             .bind(username)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
+    }
+
+    /// Moves the progress and attempts of `source` over to this user and deletes `source`.
+    /// A lesson both users have keeps the further status, the module progress cache of this
+    /// user is recounted afterwards. Everything happens in one transaction.
+    pub async fn merge_from(
+        &self,
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        source: UserEntity,
+    ) -> DatabaseResult<()> {
+        let mut tx = mm.begin().await?;
+
+        // `progress_status` values are ordered not_started < in_progress < done
+        sqlx::query(
+            r#"
+            INSERT INTO user_progress (id, user_id, lesson_id, status)
+            SELECT gen_random_uuid(), $1, up.lesson_id, up.status
+            FROM user_progress up
+            WHERE up.user_id = $2
+            ON CONFLICT (user_id, lesson_id)
+            DO UPDATE SET status = GREATEST(user_progress.status, EXCLUDED.status)
+            "#
+        )
+        .bind(self.id)
+        .bind(source.id)
+        .execute(&mut *tx)
+        .await?;
+
+        // offline attempts synced by both accounts would break the (user_id, client_id) index
+        sqlx::query(
+            r#"
+            DELETE FROM user_task_attempts src
+            USING user_task_attempts dst
+            WHERE src.user_id = $2 AND dst.user_id = $1 AND src.client_id = dst.client_id
+            "#
+        )
+        .bind(self.id)
+        .bind(source.id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("UPDATE user_task_attempts SET user_id = $1 WHERE user_id = $2")
+            .bind(self.id)
+            .bind(source.id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM user_module_progress WHERE user_id = $1")
+            .bind(self.id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO user_module_progress (user_id, module_id, completed, total)
+            SELECT
+                up.user_id,
+                l.module_id,
+                COUNT(*) FILTER (WHERE up.status = 'done'),
+                (SELECT COUNT(*) FROM lessons ml WHERE ml.module_id = l.module_id)
+            FROM user_progress up
+            JOIN lessons l ON l.id = up.lesson_id
+            WHERE up.user_id = $1
+            GROUP BY up.user_id, l.module_id
+            "#
+        )
+        .bind(self.id)
+        .execute(&mut *tx)
+        .await?;
+
+        // the rest of the source's rows go with it
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(source.id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Changes the user's role. Returns `None` without changing anything when
+    /// this would leave no admin at all
+    pub async fn set_role(
+        &self,
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        role: &UserRole,
+    ) -> DatabaseResult<Option<Self>> {
+        let mut tx = mm.begin().await?;
+
+        // locking the admins keeps two concurrent demotions from both passing the check
+        let admins: Vec<Uuid> =
+            sqlx::query_scalar("SELECT id FROM users WHERE role = 'admin' FOR UPDATE")
+                .fetch_all(&mut *tx)
+                .await?;
+        if *role != UserRole::Admin && admins == [self.id] {
             return Ok(None);
         }
-        Ok(Some(result?))
+
+        let updated = sqlx::query_as::<_, Self>(
+            "UPDATE users SET role = $1, updated_at = now() WHERE id = $2 RETURNING *",
+        )
+        .bind(role.to_string())
+        .bind(self.id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(updated))
     }
 }
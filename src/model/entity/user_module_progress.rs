@@ -0,0 +1,146 @@
+use crate::model::repo::ResourceTyped;
+use crate::model::{ModelManager, error::DatabaseResult};
+use crate::web::AuthenticatedUser;
+use serde::{Deserialize, Serialize};
+use sqlx::PgExecutor;
+use sqlx::prelude::FromRow;
+use uuid::Uuid;
+
+/// Cached lesson counts of one user in one module.
+/// Rows only exist for modules the user has touched, the rest count as zero.
+#[derive(Debug, Serialize, Deserialize, FromRow, utoipa::ToSchema)]
+pub struct UserModuleProgress {
+    user_id: Uuid,
+    module_id: Uuid,
+    completed: i32,
+    total: i32,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ResourceTyped for UserModuleProgress {
+    fn get_resource_type() -> crate::model::ResourceType {
+        crate::model::ResourceType::UserModuleProgress
+    }
+}
+
+impl UserModuleProgress {
+    pub fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+
+    pub fn module_id(&self) -> Uuid {
+        self.module_id
+    }
+
+    pub fn completed(&self) -> i32 {
+        self.completed
+    }
+
+    pub fn total(&self) -> i32 {
+        self.total
+    }
+
+    pub fn updated_at(&self) -> &chrono::DateTime<chrono::Utc> {
+        &self.updated_at
+    }
+
+    pub async fn find(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        user_id: Uuid,
+        module_id: Uuid,
+    ) -> DatabaseResult<Option<Self>> {
+        let result = sqlx::query_as(
+            "SELECT * FROM user_module_progress WHERE user_id = $1 AND module_id = $2",
+        )
+        .bind(user_id)
+        .bind(module_id)
+        .fetch_optional(mm.executor())
+        .await?;
+        Ok(result)
+    }
+
+    /// Recounts the row of `user_id` in the module containing `lesson_id`.
+    /// Takes an executor so it can run in the transaction that changed the progress.
+    pub async fn refresh_for_lesson<'e>(
+        executor: impl PgExecutor<'e>,
+        user_id: Uuid,
+        lesson_id: Uuid,
+    ) -> DatabaseResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_module_progress (user_id, module_id, completed, total)
+            SELECT
+                $1,
+                l.module_id,
+                COUNT(up.id) FILTER (WHERE up.status = 'done'),
+                COUNT(ml.id)
+            FROM lessons l
+            JOIN lessons ml ON ml.module_id = l.module_id
+            LEFT JOIN user_progress up ON up.lesson_id = ml.id AND up.user_id = $1
+            WHERE l.id = $2
+            GROUP BY l.module_id
+            ON CONFLICT (user_id, module_id)
+            DO UPDATE SET completed = EXCLUDED.completed, total = EXCLUDED.total, updated_at = now()
+            "#,
+        )
+        .bind(user_id)
+        .bind(lesson_id)
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// Recounts every cached row of `module_id`, used when its lessons change
+    pub async fn refresh_module<'e>(
+        executor: impl PgExecutor<'e>,
+        module_id: Uuid,
+    ) -> DatabaseResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE user_module_progress ump
+            SET
+                completed = (
+                    SELECT COUNT(*) FROM user_progress up
+                    JOIN lessons l ON l.id = up.lesson_id
+                    WHERE l.module_id = ump.module_id AND up.user_id = ump.user_id AND up.status = 'done'
+                ),
+                total = (SELECT COUNT(*) FROM lessons l WHERE l.module_id = ump.module_id),
+                updated_at = now()
+            WHERE ump.module_id = $1
+            "#,
+        )
+        .bind(module_id)
+        .execute(executor)
+        .await?;
+        Ok(())
+    }
+
+    /// Drops the cache and recomputes it from `user_progress`, returns the number of rows written
+    pub async fn rebuild(mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<u64> {
+        let mut tx = mm.begin().await?;
+
+        sqlx::query("DELETE FROM user_module_progress")
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO user_module_progress (user_id, module_id, completed, total)
+            SELECT
+                up.user_id,
+                l.module_id,
+                COUNT(*) FILTER (WHERE up.status = 'done'),
+                (SELECT COUNT(*) FROM lessons ml WHERE ml.module_id = l.module_id)
+            FROM user_progress up
+            JOIN lessons l ON l.id = up.lesson_id
+            GROUP BY up.user_id, l.module_id
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -1,19 +1,40 @@
 use crate::impl_paginatable_for;
-use crate::model::access::HasOwner;
+use crate::model::access::{HasOwner, Owner};
+use crate::model::entity::UserModuleProgress;
 use crate::model::repo::ResourceTyped;
-use crate::model::{ModelManager, error::DatabaseResult, repo::CrudRepository};
+use crate::model::{ModelManager, Page, Sort, Unsorted, error::DatabaseResult, repo::CrudRepository};
 use crate::web::AuthenticatedUser;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use uuid::Uuid;
 
+/// Where a user is with a lesson, the `progress_status` type of `user_progress.status`
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, utoipa::ToSchema,
+)]
+#[sqlx(type_name = "progress_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressStatus {
+    #[default]
+    NotStarted,
+    InProgress,
+    Done,
+}
+
+impl ProgressStatus {
+    /// Only done lessons count as completed
+    pub fn is_done(self) -> bool {
+        self == Self::Done
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, FromRow, utoipa::ToSchema)]
 pub struct UserProgress {
     id: Uuid,
     user_id: Uuid,
     lesson_id: Uuid,
-    status: bool,
+    status: ProgressStatus,
 }
 
 impl ResourceTyped for UserProgress {
@@ -35,7 +56,7 @@ impl UserProgress {
         self.lesson_id
     }
 
-    pub fn status(&self) -> bool {
+    pub fn status(&self) -> ProgressStatus {
         self.status
     }
 }
@@ -43,39 +64,26 @@ impl UserProgress {
 pub struct UserProgressCreate {
     user_id: Uuid,
     lesson_id: Uuid,
-    status: bool,
+    status: ProgressStatus,
 }
 
 impl UserProgressCreate {
-    pub fn new(user_id: Uuid, lesson_id: Uuid, status: bool) -> Self {
+    pub fn new(user_id: Uuid, lesson_id: Uuid, status: ProgressStatus) -> Self {
         Self { user_id, lesson_id, status }
     }
 }
 
 #[async_trait]
 impl CrudRepository<UserProgress, UserProgressCreate, uuid::Uuid> for UserProgress {
+    type SortField = Unsorted;
+
+    /// A user has one row per lesson, so this is the same as `upsert`
     async fn create(
         mm: &ModelManager,
-        _actor: &AuthenticatedUser,
+        actor: &AuthenticatedUser,
         data: UserProgressCreate,
     ) -> DatabaseResult<Self> {
-        let row = sqlx::query_as(
-            r#"
-            INSERT INTO user_progress (id, user_id, lesson_id, status)
-            VALUES ($1,$2,$3,$4)
-            ON CONFLICT (user_id, lesson_id)
-            DO UPDATE SET status = EXCLUDED.status
-            RETURNING id, user_id, lesson_id, status
-            "#
-        )
-        .bind(Uuid::new_v4())
-        .bind(data.user_id)
-        .bind(data.lesson_id)
-        .bind(data.status)
-        .fetch_one(mm.executor())
-        .await?;
-
-        Ok(row)
+        Self::upsert(mm, actor, data).await
     }
 
     async fn update(
@@ -84,16 +92,21 @@ impl CrudRepository<UserProgress, UserProgressCreate, uuid::Uuid> for UserProgre
         _actor: &AuthenticatedUser,
         data: UserProgressCreate,
     ) -> DatabaseResult<Self> {
+        let mut tx = mm.begin().await?;
         sqlx::query(
             "UPDATE user_progress SET user_id = $1, lesson_id = $2, status = $3 WHERE id = $4",
         )
         .bind(data.user_id)
         .bind(data.lesson_id)
-        .bind(&data.status)
+        .bind(data.status)
         .bind(self.id)
-        .execute(mm.executor())
+        .execute(&mut *tx)
         .await?;
 
+        UserModuleProgress::refresh_for_lesson(&mut *tx, self.user_id, self.lesson_id).await?;
+        UserModuleProgress::refresh_for_lesson(&mut *tx, data.user_id, data.lesson_id).await?;
+        tx.commit().await?;
+
         self.user_id = data.user_id;
         self.lesson_id = data.lesson_id;
         self.status = data.status;
@@ -101,10 +114,14 @@ impl CrudRepository<UserProgress, UserProgressCreate, uuid::Uuid> for UserProgre
     }
 
     async fn delete(self, mm: &ModelManager, _actor: &AuthenticatedUser) -> DatabaseResult<()> {
+        let mut tx = mm.begin().await?;
         sqlx::query("DELETE FROM user_progress WHERE id = $1")
             .bind(self.id)
-            .execute(mm.executor())
+            .execute(&mut *tx)
             .await?;
+
+        UserModuleProgress::refresh_for_lesson(&mut *tx, self.user_id, self.lesson_id).await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -115,13 +132,9 @@ impl CrudRepository<UserProgress, UserProgressCreate, uuid::Uuid> for UserProgre
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM user_progress WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -129,6 +142,7 @@ impl CrudRepository<UserProgress, UserProgressCreate, uuid::Uuid> for UserProgre
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        _sort: Option<Sort<Unsorted>>,
     ) -> DatabaseResult<Vec<Self>> {
         let result = sqlx::query_as("SELECT * FROM user_progress LIMIT $1 OFFSET $2")
             .bind(limit)
@@ -148,11 +162,88 @@ impl CrudRepository<UserProgress, UserProgressCreate, uuid::Uuid> for UserProgre
 }
 
 impl UserProgress {
+    /// Sets the user's status of the lesson, inserting the row on the first call and
+    /// updating it on later ones
+    pub async fn upsert(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        data: UserProgressCreate,
+    ) -> DatabaseResult<Self> {
+        let mut tx = mm.begin().await?;
+        let row = sqlx::query_as(
+            r#"
+            INSERT INTO user_progress (id, user_id, lesson_id, status)
+            VALUES ($1,$2,$3,$4)
+            ON CONFLICT (user_id, lesson_id)
+            DO UPDATE SET status = EXCLUDED.status
+            RETURNING id, user_id, lesson_id, status
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(data.user_id)
+        .bind(data.lesson_id)
+        .bind(data.status)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        UserModuleProgress::refresh_for_lesson(&mut *tx, data.user_id, data.lesson_id).await?;
+        tx.commit().await?;
+        Ok(row)
+    }
+
+    /// Deletes the lesson progress and task attempts of `user_id`, only those inside
+    /// `module_id` when given. Returns the number of progress rows and attempts removed
+    pub async fn reset(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        user_id: Uuid,
+        module_id: Option<Uuid>,
+    ) -> DatabaseResult<(u64, u64)> {
+        let mut tx = mm.begin().await?;
+        let attempts = sqlx::query(
+            r#"
+            DELETE FROM user_task_attempts
+            WHERE user_id = $1
+            AND ($2::UUID IS NULL OR task_id IN (
+                SELECT t.id FROM tasks t JOIN lessons l ON l.id = t.lesson_id WHERE l.module_id = $2
+            ))
+            "#,
+        )
+        .bind(user_id)
+        .bind(module_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        let lessons = sqlx::query(
+            r#"
+            DELETE FROM user_progress
+            WHERE user_id = $1
+            AND ($2::UUID IS NULL OR lesson_id IN (SELECT id FROM lessons WHERE module_id = $2))
+            "#,
+        )
+        .bind(user_id)
+        .bind(module_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+        // untouched modules count as zero, so the cached rows can simply go
+        sqlx::query("DELETE FROM user_module_progress WHERE user_id = $1 AND ($2::UUID IS NULL OR module_id = $2)")
+            .bind(user_id)
+            .bind(module_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok((lessons, attempts))
+    }
+
     pub async fn count_completed(
         mm: &ModelManager,
         actor: &AuthenticatedUser,
     ) -> DatabaseResult<i64> {
-        let result: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_progress WHERE user_id = $1 AND status = TRUE")
+        let result: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_progress WHERE user_id = $1 AND status = 'done'")
             .bind(actor.user_id())
             .fetch_one(mm.executor())
             .await?;
@@ -164,15 +255,59 @@ impl_paginatable_for!(UserProgress, UserProgressCreate, Uuid);
 
 #[async_trait]
 impl HasOwner for UserProgress {
-    type OwnerId = uuid::Uuid;
-
-    async fn get_owner_id(
+    async fn get_owner(
         &self,
         _mm: &ModelManager,
         _actor: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId> {
-        Ok(self.user_id)
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::User(self.user_id))
     }
 }
 
 // Utils
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct UserProgressWithLessonRow {
+    pub id: Uuid,
+    pub lesson_id: Uuid,
+    pub lesson_title: String,
+    pub status: ProgressStatus,
+}
+
+impl UserProgressWithLessonRow {
+    pub async fn page_by_user(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        user_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> DatabaseResult<Page<Self>> {
+        let items = sqlx::query_as(
+            r#"
+            SELECT
+                up.id,
+                up.lesson_id,
+                l.title AS lesson_title,
+                up.status
+            FROM user_progress up
+            JOIN lessons l ON l.id = up.lesson_id
+            JOIN modules m ON m.id = l.module_id
+            WHERE up.user_id = $1
+            ORDER BY m.order_index, l.order_index, up.id
+            LIMIT $2 OFFSET $3
+            "#
+        )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(mm.executor())
+        .await?;
+
+        let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_progress WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(mm.executor())
+            .await?;
+
+        Ok(Page::new(items, total, limit, offset))
+    }
+}
@@ -1,10 +1,12 @@
 
-use crate::impl_paginatable_for;
-use crate::model::access::HasOwner;
+use crate::{impl_cursor_paginatable_for, impl_paginatable_for};
+use crate::model::access::{HasOwner, Owner};
+use crate::model::entity::UserModuleProgress;
 use crate::model::repo::ResourceTyped;
-use crate::model::{ModelManager, error::DatabaseResult, repo::CrudRepository};
+use crate::model::{ModelManager, Sort, Unsorted, error::DatabaseResult, repo::CrudRepository};
 use crate::web::AuthenticatedUser;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use uuid::Uuid;
@@ -14,8 +16,10 @@ pub struct UserTaskAttempt {
     id: Uuid,
     user_id: Uuid,
     task_id: Uuid,
-    selected_answer_id: Uuid,
+    /// `None` for `multi_select` attempts, see `selected_answer_ids`
+    selected_answer_id: Option<Uuid>,
     is_correct: bool,
+    created_at: DateTime<Utc>,
 }
 
 impl ResourceTyped for UserTaskAttempt {
@@ -37,24 +41,37 @@ impl UserTaskAttempt {
         self.task_id
     }
 
-    pub fn selected_answer_id(&self) -> Uuid {
+    pub fn selected_answer_id(&self) -> Option<Uuid> {
         self.selected_answer_id
     }
 
     pub fn is_correct(&self) -> bool {
         self.is_correct
     }
+
+    pub fn created_at(&self) -> &DateTime<Utc> {
+        &self.created_at
+    }
 }
 
 pub struct UserTaskAttemptCreate {
     pub user_id: Uuid,
     pub task_id: Uuid,
-    pub selected_answer_id: Uuid,
+    /// `None` for `multi_select` attempts, their answers go to `create_with_selection`
+    pub selected_answer_id: Option<Uuid>,
     pub is_correct: bool,
 }
 
+/// Attempt made while offline, `client_id` is generated by the client and dedupes replays
+pub struct UserTaskAttemptSync {
+    pub client_id: Uuid,
+    pub task_id: Uuid,
+    pub selected_answer_id: Uuid,
+    pub attempted_at: DateTime<Utc>,
+}
+
 impl UserTaskAttemptCreate {
-    pub fn new(user_id: Uuid, task_id: Uuid, selected_answer_id: Uuid, is_correct: bool) -> Self {
+    pub fn new(user_id: Uuid, task_id: Uuid, selected_answer_id: Option<Uuid>, is_correct: bool) -> Self {
         Self {
             user_id,
             task_id,
@@ -66,6 +83,8 @@ impl UserTaskAttemptCreate {
 
 #[async_trait]
 impl CrudRepository<UserTaskAttempt, UserTaskAttemptCreate, uuid::Uuid> for UserTaskAttempt {
+    type SortField = Unsorted;
+
     async fn create(
         mm: &ModelManager,
         _actor: &AuthenticatedUser,
@@ -75,7 +94,7 @@ impl CrudRepository<UserTaskAttempt, UserTaskAttemptCreate, uuid::Uuid> for User
             r#"
             INSERT INTO user_task_attempts (id, user_id, task_id, selected_answer_id, is_correct)
             VALUES ($1,$2,$3,$4,$5)
-            RETURNING id, user_id, task_id, selected_answer_id, is_correct 
+            RETURNING id, user_id, task_id, selected_answer_id, is_correct, created_at
             "#
         )
         .bind(Uuid::new_v4())
@@ -128,13 +147,9 @@ impl CrudRepository<UserTaskAttempt, UserTaskAttemptCreate, uuid::Uuid> for User
     ) -> DatabaseResult<Option<Self>> {
         let result = sqlx::query_as("SELECT * FROM user_task_attempts WHERE id = $1")
             .bind(id)
-            .fetch_one(mm.executor())
-            .await;
-        if let Err(sqlx::Error::RowNotFound) = result {
-            return Ok(None);
-        }
-
-        Ok(Some(result?))
+            .fetch_optional(mm.executor())
+            .await?;
+        Ok(result)
     }
 
     async fn list(
@@ -142,8 +157,9 @@ impl CrudRepository<UserTaskAttempt, UserTaskAttemptCreate, uuid::Uuid> for User
         _actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        _sort: Option<Sort<Unsorted>>,
     ) -> DatabaseResult<Vec<Self>> {
-        let result = sqlx::query_as("SELECT * FROM user_task_attempts LIMIT $1 OFFSET $2")
+        let result = sqlx::query_as("SELECT * FROM user_task_attempts ORDER BY created_at, id LIMIT $1 OFFSET $2")
             .bind(limit)
             .bind(offset)
             .fetch_all(mm.executor())
@@ -162,6 +178,188 @@ impl CrudRepository<UserTaskAttempt, UserTaskAttemptCreate, uuid::Uuid> for User
 }
 
 impl UserTaskAttempt {
+    /// Stores a `multi_select` attempt along with every answer picked in it
+    pub async fn create_with_selection(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        data: UserTaskAttemptCreate,
+        answer_ids: &[Uuid],
+    ) -> DatabaseResult<Self> {
+        let mut tx = mm.begin().await?;
+        let row: Self = sqlx::query_as(
+            r#"
+            INSERT INTO user_task_attempts (id, user_id, task_id, selected_answer_id, is_correct)
+            VALUES ($1,$2,$3,$4,$5)
+            RETURNING id, user_id, task_id, selected_answer_id, is_correct, created_at
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(data.user_id)
+        .bind(data.task_id)
+        .bind(data.selected_answer_id)
+        .bind(data.is_correct)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO user_task_attempt_answers (attempt_id, answer_id)
+            SELECT $1, UNNEST($2::UUID[])
+            "#
+        )
+        .bind(row.id)
+        .bind(answer_ids)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(row)
+    }
+
+    /// Answers picked in the attempt, empty unless it was made on a `multi_select` task
+    pub async fn selected_answer_ids(&self, mm: &ModelManager) -> DatabaseResult<Vec<Uuid>> {
+        let ids = sqlx::query_scalar(
+            "SELECT answer_id FROM user_task_attempt_answers WHERE attempt_id = $1 ORDER BY answer_id",
+        )
+        .bind(self.id)
+        .fetch_all(mm.executor())
+        .await?;
+        Ok(ids)
+    }
+
+    /// Re-evaluates every attempt on `task_id` against the answers' current `is_correct` flags,
+    /// `multi_select` attempts by comparing their selection with the current correct set.
+    /// Answers of the task are locked for the duration, so a concurrent edit either lands
+    /// before the recount or waits for it. Returns the number of attempts that flipped.
+    pub async fn recompute_for_task(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        task_id: Uuid,
+    ) -> DatabaseResult<u64> {
+        let mut tx = mm.begin().await?;
+
+        sqlx::query("SELECT id FROM task_answers WHERE task_id = $1 FOR UPDATE")
+            .bind(task_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // `multi_select` attempts have no single answer, so this leaves them alone
+        let single = sqlx::query(
+            r#"
+            UPDATE user_task_attempts uta
+            SET is_correct = ta.is_correct
+            FROM task_answers ta
+            WHERE ta.id = uta.selected_answer_id
+            AND uta.task_id = $1
+            AND uta.is_correct <> ta.is_correct
+            "#
+        )
+        .bind(task_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // right when no wrong answer was picked and no correct one left out
+        let selections = sqlx::query(
+            r#"
+            UPDATE user_task_attempts uta
+            SET is_correct = verdict.is_correct
+            FROM (
+                SELECT
+                    a.id,
+                    NOT EXISTS (
+                        SELECT 1
+                        FROM user_task_attempt_answers utaa
+                        JOIN task_answers ta ON ta.id = utaa.answer_id
+                        WHERE utaa.attempt_id = a.id AND NOT ta.is_correct
+                    )
+                    AND NOT EXISTS (
+                        SELECT 1
+                        FROM task_answers ta
+                        WHERE ta.task_id = a.task_id AND ta.is_correct
+                        AND NOT EXISTS (
+                            SELECT 1 FROM user_task_attempt_answers utaa
+                            WHERE utaa.attempt_id = a.id AND utaa.answer_id = ta.id
+                        )
+                    ) AS is_correct
+                FROM user_task_attempts a
+                JOIN tasks t ON t.id = a.task_id
+                WHERE a.task_id = $1 AND t.task_type = 'multi_select'
+            ) verdict
+            WHERE uta.id = verdict.id
+            AND uta.is_correct <> verdict.is_correct
+            "#
+        )
+        .bind(task_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(single.rows_affected() + selections.rows_affected())
+    }
+
+    /// Stores a batch of offline attempts in one transaction and marks lessons of correct ones done.
+    /// Attempts whose `client_id` was already synced by the user are skipped.
+    /// The answer decides correctness, except for `string_cmp` where the typed text only lived on the client.
+    /// Returns the number of attempts actually inserted.
+    pub async fn sync_batch(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        attempts: Vec<UserTaskAttemptSync>,
+    ) -> DatabaseResult<u64> {
+        let mut tx = mm.begin().await?;
+        let mut inserted = 0;
+
+        for attempt in attempts {
+            let row: Option<(bool, Uuid)> = sqlx::query_as(
+                r#"
+                INSERT INTO user_task_attempts
+                    (id, user_id, task_id, selected_answer_id, is_correct, client_id, attempted_at)
+                SELECT $1, $2, t.id, ta.id, ta.is_correct, $5, $6
+                FROM task_answers ta
+                JOIN tasks t ON t.id = ta.task_id
+                WHERE ta.id = $4 AND t.id = $3
+                ON CONFLICT (user_id, client_id) DO NOTHING
+                RETURNING is_correct, (SELECT lesson_id FROM tasks WHERE id = task_id)
+                "#
+            )
+            .bind(Uuid::new_v4())
+            .bind(actor.user_id())
+            .bind(attempt.task_id)
+            .bind(attempt.selected_answer_id)
+            .bind(attempt.client_id)
+            .bind(attempt.attempted_at)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let Some((is_correct, lesson_id)) = row else {
+                continue;
+            };
+            inserted += 1;
+
+            if is_correct {
+                sqlx::query(
+                    r#"
+                    INSERT INTO user_progress (id, user_id, lesson_id, status)
+                    VALUES ($1,$2,$3,'done')
+                    ON CONFLICT (user_id, lesson_id)
+                    DO UPDATE SET status = 'done'
+                    "#
+                )
+                .bind(Uuid::new_v4())
+                .bind(actor.user_id())
+                .bind(lesson_id)
+                .execute(&mut *tx)
+                .await?;
+
+                UserModuleProgress::refresh_for_lesson(&mut *tx, actor.user_id(), lesson_id)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
     pub async fn count_correct(mm: &ModelManager, actor: &AuthenticatedUser) -> DatabaseResult<i64> {
         let result: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_task_attempts WHERE user_id = $1 AND is_correct = TRUE")
             .bind(actor.user_id())
@@ -172,16 +370,120 @@ impl UserTaskAttempt {
 }
 
 impl_paginatable_for!(UserTaskAttempt, UserTaskAttemptCreate, Uuid);
+impl_cursor_paginatable_for!(UserTaskAttempt, "user_task_attempts");
 
 #[async_trait]
 impl HasOwner for UserTaskAttempt {
-    type OwnerId = uuid::Uuid;
-
-    async fn get_owner_id(
+    async fn get_owner(
         &self,
         _mm: &ModelManager,
         _actor: &AuthenticatedUser,
-    ) -> DatabaseResult<Self::OwnerId> {
-        Ok(self.user_id)
+    ) -> DatabaseResult<Owner> {
+        Ok(Owner::User(self.user_id))
+    }
+}
+
+// Utils
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct AnswerDistributionRow {
+    pub answer_id: Uuid,
+    pub answer_text: String,
+    pub is_correct: bool,
+    /// How many attempts selected this answer
+    pub count: i64,
+    /// Share of the task's attempts that selected this answer, 0 to 100. Adds up to more
+    /// than 100 for `multi_select` tasks, where an attempt picks several answers
+    pub percent: f64,
+}
+
+impl AnswerDistributionRow {
+    /// Selection counts for every answer of the task, including never chosen ones.
+    /// `multi_select` attempts count once for every answer they picked
+    pub async fn fetch_by_task(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        task_id: Uuid,
+    ) -> DatabaseResult<Vec<Self>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT
+                ta.id AS answer_id,
+                ta.answer_text,
+                ta.is_correct,
+                COUNT(picks.answer_id) AS count,
+                COALESCE(
+                    100.0 * COUNT(picks.answer_id)
+                        / NULLIF((SELECT COUNT(*) FROM user_task_attempts WHERE task_id = $1), 0),
+                    0
+                )::FLOAT8 AS percent
+            FROM task_answers ta
+            LEFT JOIN (
+                SELECT selected_answer_id AS answer_id
+                FROM user_task_attempts
+                WHERE task_id = $1 AND selected_answer_id IS NOT NULL
+                UNION ALL
+                SELECT utaa.answer_id
+                FROM user_task_attempt_answers utaa
+                JOIN user_task_attempts uta ON uta.id = utaa.attempt_id
+                WHERE uta.task_id = $1
+            ) picks ON picks.answer_id = ta.id
+            WHERE ta.task_id = $1
+            GROUP BY ta.id
+            ORDER BY count DESC, ta.answer_text
+            "#
+        )
+        .bind(task_id)
+        .fetch_all(mm.executor())
+        .await?;
+
+        Ok(rows)
+    }
+}
+
+/// One user's standing, only the username identifies them
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, utoipa::ToSchema)]
+pub struct LeaderboardEntry {
+    pub username: String,
+    pub correct_answers: i64,
+    pub completed_lessons: i64,
+}
+
+impl LeaderboardEntry {
+    /// Top `limit` users by correct attempts, completed lessons break ties.
+    /// Users without a correct attempt aren't ranked
+    pub async fn fetch_top(
+        mm: &ModelManager,
+        _actor: &AuthenticatedUser,
+        limit: i64,
+    ) -> DatabaseResult<Vec<Self>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT
+                u.username,
+                a.correct_answers,
+                COALESCE(p.completed_lessons, 0) AS completed_lessons
+            FROM (
+                SELECT user_id, COUNT(*) AS correct_answers
+                FROM user_task_attempts
+                WHERE is_correct
+                GROUP BY user_id
+            ) a
+            JOIN users u ON u.id = a.user_id
+            LEFT JOIN (
+                SELECT user_id, COUNT(*) AS completed_lessons
+                FROM user_progress
+                WHERE status = 'done'
+                GROUP BY user_id
+            ) p ON p.user_id = a.user_id
+            ORDER BY a.correct_answers DESC, completed_lessons DESC, u.username
+            LIMIT $1
+            "#
+        )
+        .bind(limit)
+        .fetch_all(mm.executor())
+        .await?;
+
+        Ok(rows)
     }
 }
@@ -6,10 +6,60 @@ pub type DatabaseResult<T> = std::result::Result<T, DatabaseError>;
 pub enum DatabaseError {
     #[error("sqlx migrate error: {0}")]
     SqlxMigrateError(#[from] sqlx::migrate::MigrateError),
+    #[error("migrations directory `{}` not found", .0.display())]
+    MigrationsDirNotFound(std::path::PathBuf),
     #[error("sqlx error: {0}")]
-    SqlxError(#[from] sqlx::Error),
+    SqlxError(sqlx::Error),
+    #[error("database unavailable: {0}")]
+    Unavailable(sqlx::Error),
+    #[error("constraint violation `{constraint}`: {error}")]
+    ConstraintViolation {
+        constraint: String,
+        error: sqlx::Error,
+    },
+    #[error("unique violation `{constraint}`")]
+    UniqueViolation { constraint: String },
     #[error("json error: {0}")]
     SerdeError(#[from] serde_json::Error),
     #[error("access to this resource is forbidden")]
     Forbidden,
+    /// The operation makes no sense for this resource, e.g. updating a token
+    #[error("unsupported operation: {0}")]
+    Unsupported(&'static str),
+}
+
+impl From<sqlx::Error> for DatabaseError {
+    /// Separates "can't reach the database" and rejected data from errors of the query itself
+    fn from(error: sqlx::Error) -> Self {
+        if let Some(constraint) = unique_violation(&error) {
+            return Self::UniqueViolation { constraint };
+        }
+
+        if let sqlx::Error::Database(db) = &error {
+            // not_null_violation, check_violation
+            if matches!(db.code().as_deref(), Some("23502" | "23514")) {
+                let constraint = db.constraint().unwrap_or("not null").to_string();
+                return Self::ConstraintViolation { constraint, error };
+            }
+        }
+
+        match error {
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => Self::Unavailable(error),
+            error => Self::SqlxError(error),
+        }
+    }
+}
+
+/// Name of the unique constraint the statement ran into, if that's what failed
+fn unique_violation(error: &sqlx::Error) -> Option<String> {
+    match error {
+        sqlx::Error::Database(db) if db.code().as_deref() == Some("23505") => {
+            Some(db.constraint().unwrap_or("unique").to_string())
+        }
+        _ => None,
+    }
 }
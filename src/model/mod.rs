@@ -1,5 +1,5 @@
 mod access;
-pub use access::check_access;
+pub use access::{HasOwner, Owner, check_access};
 
 mod database;
 pub use database::DbConnection;
@@ -9,10 +9,25 @@ pub mod entity;
 mod error;
 pub use error::{DatabaseError, DatabaseResult};
 
+mod course;
+pub use course::{
+    AnswerImport, CourseImport, CourseImportError, CourseImportResult, ImportSummary, LessonImport,
+    ModuleImport, TaskImport,
+};
+
 mod repo;
-pub use repo::{CrudRepository, Page, PaginatableRepository, ResourceType, ResourceTyped};
+pub use repo::{
+    ContentSort, CrudRepository, CursorPage, CursorPaginatableRepository, KeysetPage, OrderCursor,
+    InvalidSort, OrderPaginatableRepository, Page, PaginatableRepository, ResourceType, ResourceTyped,
+    Sort, SortDirection, SortField, UnknownResourceType, Unsorted,
+};
+
+use std::{future::Future, pin::Pin};
 
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
+
+/// Future returned by the closure given to [`ModelManager::with_transaction`]
+pub type TransactionFuture<'c, T> = Pin<Box<dyn Future<Output = DatabaseResult<T>> + Send + 'c>>;
 
 #[derive(Debug, Clone)]
 pub struct ModelManager {
@@ -27,4 +42,30 @@ impl ModelManager {
     pub fn executor(&self) -> &PgPool {
         self.database.pool()
     }
+
+    /// Starts a transaction, it rolls back when dropped without `commit`
+    pub async fn begin(&self) -> DatabaseResult<Transaction<'_, Postgres>> {
+        Ok(self.executor().begin().await?)
+    }
+
+    /// Runs `f` in a transaction, committing when it returns `Ok` and rolling back otherwise.
+    /// The future has to be boxed so it can borrow the transaction:
+    /// `mm.with_transaction(|tx| Box::pin(async move { ... }))`
+    pub async fn with_transaction<T, F>(&self, f: F) -> DatabaseResult<T>
+    where
+        T: Send,
+        F: for<'c> FnOnce(&'c mut Transaction<'_, Postgres>) -> TransactionFuture<'c, T> + Send,
+    {
+        let mut tx = self.begin().await?;
+        match f(&mut tx).await {
+            Ok(value) => {
+                tx.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                Err(e)
+            }
+        }
+    }
 }
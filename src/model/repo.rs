@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+use uuid::Uuid;
+
 use crate::{
     model::{ModelManager, error::DatabaseResult},
     web::AuthenticatedUser,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResourceType {
     User,
     Module,
@@ -13,8 +15,175 @@ pub enum ResourceType {
     Task,
     Answer,
     UserProgress,
+    UserModuleProgress,
     UserTaskAttempt,
     ProgressToken,
+    ResumeToken,
+    RefreshToken,
+}
+
+impl ResourceType {
+    pub const ALL: [ResourceType; 11] = [
+        Self::User,
+        Self::Module,
+        Self::Lesson,
+        Self::Task,
+        Self::Answer,
+        Self::UserProgress,
+        Self::UserModuleProgress,
+        Self::UserTaskAttempt,
+        Self::ProgressToken,
+        Self::ResumeToken,
+        Self::RefreshToken,
+    ];
+
+    /// Name used in messages and logs, e.g. `user_task_attempt`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Module => "module",
+            Self::Lesson => "lesson",
+            Self::Task => "task",
+            Self::Answer => "answer",
+            Self::UserProgress => "user_progress",
+            Self::UserModuleProgress => "user_module_progress",
+            Self::UserTaskAttempt => "user_task_attempt",
+            Self::ProgressToken => "progress_token",
+            Self::ResumeToken => "resume_token",
+            Self::RefreshToken => "refresh_token",
+        }
+    }
+}
+
+impl std::fmt::Display for ResourceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown resource type `{0}`")]
+pub struct UnknownResourceType(String);
+
+impl std::str::FromStr for ResourceType {
+    type Err = UnknownResourceType;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| UnknownResourceType(s.to_string()))
+    }
+}
+
+/// Order of module and lesson listings, set by `app.default_content_sort`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentSort {
+    #[default]
+    OrderIndex,
+    Title,
+    CreatedAt,
+}
+
+impl ContentSort {
+    /// `ORDER BY` list for the table aliased as `alias`, ties fall back to the id
+    pub fn order_by(self, alias: &str) -> String {
+        let column = match self {
+            Self::OrderIndex => "order_index",
+            Self::Title => "title",
+            Self::CreatedAt => "created_at",
+        };
+        format!("{alias}.{column}, {alias}.id")
+    }
+}
+
+/// Column an entity listing can be ordered by, see [`Sort`].
+///
+/// Only the names returned by `as_str` ever reach the SQL, which is what makes formatting them
+/// into `ORDER BY` safe.
+pub trait SortField: Sized + Copy + Send + Sync + 'static {
+    const ALL: &'static [Self];
+
+    /// Name in the `sort` parameter, same as the column
+    fn as_str(self) -> &'static str;
+}
+
+/// Sort field of entities that can't be sorted, no `Sort` of it can be built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unsorted {}
+
+impl SortField for Unsorted {
+    const ALL: &'static [Self] = &[];
+
+    fn as_str(self) -> &'static str {
+        match self {}
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+/// Requested order of a listing, parsed from `field:direction`, e.g. `username:desc`.
+/// The direction may be left out and defaults to ascending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort<F> {
+    pub field: F,
+    pub direction: SortDirection,
+}
+
+impl<F: SortField> Sort<F> {
+    pub fn new(field: F, direction: SortDirection) -> Self {
+        Self { field, direction }
+    }
+
+    /// `ORDER BY` list, ties fall back to the id in the same direction
+    pub fn order_by(&self) -> String {
+        let direction = self.direction.as_sql();
+        format!("{} {direction}, id {direction}", self.field.as_str())
+    }
+
+    /// `ORDER BY` list of `sort`, or `default` when there is none
+    pub fn order_by_or(sort: Option<Self>, default: &str) -> String {
+        sort.map_or_else(|| default.to_string(), |s| s.order_by())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid sort `{0}`")]
+pub struct InvalidSort(String);
+
+impl<F: SortField> std::str::FromStr for Sort<F> {
+    type Err = InvalidSort;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidSort(s.to_string());
+        let (field, direction) = s.split_once(':').unwrap_or((s, "asc"));
+        let field = F::ALL
+            .iter()
+            .copied()
+            .find(|f| f.as_str() == field)
+            .ok_or_else(invalid)?;
+        let direction = match direction {
+            "asc" => SortDirection::Asc,
+            "desc" => SortDirection::Desc,
+            _ => return Err(invalid()),
+        };
+        Ok(Self::new(field, direction))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
@@ -23,6 +192,8 @@ pub struct Page<T> {
     pub total: i64,
     pub limit: i64,
     pub offset: i64,
+    /// `offset` is past the last item, clients should go back to the first page
+    pub out_of_range: bool,
 }
 
 impl<T> Page<T> {
@@ -32,10 +203,35 @@ impl<T> Page<T> {
             total,
             limit,
             offset,
+            out_of_range: offset > 0 && offset >= total,
         }
     }
 }
 
+/// Position of the last item of a `KeysetPage`, the next page starts right after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct OrderCursor {
+    pub order_index: i32,
+    pub id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct KeysetPage<T> {
+    pub items: Vec<T>,
+    /// `None` when this is the last page
+    pub next: Option<OrderCursor>,
+}
+
+/// Page of a cursor traversal, pass `next_cursor` to get the following one
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    /// Id of the last item, `None` when this is the last page
+    pub next_cursor: Option<Uuid>,
+}
+
 pub trait ResourceTyped {
     fn get_resource_type() -> ResourceType;
 }
@@ -46,6 +242,9 @@ where
     T: ResourceTyped,
     V: Clone + Copy,
 {
+    /// Columns `list` can order by, [`Unsorted`] for none
+    type SortField: SortField;
+
     async fn create(
         mm: &ModelManager,
         actor: &AuthenticatedUser,
@@ -74,6 +273,7 @@ where
         actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        sort: Option<Sort<Self::SortField>>,
     ) -> DatabaseResult<Vec<T>>;
     async fn count(mm: &ModelManager, actor: &AuthenticatedUser) -> DatabaseResult<i64>;
 }
@@ -89,9 +289,45 @@ where
         actor: &AuthenticatedUser,
         limit: i64,
         offset: i64,
+        sort: Option<Sort<<T as CrudRepository<T, CreateUpdate, V>>::SortField>>,
     ) -> DatabaseResult<Page<T>>;
 }
 
+/// Pagination in course order for entities with an `order_index`.
+///
+/// Unlike offset pages these are keyed by `(order_index, id)`, so reordering or inserting items
+/// between requests doesn't shift later pages.
+#[async_trait::async_trait]
+pub trait OrderPaginatableRepository<T>
+where
+    T: ResourceTyped,
+{
+    async fn page_by_order(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        after_order: Option<OrderCursor>,
+        limit: i64,
+    ) -> DatabaseResult<KeysetPage<T>>;
+}
+
+/// Pagination in insertion order for entities with a `created_at` column.
+///
+/// Pages are keyed by `(created_at, id)` of the row the cursor names, so they stay cheap on large
+/// tables and rows inserted meanwhile don't shift them. A cursor naming a deleted row yields an
+/// empty page.
+#[async_trait::async_trait]
+pub trait CursorPaginatableRepository<T>
+where
+    T: ResourceTyped,
+{
+    async fn page_after(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        cursor: Option<Uuid>,
+        limit: i64,
+    ) -> DatabaseResult<CursorPage<T>>;
+}
+
 #[macro_export]
 macro_rules! impl_paginatable_for {
     ($ent:ident, $ent_create:ident, $ent_id:ident) => {
@@ -102,11 +338,93 @@ macro_rules! impl_paginatable_for {
                 actor: &AuthenticatedUser,
                 limit: i64,
                 offset: i64,
+                sort: Option<
+                    $crate::model::Sort<
+                        <$ent as $crate::model::CrudRepository<$ent, $ent_create, $ent_id>>::SortField,
+                    >,
+                >,
             ) -> DatabaseResult<$crate::model::Page<$ent>> {
-                let items = $ent::list(mm, actor, limit, offset).await?;
+                let items = $ent::list(mm, actor, limit, offset, sort).await?;
                 let count = $ent::count(mm, actor).await?;
                 Ok($crate::model::Page::new(items, count, limit, offset))
             }
         }
     };
 }
+
+#[macro_export]
+macro_rules! impl_order_paginatable_for {
+    ($ent:ident, $table:literal) => {
+        #[async_trait::async_trait]
+        impl $crate::model::OrderPaginatableRepository<$ent> for $ent {
+            async fn page_by_order(
+                mm: &ModelManager,
+                _actor: &AuthenticatedUser,
+                after_order: Option<$crate::model::OrderCursor>,
+                limit: i64,
+            ) -> DatabaseResult<$crate::model::KeysetPage<$ent>> {
+                let items: Vec<$ent> = sqlx::query_as(concat!(
+                    "SELECT * FROM ",
+                    $table,
+                    " WHERE $1::INT IS NULL OR (order_index, id) > ($1, $2)",
+                    " ORDER BY order_index, id LIMIT $3"
+                ))
+                .bind(after_order.map(|c| c.order_index))
+                .bind(after_order.map(|c| c.id))
+                .bind(limit)
+                .fetch_all(mm.executor())
+                .await?;
+
+                let next = if (items.len() as i64) < limit {
+                    None
+                } else {
+                    items.last().map(|last| $crate::model::OrderCursor {
+                        order_index: last.order_index(),
+                        id: last.id(),
+                    })
+                };
+                Ok($crate::model::KeysetPage { items, next })
+            }
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! impl_cursor_paginatable_for {
+    ($ent:ident, $table:literal) => {
+        #[async_trait::async_trait]
+        impl $crate::model::CursorPaginatableRepository<$ent> for $ent {
+            async fn page_after(
+                mm: &ModelManager,
+                actor: &AuthenticatedUser,
+                cursor: Option<Uuid>,
+                limit: i64,
+            ) -> DatabaseResult<$crate::model::CursorPage<$ent>> {
+                let items: Vec<$ent> = sqlx::query_as(concat!(
+                    "SELECT * FROM ",
+                    $table,
+                    " WHERE $1::UUID IS NULL OR (created_at, id) > (SELECT created_at, id FROM ",
+                    $table,
+                    " WHERE id = $1) ORDER BY created_at, id LIMIT $2"
+                ))
+                .bind(cursor)
+                .bind(limit)
+                .fetch_all(mm.executor())
+                .await?;
+
+                let next_cursor = if (items.len() as i64) < limit {
+                    None
+                } else {
+                    items.last().map(|last| last.id())
+                };
+                let total = $ent::count(mm, actor).await?;
+                Ok($crate::model::CursorPage {
+                    items,
+                    total,
+                    limit,
+                    next_cursor,
+                })
+            }
+        }
+    };
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    model::{
+        ModelManager,
+        entity::{ProgressToken, RefreshToken, ResumeToken},
+    },
+    web::AuthenticatedUser,
+};
+
+/// Deletes expired progress, resume and refresh tokens every `every` until `shutdown` is
+/// cancelled, so requests don't have to. Failed runs are logged and retried on the next tick.
+pub async fn progress_token_cleanup(mm: ModelManager, every: Duration, shutdown: CancellationToken) {
+    let system = AuthenticatedUser::system();
+    let mut interval = tokio::time::interval(every);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+
+        match ProgressToken::cleanup_expired(&mm, &system).await {
+            Ok(n) => tracing::debug!("progress_tokens: cleaned up {} expired tokens", n),
+            Err(e) => tracing::warn!("progress_tokens: cleanup failed: {}", e),
+        }
+        match ResumeToken::cleanup_expired(&mm, &system).await {
+            Ok(n) => tracing::debug!("resume_tokens: cleaned up {} expired tokens", n),
+            Err(e) => tracing::warn!("resume_tokens: cleanup failed: {}", e),
+        }
+        match RefreshToken::cleanup_expired(&mm, &system).await {
+            Ok(n) => tracing::debug!("refresh_tokens: cleaned up {} expired tokens", n),
+            Err(e) => tracing::warn!("refresh_tokens: cleanup failed: {}", e),
+        }
+    }
+
+    tracing::debug!("progress_tokens: cleanup stopped");
+}
@@ -0,0 +1,64 @@
+use pulldown_cmark::{Event, Options, Parser, TagEnd, html};
+
+/// Markdown of authored content rendered to HTML. Raw HTML in the markdown is passed through
+/// `ammonia`, so scripts, event handler attributes and `javascript:` links never reach clients.
+pub fn render_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut unsafe_html = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options));
+    ammonia::clean(&unsafe_html)
+}
+
+/// Plain text of a markdown fragment, e.g. a lesson search snippet. Formatting and link targets
+/// are dropped, code keeps its text and block boundaries become single spaces.
+pub fn strip_markdown(markdown: &str) -> String {
+    let mut text = String::with_capacity(markdown.len());
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(t) | Event::Code(t) | Event::InlineMath(t) | Event::DisplayMath(t) => {
+                text.push_str(&t)
+            }
+            Event::SoftBreak
+            | Event::HardBreak
+            | Event::Rule
+            | Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::CodeBlock) => {
+                text.push(' ')
+            }
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strip_markdown_test() {
+        let markdown = "# Ownership\n\nEvery value has **one** [owner](https://doc.rust-lang.org).\n\n- `move` semantics\n- borrowing\n\n```rust\nlet s = String::new();\n```";
+        assert_eq!(
+            strip_markdown(markdown),
+            "Ownership Every value has one owner. move semantics borrowing let s = String::new();"
+        );
+        // fragments cut out of a lesson may end inside the syntax
+        assert_eq!(strip_markdown("a **bold cut"), "a **bold cut");
+        assert_eq!(strip_markdown(""), "");
+    }
+
+    #[test]
+    fn render_html_test() {
+        assert_eq!(
+            render_html("# Title\n\n[docs](https://doc.rust-lang.org)"),
+            "<h1>Title</h1>\n<p><a href=\"https://doc.rust-lang.org\" rel=\"noopener noreferrer\">docs</a></p>\n"
+        );
+
+        let html = render_html(
+            "<script>alert(1)</script>\n\n<img src=\"a.png\" onerror=\"alert(2)\">\n\n[x](javascript:alert(3))",
+        );
+        assert!(!html.contains("script"), "{html}");
+        assert!(!html.contains("onerror"), "{html}");
+        assert!(!html.contains("javascript"), "{html}");
+        assert!(html.contains("<img src=\"a.png\">"), "{html}");
+    }
+}
@@ -1,2 +1,4 @@
+pub mod cleanup;
+pub mod markdown;
 pub mod signal;
 pub mod uploads;
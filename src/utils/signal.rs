@@ -35,6 +35,4 @@ pub async fn shutdown_signal() {
             warn!("SIGINT (Ctrl+C) received, shutting down gracefully...");
         }
     }
-
-    std::process::exit(0);
 }
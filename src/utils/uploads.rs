@@ -1,6 +1,122 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
-pub fn get_uploads_dir() -> std::io::Result<PathBuf> {
-    let cwd = std::env::current_dir()?;
-    Ok(cwd.join("uploads"))
+/// File extension of an accepted image upload, none unless `content_type` is png, jpeg or webp
+/// and `bytes` start with that format's signature
+pub fn image_extension(content_type: &str, bytes: &[u8]) -> Option<&'static str> {
+    let (extension, matches) = match content_type {
+        "image/png" => ("png", bytes.starts_with(b"\x89PNG\r\n\x1a\n")),
+        "image/jpeg" => ("jpg", bytes.starts_with(&[0xFF, 0xD8, 0xFF])),
+        "image/webp" => (
+            "webp",
+            bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP",
+        ),
+        _ => return None,
+    };
+    matches.then_some(extension)
+}
+
+/// Deletes files under `dir` that are not in `referenced` and were last modified more than `grace` ago.
+/// `referenced` holds paths relative to `dir` joined with `/`, the way `task_answers.image` stores them.
+/// Returns the removed paths in the same form, a missing `dir` has nothing to remove.
+pub fn remove_orphans(
+    dir: &Path,
+    referenced: &HashSet<String>,
+    grace: Duration,
+) -> std::io::Result<Vec<String>> {
+    let mut removed = Vec::new();
+    if !dir.is_dir() {
+        return Ok(removed);
+    }
+
+    let cutoff = SystemTime::now().checked_sub(grace).unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+            // symlinks are left alone, they may point outside of the uploads
+            let meta = std::fs::symlink_metadata(&path)?;
+            if meta.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if !meta.is_file() || meta.modified()? > cutoff {
+                continue;
+            }
+
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if !referenced.contains(&relative) {
+                std::fs::remove_file(&path)?;
+                removed.push(relative);
+            }
+        }
+    }
+
+    removed.sort();
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn touch(path: &Path, age: Duration) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = std::fs::File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn remove_orphans_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let day = Duration::from_secs(60 * 60 * 24);
+
+        touch(&dir.path().join("used.png"), 2 * day);
+        touch(&dir.path().join("tasks/used.png"), 2 * day);
+        touch(&dir.path().join("orphan.png"), 2 * day);
+        touch(&dir.path().join("tasks/orphan.png"), 2 * day);
+        // still within the grace period, its answer may not be saved yet
+        touch(&dir.path().join("fresh.png"), Duration::ZERO);
+
+        let referenced = HashSet::from(["used.png".to_string(), "tasks/used.png".to_string()]);
+        let removed = remove_orphans(dir.path(), &referenced, day).unwrap();
+
+        assert_eq!(removed, vec!["orphan.png", "tasks/orphan.png"]);
+        assert!(dir.path().join("used.png").exists());
+        assert!(dir.path().join("tasks/used.png").exists());
+        assert!(dir.path().join("fresh.png").exists());
+        assert!(!dir.path().join("orphan.png").exists());
+        assert!(!dir.path().join("tasks/orphan.png").exists());
+    }
+
+    #[test]
+    fn image_extension_test() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert_eq!(image_extension("image/png", png), Some("png"));
+        assert_eq!(image_extension("image/jpeg", &[0xFF, 0xD8, 0xFF, 0xE0]), Some("jpg"));
+        assert_eq!(image_extension("image/webp", b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        // the declared type has to match the content
+        assert_eq!(image_extension("image/jpeg", png), None);
+        assert_eq!(image_extension("image/gif", b"GIF89a"), None);
+        assert_eq!(image_extension("text/plain", b"hello"), None);
+    }
+
+    #[test]
+    fn remove_orphans_missing_dir_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let removed =
+            remove_orphans(&dir.path().join("missing"), &HashSet::new(), Duration::ZERO).unwrap();
+        assert!(removed.is_empty());
+    }
 }
@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::web::dto::modules::ModuleWithLessons;
+
+type CacheEntry = (Instant, Vec<ModuleWithLessons>);
+
+/// In-memory TTL cache for the module catalog.
+///
+/// Lesson completion is part of every entry, so listings are cached per user
+/// (`None` holds the anonymous catalog).
+/// Entries are dropped when the TTL runs out, when the user's progress changes,
+/// or all at once when course content is modified.
+#[derive(Debug)]
+pub struct ModuleCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<Option<Uuid>, CacheEntry>>,
+    loads: AtomicU64,
+}
+
+impl ModuleCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+            loads: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached listing for `user_id` if it's still fresh
+    pub fn get(&self, user_id: Option<Uuid>) -> Option<Vec<ModuleWithLessons>> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .get(&user_id)
+            .filter(|(stored_at, _)| stored_at.elapsed() < self.ttl)
+            .map(|(_, modules)| modules.clone())
+    }
+
+    /// Stores a freshly loaded listing. Every call counts as a database load.
+    pub fn put(&self, user_id: Option<Uuid>, modules: Vec<ModuleWithLessons>) {
+        self.loads.fetch_add(1, Ordering::Relaxed);
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|_, (stored_at, _)| stored_at.elapsed() < self.ttl);
+        entries.insert(user_id, (Instant::now(), modules));
+    }
+
+    /// Drops the listing of a single user, e.g. after their progress changed
+    pub fn invalidate_user(&self, user_id: Uuid) {
+        self.entries.write().unwrap().remove(&Some(user_id));
+    }
+
+    /// Drops every listing, e.g. after modules or lessons were modified
+    pub fn invalidate_all(&self) {
+        self.entries.write().unwrap().clear();
+    }
+
+    /// Number of listings loaded from the database so far
+    pub fn loads(&self) -> u64 {
+        self.loads.load(Ordering::Relaxed)
+    }
+}
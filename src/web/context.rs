@@ -3,26 +3,48 @@
 
 use axum::{extract::FromRequestParts, http::request::Parts};
 
-use crate::web::{WebResult, error::WebError};
+use crate::{
+    model::ResourceType,
+    web::{WebResult, error::WebError},
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AuthenticatedUser {
     user_id: uuid::Uuid,
     user_role: UserRole,
+    /// Set only by `system`, no signed in user ever has it
+    system: bool,
 }
 
 impl AuthenticatedUser {
     pub fn new(user_id: uuid::Uuid, user_role: UserRole) -> Self {
-        Self { user_id, user_role }
+        Self {
+            user_id,
+            user_role,
+            system: false,
+        }
     }
 
+    /// Stand-in admin that isn't backed by an account, for tests
     pub fn admin() -> Self {
+        Self::new(uuid::Uuid::max(), UserRole::Admin)
+    }
+
+    /// Actor of the server's own queries, e.g. looking up a user before they're signed in or
+    /// background jobs. Passes access checks like an admin, but never equals a real one
+    pub fn system() -> Self {
         Self {
+            user_id: uuid::Uuid::nil(),
             user_role: UserRole::Admin,
-            user_id: uuid::Uuid::max(), // admin ID
+            system: true,
         }
     }
 
+    /// Whether this is the `system` actor rather than a signed in user
+    pub fn is_system(&self) -> bool {
+        self.system
+    }
+
     pub fn user_id(&self) -> uuid::Uuid {
         self.user_id
     }
@@ -35,22 +57,51 @@ impl AuthenticatedUser {
 #[derive(Debug, Clone, PartialEq)]
 pub enum UserRole {
     Admin,
+    /// Authors course content, but can't manage users
+    Instructor,
     User,
 }
 
+impl UserRole {
+    /// Whether the role may create and change modules, lessons, tasks and answers
+    pub fn can_author(&self) -> bool {
+        matches!(self, Self::Admin | Self::Instructor)
+    }
+}
+
 impl From<&str> for UserRole {
     fn from(value: &str) -> Self {
         match value {
             "admin" => Self::Admin,
+            "instructor" => Self::Instructor,
             _ => Self::User,
         }
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+#[error("unknown role `{0}`, expected admin, instructor or user")]
+pub struct UnknownUserRole(String);
+
+/// Strict counterpart of `From<&str>`, for roles coming from a request
+impl std::str::FromStr for UserRole {
+    type Err = UnknownUserRole;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "admin" => Ok(Self::Admin),
+            "instructor" => Ok(Self::Instructor),
+            "user" => Ok(Self::User),
+            _ => Err(UnknownUserRole(s.to_string())),
+        }
+    }
+}
+
 impl std::fmt::Display for UserRole {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::User => write!(f, "user"),
+            Self::Instructor => write!(f, "instructor"),
             Self::Admin => write!(f, "admin"),
         }
     }
@@ -94,3 +145,63 @@ where
         }
     }
 }
+
+/// Signed in user with the admin role. Rejects with `401` without a session and with `403` for
+/// everyone else, so admin-only handlers don't have to check the role themselves
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthenticatedUser);
+
+impl<S> FromRequestParts<S> for AdminUser
+where
+    S: Send + Sync,
+{
+    type Rejection = WebError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ctx = RequestContext::from_request_parts(parts, state).await?;
+        let user = ctx.user()?;
+        if user.user_role() != UserRole::Admin {
+            return Err(WebError::resource_forbidden(ResourceType::User));
+        }
+        Ok(Self(user.clone()))
+    }
+}
+
+/// Signed in admin or instructor, for the content authoring routes. Rejects like `AdminUser`
+#[derive(Debug, Clone)]
+pub struct AuthorUser(pub AuthenticatedUser);
+
+impl<S> FromRequestParts<S> for AuthorUser
+where
+    S: Send + Sync,
+{
+    type Rejection = WebError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ctx = RequestContext::from_request_parts(parts, state).await?;
+        let user = ctx.user()?;
+        if !user.user_role().can_author() {
+            return Err(WebError::resource_forbidden(ResourceType::User));
+        }
+        Ok(Self(user.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_actor_test() {
+        let system = AuthenticatedUser::system();
+        let admin = AuthenticatedUser::new(uuid::Uuid::new_v4(), UserRole::Admin);
+
+        assert!(system.is_system());
+        assert!(!admin.is_system());
+        assert!(!AuthenticatedUser::admin().is_system());
+        assert_ne!(system, admin);
+        assert_ne!(system, AuthenticatedUser::admin());
+        assert_ne!(system, AuthenticatedUser::new(uuid::Uuid::nil(), UserRole::Admin));
+        assert_eq!(system, AuthenticatedUser::system());
+    }
+}
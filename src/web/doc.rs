@@ -16,21 +16,75 @@ impl Modify for CookieAuthModifier {
 
 #[derive(OpenApi)]
 #[openapi(
+    info(title = "flern-api", version = env!("CARGO_PKG_VERSION")),
+    servers(
+        (url = "/", description = "The server serving this document")
+    ),
+    tags(
+        (name = "account", description = "Signing up and in, sessions and user management"),
+        (name = "modules", description = "Module catalog, authoring and gating"),
+        (name = "lessons", description = "Lesson content, authoring and completion"),
+        (name = "tasks", description = "Tasks, answers and attempts"),
+        (name = "progress", description = "Sharing and resuming progress"),
+        (name = "admin", description = "Maintenance and reports for admins"),
+    ),
     paths(
         crate::web::routes::user::user_signup_handler, 
         crate::web::routes::user::user_signin_handler,
+        crate::web::routes::user::user_refresh_handler,
+        crate::web::routes::user::user_verify_handler,
+        crate::web::routes::user::user_me_handler,
+        crate::web::routes::user::user_lessons_handler,
         crate::web::routes::user::user_list_handler,
+        crate::web::routes::user::user_progress_handler,
         crate::web::routes::user::user_update_handler,
         crate::web::routes::user::user_delete_handler,
+        crate::web::routes::user::user_merge_handler,
+        crate::web::routes::user::user_role_handler,
         crate::web::routes::user::user_logout_handler,
         crate::web::routes::modules::modules_list_handler,
+        crate::web::routes::modules::modules_create_handler,
+        crate::web::routes::modules::modules_update_handler,
+        crate::web::routes::modules::modules_delete_handler,
+        crate::web::routes::modules::modules_toc_handler,
+        crate::web::routes::modules::modules_gating_get_handler,
+        crate::web::routes::modules::modules_gating_update_handler,
+        crate::web::routes::modules::modules_lesson_order_handler,
+        crate::web::routes::modules::modules_recommended_handler,
+        crate::web::routes::modules::modules_progress_batch_handler,
         crate::web::routes::lessons::lessons_get_handler,
+        crate::web::routes::lessons::lessons_search_handler,
+        crate::web::routes::lessons::lessons_get_html_handler,
+        crate::web::routes::lessons::lessons_create_handler,
+        crate::web::routes::lessons::lessons_update_handler,
+        crate::web::routes::lessons::lessons_delete_handler,
+        crate::web::routes::lessons::lessons_get_status_handler,
         crate::web::routes::lessons::lessons_mark_done_handler,
         crate::web::routes::lessons::lessons_get_tasks_handler,
         crate::web::routes::lessons::lessons_get_next_handler,
+        crate::web::routes::tasks::tasks_create_handler,
+        crate::web::routes::tasks::tasks_update_handler,
+        crate::web::routes::tasks::tasks_delete_handler,
+        crate::web::routes::tasks::tasks_answer_create_handler,
+        crate::web::routes::tasks::tasks_answer_update_handler,
+        crate::web::routes::tasks::tasks_answer_image_handler,
+        crate::web::routes::tasks::tasks_answer_delete_handler,
         crate::web::routes::tasks::tasks_check_answer_handler,
+        crate::web::routes::tasks::tasks_for_lessons_handler,
+        crate::web::routes::tasks::tasks_sync_attempts_handler,
+        crate::web::routes::tasks::tasks_recompute_attempts_handler,
+        crate::web::routes::tasks::tasks_answer_distribution_handler,
         crate::web::routes::progress::progress_get_handler,
+        crate::web::routes::progress::progress_reset_handler,
+        crate::web::routes::progress::progress_leaderboard_handler,
+        crate::web::routes::progress::progress_modules_handler,
         crate::web::routes::progress::progress_token_handler,
+        crate::web::routes::progress::progress_resume_link_handler,
+        crate::web::routes::progress::progress_resume_handler,
+        crate::web::routes::admin::admin_tasks_by_type_handler,
+        crate::web::routes::admin::admin_rebuild_progress_cache_handler,
+        crate::web::routes::admin::admin_gc_uploads_handler,
+        crate::web::routes::admin::admin_reset_user_progress_handler,
     ),
     modifiers(&CookieAuthModifier),
 )]
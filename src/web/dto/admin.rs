@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct RebuildProgressCacheResponse {
+    /// Number of user/module rows written to the cache
+    pub rows: u64,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct GcUploadsResponse {
+    /// Removed files, relative to the uploads directory
+    pub removed: Vec<String>,
+}
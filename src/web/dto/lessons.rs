@@ -1,9 +1,10 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::model::entity::LessonWithStatusRow;
+use crate::model::entity::{LessonSearchRow, LessonTocRow, LessonWithStatusRow};
+use crate::utils::markdown::{render_html, strip_markdown};
 
-#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct LessonResponse {
     id: Uuid,
     module_id: Uuid,
@@ -13,6 +14,33 @@ pub struct LessonResponse {
     order_index: i32,
 }
 
+impl LessonResponse {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn module_id(&self) -> Uuid {
+        self.module_id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Whether the requesting user completed the lesson
+    pub fn status(&self) -> bool {
+        self.status
+    }
+
+    pub fn order_index(&self) -> i32 {
+        self.order_index
+    }
+}
+
 impl From<LessonWithStatusRow> for LessonResponse {
     fn from(row: LessonWithStatusRow) -> Self {
         Self {
@@ -25,3 +53,90 @@ impl From<LessonWithStatusRow> for LessonResponse {
         }
     }
 }
+
+/// Lesson content rendered to sanitized HTML, clients can insert it as is
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct LessonHtmlResponse {
+    pub id: Uuid,
+    pub module_id: Uuid,
+    pub title: String,
+    pub html: String,
+}
+
+impl From<LessonResponse> for LessonHtmlResponse {
+    fn from(lesson: LessonResponse) -> Self {
+        Self {
+            html: render_html(&lesson.content),
+            id: lesson.id,
+            module_id: lesson.module_id,
+            title: lesson.title,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct LessonTocItem {
+    id: Uuid,
+    title: String,
+    order_index: i32,
+}
+
+impl From<LessonTocRow> for LessonTocItem {
+    fn from(row: LessonTocRow) -> Self {
+        Self {
+            id: row.id,
+            title: row.title,
+            order_index: row.order_index,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct LessonStatusResponse {
+    pub completed: bool,
+}
+
+/// Completion filter of `GET /api/v1/account/lessons`
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LessonStatusFilter {
+    Completed,
+    Incomplete,
+}
+
+impl LessonStatusFilter {
+    pub fn completed(self) -> bool {
+        self == Self::Completed
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LessonStatusQuery {
+    /// All lessons when omitted
+    pub status: Option<LessonStatusFilter>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LessonSearchQuery {
+    pub q: String,
+}
+
+/// Search result, the snippet is plain text around the matched words
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct LessonSearchHit {
+    pub id: Uuid,
+    pub module_id: Uuid,
+    pub title: String,
+    pub snippet: String,
+}
+
+impl From<LessonSearchRow> for LessonSearchHit {
+    fn from(row: LessonSearchRow) -> Self {
+        Self {
+            id: row.id,
+            module_id: row.module_id,
+            title: row.title,
+            snippet: strip_markdown(&row.headline),
+        }
+    }
+}
@@ -2,4 +2,5 @@ pub mod modules;
 pub mod lessons;
 pub mod tasks;
 pub mod progress;
+pub mod admin;
 
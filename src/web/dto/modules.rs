@@ -1,11 +1,12 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
-    model::{DatabaseResult, entity::ModuleWithLessonsRow},
+    model::{DatabaseResult, entity::{Module, ModuleListOptions, ModuleWithLessonsRow}},
 };
 
-#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct LessonShort {
     pub id: Uuid,
     pub title: String,
@@ -13,15 +14,39 @@ pub struct LessonShort {
     pub order_index: i32,
 }
 
-#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct ModuleWithLessons {
     pub id: Uuid,
     pub title: String,
     pub description: String,
     pub order_index: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub lessons: Vec<LessonShort>,
 }
 
+/// `ModuleWithLessons` as sent to `application/vnd.flern.v1+json` clients, without timestamps
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ModuleWithLessonsV1 {
+    pub id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub order_index: i32,
+    pub lessons: Vec<LessonShort>,
+}
+
+impl From<ModuleWithLessons> for ModuleWithLessonsV1 {
+    fn from(value: ModuleWithLessons) -> Self {
+        Self {
+            id: value.id,
+            title: value.title,
+            description: value.description,
+            order_index: value.order_index,
+            lessons: value.lessons,
+        }
+    }
+}
+
 impl TryFrom<ModuleWithLessonsRow> for ModuleWithLessons {
     type Error = serde_json::Error;
 
@@ -31,6 +56,8 @@ impl TryFrom<ModuleWithLessonsRow> for ModuleWithLessons {
             title: value.title,
             description: value.description,
             order_index: value.order_index,
+            created_at: value.created_at,
+            updated_at: value.updated_at,
             lessons: serde_json::from_value(value.lessons)?,
         })
     }
@@ -44,3 +71,53 @@ impl ModuleWithLessons {
             .collect::<Result<_, _>>()?)
     }
 }
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModulesListQuery {
+    #[serde(default)]
+    pub only_incomplete: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl From<ModulesListQuery> for ModuleListOptions {
+    fn from(value: ModulesListQuery) -> Self {
+        Self {
+            only_incomplete: value.only_incomplete,
+            limit: value.limit,
+            offset: value.offset.unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ModuleGating {
+    pub module_id: Uuid,
+    /// Percent of the previous module's lessons to complete before this one opens, 0 means always open
+    pub min_prerequisite_percent: i32,
+}
+
+impl From<&Module> for ModuleGating {
+    fn from(value: &Module) -> Self {
+        Self {
+            module_id: value.id(),
+            min_prerequisite_percent: value.min_prerequisite_percent(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct ModuleGatingRequest {
+    pub min_prerequisite_percent: i32,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct LessonOrderRequest {
+    /// Every lesson of the module, first to last
+    pub lesson_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct ModuleProgressBatchRequest {
+    pub ids: Vec<Uuid>,
+}
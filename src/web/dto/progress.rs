@@ -1,4 +1,7 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::model::entity::ModuleCompletionRow;
 
 #[derive(Serialize, utoipa::ToSchema)]
 pub struct UserProgressResponse {
@@ -26,3 +29,52 @@ impl UserProgressResponse {
         }
     }
 }
+
+/// Lessons completed by the current user in one module
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ModuleProgress {
+    pub module_id: Uuid,
+    pub title: String,
+    pub completed: i64,
+    pub total: i64,
+}
+
+impl From<ModuleCompletionRow> for ModuleProgress {
+    fn from(value: ModuleCompletionRow) -> Self {
+        Self {
+            module_id: value.module_id,
+            title: value.title,
+            completed: value.completed,
+            total: value.total,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgressResetQuery {
+    /// Resets only this module's lessons, everything when omitted
+    pub module_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ProgressResetResponse {
+    /// Lesson progress rows removed
+    pub lessons: u64,
+    /// Task attempts removed
+    pub attempts: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaderboardQuery {
+    /// 10 when omitted
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, utoipa::ToSchema)]
+pub struct ProgressShareRequest {
+    /// Lifetime of the token, 30 minutes when omitted. Capped at the configured maximum
+    pub ttl_minutes: Option<u32>,
+    /// Delete the token after its first successful read
+    #[serde(default)]
+    pub single_use: bool,
+}
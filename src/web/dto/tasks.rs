@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::model::entity::{Answer, LessonTask};
+use chrono::{DateTime, Utc};
+
+use crate::model::entity::{Answer, AnswerCreate, LessonTask, UserTaskAttemptSync};
 
 #[derive(Serialize, Deserialize, utoipa::ToSchema)]
 pub struct TaskResponse {
@@ -9,6 +11,9 @@ pub struct TaskResponse {
     question: String,
     task_type: String,
     answers: Vec<AnswerResponse>,
+    /// Only for tasks with a check delay, has to be sent back with the check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    served_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, utoipa::ToSchema)]
@@ -29,23 +34,111 @@ impl TaskResponse {
             answers: answers.into_iter().map(|a| AnswerResponse {
                 id: a.id(),
                 answer_text: if hide_text { None } else { Some(a.answer_text().to_string()) },
-            }).collect()
+            }).collect(),
+            served_token: None,
         }
     }
+
+    pub fn with_served_token(mut self, token: String) -> Self {
+        self.served_token = Some(token);
+        self
+    }
+
+    pub fn served_token(&self) -> Option<&str> {
+        self.served_token.as_deref()
+    }
 }
 
+/// Answer fields accepted by the admin routes, the task comes from the path
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct AnswerBody {
+    pub answer_text: String,
+    #[serde(default)]
+    pub image: String,
+    pub is_correct: Option<bool>,
+}
+
+impl AnswerBody {
+    pub fn into_create(self, task_id: Uuid) -> AnswerCreate {
+        AnswerCreate {
+            task_id,
+            answer_text: self.answer_text,
+            image: self.image,
+            is_correct: self.is_correct,
+        }
+    }
+}
 
 // TaskCheck
 #[derive(Serialize, Deserialize, utoipa::ToSchema)]
 pub struct TaskCheckRequest {
-    pub answer_id: Uuid,
+    /// Checked answer, required unless the task is a `multi_select` one
+    pub answer_id: Option<Uuid>,
+    /// Checked task, needed instead of `answer_id` for `multi_select` tasks
+    pub task_id: Option<Uuid>,
     pub task_type: String,
     pub user_answer: Option<String>,
+    /// Every answer picked in a `multi_select` task, ignored by the other types
+    pub answer_ids: Option<Vec<Uuid>>,
+    /// `served_token` the task came with, required when the task has a check delay
+    pub served_token: Option<String>,
 }
 
-#[derive(Serialize, utoipa::ToSchema)]
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
 pub struct TaskCheckResponse {
     pub is_correct: bool,
     pub explanation: String,
+    /// Image of the checked answer, empty when no `answer_id` was sent
     pub image: String,
+    /// Answer to highlight as the right one, never sent for `string_cmp` and `multi_select` tasks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correct_answer_id: Option<Uuid>,
+    /// Every correct answer, only sent for wrong checks of tasks with `reveal_on_fail`
+    /// and never for `string_cmp` tasks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correct_answer_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct RecomputeAttemptsResponse {
+    /// Number of attempts whose correctness changed
+    pub flipped: u64,
+}
+
+#[derive(Deserialize, utoipa::ToSchema)]
+pub struct TasksForLessonsRequest {
+    pub lesson_ids: Vec<Uuid>,
+}
+
+// Offline sync
+/// Attempts are judged by the selected answer. `multi_select` and `string_cmp` tasks can't
+/// be synced, their selection or typed text has to be checked online
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct TaskAttemptSyncItem {
+    /// Generated by the client, sending the same id again is a no-op
+    pub client_id: Uuid,
+    pub task_id: Uuid,
+    pub selected_answer_id: Uuid,
+    pub attempted_at: DateTime<Utc>,
+    /// `served_token` the task came with, required when the task has a check delay
+    pub served_token: Option<String>,
+}
+
+impl From<TaskAttemptSyncItem> for UserTaskAttemptSync {
+    fn from(value: TaskAttemptSyncItem) -> Self {
+        Self {
+            client_id: value.client_id,
+            task_id: value.task_id,
+            selected_answer_id: value.selected_answer_id,
+            attempted_at: value.attempted_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, utoipa::ToSchema)]
+pub struct TaskAttemptSyncResponse {
+    /// Attempts stored by this request
+    pub inserted: u64,
+    /// Attempts skipped because they were synced before
+    pub duplicates: u64,
 }
@@ -1,14 +1,53 @@
-use axum::{Json, http::StatusCode, response::IntoResponse};
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header},
+    response::IntoResponse,
+};
 use thiserror::Error;
 
 use crate::{
     auth::CryptError,
     error::log_error,
     model::{DatabaseError, ResourceType},
+    web::{
+        ApiVersion,
+        middlewares::{current_api_version, current_request_id},
+    },
 };
 
 pub type WebResult<T> = std::result::Result<T, WebError>;
 
+/// Hint for clients when the database can't be reached
+const RETRY_AFTER_SECS: u32 = 5;
+
+/// Stable name of an error, sent as `code` in error bodies. Variants are only ever added, a
+/// client may match on them while the messages change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+pub enum ErrorCode {
+    ResourceNotFound,
+    ResourceForbidden,
+    ResourceConflict,
+    ResourceFetchError,
+    ResourceBadRequest,
+    ResourceLocked,
+    ResourceLimitExceeded,
+    ResourceUnprocessable,
+    AuthenticationCookieNotFound,
+    AuthenticationCookieInvalid,
+    AuthenticationTokenMalformed,
+    AuthenticationRequired,
+    AuthenticationInvalidCredentials,
+    AuthenticationRefreshTokenInvalid,
+    AuthenticationSessionRevoked,
+    RegistrationUserConflict,
+    UserBadRequest,
+    UserUnsupportedMediaType,
+    UserTooManyRequests,
+    UserNotAcceptable,
+    ServerCryptError,
+    ServerIoError,
+}
+
 #[derive(Debug, Error)]
 pub enum RegistrationError {
     #[error("RegistrationUserConflict")]
@@ -16,6 +55,7 @@ pub enum RegistrationError {
 }
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum AuthenticationError {
     #[error("AuthenticationCookieNotFound, cookie: {cookie}")]
     AuthenticationCookieNotFound { cookie: String },
@@ -26,37 +66,86 @@ pub enum AuthenticationError {
         error: jsonwebtoken::errors::Error,
     },
 
+    #[error("AuthenticationTokenMalformed, cookie: {cookie}. Subject: {sub}")]
+    AuthenticationTokenMalformed { cookie: String, sub: String },
+
     #[error("AuthenticationRequired")]
     AuthenticationRequired,
 
     #[error("AuthenticationInvalidCredentials")]
     AuthenticationInvalidCredentials,
+
+    #[error("AuthenticationRefreshTokenInvalid")]
+    AuthenticationRefreshTokenInvalid,
+
+    /// The user's role changed or the account is gone since the token was issued
+    #[error("AuthenticationSessionRevoked")]
+    AuthenticationSessionRevoked,
 }
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum UserError {
     #[error("UserBadRequest, message: {message}")]
-    UserBadRequest { message: String }
+    UserBadRequest { message: String },
+
+    #[error("UserUnsupportedMediaType")]
+    UserUnsupportedMediaType,
+
+    #[error("UserTooManyRequests")]
+    UserTooManyRequests,
+
+    /// `Accept` asks for an API version that doesn't exist
+    #[error("UserNotAcceptable, message: {message}")]
+    UserNotAcceptable { message: String },
 }
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum ResourceError {
-    #[error("ResourceNotFound: {resource_type:?}")]
+    #[error("ResourceNotFound: {resource_type}")]
     ResourceNotFound { resource_type: ResourceType },
 
-    #[error("ResourceForbidden: {resource_type:?}")]
+    #[error("ResourceForbidden: {resource_type}")]
     ResourceForbidden { resource_type: ResourceType },
 
-    #[error("ResourceFetchError: {resource_type:?}. Error: {error}")]
+    #[error("ResourceConflict: {resource_type}, constraint: {constraint}")]
+    ResourceConflict {
+        resource_type: ResourceType,
+        constraint: String,
+    },
+
+    #[error("ResourceFetchError: {resource_type}. Error: {error}")]
     ResourceFetchError {
         resource_type: ResourceType,
         error: DatabaseError,
     },
 
-    #[error("ResourceBadRequest: {resource_type:?}")]
+    #[error("ResourceBadRequest: {resource_type}, message: {message}")]
     ResourceBadRequest {
         resource_type: ResourceType,
-        // TODO: Maybe some string of details
+        message: String,
+    },
+
+    /// Resource exists but the user hasn't unlocked it yet
+    #[error("ResourceLocked: {resource_type}, reason: {reason}")]
+    ResourceLocked {
+        resource_type: ResourceType,
+        reason: String,
+    },
+
+    /// User already holds as many of these as allowed
+    #[error("ResourceLimitExceeded: {resource_type}, message: {message}")]
+    ResourceLimitExceeded {
+        resource_type: ResourceType,
+        message: String,
+    },
+
+    /// Request is well-formed but breaks a business rule
+    #[error("ResourceUnprocessable: {resource_type}, message: {message}")]
+    ResourceUnprocessable {
+        resource_type: ResourceType,
+        message: String,
     },
 }
 
@@ -73,6 +162,13 @@ impl ServerError {
         StatusCode::INTERNAL_SERVER_ERROR
     }
 
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ServerCryptError(_) => ErrorCode::ServerCryptError,
+            Self::ServerIoError(_) => ErrorCode::ServerIoError,
+        }
+    }
+
     pub fn client_display(&self) -> String {
         String::from("Internal server error.")
     }
@@ -85,6 +181,12 @@ impl RegistrationError {
         }
     }
 
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::RegistrationUserConflict => ErrorCode::RegistrationUserConflict,
+        }
+    }
+
     pub fn client_display(&self) -> String {
         match self {
             Self::RegistrationUserConflict => {
@@ -101,6 +203,21 @@ impl AuthenticationError {
             Self::AuthenticationCookieNotFound { .. } => StatusCode::NOT_FOUND,
             Self::AuthenticationInvalidCredentials => StatusCode::UNAUTHORIZED,
             Self::AuthenticationCookieInvalid { .. } => StatusCode::BAD_REQUEST,
+            Self::AuthenticationTokenMalformed { .. } => StatusCode::BAD_REQUEST,
+            Self::AuthenticationRefreshTokenInvalid => StatusCode::UNAUTHORIZED,
+            Self::AuthenticationSessionRevoked => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::AuthenticationCookieNotFound { .. } => ErrorCode::AuthenticationCookieNotFound,
+            Self::AuthenticationCookieInvalid { .. } => ErrorCode::AuthenticationCookieInvalid,
+            Self::AuthenticationTokenMalformed { .. } => ErrorCode::AuthenticationTokenMalformed,
+            Self::AuthenticationRequired => ErrorCode::AuthenticationRequired,
+            Self::AuthenticationInvalidCredentials => ErrorCode::AuthenticationInvalidCredentials,
+            Self::AuthenticationRefreshTokenInvalid => ErrorCode::AuthenticationRefreshTokenInvalid,
+            Self::AuthenticationSessionRevoked => ErrorCode::AuthenticationSessionRevoked,
         }
     }
 
@@ -112,10 +229,19 @@ impl AuthenticationError {
             Self::AuthenticationCookieNotFound { .. } => {
                 String::from("Authentication error, cookie not found.")
             }
+            Self::AuthenticationTokenMalformed { .. } => {
+                String::from("Authentication error, token malformed.")
+            }
             Self::AuthenticationRequired => String::from("Authentication required."),
             Self::AuthenticationInvalidCredentials => {
                 String::from("Authentication error, user not found or password is invalid.")
             }
+            Self::AuthenticationRefreshTokenInvalid => {
+                String::from("Authentication error, refresh token expired or already used.")
+            }
+            Self::AuthenticationSessionRevoked => {
+                String::from("Authentication error, session revoked, sign in again.")
+            }
         }
     }
 }
@@ -124,6 +250,18 @@ impl UserError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             Self::UserBadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::UserUnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::UserTooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            Self::UserNotAcceptable { .. } => StatusCode::NOT_ACCEPTABLE,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::UserBadRequest { .. } => ErrorCode::UserBadRequest,
+            Self::UserUnsupportedMediaType => ErrorCode::UserUnsupportedMediaType,
+            Self::UserTooManyRequests => ErrorCode::UserTooManyRequests,
+            Self::UserNotAcceptable { .. } => ErrorCode::UserNotAcceptable,
         }
     }
 
@@ -132,6 +270,11 @@ impl UserError {
             Self::UserBadRequest { message } => {
                 format!("Bad request: {message}")
             }
+            Self::UserUnsupportedMediaType => {
+                String::from("Unsupported media type, expected `Content-Type: application/json`.")
+            }
+            Self::UserTooManyRequests => String::from("Too many requests, try again later."),
+            Self::UserNotAcceptable { message } => format!("Not acceptable: {message}"),
         }
     }
 }
@@ -141,8 +284,33 @@ impl ResourceError {
         match self {
             Self::ResourceNotFound { .. } => StatusCode::NOT_FOUND,
             Self::ResourceForbidden { .. } => StatusCode::FORBIDDEN,
+            Self::ResourceConflict { .. } => StatusCode::CONFLICT,
+            Self::ResourceLocked { .. } => StatusCode::FORBIDDEN,
+            Self::ResourceFetchError {
+                error: DatabaseError::Unavailable(_),
+                ..
+            } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::ResourceFetchError {
+                error: DatabaseError::ConstraintViolation { .. },
+                ..
+            } => StatusCode::BAD_REQUEST,
             Self::ResourceFetchError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             Self::ResourceBadRequest { .. } => StatusCode::BAD_REQUEST,
+            Self::ResourceUnprocessable { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::ResourceLimitExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ResourceNotFound { .. } => ErrorCode::ResourceNotFound,
+            Self::ResourceForbidden { .. } => ErrorCode::ResourceForbidden,
+            Self::ResourceConflict { .. } => ErrorCode::ResourceConflict,
+            Self::ResourceFetchError { .. } => ErrorCode::ResourceFetchError,
+            Self::ResourceBadRequest { .. } => ErrorCode::ResourceBadRequest,
+            Self::ResourceLocked { .. } => ErrorCode::ResourceLocked,
+            Self::ResourceLimitExceeded { .. } => ErrorCode::ResourceLimitExceeded,
+            Self::ResourceUnprocessable { .. } => ErrorCode::ResourceUnprocessable,
         }
     }
 
@@ -150,10 +318,30 @@ impl ResourceError {
         match self {
             Self::ResourceNotFound { .. } => String::from("Resource error, resource not found."),
             Self::ResourceForbidden { .. } => String::from("Resource error, resource forbidden."),
+            Self::ResourceConflict { .. } => String::from("Resource error, resource already exists."),
+            Self::ResourceLocked { reason, .. } => {
+                format!("Resource error, resource locked: {reason}")
+            }
+            Self::ResourceFetchError {
+                error: DatabaseError::Unavailable(_),
+                ..
+            } => String::from("Database unavailable, try again later."),
+            Self::ResourceFetchError {
+                error: DatabaseError::ConstraintViolation { .. },
+                ..
+            } => String::from("Resource error, bad request."),
             Self::ResourceFetchError { .. } => {
                 String::from("Resource error, unable to fetch resource.")
             }
-            Self::ResourceBadRequest { .. } => String::from("Resource error, bad request."),
+            Self::ResourceBadRequest { message, .. } => {
+                format!("Resource error, bad request: {message}")
+            }
+            Self::ResourceUnprocessable { message, .. } => {
+                format!("Resource error, unprocessable: {message}")
+            }
+            Self::ResourceLimitExceeded { message, .. } => {
+                format!("Resource error, limit exceeded: {message}")
+            }
         }
     }
 }
@@ -185,16 +373,50 @@ impl WebError {
         })
     }
 
-    pub fn resource_fetch_error(r#type: ResourceType, error: DatabaseError) -> Self {
-        Self::ResourceError(ResourceError::ResourceFetchError {
+    pub fn resource_locked<S: Into<String>>(r#type: ResourceType, reason: S) -> Self {
+        Self::ResourceError(ResourceError::ResourceLocked {
             resource_type: r#type,
-            error,
+            reason: reason.into(),
         })
     }
 
-    pub fn resource_bad_request(r#type: ResourceType) -> Self {
+    /// Duplicates become `409`, the user ones keep the signup wording
+    pub fn resource_fetch_error(r#type: ResourceType, error: DatabaseError) -> Self {
+        match error {
+            DatabaseError::UniqueViolation { .. } if matches!(r#type, ResourceType::User) => {
+                Self::registration_conflict()
+            }
+            DatabaseError::UniqueViolation { constraint } => {
+                Self::ResourceError(ResourceError::ResourceConflict {
+                    resource_type: r#type,
+                    constraint,
+                })
+            }
+            error => Self::ResourceError(ResourceError::ResourceFetchError {
+                resource_type: r#type,
+                error,
+            }),
+        }
+    }
+
+    pub fn resource_bad_request<S: Into<String>>(r#type: ResourceType, message: S) -> Self {
         Self::ResourceError(ResourceError::ResourceBadRequest {
             resource_type: r#type,
+            message: message.into(),
+        })
+    }
+
+    pub fn resource_unprocessable<S: Into<String>>(r#type: ResourceType, message: S) -> Self {
+        Self::ResourceError(ResourceError::ResourceUnprocessable {
+            resource_type: r#type,
+            message: message.into(),
+        })
+    }
+
+    pub fn resource_limit_exceeded<S: Into<String>>(r#type: ResourceType, message: S) -> Self {
+        Self::ResourceError(ResourceError::ResourceLimitExceeded {
+            resource_type: r#type,
+            message: message.into(),
         })
     }
 
@@ -214,6 +436,14 @@ impl WebError {
         })
     }
 
+    /// Token with a valid signature whose subject isn't a user id
+    pub fn auth_token_malformed<S: Into<String>>(cookie: S, sub: String) -> Self {
+        Self::AuthenticationError(AuthenticationError::AuthenticationTokenMalformed {
+            cookie: cookie.into(),
+            sub,
+        })
+    }
+
     pub fn auth_required() -> Self {
         Self::AuthenticationError(AuthenticationError::AuthenticationRequired)
     }
@@ -222,6 +452,14 @@ impl WebError {
         Self::AuthenticationError(AuthenticationError::AuthenticationInvalidCredentials)
     }
 
+    pub fn auth_refresh_token_invalid() -> Self {
+        Self::AuthenticationError(AuthenticationError::AuthenticationRefreshTokenInvalid)
+    }
+
+    pub fn auth_session_revoked() -> Self {
+        Self::AuthenticationError(AuthenticationError::AuthenticationSessionRevoked)
+    }
+
     pub fn registration_conflict() -> Self {
         Self::RegistrationError(RegistrationError::RegistrationUserConflict)
     }
@@ -234,10 +472,24 @@ impl WebError {
         Self::ServerError(ServerError::ServerIoError(e))
     }
 
+    /// `400` with `msg` shown to the client as it is, so keep it to validation text and never
+    /// pass error details of the database or the server
     pub fn user_bad_request(msg: String) -> Self {
         Self::UserError(UserError::UserBadRequest { message: msg })
     }
 
+    pub fn user_unsupported_media_type() -> Self {
+        Self::UserError(UserError::UserUnsupportedMediaType)
+    }
+
+    pub fn user_too_many_requests() -> Self {
+        Self::UserError(UserError::UserTooManyRequests)
+    }
+
+    pub fn user_not_acceptable(msg: String) -> Self {
+        Self::UserError(UserError::UserNotAcceptable { message: msg })
+    }
+
     pub fn status_code(&self) -> axum::http::StatusCode {
         match self {
             Self::ResourceError(e) => e.status_code(),
@@ -248,6 +500,17 @@ impl WebError {
         }
     }
 
+    /// Stable name of the error for clients to match on, sent from API `v2` on
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ResourceError(e) => e.code(),
+            Self::RegistrationError(e) => e.code(),
+            Self::AuthenticationError(e) => e.code(),
+            Self::ServerError(e) => e.code(),
+            Self::UserError(e) => e.code(),
+        }
+    }
+
     pub fn client_display(&self) -> String {
         match self {
             Self::ResourceError(e) => e.client_display(),
@@ -267,6 +530,11 @@ pub struct ErrorResponse {
     pub status_code: String,
     /// Optional debug details (only in debug mode)
     pub details: Option<String>,
+    /// Id of the failed request, same as its `X-Request-Id` header, to find it in the logs
+    pub request_id: Option<String>,
+    /// Stable name of the error, e.g. `ResourceNotFound`. Only in API `v2` and later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
 }
 
 impl IntoResponse for WebError {
@@ -284,8 +552,67 @@ impl IntoResponse for WebError {
             } else {
                 None
             },
+            request_id: current_request_id(),
+            code: (current_api_version() >= ApiVersion::V2).then(|| self.code()),
         };
 
-        (status_code, Json(body)).into_response()
+        let mut response = (status_code, Json(body)).into_response();
+        if status_code == StatusCode::SERVICE_UNAVAILABLE {
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(RETRY_AFTER_SECS),
+            );
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_code_test() {
+        let resource = ResourceType::Lesson;
+        let jwt = || jsonwebtoken::errors::Error::from(jsonwebtoken::errors::ErrorKind::InvalidToken);
+        let cases = [
+            (WebError::resource_not_found(resource), "ResourceNotFound"),
+            (WebError::resource_forbidden(resource), "ResourceForbidden"),
+            (
+                WebError::resource_fetch_error(resource, DatabaseError::UniqueViolation { constraint: "c".into() }),
+                "ResourceConflict",
+            ),
+            (WebError::resource_fetch_error(resource, DatabaseError::Forbidden), "ResourceFetchError"),
+            (WebError::resource_bad_request(resource, "bad"), "ResourceBadRequest"),
+            (WebError::resource_locked(resource, "locked"), "ResourceLocked"),
+            (WebError::resource_limit_exceeded(resource, "limit"), "ResourceLimitExceeded"),
+            (WebError::resource_unprocessable(resource, "rule"), "ResourceUnprocessable"),
+            (WebError::auth_cookie_not_found("SID"), "AuthenticationCookieNotFound"),
+            (WebError::auth_cookie_invalid("SID", jwt()), "AuthenticationCookieInvalid"),
+            (WebError::auth_token_malformed("SID", "sub".into()), "AuthenticationTokenMalformed"),
+            (WebError::auth_required(), "AuthenticationRequired"),
+            (WebError::auth_invalid_credentials(), "AuthenticationInvalidCredentials"),
+            (WebError::auth_refresh_token_invalid(), "AuthenticationRefreshTokenInvalid"),
+            (WebError::auth_session_revoked(), "AuthenticationSessionRevoked"),
+            (WebError::registration_conflict(), "RegistrationUserConflict"),
+            (
+                WebError::resource_fetch_error(
+                    ResourceType::User,
+                    DatabaseError::UniqueViolation { constraint: "c".into() },
+                ),
+                "RegistrationUserConflict",
+            ),
+            (WebError::user_bad_request("bad".into()), "UserBadRequest"),
+            (WebError::user_unsupported_media_type(), "UserUnsupportedMediaType"),
+            (WebError::user_too_many_requests(), "UserTooManyRequests"),
+            (WebError::user_not_acceptable("v9".into()), "UserNotAcceptable"),
+            (WebError::server_crypt_error(CryptError::from(jwt())), "ServerCryptError"),
+            (WebError::server_io_error(std::io::Error::other("io")), "ServerIoError"),
+        ];
+
+        for (error, expected) in cases {
+            let code = serde_json::to_value(error.code()).unwrap();
+            assert_eq!(code, expected, "{error}");
+        }
     }
 }
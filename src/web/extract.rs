@@ -0,0 +1,52 @@
+use axum::{
+    Json,
+    extract::{FromRequest, OptionalFromRequest, Request, rejection::JsonRejection},
+};
+use serde::de::DeserializeOwned;
+
+use crate::web::WebError;
+
+/// Drop-in replacement for `axum::Json` on request bodies.
+///
+/// Rejections are turned into regular `WebError`s so clients always get the JSON error shape:
+/// a missing or non-JSON `Content-Type` is a 415, anything else wrong with the body is a 400.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonBody<T>(pub T);
+
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = WebError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match <Json<T> as FromRequest<S>>::from_request(req, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(JsonRejection::MissingJsonContentType(_)) => {
+                Err(WebError::user_unsupported_media_type())
+            }
+            Err(e) => Err(WebError::user_bad_request(e.body_text())),
+        }
+    }
+}
+
+/// `Option<JsonBody<T>>` is `None` for requests without a `Content-Type`, e.g. an empty body.
+/// Bodies that are sent are rejected the same way as with `JsonBody<T>`.
+impl<T, S> OptionalFromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = WebError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Option<Self>, Self::Rejection> {
+        match <Json<T> as OptionalFromRequest<S>>::from_request(req, state).await {
+            Ok(value) => Ok(value.map(|Json(value)| Self(value))),
+            Err(JsonRejection::MissingJsonContentType(_)) => {
+                Err(WebError::user_unsupported_media_type())
+            }
+            Err(e) => Err(WebError::user_bad_request(e.body_text())),
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use axum::{
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::web::{ApiVersion, WebError};
+
+tokio::task_local! {
+    static API_VERSION: ApiVersion;
+}
+
+/// Version the client asked for, the latest one outside of `api_version_fn`
+pub fn current_api_version() -> ApiVersion {
+    API_VERSION.try_with(|version| *version).unwrap_or(ApiVersion::LATEST)
+}
+
+/// Picks the response version from the `Accept` header. Handlers read it with the
+/// `ApiVersion` extractor, error bodies through `current_api_version`.
+pub async fn api_version_fn(mut req: Request, next: Next) -> Response {
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    let version = match ApiVersion::negotiate(accept) {
+        Ok(version) => version,
+        Err(requested) => {
+            return WebError::user_not_acceptable(format!(
+                "unsupported API version `v{requested}`, the latest is `v{}`",
+                ApiVersion::LATEST.number()
+            ))
+            .into_response();
+        }
+    };
+
+    req.extensions_mut().insert(version);
+    API_VERSION.scope(version, next.run(req)).await
+}
@@ -1,17 +1,19 @@
 use axum::{
     extract::{Request, State},
+    http::header::AUTHORIZATION,
     middleware::Next,
     response::Response,
 };
 use tower_cookies::Cookies;
 
 use crate::{
-    Config, auth,
+    auth,
     model::{CrudRepository, ResourceTyped, entity::UserEntity},
-    web::{AppState, RequestContext, context::AuthenticatedUser, error::WebError},
+    web::{AppState, RequestContext, UserRole, context::AuthenticatedUser, error::WebError},
 };
 
 pub static AUTH_TOKEN: &str = "SID";
+pub static REFRESH_TOKEN: &str = "RID";
 
 pub async fn extract_context_fn(
     State(state): State<AppState>,
@@ -19,24 +21,41 @@ pub async fn extract_context_fn(
     mut req: Request,
     next: Next,
 ) -> Result<Response, WebError> {
-    let token = match cookies.get(AUTH_TOKEN) {
-        Some(token) => token,
-        None => {
-            req.extensions_mut().insert(RequestContext::new(None));
-            return Ok(next.run(req).await);
-        }
+    // the cookie wins, the header is there for clients that can't keep cookies
+    let (token, source) = match cookies.get(AUTH_TOKEN) {
+        Some(cookie) => (cookie.value().to_string(), AUTH_TOKEN),
+        None => match bearer_token(&req) {
+            Some(token) => (token.to_string(), AUTHORIZATION.as_str()),
+            None => {
+                req.extensions_mut().insert(RequestContext::new(None));
+                return Ok(next.run(req).await);
+            }
+        },
     };
 
-    let claims = auth::process_token(token.value(), Config::get_or_init().await.app().jwt())
-        .map_err(|e| WebError::auth_cookie_invalid(AUTH_TOKEN, e))?;
+    let claims = auth::process_token(&token, state.config().app().jwt())
+        .map_err(|e| WebError::auth_cookie_invalid(source, e))?;
 
+    // only we sign tokens, but a leaked secret or an old format must not crash the request
     let id = claims
         .claims
         .sub
         .parse::<uuid::Uuid>()
-        .expect("Unable to parse `sub` in token");
+        .map_err(|_| WebError::auth_token_malformed(source, claims.claims.sub.clone()))?;
+
+    // the role in the token goes stale once it changes or the user is deleted
+    if state.sessions().is_revoked(id, claims.claims.issued_at) {
+        return Err(WebError::auth_session_revoked());
+    }
 
-    let role = UserEntity::find_by_id(state.pool(), &AuthenticatedUser::admin(), id)
+    // tokens carry the role, only older ones without it need the lookup
+    if let Some(role) = claims.claims.role.as_deref() {
+        let user = AuthenticatedUser::new(id, UserRole::from(role));
+        req.extensions_mut().insert(RequestContext::new(Some(user)));
+        return Ok(next.run(req).await);
+    }
+
+    let role = UserEntity::find_by_id(state.pool(), &AuthenticatedUser::system(), id)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
@@ -54,3 +73,13 @@ pub async fn extract_context_fn(
         }
     }
 }
+
+/// Token from an `Authorization: Bearer <token>` header, if there is one
+fn bearer_token(req: &Request) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
@@ -0,0 +1,44 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{Config, web::middlewares::current_request_id};
+
+/// Logs method, path, status and latency of every request outside `app.log_exclude_paths`
+pub async fn log_request_fn(
+    State(config): State<&'static Config>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let path = req.uri().path().to_string();
+    if is_excluded(&path, config.app().log_exclude_paths()) {
+        return next.run(req).await;
+    }
+
+    let method = req.method().clone();
+    let started = Instant::now();
+    let response = next.run(req).await;
+
+    tracing::info!(
+        %method,
+        path,
+        status = response.status().as_u16(),
+        latency_ms = started.elapsed().as_millis() as u64,
+        request_id = current_request_id().as_deref().unwrap_or("-"),
+        "request"
+    );
+    response
+}
+
+/// An excluded path also covers everything below it, `/metrics` skips `/metrics/db` too
+fn is_excluded(path: &str, excluded: &[String]) -> bool {
+    excluded.iter().any(|prefix| {
+        let prefix = prefix.trim_end_matches('/');
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
@@ -1,2 +1,16 @@
 mod auth;
-pub use auth::{AUTH_TOKEN, extract_context_fn};
+pub use auth::{AUTH_TOKEN, REFRESH_TOKEN, extract_context_fn};
+
+mod log;
+pub use log::log_request_fn;
+
+mod api_version;
+pub use api_version::{api_version_fn, current_api_version};
+
+mod request_id;
+pub use request_id::{RequestId, X_REQUEST_ID, current_request_id, request_id_fn};
+
+mod rate_limit;
+pub use rate_limit::{
+    X_RATELIMIT_LIMIT, X_RATELIMIT_REMAINING, X_RATELIMIT_RESET, auth_rate_limit_fn, rate_limit_fn,
+};
@@ -0,0 +1,71 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::web::{AppState, RateLimitStatus, RateLimiter, WebError};
+
+pub const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+pub const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+pub const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Counts the request against the client's budget and reports it in `X-RateLimit-*` headers.
+/// Over the limit the request is answered with `429` and `Retry-After` instead.
+pub async fn rate_limit_fn(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let limiter = state.rate_limiter();
+    if !limiter.is_enabled() {
+        return next.run(req).await;
+    }
+
+    let (status, reset_secs) = check(limiter, &req);
+    let mut response = if status.allowed {
+        next.run(req).await
+    } else {
+        too_many_requests(reset_secs)
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(status.limit));
+    headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(status.remaining));
+    headers.insert(X_RATELIMIT_RESET, HeaderValue::from(reset_secs));
+    response
+}
+
+/// Throttles sign-in and sign-up against the auth budget, so passwords can't be guessed at the
+/// pace of the general limit. Only answers with `429` and `Retry-After`, the `X-RateLimit-*`
+/// headers keep describing the general budget.
+pub async fn auth_rate_limit_fn(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let limiter = state.auth_rate_limiter();
+    if !limiter.is_enabled() {
+        return next.run(req).await;
+    }
+
+    match check(limiter, &req) {
+        (status, _) if status.allowed => next.run(req).await,
+        (_, reset_secs) => too_many_requests(reset_secs),
+    }
+}
+
+/// Counts the request and returns the budget with the seconds until it's restored
+fn check(limiter: &RateLimiter, req: &Request) -> (RateLimitStatus, u64) {
+    let client = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let status = limiter.check(client);
+    // rounded up, so waiting that long always restores the budget
+    let reset_secs = status.reset.as_secs() + u64::from(status.reset.subsec_nanos() > 0);
+    (status, reset_secs)
+}
+
+fn too_many_requests(reset_secs: u64) -> Response {
+    let mut response = WebError::user_too_many_requests().into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(reset_secs));
+    response
+}
@@ -1,14 +1,29 @@
 mod context;
-pub use context::{AuthenticatedUser, RequestContext, UserRole};
+pub use context::{AdminUser, AuthenticatedUser, AuthorUser, RequestContext, UnknownUserRole, UserRole};
 
 mod error;
-pub use error::{WebError, WebResult};
+pub use error::{ErrorCode, WebError, WebResult};
+
+mod extract;
+pub use extract::JsonBody;
+
+mod version;
+pub use version::ApiVersion;
 
 pub mod middlewares;
 
 mod state;
 pub use state::AppState;
 
+mod cache;
+pub use cache::ModuleCache;
+
+mod rate_limit;
+pub use rate_limit::{RateLimitStatus, RateLimiter};
+
+mod sessions;
+pub use sessions::SessionRevocations;
+
 pub mod routes;
 
 pub mod dto;
@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// In-memory fixed-window request counter per client IP.
+///
+/// Requests without a known peer address (e.g. in-process test servers) share one budget.
+/// A limit of `0` turns the limiter off.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    clients: Mutex<HashMap<Option<IpAddr>, (Instant, u32)>>,
+}
+
+/// Budget of a client after counting one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until the budget is restored
+    pub reset: Duration,
+    /// `false` when the request is over the limit and should be rejected
+    pub allowed: bool,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.limit > 0
+    }
+
+    /// Counts a request of `client` against its budget
+    pub fn check(&self, client: Option<IpAddr>) -> RateLimitStatus {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|_, (started_at, _)| now.duration_since(*started_at) < self.window);
+
+        let (started_at, count) = clients.entry(client).or_insert((now, 0));
+        *count = count.saturating_add(1);
+
+        RateLimitStatus {
+            limit: self.limit,
+            remaining: self.limit.saturating_sub(*count),
+            reset: self.window.saturating_sub(now.duration_since(*started_at)),
+            allowed: *count <= self.limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_test() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let client = Some(IpAddr::from([127, 0, 0, 1]));
+
+        let first = limiter.check(client);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert!(first.reset <= Duration::from_secs(60));
+
+        assert_eq!(limiter.check(client).remaining, 0);
+        let over = limiter.check(client);
+        assert!(!over.allowed);
+        assert_eq!(over.remaining, 0);
+
+        // other clients have their own budget
+        assert!(limiter.check(Some(IpAddr::from([10, 0, 0, 1]))).allowed);
+        assert!(limiter.check(None).allowed);
+    }
+
+    #[test]
+    fn rate_limiter_window_test() {
+        let limiter = RateLimiter::new(1, Duration::ZERO);
+        // every request starts a fresh window
+        assert!(limiter.check(None).allowed);
+        assert!(limiter.check(None).allowed);
+    }
+}
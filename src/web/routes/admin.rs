@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    middleware,
+    response::IntoResponse,
+    routing::{delete, get, post},
+};
+use uuid::Uuid;
+
+use crate::{
+    model::{
+        ResourceTyped,
+        CrudRepository,
+        entity::{Answer, LessonTask, TaskTypeCountRow, UserEntity, UserModuleProgress},
+    },
+    web::{
+        AdminUser, AppState, WebError, WebResult,
+        dto::{
+            admin::{GcUploadsResponse, RebuildProgressCacheResponse},
+            progress::{ProgressResetQuery, ProgressResetResponse},
+        },
+        error::ErrorResponse, middlewares,
+    },
+    utils::uploads,
+};
+
+pub fn routes<S>(state: AppState) -> Router<S> {
+    Router::new()
+        .route("/tasks-by-type", get(admin_tasks_by_type_handler))
+        .route("/rebuild-progress-cache", post(admin_rebuild_progress_cache_handler))
+        .route("/gc-uploads", post(admin_gc_uploads_handler))
+        .route("/users/{id}/progress", delete(admin_reset_user_progress_handler))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middlewares::extract_context_fn,
+        ))
+        .with_state(state)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/tasks-by-type",
+    description = "Count tasks of every type across all lessons. Admin only",
+    responses(
+        (status = 200, description = "Successfully counted tasks", body = Vec<TaskTypeCountRow>),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "admin",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn admin_tasks_by_type_handler(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let counts = TaskTypeCountRow::fetch_all(state.pool(), &user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(counts)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/rebuild-progress-cache",
+    description = "Recompute the per module progress cache of every user from scratch. Admin only",
+    responses(
+        (status = 200, description = "Cache rebuilt", body = RebuildProgressCacheResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "admin",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn admin_rebuild_progress_cache_handler(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let rows = UserModuleProgress::rebuild(state.pool(), &user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserModuleProgress::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(RebuildProgressCacheResponse { rows })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/gc-uploads",
+    description = "Remove files from `uploads/` that no answer references and that are older than `uploads_gc_grace_secs`. Admin only",
+    responses(
+        (status = 200, description = "Orphaned uploads removed", body = GcUploadsResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "admin",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn admin_gc_uploads_handler(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let referenced: HashSet<String> = Answer::referenced_images(state.pool(), &user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?
+        .into_iter()
+        .map(|image| image.trim_start_matches('/').to_string())
+        .collect();
+
+    let dir = state.config().app().uploads_dir().to_path_buf();
+    let grace = state.config().app().uploads_gc_grace();
+    let removed =
+        tokio::task::spawn_blocking(move || uploads::remove_orphans(&dir, &referenced, grace))
+            .await
+            .map_err(|e| WebError::server_io_error(e.into()))?
+            .map_err(WebError::server_io_error)?;
+    if !removed.is_empty() {
+        tracing::info!("uploads gc: removed {} orphaned files", removed.len());
+    }
+
+    Ok((StatusCode::OK, Json(GcUploadsResponse { removed })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/admin/users/{user_id}/progress",
+    description = "Clears another user's lesson progress and task attempts. Admin only, users reset themselves through `/api/v1/progress/`",
+    params(
+        ("user_id" = Uuid, Path, description = "ID of the user to reset"),
+        ("module_id" = Option<Uuid>, Query, description = "Only reset this module's lessons"),
+    ),
+    responses(
+        (status = 200, description = "Progress reset", body = ProgressResetResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 404, description = "User or module not found", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "admin",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn admin_reset_user_progress_handler(
+    AdminUser(user): AdminUser,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ProgressResetQuery>,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    UserEntity::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(UserEntity::get_resource_type()))?;
+
+    let reset = super::progress::reset_progress(&state, &user, id, query.module_id).await?;
+
+    Ok((StatusCode::OK, Json(reset)))
+}
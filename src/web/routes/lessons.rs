@@ -1,20 +1,33 @@
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::routing::post;
 use axum::Json;
 use axum::{extract::State, middleware, response::IntoResponse, routing::get, Router};
 use axum::http::StatusCode;
 use uuid::Uuid;
 
-use crate::model::entity::{Answer, Lesson, LessonTask, LessonWithStatusRow, UserProgress, UserProgressCreate};
-use crate::model::{CrudRepository, ResourceTyped};
-use crate::web::dto::lessons::LessonResponse;
+use crate::model::entity::{Lesson, LessonCreate, LessonSearchRow, LessonTask, LessonWithStatusRow, Module, ModuleGateRow, ProgressStatus, UserProgress, UserProgressCreate};
+use crate::model::{CrudRepository, Page, ResourceTyped};
+use crate::web::dto::lessons::{LessonHtmlResponse, LessonResponse, LessonSearchHit, LessonSearchQuery, LessonStatusResponse};
+use crate::web::routes::PaginationQuery;
 use crate::web::dto::tasks::TaskResponse;
 use crate::web::error::ErrorResponse;
-use crate::web::{middlewares, AppState, RequestContext, WebError, WebResult};
+use crate::web::{middlewares, AdminUser, AppState, AuthenticatedUser, AuthorUser, JsonBody, RequestContext, WebError, WebResult};
+
+/// Most search results a single page may hold
+const MAX_SEARCH_LIMIT: i64 = 50;
 
 pub fn routes<S>(state: AppState) -> Router<S> {
     Router::new()
-        .route("/{id}", get(lessons_get_handler))
+        .route("/", post(lessons_create_handler))
+        .route("/search", get(lessons_search_handler))
+        .route(
+            "/{id}",
+            get(lessons_get_handler)
+                .put(lessons_update_handler)
+                .delete(lessons_delete_handler),
+        )
+        .route("/{id}/html", get(lessons_get_html_handler))
+        .route("/{id}/status", get(lessons_get_status_handler))
         .route("/{id}/done", post(lessons_mark_done_handler))
         .route("/{id}/tasks", get(lessons_get_tasks_handler))
         .route("/{id}/next", get(lessons_get_next_handler))
@@ -36,6 +49,7 @@ pub fn routes<S>(state: AppState) -> Router<S> {
         (status = 200, description = "Lesson found", body = LessonResponse),
         (status = 404, description = "Lesson not found", body = ErrorResponse),
         (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "Previous module isn't completed enough yet", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     ),
     security(
@@ -48,15 +62,241 @@ async fn lessons_get_handler(
     Path(id): Path<Uuid>,
     ctx: RequestContext,
 ) -> WebResult<impl IntoResponse> {
-    let user = ctx.user()?; 
-    let lesson = LessonWithStatusRow::find_by_id(state.pool(), &user, id)
+    let user = ctx.user()?;
+    let lesson = find_readable(&state, user, id).await?;
+
+    Ok((StatusCode::OK, Json(lesson)))
+}
+
+/// Lesson `user` may read, locked ones are a `ResourceLocked` unless the user is an author
+async fn find_readable(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    id: Uuid,
+) -> WebResult<LessonResponse> {
+    let lesson = LessonWithStatusRow::find_by_id(state.pool(), user, id)
         .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
         .map(LessonResponse::from)
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+    ensure_unlocked(state, user, id).await?;
+
+    Ok(lesson)
+}
+
+/// `ResourceLocked` when the lesson's module is gated for `user`, authors can read ahead
+pub(crate) async fn ensure_unlocked(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    lesson_id: Uuid,
+) -> WebResult<()> {
+    if user.user_role().can_author() {
+        return Ok(());
+    }
+
+    let gate = ModuleGateRow::find_by_lesson(state.pool(), user, lesson_id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
+    if let Some(gate) = gate.filter(ModuleGateRow::is_locked) {
+        return Err(WebError::resource_locked(
+            Lesson::get_resource_type(),
+            format!(
+                "complete at least {}% of the previous module first, {:.0}% done",
+                gate.required_percent, gate.completed_percent
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/lessons/{lesson_id}/html",
+    description = "Lesson content rendered from markdown to sanitized HTML. Scripts, event handlers and `javascript:` links are removed",
+    params(
+        ("lesson_id" = Uuid, Path, description = "ID of the lesson to render")
+    ),
+    responses(
+        (status = 200, description = "Lesson rendered", body = LessonHtmlResponse),
+        (status = 404, description = "Lesson not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "Previous module isn't completed enough yet", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "lessons"
+)]
+async fn lessons_get_html_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    ctx: RequestContext,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let lesson = find_readable(&state, user, id).await?;
+
+    Ok((StatusCode::OK, Json(LessonHtmlResponse::from(lesson))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/lessons/",
+    description = "Creates a lesson in the given module. Admin or instructor",
+    request_body = LessonCreate,
+    responses(
+        (status = 201, description = "Lesson created", body = Lesson,
+            headers(("Location" = String, description = "Path of the new lesson"))),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "lessons"
+)]
+async fn lessons_create_handler(
+    State(state): State<AppState>,
+    AuthorUser(user): AuthorUser,
+    JsonBody(payload): JsonBody<LessonCreate>,
+) -> WebResult<impl IntoResponse> {
+    ensure_module_exists(&state, &user, payload.module_id).await?;
+    let lesson = Lesson::create(state.pool(), &user, payload)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?;
+    state.module_cache().invalidate_all();
+
+    Ok(super::created(format!("/api/v1/lessons/{}", lesson.id()), lesson))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/lessons/{lesson_id}",
+    description = "Replaces the lesson, it can be moved to another module too. Admin or instructor",
+    params(
+        ("lesson_id" = Uuid, Path, description = "ID of the lesson to update")
+    ),
+    request_body = LessonCreate,
+    responses(
+        (status = 200, description = "Lesson updated", body = Lesson),
+        (status = 404, description = "Lesson or module not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "lessons"
+)]
+async fn lessons_update_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    AuthorUser(user): AuthorUser,
+    JsonBody(payload): JsonBody<LessonCreate>,
+) -> WebResult<impl IntoResponse> {
+    let lesson = Lesson::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+
+    ensure_module_exists(&state, &user, payload.module_id).await?;
+    let lesson = lesson
+        .update(state.pool(), &user, payload)
+        .await
         .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?;
+    state.module_cache().invalidate_all();
 
     Ok((StatusCode::OK, Json(lesson)))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/v1/lessons/{lesson_id}",
+    description = "Deletes the lesson together with its tasks and everyone's progress on it. Admin only",
+    params(
+        ("lesson_id" = Uuid, Path, description = "ID of the lesson to delete")
+    ),
+    responses(
+        (status = 200, description = "Lesson deleted"),
+        (status = 404, description = "Lesson not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "lessons"
+)]
+async fn lessons_delete_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    AdminUser(user): AdminUser,
+) -> WebResult<impl IntoResponse> {
+    let lesson = Lesson::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+
+    lesson
+        .delete_cascade(state.pool(), &user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?;
+    state.module_cache().invalidate_all();
+
+    Ok(StatusCode::OK)
+}
+
+/// Lessons point at a module, a missing one is a 404 rather than a foreign key error
+async fn ensure_module_exists(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    module_id: Uuid,
+) -> WebResult<()> {
+    Module::find_by_id(state.pool(), user, module_id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Module::get_resource_type()))?;
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/lessons/{lesson_id}/status",
+    description = "Whether the current user completed the lesson, without its content",
+    params(
+        ("lesson_id" = Uuid, Path, description = "ID of the lesson to check")
+    ),
+    responses(
+        (status = 200, description = "Status found", body = LessonStatusResponse),
+        (status = 404, description = "Lesson not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "lessons"
+)]
+async fn lessons_get_status_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    ctx: RequestContext,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let completed = Lesson::is_completed_by(state.pool(), user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+
+    Ok((StatusCode::OK, Json(LessonStatusResponse { completed })))
+}
+
 #[utoipa::path(
     post,
     path = "/api/v1/lessons/{lesson_id}/done",
@@ -68,6 +308,7 @@ async fn lessons_get_handler(
         (status = 200, description = "Lesson marked"),
         (status = 404, description = "Lesson not found", body = ErrorResponse),
         (status = 401, description = "You're not allowed to do this", body = ErrorResponse),
+        (status = 403, description = "Previous module isn't completed enough yet", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     ),
     security(
@@ -81,7 +322,7 @@ async fn lessons_mark_done_handler(
     ctx: RequestContext,
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.user()?;
-    let exists = Lesson::find_by_id(state.pool(), &user, id)
+    let exists = Lesson::find_by_id(state.pool(), user, id)
         .await
         .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
         .is_some();
@@ -89,14 +330,16 @@ async fn lessons_mark_done_handler(
     if !exists {
         return Err(WebError::resource_not_found(Lesson::get_resource_type()));
     }
+    ensure_unlocked(&state, user, id).await?;
 
-    UserProgress::create(state.pool(), &user, UserProgressCreate::new(
+    UserProgress::upsert(state.pool(), user, UserProgressCreate::new(
         user.user_id(),
         id,
-        true
+        ProgressStatus::Done
     ))
     .await
     .map_err(|e| WebError::resource_fetch_error(crate::model::ResourceType::UserProgress, e))?;
+    state.module_cache().invalidate_user(user.user_id());
 
     Ok(StatusCode::OK)
 }
@@ -104,7 +347,7 @@ async fn lessons_mark_done_handler(
 #[utoipa::path(
     get,
     path = "/api/v1/lessons/{lesson_id}/tasks",
-    description = "Get all tasks for this lesson",
+    description = "Get all tasks for this lesson. Tasks with a check delay come with a `served_token` to send back when checking them",
     params(
         ("lesson_id" = Uuid, Path, description = "ID of the lesson to get tasks for")
     ),
@@ -112,12 +355,13 @@ async fn lessons_mark_done_handler(
         (status = 200, description = "Tasks found", body = Vec<TaskResponse>),
         (status = 404, description = "Lesson not found", body = ErrorResponse),
         (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "Previous module isn't completed enough yet", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     ),
     security(
         ("cookie" = [])
     ),
-    tag = "tasks"
+    tag = "lessons"
 )]
 async fn lessons_get_tasks_handler(
     State(state): State<AppState>,
@@ -125,7 +369,7 @@ async fn lessons_get_tasks_handler(
     ctx: RequestContext,
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.user()?;
-    let exists = Lesson::find_by_id(state.pool(), &user, id)
+    let exists = Lesson::find_by_id(state.pool(), user, id)
         .await
         .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?
         .is_some();
@@ -133,21 +377,12 @@ async fn lessons_get_tasks_handler(
     if !exists {
         return Err(WebError::resource_not_found(LessonTask::get_resource_type()));
     }
+    ensure_unlocked(&state, user, id).await?;
 
-    let tasks = LessonTask::find_all_by_lesson(state.pool(), &user, id)
+    let tasks = LessonTask::find_all_by_lesson(state.pool(), user, id)
         .await
         .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
-
-    // fetch all answers
-    let mut responses = Vec::new();
-    for task in tasks {
-        let answers = Answer::find_all_by_task(state.pool(), &user, task.id())
-            .await
-            .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
-
-        let response = TaskResponse::from_entity(task, answers);
-        responses.push(response);
-    }
+    let responses = super::tasks::task_responses(&state, user, tasks).await?;
 
     Ok((StatusCode::OK, Json(responses)))
 } 
@@ -163,6 +398,7 @@ async fn lessons_get_tasks_handler(
         (status = 200, description = "Found next lesson", body = LessonResponse),
         (status = 404, description = "Next lesson not found", body = ErrorResponse),
         (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "Previous module isn't completed enough yet", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     ),
     security(
@@ -176,14 +412,60 @@ async fn lessons_get_next_handler(
     ctx: RequestContext,
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.user()?;
-    let next = LessonWithStatusRow::find_next_uncompleted(state.pool(), &user, id)
+    let next = LessonWithStatusRow::find_next_uncompleted(state.pool(), user, id)
         .await
-        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?;
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+    ensure_unlocked(&state, user, next.id).await?;
 
+    Ok((StatusCode::OK, Json(LessonResponse::from(next))))
+}
 
-    if let Some(next) = next {
-        Ok((StatusCode::OK, Json(LessonResponse::from(next))))
-    } else {
-        Err(WebError::resource_not_found(Lesson::get_resource_type()))
+#[utoipa::path(
+    get,
+    path = "/api/v1/lessons/search",
+    description = "Full-text search over lesson titles and content, the most relevant lessons first",
+    params(
+        ("q" = String, Query, description = "Search words"),
+        ("limit" = i64, Query, description = "Page size, 1 to 50"),
+        ("offset" = i64, Query, description = "Page offset"),
+    ),
+    responses(
+        (status = 200, description = "Returns requested page", body = Page<LessonSearchHit>),
+        (status = 400, description = "Empty query, limit or offset out of range", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "lessons"
+)]
+async fn lessons_search_handler(
+    State(state): State<AppState>,
+    Query(search): Query<LessonSearchQuery>,
+    Query(page): Query<PaginationQuery>,
+    ctx: RequestContext,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let query = search.q.trim();
+    let bad_request =
+        |message: String| WebError::resource_bad_request(Lesson::get_resource_type(), message);
+    if query.is_empty() {
+        return Err(bad_request(String::from("q must not be empty")));
+    }
+    if !(1..=MAX_SEARCH_LIMIT).contains(&page.limit) {
+        return Err(bad_request(format!("limit must be between 1 and {MAX_SEARCH_LIMIT}")));
+    }
+    if page.offset < 0 {
+        return Err(bad_request(String::from("offset must not be negative")));
     }
+
+    let found = LessonSearchRow::search(state.pool(), user, query, page.limit, page.offset)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?;
+    let hits = found.items.into_iter().map(LessonSearchHit::from).collect();
+    let found = Page::new(hits, found.total, found.limit, found.offset);
+
+    Ok((StatusCode::OK, Json(found)))
 }
@@ -1,8 +1,22 @@
-use crate::{web::{doc::ApiDoc, AppState}, Config};
-use axum::Router;
-use serde::Deserialize;
+use crate::{
+    model::{InvalidSort, ResourceType, Sort, SortField},
+    web::{doc::ApiDoc, middlewares, AppState, WebError, WebResult},
+    Config,
+};
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
 use tower_cookies::CookieManagerLayer;
-use tower_http::{cors::CorsLayer, services::ServeDir};
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowHeaders, AllowOrigin, CorsLayer},
+    services::ServeDir,
+};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
@@ -11,6 +25,7 @@ pub mod modules;
 pub mod lessons;
 pub mod tasks;
 pub mod progress;
+pub mod admin;
 
 
 #[derive(Debug, Clone, Deserialize, utoipa::ToSchema)]
@@ -19,6 +34,90 @@ pub struct PaginationQuery {
     offset: i64,
 }
 
+/// `sort=field:direction` of listings, e.g. `username:desc`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SortQuery {
+    sort: Option<String>,
+}
+
+impl SortQuery {
+    /// Fields outside of `F`'s allow-list are a `ResourceBadRequest` of `resource_type`
+    pub fn parse<F: SortField>(&self, resource_type: ResourceType) -> WebResult<Option<Sort<F>>> {
+        self.sort
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e: InvalidSort| WebError::resource_bad_request(resource_type, e.to_string()))
+    }
+}
+
+/// `201 Created` with a `Location` header pointing at the new resource
+pub(crate) fn created<T: Serialize>(location: String, body: T) -> impl IntoResponse {
+    (StatusCode::CREATED, [(header::LOCATION, location)], Json(body))
+}
+
+/// Files of `uploads_dir`, cacheable for `uploads_max_age`. Missing files and directories are `404`
+fn static_files<S: Send + Sync + Clone + 'static>(config: &'static Config) -> Router<S> {
+    let serve = ServeDir::new(config.app().uploads_dir()).append_index_html_on_directories(false);
+    let cache_control = HeaderValue::from_str(&format!(
+        "public, max-age={}",
+        config.app().uploads_max_age().as_secs()
+    ))
+    .expect("cache control is ascii");
+
+    Router::new()
+        .fallback_service(serve)
+        .layer(middleware::map_response_with_state(cache_control, add_cache_control))
+}
+
+async fn add_cache_control(State(value): State<HeaderValue>, mut response: Response) -> Response {
+    if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+        response.headers_mut().insert(header::CACHE_CONTROL, value);
+    }
+    response
+}
+
+/// CORS from the `[cors]` section. Without it debug builds allow everything and release builds
+/// stay same-origin
+fn cors_layer(config: &'static Config) -> CorsLayer {
+    let Some(cors) = config.cors() else {
+        return if cfg!(debug_assertions) {
+            CorsLayer::very_permissive()
+        } else {
+            CorsLayer::new()
+        };
+    };
+
+    let allow_origin = if cors.allowed_origins().iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            cors.allowed_origins()
+                .iter()
+                .map(|origin| HeaderValue::from_str(origin).expect("origins are validated at load")),
+        )
+    };
+    let methods = cors
+        .allowed_methods()
+        .iter()
+        .map(|method| method.parse().expect("methods are validated at load"))
+        .collect::<Vec<Method>>();
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(AllowHeaders::mirror_request())
+        .allow_credentials(cors.allow_credentials())
+        .expose_headers([
+            middlewares::X_REQUEST_ID,
+            middlewares::X_RATELIMIT_LIMIT,
+            middlewares::X_RATELIMIT_REMAINING,
+            middlewares::X_RATELIMIT_RESET,
+            header::RETRY_AFTER,
+            header::LOCATION,
+        ])
+}
+
 pub fn build_app<S: Send + Sync + Clone + 'static>(state: AppState, config: &'static Config) -> Router<S> {
     let mut router = Router::new()
         .nest("/api/v1/account/", user::routes(state.clone()))
@@ -26,9 +125,11 @@ pub fn build_app<S: Send + Sync + Clone + 'static>(state: AppState, config: &'st
         .nest("/api/v1/lessons/", lessons::routes(state.clone()))
         .nest("/api/v1/tasks/", tasks::routes(state.clone()))
         .nest("/api/v1/progress/", progress::routes(state.clone()))
-        .nest_service("/api/v1/static/", ServeDir::new("uploads"))
+        .nest("/api/v1/admin/", admin::routes(state.clone()))
+        .layer(middleware::from_fn_with_state(state.clone(), middlewares::rate_limit_fn))
+        .nest("/api/v1/static/", static_files(config))
         .layer(CookieManagerLayer::default())
-        .layer(CorsLayer::very_permissive())
+        .layer(cors_layer(config))
         .with_state(state);
 
     if config.app().docs() {
@@ -41,5 +142,13 @@ pub fn build_app<S: Send + Sync + Clone + 'static>(state: AppState, config: &'st
             );
     }
 
+    // the default predicate skips images, so uploads aren't compressed twice
+    if config.app().compression() {
+        router = router.layer(CompressionLayer::new());
+    }
+
     router
+        .layer(middleware::from_fn(middlewares::api_version_fn))
+        .layer(middleware::from_fn_with_state(config, middlewares::log_request_fn))
+        .layer(middleware::from_fn(middlewares::request_id_fn))
 }
@@ -1,22 +1,50 @@
 use axum::{
-    Json, Router, extract::State, http::StatusCode, middleware, response::IntoResponse,
-    routing::get,
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
 };
+use uuid::Uuid;
 
 use crate::{
     model::{
-        ResourceTyped,
-        entity::{Module, ModuleWithLessonsRow},
+        CrudRepository, ResourceTyped,
+        entity::{
+            Lesson, LessonTocRow, Module, ModuleCreate, ModuleListOptions, ModuleProgressRow,
+            ModuleWithLessonsRow,
+        },
     },
     web::{
-        AppState, RequestContext, WebError, WebResult, dto::modules::ModuleWithLessons,
+        AdminUser, ApiVersion, AuthorUser, AppState, AuthenticatedUser, JsonBody, RequestContext, WebError,
+        WebResult,
+        dto::{
+            lessons::LessonTocItem,
+            modules::{
+                LessonOrderRequest, ModuleGating, ModuleGatingRequest, ModuleProgressBatchRequest,
+                ModuleWithLessons, ModuleWithLessonsV1, ModulesListQuery,
+            },
+        },
         error::ErrorResponse, middlewares,
     },
 };
 
 pub fn routes<S>(state: AppState) -> Router<S> {
     Router::new()
-        .route("/", get(modules_list_handler))
+        .route("/", get(modules_list_handler).post(modules_create_handler))
+        .route(
+            "/{id}",
+            put(modules_update_handler).delete(modules_delete_handler),
+        )
+        .route("/recommended", get(modules_recommended_handler))
+        .route("/progress/batch", post(modules_progress_batch_handler))
+        .route("/{id}/toc", get(modules_toc_handler))
+        .route("/{id}/lesson-order", put(modules_lesson_order_handler))
+        .route(
+            "/{id}/gating",
+            get(modules_gating_get_handler).put(modules_gating_update_handler),
+        )
         .layer(middleware::from_fn_with_state(
             state.clone(),
             middlewares::extract_context_fn,
@@ -27,9 +55,16 @@ pub fn routes<S>(state: AppState) -> Router<S> {
 #[utoipa::path(
     get,
     path = "/api/v1/modules/",
-    description = "List ALL modules objects with lessons. See success response body",
+    description = "List ALL modules objects with lessons. See success response body. Open to anonymous visitors when `public_catalog` is on, lessons are never completed for them. `Accept: application/vnd.flern.v1+json` gets the shape without timestamps",
+    params(
+        ("only_incomplete" = Option<bool>, Query, description = "Leave out lessons the user completed, modules are kept"),
+        ("limit" = Option<i64>, Query, description = "Number of modules, all when omitted"),
+        ("offset" = Option<i64>, Query, description = "Modules to skip"),
+    ),
     responses(
         (status = 200, description = "Successfully collected modules", body = Vec<ModuleWithLessons>),
+        (status = 400, description = "Negative limit or offset", body = ErrorResponse),
+        (status = 406, description = "Unknown API version in `Accept`", body = ErrorResponse),
         (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
         (status = 500, description = "Internal Server Error", body = ErrorResponse),
     ),
@@ -40,13 +75,408 @@ pub fn routes<S>(state: AppState) -> Router<S> {
 )]
 async fn modules_list_handler(
     ctx: RequestContext,
+    version: ApiVersion,
+    Query(query): Query<ModulesListQuery>,
+    State(state): State<AppState>,
+) -> WebResult<Response> {
+    let user = catalog_user(&state, &ctx)?;
+    let options = ModuleListOptions::from(query);
+    if options.limit.is_some_and(|limit| limit < 0) || options.offset < 0 {
+        return Err(WebError::resource_bad_request(
+            Module::get_resource_type(),
+            "limit and offset must not be negative",
+        ));
+    }
+
+    // only the full listing is cached
+    let cached = options == ModuleListOptions::default();
+    let user_id = user.map(|u| u.user_id());
+    if cached && let Some(modules) = state.module_cache().get(user_id) {
+        return Ok(modules_list_response(modules, version));
+    }
+
+    let sort = state.config().app().default_content_sort();
+    let rows = match user {
+        Some(user) => ModuleWithLessonsRow::fetch_all(state.pool(), user, sort, options).await,
+        None => ModuleWithLessonsRow::fetch_all_anonymous(state.pool(), sort, options).await,
+    };
+    let modules = rows
+        .and_then(ModuleWithLessons::from_rows)
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
+    if cached {
+        state.module_cache().put(user_id, modules.clone());
+    }
+
+    Ok(modules_list_response(modules, version))
+}
+
+/// The cache always holds the latest shape, older ones are derived from it
+fn modules_list_response(modules: Vec<ModuleWithLessons>, version: ApiVersion) -> Response {
+    match version {
+        ApiVersion::V1 => {
+            let modules: Vec<ModuleWithLessonsV1> = modules.into_iter().map(Into::into).collect();
+            (StatusCode::OK, Json(modules)).into_response()
+        }
+        ApiVersion::V2 => (StatusCode::OK, Json(modules)).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/modules/",
+    description = "Creates a new module. Admin or instructor",
+    request_body = ModuleCreate,
+    responses(
+        (status = 201, description = "Module created", body = Module,
+            headers(("Location" = String, description = "Path of the new module"))),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_create_handler(
+    AuthorUser(user): AuthorUser,
     State(state): State<AppState>,
+    JsonBody(payload): JsonBody<ModuleCreate>,
 ) -> WebResult<impl IntoResponse> {
+    let module = Module::create(state.pool(), &user, payload)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
+    state.module_cache().invalidate_all();
+
+    Ok(super::created(format!("/api/v1/modules/{}", module.id()), module))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/modules/{module_id}",
+    description = "Replaces title, description and order of the module. Admin or instructor",
+    params(
+        ("module_id" = Uuid, Path, description = "ID of the module")
+    ),
+    request_body = ModuleCreate,
+    responses(
+        (status = 200, description = "Module updated", body = Module),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_update_handler(
+    AuthorUser(user): AuthorUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    JsonBody(payload): JsonBody<ModuleCreate>,
+) -> WebResult<impl IntoResponse> {
+    let module = Module::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Module::get_resource_type()))?;
+
+    let module = module
+        .update(state.pool(), &user, payload)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
+    state.module_cache().invalidate_all();
+
+    Ok((StatusCode::OK, Json(module)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/modules/{module_id}",
+    description = "Deletes the module with all of its lessons. Admin only",
+    params(
+        ("module_id" = Uuid, Path, description = "ID of the module")
+    ),
+    responses(
+        (status = 200, description = "Module deleted"),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_delete_handler(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> WebResult<impl IntoResponse> {
+    let module = Module::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Module::get_resource_type()))?;
+
+    module
+        .delete_cascade(state.pool(), &user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
+    state.module_cache().invalidate_all();
+
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/modules/recommended",
+    description = "Module to take next: the first one the current user hasn't started, otherwise the least completed one",
+    responses(
+        (status = 200, description = "Found a module to take", body = ModuleWithLessons),
+        (status = 204, description = "Every lesson is already completed"),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_recommended_handler(
+    ctx: RequestContext,
+    State(state): State<AppState>,
+) -> WebResult<Response> {
     let user = ctx.user()?;
-    let modules = ModuleWithLessonsRow::fetch_all(state.pool(), &user)
+    let sort = state.config().app().default_content_sort();
+    let row = ModuleWithLessonsRow::fetch_recommended(state.pool(), user, sort)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
+
+    let Some(row) = row else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+    let module = ModuleWithLessons::try_from(row)
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e.into()))?;
+
+    Ok((StatusCode::OK, Json(module)).into_response())
+}
+
+/// Catalog routes require a user unless `app.public_catalog` is on
+fn catalog_user<'a>(
+    state: &AppState,
+    ctx: &'a RequestContext,
+) -> WebResult<Option<&'a AuthenticatedUser>> {
+    if state.config().app().public_catalog() {
+        Ok(ctx.maybe_user())
+    } else {
+        ctx.user().map(Some)
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/modules/{module_id}/toc",
+    description = "Table of contents of the module: lesson ids and titles in order, without content or status. Open to anonymous visitors when `public_catalog` is on",
+    params(
+        ("module_id" = Uuid, Path, description = "ID of the module")
+    ),
+    responses(
+        (status = 200, description = "Successfully collected table of contents", body = Vec<LessonTocItem>),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_toc_handler(
+    ctx: RequestContext,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> WebResult<impl IntoResponse> {
+    let anonymous = AuthenticatedUser::system();
+    let user = catalog_user(&state, &ctx)?.unwrap_or(&anonymous);
+    let exists = Module::find_by_id(state.pool(), user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .is_some();
+
+    if !exists {
+        return Err(WebError::resource_not_found(Module::get_resource_type()));
+    }
+
+    let sort = state.config().app().default_content_sort();
+    let toc: Vec<LessonTocItem> = LessonTocRow::fetch_by_module(state.pool(), user, id, sort)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .into_iter()
+        .map(LessonTocItem::from)
+        .collect();
+
+    Ok((StatusCode::OK, Json(toc)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/modules/{module_id}/gating",
+    description = "How much of the previous module has to be completed before this module's lessons open",
+    params(
+        ("module_id" = Uuid, Path, description = "ID of the module")
+    ),
+    responses(
+        (status = 200, description = "Gating found", body = ModuleGating),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_gating_get_handler(
+    ctx: RequestContext,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let module = Module::find_by_id(state.pool(), user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Module::get_resource_type()))?;
+
+    Ok((StatusCode::OK, Json(ModuleGating::from(&module))))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/modules/{module_id}/gating",
+    description = "Sets how much of the previous module has to be completed before this module's lessons open. Admin or instructor",
+    params(
+        ("module_id" = Uuid, Path, description = "ID of the module")
+    ),
+    request_body = ModuleGatingRequest,
+    responses(
+        (status = 200, description = "Gating updated", body = ModuleGating),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 422, description = "Percent is not within 0..=100", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_gating_update_handler(
+    AuthorUser(user): AuthorUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    JsonBody(payload): JsonBody<ModuleGatingRequest>,
+) -> WebResult<impl IntoResponse> {
+    if !(0..=100).contains(&payload.min_prerequisite_percent) {
+        return Err(WebError::resource_unprocessable(
+            Module::get_resource_type(),
+            format!(
+                "min_prerequisite_percent must be within 0..=100, got {}",
+                payload.min_prerequisite_percent
+            ),
+        ));
+    }
+
+    let module = Module::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Module::get_resource_type()))?;
+
+    let module = module
+        .set_min_prerequisite_percent(state.pool(), &user, payload.min_prerequisite_percent)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(ModuleGating::from(&module))))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/modules/{module_id}/lesson-order",
+    description = "Rewrites the order of the module's lessons to the given one. Admin or instructor",
+    params(
+        ("module_id" = Uuid, Path, description = "ID of the module")
+    ),
+    request_body = LessonOrderRequest,
+    responses(
+        (status = 204, description = "Lessons reordered"),
+        (status = 400, description = "Ids are not exactly the module's lessons", body = ErrorResponse),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_lesson_order_handler(
+    AuthorUser(user): AuthorUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    JsonBody(payload): JsonBody<LessonOrderRequest>,
+) -> WebResult<impl IntoResponse> {
+    Module::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Module::get_resource_type()))?;
+
+    let reordered = Lesson::reorder(state.pool(), &user, id, &payload.lesson_ids)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?;
+    if !reordered {
+        return Err(WebError::resource_bad_request(
+            Lesson::get_resource_type(),
+            "lesson_ids must list every lesson of the module exactly once",
+        ));
+    }
+    state.module_cache().invalidate_all();
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/modules/progress/batch",
+    description = "Progress of the current user in each of the given modules. Unknown ids are left out",
+    request_body = ModuleProgressBatchRequest,
+    responses(
+        (status = 200, description = "Successfully collected progress", body = Vec<ModuleProgressRow>),
+        (status = 401, description = "You had to be authorized to do this", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    tag = "modules",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn modules_progress_batch_handler(
+    ctx: RequestContext,
+    State(state): State<AppState>,
+    JsonBody(payload): JsonBody<ModuleProgressBatchRequest>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let progress = ModuleProgressRow::fetch_by_ids(state.pool(), user, &payload.ids)
         .await
-        .and_then(ModuleWithLessons::from_rows)
         .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?;
 
-    Ok((StatusCode::OK, Json(modules)))
+    Ok((StatusCode::OK, Json(progress)))
 }
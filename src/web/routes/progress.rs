@@ -1,24 +1,46 @@
 use axum::{
-    extract::{Path, State}, http::StatusCode, middleware, response::IntoResponse, routing::{get, post}, Json, Router
+    extract::{Path, Query, State}, http::StatusCode, middleware, response::IntoResponse,
+    routing::{delete, get, post}, Json, Router
 };
+use uuid::Uuid;
 
 use crate::{
     model::{
         CrudRepository, ResourceTyped,
         entity::{
-            Lesson, ProgressToken, ProgressTokenCreate, UserEntity, UserProgress, UserTaskAttempt,
+            LeaderboardEntry, Lesson, LessonWithStatusRow, Module, ModuleCompletionRow, ProgressToken,
+            ProgressTokenCreate, ResumeToken, ResumeTokenCreate, UserEntity, UserProgress, UserTaskAttempt,
         },
     },
     web::{
-        AppState, AuthenticatedUser, RequestContext, WebError, WebResult,
-        dto::progress::UserProgressResponse, error::ErrorResponse, middlewares,
+        AppState, AuthenticatedUser, JsonBody, RequestContext, WebError, WebResult,
+        dto::{
+            lessons::LessonResponse,
+            progress::{
+                LeaderboardQuery, ModuleProgress, ProgressResetQuery, ProgressResetResponse,
+                ProgressShareRequest, UserProgressResponse,
+            },
+        },
+        error::ErrorResponse, middlewares,
     },
 };
 
+/// Leaderboard size when the client doesn't ask for one, and the most it may ask for
+const DEFAULT_LEADERBOARD_LIMIT: i64 = 10;
+const MAX_LEADERBOARD_LIMIT: i64 = 100;
+
+/// Share token lifetime when the client doesn't ask for one
+const DEFAULT_SHARE_TTL_MINUTES: u32 = 30;
+
 pub fn routes<S>(state: AppState) -> Router<S> {
     Router::new()
+        .route("/", delete(progress_reset_handler))
         .route("/{token}", get(progress_get_handler))
+        .route("/modules", get(progress_modules_handler))
+        .route("/leaderboard", get(progress_leaderboard_handler))
         .route("/share", post(progress_token_handler))
+        .route("/resume-link", post(progress_resume_link_handler))
+        .route("/resume/{token}", get(progress_resume_handler))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             middlewares::extract_context_fn,
@@ -36,6 +58,7 @@ pub fn routes<S>(state: AppState) -> Router<S> {
     responses(
         (status = 200, description = "Progress found", body = UserProgressResponse),
         (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 404, description = "Token not found or single use token already read", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     ),
     tag = "progress",
@@ -44,17 +67,10 @@ async fn progress_get_handler(
     Path(token): Path<String>,
     State(state): State<AppState>,
 ) -> WebResult<impl IntoResponse> {
-    let admin = AuthenticatedUser::admin();
-
-    // cleanup old tokens
-    ProgressToken::cleanup_expired(state.pool(), &admin)
-        .await
-        .map(|n| tracing::debug!("progress_tokens: cleaned up {} expired tokens", n))
-        .map_err(|e| WebError::resource_fetch_error(ProgressToken::get_resource_type(), e))?;
-
+    let system = AuthenticatedUser::system();
 
     // find database progress
-    let progress_token = ProgressToken::find_by_token(state.pool(), &admin, &token)
+    let progress_token = ProgressToken::find_by_token(state.pool(), &system, &token)
         .await
         .map_err(|e| WebError::resource_fetch_error(ProgressToken::get_resource_type(), e))?;
 
@@ -69,7 +85,7 @@ async fn progress_get_handler(
     if *token.expires_at() < chrono::Utc::now() {
         // delete token here
         token
-            .delete(state.pool(), &admin)
+            .delete(state.pool(), &system)
             .await
             .map_err(|e| WebError::resource_fetch_error(ProgressToken::get_resource_type(), e))?;
 
@@ -80,7 +96,7 @@ async fn progress_get_handler(
 
     // check that this user really exists
     let token_user =
-        UserEntity::find_by_id(state.pool(), &admin, token.user_id())
+        UserEntity::find_by_id(state.pool(), &system, token.user_id())
             .await
             .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
@@ -99,6 +115,17 @@ async fn progress_get_handler(
     )
     .map_err(|e| WebError::resource_fetch_error(UserProgress::get_resource_type(), e))?;
 
+    // a single use token is only good for one successful read, racing readers get 404
+    if token.single_use() {
+        let consumed = token
+            .consume(state.pool(), &system)
+            .await
+            .map_err(|e| WebError::resource_fetch_error(ProgressToken::get_resource_type(), e))?;
+        if !consumed {
+            return Err(WebError::resource_not_found(ProgressToken::get_resource_type()));
+        }
+    }
+
     let res = UserProgressResponse::new(
         total_lessons,
         completed_lessons,
@@ -110,13 +137,136 @@ async fn progress_get_handler(
     Ok((StatusCode::OK, Json(res)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/progress/modules",
+    description = "Completed and total lessons of every module for the current user",
+    responses(
+        (status = 200, description = "Progress found", body = Vec<ModuleProgress>),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "progress",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn progress_modules_handler(
+    ctx: RequestContext,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let sort = state.config().app().default_content_sort();
+    let progress: Vec<ModuleProgress> = ModuleCompletionRow::fetch_all(state.pool(), user, sort)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+        .into_iter()
+        .map(ModuleProgress::from)
+        .collect();
+
+    Ok((StatusCode::OK, Json(progress)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/progress/leaderboard",
+    description = "Users with the most correct answers, completed lessons break ties. Users are only shown by username",
+    params(
+        ("limit" = Option<i64>, Query, description = "Number of users, 1 to 100, defaults to 10"),
+    ),
+    responses(
+        (status = 200, description = "Leaderboard collected", body = Vec<LeaderboardEntry>),
+        (status = 400, description = "Limit out of range", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "progress",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn progress_leaderboard_handler(
+    ctx: RequestContext,
+    Query(query): Query<LeaderboardQuery>,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let limit = query.limit.unwrap_or(DEFAULT_LEADERBOARD_LIMIT);
+    if !(1..=MAX_LEADERBOARD_LIMIT).contains(&limit) {
+        return Err(WebError::resource_bad_request(
+            UserTaskAttempt::get_resource_type(),
+            format!("limit must be between 1 and {MAX_LEADERBOARD_LIMIT}"),
+        ));
+    }
+
+    let entries = LeaderboardEntry::fetch_top(state.pool(), user, limit)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserTaskAttempt::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(entries)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/progress/",
+    description = "Clears the current user's lesson progress and task attempts to retake the course. Admins resetting someone else use `/api/v1/admin/users/{user_id}/progress`",
+    params(
+        ("module_id" = Option<Uuid>, Query, description = "Only reset this module's lessons"),
+    ),
+    responses(
+        (status = 200, description = "Progress reset", body = ProgressResetResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 404, description = "Module not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "progress",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn progress_reset_handler(
+    ctx: RequestContext,
+    Query(query): Query<ProgressResetQuery>,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let reset = reset_progress(&state, user, user.user_id(), query.module_id).await?;
+
+    Ok((StatusCode::OK, Json(reset)))
+}
+
+/// Resets `user_id`'s progress on behalf of `actor`, who is either that user or an admin
+pub(crate) async fn reset_progress(
+    state: &AppState,
+    actor: &AuthenticatedUser,
+    user_id: Uuid,
+    module_id: Option<Uuid>,
+) -> WebResult<ProgressResetResponse> {
+    if let Some(module_id) = module_id {
+        Module::find_by_id(state.pool(), actor, module_id)
+            .await
+            .map_err(|e| WebError::resource_fetch_error(Module::get_resource_type(), e))?
+            .ok_or(WebError::resource_not_found(Module::get_resource_type()))?;
+    }
+
+    let (lessons, attempts) = UserProgress::reset(state.pool(), actor, user_id, module_id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserProgress::get_resource_type(), e))?;
+    state.module_cache().invalidate_user(user_id);
+
+    Ok(ProgressResetResponse { lessons, attempts })
+}
+
 #[utoipa::path(
     post,
     path = "/api/v1/progress/share",
     description = "Generate a share token for the current's user progress",
+    request_body(content = Option<ProgressShareRequest>, description = "Optional token lifetime and single use flag"),
     responses(
         (status = 200, description = "Token generated", body = ProgressToken),
+        (status = 400, description = "Lifetime of zero minutes", body = ErrorResponse),
         (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 429, description = "Too many active share tokens", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     ),
     tag = "progress",
@@ -127,19 +277,136 @@ async fn progress_get_handler(
 async fn progress_token_handler(
     ctx: RequestContext,
     State(state): State<AppState>,
+    payload: Option<JsonBody<ProgressShareRequest>>,
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.user()?;
+    let payload = payload.map(|JsonBody(payload)| payload).unwrap_or_default();
+    let requested = payload.ttl_minutes.unwrap_or(DEFAULT_SHARE_TTL_MINUTES);
+    if requested == 0 {
+        return Err(WebError::user_bad_request(String::from(
+            "ttl_minutes must be positive",
+        )));
+    }
+    let ttl_minutes = requested.min(state.config().app().max_share_ttl_minutes());
+
+    let limit = state.config().app().max_active_share_tokens();
+    let active = ProgressToken::count_active(state.pool(), user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(ProgressToken::get_resource_type(), e))?;
+    if active >= limit {
+        return Err(WebError::resource_limit_exceeded(
+            ProgressToken::get_resource_type(),
+            format!("at most {limit} active share tokens, wait for one to expire"),
+        ));
+    }
+
     let token = crate::auth::token::generate_token();
-    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(30);
+    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(ttl_minutes.into());
     let token_create = ProgressTokenCreate {
         user_id: user.user_id(),
         token,
         expires_at,
+        single_use: payload.single_use,
     };
 
-    let progress = ProgressToken::create(state.pool(), &user, token_create)
+    let progress = ProgressToken::create(state.pool(), user, token_create)
         .await
         .map_err(|e| WebError::resource_fetch_error(ProgressToken::get_resource_type(), e))?;
 
     Ok((StatusCode::OK, Json(progress)))
 }
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/progress/resume-link",
+    description = "Generate a short-lived token pointing at the current user's current lesson",
+    responses(
+        (status = 200, description = "Token generated", body = ResumeToken),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 404, description = "There is no uncompleted lesson to resume", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "progress",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn progress_resume_link_handler(
+    ctx: RequestContext,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let current = LessonWithStatusRow::find_current(state.pool(), user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+
+    let token_create = ResumeTokenCreate {
+        token: crate::auth::token::generate_token(),
+        user_id: user.user_id(),
+        lesson_id: current.id,
+        expires_at: chrono::Utc::now() + chrono::Duration::minutes(30),
+    };
+
+    let resume = ResumeToken::create(state.pool(), user, token_create)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(ResumeToken::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(resume)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/progress/resume/{token}",
+    description = "Resolve a resume token to its lesson, as seen by the user who created it",
+    params(
+        ("token" = String, Path, description = "Token recieved from /api/v1/progress/resume-link")
+    ),
+    responses(
+        (status = 200, description = "Lesson found", body = LessonResponse),
+        (status = 400, description = "Token expired", body = ErrorResponse),
+        (status = 403, description = "The lesson's module isn't unlocked for the token's user", body = ErrorResponse),
+        (status = 404, description = "Token or lesson not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "progress",
+)]
+async fn progress_resume_handler(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let system = AuthenticatedUser::system();
+
+    // expired tokens are deleted by the cleanup worker, one found in between is rejected below
+    let token = ResumeToken::find_by_token(state.pool(), &system, &token)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(ResumeToken::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(ResumeToken::get_resource_type()))?;
+
+    if *token.expires_at() < chrono::Utc::now() {
+        token
+            .delete(state.pool(), &system)
+            .await
+            .map_err(|e| WebError::resource_fetch_error(ResumeToken::get_resource_type(), e))?;
+
+        return Err(WebError::user_bad_request(
+            "This token has been expired".to_string(),
+        ));
+    }
+
+    let token_user = UserEntity::find_by_id(state.pool(), &system, token.user_id())
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(UserEntity::get_resource_type()))?;
+    let token_user = AuthenticatedUser::new(token_user.id(), token_user.role());
+
+    let lesson = LessonWithStatusRow::find_by_id(state.pool(), &token_user, token.lesson_id())
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .map(LessonResponse::from)
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+    // the module may have been locked since the link was created
+    super::lessons::ensure_unlocked(&state, &token_user, token.lesson_id()).await?;
+
+    Ok((StatusCode::OK, Json(lesson)))
+}
@@ -1,21 +1,52 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{collections::{HashMap, HashSet}, path::PathBuf, str::FromStr};
 
 use crate::{
+    auth::{self, TaskServedClaims, generate_task_token},
+    utils::uploads,
     model::{
-        entity::{Answer, LessonTask, UserProgress, UserProgressCreate, UserTaskAttempt, UserTaskAttemptCreate}, CrudRepository, ResourceTyped
+        entity::{Answer, AnswerDistributionRow, Lesson, LessonTask, LessonTaskCreate, ProgressStatus, UserProgress, UserProgressCreate, UserTaskAttempt, UserTaskAttemptCreate, UserTaskAttemptSync}, CrudRepository, ResourceTyped
     },
     web::{
-        dto::tasks::{TaskCheckRequest, TaskCheckResponse}, error::ErrorResponse, middlewares, AppState, RequestContext, WebError, WebResult
-    }, Config,
+        dto::tasks::{AnswerBody, RecomputeAttemptsResponse, TaskAttemptSyncItem, TaskAttemptSyncResponse, TaskCheckRequest, TaskCheckResponse, TaskResponse, TasksForLessonsRequest}, error::ErrorResponse, middlewares, AdminUser, AppState, AuthenticatedUser, AuthorUser, JsonBody, RequestContext, WebError, WebResult
+    },
 };
 use axum::{
-    Json, Router, extract::State, http::StatusCode, middleware, response::IntoResponse,
-    routing::post,
+    Json, Router,
+    extract::{DefaultBodyLimit, Multipart, Path, State, multipart::MultipartRejection},
+    http::StatusCode, middleware, response::IntoResponse,
+    routing::{get, post, put},
 };
+use uuid::Uuid;
+
+/// Upper bound on attempts accepted by a single sync request
+const MAX_SYNC_BATCH: usize = 500;
+
+/// Upper bound on lessons whose tasks are fetched by a single request
+const MAX_LESSONS_PER_FETCH: usize = 50;
+
+/// Room for multipart boundaries and headers on top of `max_upload_bytes`
+const MULTIPART_OVERHEAD: usize = 16 * 1024;
 
 pub fn routes<S>(state: AppState) -> Router<S> {
     Router::new()
+        .route("/", post(tasks_create_handler))
+        .route("/{id}", put(tasks_update_handler).delete(tasks_delete_handler))
+        .route("/{id}/answers", post(tasks_answer_create_handler))
+        .route(
+            "/{id}/answers/{answer_id}",
+            put(tasks_answer_update_handler).delete(tasks_answer_delete_handler),
+        )
+        .route(
+            "/{id}/answers/{answer_id}/image",
+            post(tasks_answer_image_handler).layer(DefaultBodyLimit::max(
+                state.config().app().max_upload_bytes() + MULTIPART_OVERHEAD,
+            )),
+        )
         .route("/check", post(tasks_check_answer_handler))
+        .route("/for-lessons", post(tasks_for_lessons_handler))
+        .route("/attempts/sync", post(tasks_sync_attempts_handler))
+        .route("/{id}/recompute-attempts", post(tasks_recompute_attempts_handler))
+        .route("/{id}/answer-distribution", get(tasks_answer_distribution_handler))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             middlewares::extract_context_fn,
@@ -26,13 +57,18 @@ pub fn routes<S>(state: AppState) -> Router<S> {
 #[utoipa::path(
     post,
     path = "/api/v1/tasks/check",
-    description = "Check if provided answer is correct and mark according lesson as completed",
+    description = "Check if provided answer is correct and mark according lesson as completed. `multi_select` tasks are correct only if `answer_ids` are exactly the correct answers, they take `task_id` instead of `answer_id`",
     request_body = TaskCheckRequest,
     responses(
         (status = 200, description = "Answer checked", body = TaskCheckResponse),
         (status = 404, description = "Answer not found", body = ErrorResponse),
+        (status = 400, description = "answer_id, user_answer or answer_ids missing for the task type, or served_token missing or invalid", body = ErrorResponse),
+        (status = 422, description = "task_type doesn't match the task, or a picked answer belongs to another task", body = ErrorResponse),
         (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "The task's lesson is in a module that isn't unlocked yet", body = ErrorResponse),
         (status = 500, description = "Internal Server Error", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 429, description = "Checked sooner than the task's min_seconds_before_check after it was served", body = ErrorResponse),
     ),
     security(
         ("cookie" = [])
@@ -42,40 +78,93 @@ pub fn routes<S>(state: AppState) -> Router<S> {
 async fn tasks_check_answer_handler(
     State(state): State<AppState>,
     ctx: RequestContext,
-    Json(req): Json<TaskCheckRequest>,
+    JsonBody(req): JsonBody<TaskCheckRequest>,
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.user()?;
-    let answer = Answer::find_by_id(state.pool(), &user, req.answer_id)
-        .await
-        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+    let answer = match req.answer_id {
+        Some(answer_id) => Some(
+            Answer::find_by_id(state.pool(), user, answer_id)
+                .await
+                .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?
+                .ok_or(WebError::resource_not_found(Answer::get_resource_type()))?,
+        ),
+        None => None,
+    };
+
+    let task_id = match (&answer, req.task_id) {
+        (Some(answer), Some(task_id)) if answer.task_id() != task_id => {
+            return Err(WebError::resource_unprocessable(
+                Answer::get_resource_type(),
+                format!("answer `{}` doesn't belong to task `{task_id}`", answer.id()),
+            ));
+        }
+        (Some(answer), _) => answer.task_id(),
+        (None, Some(task_id)) => task_id,
+        (None, None) => {
+            return Err(WebError::user_bad_request(
+                "answer_id or task_id missing. You should pass the task_id when checking a multi_select task without answer_id".to_string(),
+            ));
+        }
+    };
+    let task = find_task(&state, user, task_id).await?;
+    super::lessons::ensure_unlocked(&state, user, task.lesson_id()).await?;
+
+    if req.task_type != task.task_type() {
+        return Err(WebError::resource_unprocessable(
+            LessonTask::get_resource_type(),
+            format!(
+                "task_type `{}` doesn't match the task, expected `{}`",
+                req.task_type,
+                task.task_type()
+            ),
+        ));
+    }
 
-    if answer.is_none() {
-        return Err(WebError::resource_not_found(Answer::get_resource_type()));
+    if task.min_seconds_before_check() > 0 {
+        check_answer_delay(&state, user, &task, req.served_token.as_deref(), chrono::Utc::now())?;
     }
-    let answer = answer.unwrap();
 
-    let is_correct = match req.task_type.as_str() {
-        "string_cmp" => {
+    let selection: HashSet<Uuid> = req.answer_ids.iter().flatten().copied().collect();
+    let is_correct = match (req.task_type.as_str(), &answer) {
+        ("multi_select", _) => {
+            if req.answer_ids.is_none() {
+                return Err(WebError::user_bad_request(
+                    "invalid answer_ids field passed. You should pass the picked answers in it if you're checking multi_select task".to_string(),
+                ));
+            }
+
+            let answers = Answer::find_all_by_task(state.pool(), user, task.id())
+                .await
+                .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+            if let Some(unknown) = selection.iter().find(|id| !answers.iter().any(|a| a.id() == **id)) {
+                return Err(WebError::resource_unprocessable(
+                    Answer::get_resource_type(),
+                    format!("answer `{unknown}` doesn't belong to the task"),
+                ));
+            }
+
+            // exactly the correct answers, missing or extra ones both fail
+            let correct: HashSet<Uuid> = answers.iter().filter(|a| a.is_correct()).map(|a| a.id()).collect();
+            correct == selection
+        }
+        (_, None) => {
+            return Err(WebError::user_bad_request(
+                "answer_id missing. You should pass the picked answer unless you're checking a multi_select task".to_string(),
+            ));
+        }
+        ("string_cmp", Some(answer)) => {
             if req.user_answer.is_none() {
-                return Err(WebError::user_bad_request(format!(
-                    "invalid user_answer field passed. You should pass some value in it if you're checking string_cmp task"
-                )));
+                return Err(WebError::user_bad_request(
+                    "invalid user_answer field passed. You should pass some value in it if you're checking string_cmp task".to_string(),
+                ));
             }
             let user_answer = req.user_answer.unwrap();
 
-            answer.answer_text() == user_answer.trim()
+            task.text_matches(answer.answer_text(), &user_answer)
         }
-        _ => answer.is_correct(),
+        (_, Some(answer)) => answer.is_correct(),
     };
 
-    let task = LessonTask::find_by_id(state.pool(), &user, answer.task_id())
-        .await
-        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
-    if task.is_none() {
-        return Err(WebError::resource_not_found(LessonTask::get_resource_type()));
-    }
-    let task = task.unwrap();
-
     // TODO: add progress mark in db
 
     // Current implementation allows multiple tasks per lesson
@@ -83,27 +172,65 @@ async fn tasks_check_answer_handler(
     // we are building according to lesson -> task, not lesson -> task(s)[]
     // according to our decision /tasks/check route will mark lesson as done too
     if is_correct {
-        UserProgress::create(
+        UserProgress::upsert(
             state.pool(),
-            &user,
-            UserProgressCreate::new(user.user_id(), task.lesson_id(), true),
+            user,
+            UserProgressCreate::new(user.user_id(), task.lesson_id(), ProgressStatus::Done),
         )
         .await
         .map_err(|e| WebError::resource_fetch_error(UserProgress::get_resource_type(), e))?;
+        state.module_cache().invalidate_user(user.user_id());
     }
-    let utc = UserTaskAttemptCreate::new(user.user_id(), task.id(), answer.id(), is_correct);
-    UserTaskAttempt::create(state.pool(), &user, utc)
-        .await
-        .map_err(|e| WebError::resource_fetch_error(UserTaskAttempt::get_resource_type(), e))?;
+    let attempt = if task.task_type() == "multi_select" {
+        let utc = UserTaskAttemptCreate::new(user.user_id(), task.id(), None, is_correct);
+        let selection: Vec<Uuid> = selection.into_iter().collect();
+        UserTaskAttempt::create_with_selection(state.pool(), user, utc, &selection).await
+    } else {
+        let answer_id = answer.as_ref().map(Answer::id);
+        let utc = UserTaskAttemptCreate::new(user.user_id(), task.id(), answer_id, is_correct);
+        UserTaskAttempt::create(state.pool(), user, utc).await
+    };
+    attempt.map_err(|e| WebError::resource_fetch_error(UserTaskAttempt::get_resource_type(), e))?;
+
+    // revealing the stored text would give `string_cmp` answers away,
+    // and authors can keep the right answers to a wrong check to themselves
+    let hidden = task.task_type() == "string_cmp" || (!is_correct && !task.reveal_on_fail());
+    let correct_answer_ids = if hidden || is_correct {
+        None
+    } else {
+        let mut ids: Vec<Uuid> = Answer::find_all_by_task(state.pool(), user, task.id())
+            .await
+            .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?
+            .into_iter()
+            .filter(Answer::is_correct)
+            .map(|a| a.id())
+            .collect();
+        ids.sort();
+        Some(ids)
+    };
+
+    // a single id can't tell which answers of a `multi_select` task are right
+    let correct_answer_id = if hidden || task.task_type() == "multi_select" {
+        None
+    } else if let Some(answer) = answer.as_ref().filter(|a| a.is_correct()) {
+        Some(answer.id())
+    } else {
+        correct_answer_ids.as_ref().and_then(|ids| ids.first().copied())
+    };
 
     // Map database path to a download URL
-    let base_url = Config::get_or_init()
-        .await
+    let base_url = state
+        .config()
         .app()
         .host_url()
         .trim_end_matches('/');
-    let image_path = PathBuf::from_str(answer.image()).unwrap();
-    let image_url = format!("{}/api/v1/static/{}", base_url, image_path.display());
+    let image_url = answer
+        .as_ref()
+        .map(|answer| {
+            let image_path = PathBuf::from_str(answer.image()).unwrap();
+            format!("{}/api/v1/static/{}", base_url, image_path.display())
+        })
+        .unwrap_or_default();
 
     Ok((
         StatusCode::OK,
@@ -111,6 +238,647 @@ async fn tasks_check_answer_handler(
             is_correct,
             explanation: task.explanation().to_string(),
             image: image_url,
+            correct_answer_id,
+            correct_answer_ids,
         }),
     ))
 }
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/for-lessons",
+    description = "Tasks of several lessons at once, keyed by lesson id. Lessons without tasks and unknown ids are left out, a lesson in a locked module fails the whole request",
+    request_body = TasksForLessonsRequest,
+    responses(
+        (status = 200, description = "Tasks found", body = HashMap<Uuid, Vec<TaskResponse>>),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "A lesson is in a module that isn't unlocked yet", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 422, description = "Too many lessons", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_for_lessons_handler(
+    State(state): State<AppState>,
+    ctx: RequestContext,
+    JsonBody(payload): JsonBody<TasksForLessonsRequest>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    if payload.lesson_ids.len() > MAX_LESSONS_PER_FETCH {
+        return Err(WebError::resource_unprocessable(
+            LessonTask::get_resource_type(),
+            format!(
+                "at most {MAX_LESSONS_PER_FETCH} lessons per request, got {}",
+                payload.lesson_ids.len()
+            ),
+        ));
+    }
+    for lesson_id in &payload.lesson_ids {
+        super::lessons::ensure_unlocked(&state, user, *lesson_id).await?;
+    }
+
+    let tasks = LessonTask::find_all_by_lessons(state.pool(), user, &payload.lesson_ids)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
+    let lesson_ids: Vec<Uuid> = tasks.iter().map(LessonTask::lesson_id).collect();
+    let responses = task_responses(&state, user, tasks).await?;
+
+    let mut by_lesson: HashMap<Uuid, Vec<TaskResponse>> = HashMap::new();
+    for (lesson_id, response) in lesson_ids.into_iter().zip(responses) {
+        by_lesson.entry(lesson_id).or_default().push(response);
+    }
+
+    Ok((StatusCode::OK, Json(by_lesson)))
+}
+
+/// Responses for `tasks` in the same order, with the answers of every task fetched at once.
+/// Tasks with a check delay get a `served_token`
+pub(crate) async fn task_responses(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    tasks: Vec<LessonTask>,
+) -> WebResult<Vec<TaskResponse>> {
+    let task_ids: Vec<Uuid> = tasks.iter().map(LessonTask::id).collect();
+    let answers = Answer::find_all_by_tasks(state.pool(), user, &task_ids)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+
+    let mut answers_by_task: HashMap<Uuid, Vec<Answer>> = HashMap::new();
+    for answer in answers {
+        answers_by_task.entry(answer.task_id()).or_default().push(answer);
+    }
+
+    let served_at = chrono::Utc::now();
+    tasks
+        .into_iter()
+        .map(|task| {
+            let delayed = task.min_seconds_before_check() > 0;
+            let claims = TaskServedClaims::new(user.user_id(), task.id(), served_at);
+            let answers = answers_by_task.remove(&task.id()).unwrap_or_default();
+            let response = TaskResponse::from_entity(task, answers);
+            if !delayed {
+                return Ok(response);
+            }
+            generate_task_token(claims, state.config().app().jwt())
+                .map(|token| response.with_served_token(token))
+                .map_err(|e| WebError::server_crypt_error(e.into()))
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/attempts/sync",
+    description = "Store attempts made offline. Attempts are deduplicated by `client_id`, the whole batch is rejected if any of them points at an unknown task or answer or at a `multi_select` or `string_cmp` task. Module gating and check delays apply as for `/api/v1/tasks/check`, the delay counts up to `attempted_at`",
+    request_body = Vec<TaskAttemptSyncItem>,
+    responses(
+        (status = 200, description = "Attempts synced", body = TaskAttemptSyncResponse),
+        (status = 400, description = "served_token missing or invalid for a task with a check delay", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "A task's lesson is in a module that isn't unlocked yet", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 422, description = "Unknown task or answer, multi_select or string_cmp task, or batch too large", body = ErrorResponse),
+        (status = 429, description = "An attempt was made sooner than its task's min_seconds_before_check after it was served", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_sync_attempts_handler(
+    State(state): State<AppState>,
+    ctx: RequestContext,
+    JsonBody(attempts): JsonBody<Vec<TaskAttemptSyncItem>>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    if attempts.len() > MAX_SYNC_BATCH {
+        return Err(WebError::resource_unprocessable(
+            UserTaskAttempt::get_resource_type(),
+            format!("at most {MAX_SYNC_BATCH} attempts per sync, got {}", attempts.len()),
+        ));
+    }
+
+    for attempt in &attempts {
+        let answer = Answer::find_by_id(state.pool(), user, attempt.selected_answer_id)
+            .await
+            .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+
+        if answer.is_none_or(|a| a.task_id() != attempt.task_id) {
+            return Err(WebError::resource_unprocessable(
+                UserTaskAttempt::get_resource_type(),
+                format!(
+                    "attempt `{}` references unknown task `{}` or answer `{}`",
+                    attempt.client_id, attempt.task_id, attempt.selected_answer_id
+                ),
+            ));
+        }
+
+        // one selected answer can't tell whether the whole set was right, and the
+        // typed text of a string_cmp attempt isn't sent at all
+        let task = find_task(&state, user, attempt.task_id).await?;
+        if matches!(task.task_type(), "multi_select" | "string_cmp") {
+            return Err(WebError::resource_unprocessable(
+                UserTaskAttempt::get_resource_type(),
+                format!(
+                    "attempt `{}` is on {} task `{}`, check those through /api/v1/tasks/check",
+                    attempt.client_id,
+                    task.task_type(),
+                    attempt.task_id
+                ),
+            ));
+        }
+
+        // the same rules as an online check, timed by when the attempt was made
+        super::lessons::ensure_unlocked(&state, user, task.lesson_id()).await?;
+        if task.min_seconds_before_check() > 0 {
+            let checked_at = attempt.attempted_at.min(chrono::Utc::now());
+            check_answer_delay(&state, user, &task, attempt.served_token.as_deref(), checked_at)?;
+        }
+    }
+
+    let total = attempts.len() as u64;
+    let attempts = attempts.into_iter().map(UserTaskAttemptSync::from).collect();
+    let inserted = UserTaskAttempt::sync_batch(state.pool(), user, attempts)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserTaskAttempt::get_resource_type(), e))?;
+    if inserted > 0 {
+        state.module_cache().invalidate_user(user.user_id());
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(TaskAttemptSyncResponse {
+            inserted,
+            duplicates: total - inserted,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/{task_id}/recompute-attempts",
+    description = "Re-evaluate past attempts of the task against its current correct answers. Admin only",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task to recompute attempts for")
+    ),
+    responses(
+        (status = 200, description = "Attempts recomputed", body = RecomputeAttemptsResponse),
+        (status = 404, description = "Task not found", body = ErrorResponse),
+        (status = 422, description = "Attempts of string_cmp tasks can't be recomputed", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_recompute_attempts_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    AdminUser(user): AdminUser,
+) -> WebResult<impl IntoResponse> {
+    let task = LessonTask::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
+    if task.is_none() {
+        return Err(WebError::resource_not_found(LessonTask::get_resource_type()));
+    }
+    let task = task.unwrap();
+
+    // string_cmp attempts are judged by the submitted text, which isn't stored
+    if task.task_type() == "string_cmp" {
+        return Err(WebError::resource_unprocessable(
+            LessonTask::get_resource_type(),
+            "attempts of string_cmp tasks can't be recomputed",
+        ));
+    }
+
+    let flipped = UserTaskAttempt::recompute_for_task(state.pool(), &user, task.id())
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserTaskAttempt::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(RecomputeAttemptsResponse { flipped })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/tasks/{task_id}/answer-distribution",
+    description = "How often each answer of the task was chosen. Admin only",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task")
+    ),
+    responses(
+        (status = 200, description = "Distribution collected", body = Vec<AnswerDistributionRow>),
+        (status = 404, description = "Task not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_answer_distribution_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    AdminUser(user): AdminUser,
+) -> WebResult<impl IntoResponse> {
+    let exists = LessonTask::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?
+        .is_some();
+    if !exists {
+        return Err(WebError::resource_not_found(LessonTask::get_resource_type()));
+    }
+
+    let distribution = AnswerDistributionRow::fetch_by_task(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserTaskAttempt::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(distribution)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/",
+    description = "Creates a task in the given lesson. Admin or instructor",
+    request_body = LessonTaskCreate,
+    responses(
+        (status = 201, description = "Task created", body = LessonTask,
+            headers(("Location" = String, description = "Path of the new task"))),
+        (status = 404, description = "Lesson not found", body = ErrorResponse),
+        (status = 422, description = "Unsupported task_type", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_create_handler(
+    State(state): State<AppState>,
+    AuthorUser(user): AuthorUser,
+    JsonBody(payload): JsonBody<LessonTaskCreate>,
+) -> WebResult<impl IntoResponse> {
+    validate_task(&state, &user, &payload).await?;
+    let task = LessonTask::create(state.pool(), &user, payload)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
+
+    Ok(super::created(format!("/api/v1/tasks/{}", task.id()), task))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/tasks/{task_id}",
+    description = "Replaces the task, it can be moved to another lesson too. Admin or instructor",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task to update")
+    ),
+    request_body = LessonTaskCreate,
+    responses(
+        (status = 200, description = "Task updated", body = LessonTask),
+        (status = 404, description = "Task or lesson not found", body = ErrorResponse),
+        (status = 422, description = "Unsupported task_type", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_update_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    AuthorUser(user): AuthorUser,
+    JsonBody(payload): JsonBody<LessonTaskCreate>,
+) -> WebResult<impl IntoResponse> {
+    let task = find_task(&state, &user, id).await?;
+    validate_task(&state, &user, &payload).await?;
+    let task = task
+        .update(state.pool(), &user, payload)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(task)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tasks/{task_id}",
+    description = "Deletes the task with its answers and attempts. Admin only",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task to delete")
+    ),
+    responses(
+        (status = 200, description = "Task deleted"),
+        (status = 404, description = "Task not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_delete_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    AdminUser(user): AdminUser,
+) -> WebResult<impl IntoResponse> {
+    find_task(&state, &user, id)
+        .await?
+        .delete(state.pool(), &user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?;
+
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/{task_id}/answers",
+    description = "Adds an answer to the task. Admin or instructor",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task")
+    ),
+    request_body = AnswerBody,
+    responses(
+        (status = 201, description = "Answer created", body = Answer,
+            headers(("Location" = String, description = "Path of the new answer"))),
+        (status = 404, description = "Task not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_answer_create_handler(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    AuthorUser(user): AuthorUser,
+    JsonBody(payload): JsonBody<AnswerBody>,
+) -> WebResult<impl IntoResponse> {
+    let task = find_task(&state, &user, id).await?;
+    let answer = Answer::create(state.pool(), &user, payload.into_create(task.id()))
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+
+    Ok(super::created(
+        format!("/api/v1/tasks/{}/answers/{}", task.id(), answer.id()),
+        answer,
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/tasks/{task_id}/answers/{answer_id}",
+    description = "Replaces an answer of the task. Past attempts keep their verdict until recomputed. Admin or instructor",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task"),
+        ("answer_id" = Uuid, Path, description = "ID of the answer to update")
+    ),
+    request_body = AnswerBody,
+    responses(
+        (status = 200, description = "Answer updated", body = Answer),
+        (status = 404, description = "Task or answer not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_answer_update_handler(
+    State(state): State<AppState>,
+    Path((id, answer_id)): Path<(Uuid, Uuid)>,
+    AuthorUser(user): AuthorUser,
+    JsonBody(payload): JsonBody<AnswerBody>,
+) -> WebResult<impl IntoResponse> {
+    let answer = find_task_answer(&state, &user, id, answer_id).await?;
+    let answer = answer
+        .update(state.pool(), &user, payload.into_create(id))
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(answer)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tasks/{task_id}/answers/{answer_id}/image",
+    description = "Uploads the answer's image as the `image` field of a multipart form. PNG, JPEG and WebP up to `max_upload_bytes` are accepted, the previous image is left for the uploads GC. Admin or instructor",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task"),
+        ("answer_id" = Uuid, Path, description = "ID of the answer")
+    ),
+    request_body(content_type = "multipart/form-data", description = "`image` file field"),
+    responses(
+        (status = 200, description = "Image stored", body = Answer),
+        (status = 400, description = "Not a multipart form, no `image` field, not a PNG, JPEG or WebP image, or too large", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to author content", body = ErrorResponse),
+        (status = 404, description = "Task or answer not found", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_answer_image_handler(
+    State(state): State<AppState>,
+    Path((id, answer_id)): Path<(Uuid, Uuid)>,
+    AuthorUser(user): AuthorUser,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> WebResult<impl IntoResponse> {
+    let answer = find_task_answer(&state, &user, id, answer_id).await?;
+
+    let bad_request =
+        |message: String| WebError::resource_bad_request(Answer::get_resource_type(), message);
+    let mut multipart = multipart.map_err(|e| bad_request(e.body_text()))?;
+    let mut field = loop {
+        match multipart.next_field().await.map_err(|e| bad_request(e.body_text()))? {
+            Some(field) if field.name() == Some("image") => break field,
+            Some(_) => continue,
+            None => return Err(bad_request(String::from("missing `image` field"))),
+        }
+    };
+
+    // oversized bodies fail while reading, the limit layer allows a bit more than the file
+    let max = state.config().app().max_upload_bytes();
+    let content_type = field.content_type().unwrap_or_default().to_string();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(|e| bad_request(e.body_text()))? {
+        if bytes.len() + chunk.len() > max {
+            return Err(bad_request(format!("image is larger than {max} bytes")));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let extension = uploads::image_extension(&content_type, &bytes)
+        .ok_or_else(|| bad_request(String::from("image must be a PNG, JPEG or WebP file")))?;
+
+    let image = format!("answers/{}.{extension}", Uuid::new_v4());
+    let path = state.config().app().uploads_dir().join(&image);
+    tokio::task::spawn_blocking(move || {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, bytes)
+    })
+    .await
+    .map_err(|e| WebError::server_io_error(e.into()))?
+    .map_err(WebError::server_io_error)?;
+
+    let answer = answer
+        .set_image(state.pool(), &user, image)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(answer)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tasks/{task_id}/answers/{answer_id}",
+    description = "Deletes an answer of the task together with attempts that selected it. Admin only",
+    params(
+        ("task_id" = Uuid, Path, description = "ID of the task"),
+        ("answer_id" = Uuid, Path, description = "ID of the answer to delete")
+    ),
+    responses(
+        (status = 200, description = "Answer deleted"),
+        (status = 404, description = "Task or answer not found", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not allowed to do this", body = ErrorResponse),
+        (status = 500, description = "Internal Server Error", body = ErrorResponse),
+    ),
+    security(
+        ("cookie" = [])
+    ),
+    tag = "tasks"
+)]
+async fn tasks_answer_delete_handler(
+    State(state): State<AppState>,
+    Path((id, answer_id)): Path<(Uuid, Uuid)>,
+    AdminUser(user): AdminUser,
+) -> WebResult<impl IntoResponse> {
+    find_task_answer(&state, &user, id, answer_id)
+        .await?
+        .delete(state.pool(), &user)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?;
+
+    Ok(StatusCode::OK)
+}
+
+async fn find_task(state: &AppState, user: &AuthenticatedUser, id: Uuid) -> WebResult<LessonTask> {
+    LessonTask::find_by_id(state.pool(), user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(LessonTask::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(LessonTask::get_resource_type()))
+}
+
+/// Answer `answer_id`, as long as it belongs to task `task_id`
+async fn find_task_answer(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    task_id: Uuid,
+    answer_id: Uuid,
+) -> WebResult<Answer> {
+    find_task(state, user, task_id).await?;
+    Answer::find_by_id(state.pool(), user, answer_id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Answer::get_resource_type(), e))?
+        .filter(|answer| answer.task_id() == task_id)
+        .ok_or(WebError::resource_not_found(Answer::get_resource_type()))
+}
+
+/// Rejects checks of a delayed task made at `checked_at`, sooner than its delay after the
+/// user was served it
+fn check_answer_delay(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    task: &LessonTask,
+    served_token: Option<&str>,
+    checked_at: chrono::DateTime<chrono::Utc>,
+) -> WebResult<()> {
+    let Some(served_token) = served_token else {
+        return Err(WebError::user_bad_request(
+            "served_token missing. You should pass the one the task was served with".to_string(),
+        ));
+    };
+    let claims = auth::process_task_token(served_token, state.config().app().jwt())
+        .map(|data| data.claims)
+        .ok()
+        .filter(|c| c.sub == user.user_id().to_string() && c.task_id == task.id().to_string())
+        .ok_or_else(|| WebError::user_bad_request("served_token is invalid or expired".to_string()))?;
+
+    let elapsed_ms = checked_at.timestamp_millis() - claims.served_at;
+    if elapsed_ms < i64::from(task.min_seconds_before_check()) * 1000 {
+        return Err(WebError::resource_limit_exceeded(
+            LessonTask::get_resource_type(),
+            format!(
+                "take at least {}s before checking the task",
+                task.min_seconds_before_check()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects task types the database would refuse and lessons that don't exist
+async fn validate_task(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    payload: &LessonTaskCreate,
+) -> WebResult<()> {
+    if !LessonTask::is_supported_type(&payload.task_type) {
+        return Err(WebError::resource_unprocessable(
+            LessonTask::get_resource_type(),
+            format!(
+                "unsupported task_type `{}`, expected one of {}",
+                payload.task_type,
+                LessonTask::TASK_TYPES.join(", ")
+            ),
+        ));
+    }
+
+    if payload.min_seconds_before_check < 0 {
+        return Err(WebError::resource_unprocessable(
+            LessonTask::get_resource_type(),
+            format!(
+                "min_seconds_before_check can't be negative, got {}",
+                payload.min_seconds_before_check
+            ),
+        ));
+    }
+
+    Lesson::find_by_id(state.pool(), user, payload.lesson_id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(Lesson::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(Lesson::get_resource_type()))?;
+    Ok(())
+}
@@ -7,64 +7,167 @@ use axum::{
     routing::{get, post, put},
 };
 use chrono::Duration;
-use serde::Deserialize;
-use tower_cookies::{Cookie, Cookies, cookie::SameSite};
+use serde::{Deserialize, Serialize};
+use tower_cookies::{
+    Cookie, Cookies,
+    cookie::{SameSite, time},
+};
 use uuid::Uuid;
 
 use crate::{
-    auth::{self, hash_password, verify_password, UserClaims}, model::{
-        check_access, entity::{UserEntity, UserEntityCreateUpdate}, CrudRepository, DatabaseError, PaginatableRepository, ResourceTyped
+    config::CookieSameSite,
+    auth::{self, hash_password, validate_password, verify_password, UserClaims}, model::{
+        check_access, entity::{LessonWithModuleRow, RefreshToken, RefreshTokenCreate, UserEntity, UserEntityCreateUpdate, UserProgress, UserProgressWithLessonRow}, CrudRepository, DatabaseError, PaginatableRepository, ResourceTyped
     }, web::{
-        error::ErrorResponse, middlewares::{self, AUTH_TOKEN}, routes::PaginationQuery, AppState, AuthenticatedUser, RequestContext, UserRole, WebError, WebResult
-    }, Config
+        dto::lessons::LessonStatusQuery, error::ErrorResponse, middlewares::{self, AUTH_TOKEN, REFRESH_TOKEN}, routes::{PaginationQuery, SortQuery}, AdminUser, AppState, AuthenticatedUser, JsonBody, RequestContext, UnknownUserRole, UserRole, WebError, WebResult
+    }
 };
 
-#[derive(Debug, Deserialize, utoipa::ToSchema)]
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
 pub struct UserCreateUpdateBody {
     pub username: String,
     pub password: String,
 }
 
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct UserUpdateBody {
+    pub username: String,
+    /// New password, the current one is kept when omitted
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct UserMergeBody {
+    /// Account whose progress is moved over, it is deleted afterwards
+    pub source_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, utoipa::ToSchema)]
+pub struct UserRoleBody {
+    /// One of `admin`, `instructor` or `user`
+    pub role: String,
+}
+
 pub fn routes<S>(state: AppState) -> Router<S> {
     let protected = Router::new()
         .route("/page", get(user_list_handler))
         .route("/verify", get(user_verify_handler))
+        .route("/me", get(user_me_handler))
+        .route("/lessons", get(user_lessons_handler))
         .route("/logout", post(user_logout_handler))
         .route(
             "/{id}",
             put(user_update_handler).delete(user_delete_handler),
         )
+        .route("/{id}/progress", get(user_progress_handler))
+        .route("/{id}/merge", post(user_merge_handler))
+        .route("/{id}/role", put(user_role_handler))
         .route_layer(middleware::from_fn_with_state(
             state.clone(),
             middlewares::extract_context_fn,
         ));
 
-    Router::new()
+    // credentials are checked here, so these get a budget of their own
+    let credentials = Router::new()
         .route("/signup", post(user_signup_handler))
         .route("/signin", post(user_signin_handler))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            middlewares::auth_rate_limit_fn,
+        ));
+
+    Router::new()
+        .merge(credentials)
+        .route("/refresh", post(user_refresh_handler))
         .merge(protected)
         .with_state(state)
 }
 
+/// Cookies carrying the refresh token are only sent back to account routes
+const REFRESH_COOKIE_PATH: &str = "/api/v1/account";
+
+/// Auth cookie with the flags from `[app]` config, `cookies.remove` needs the same ones
+fn auth_cookie(
+    state: &AppState,
+    name: &'static str,
+    value: String,
+    path: &'static str,
+) -> Cookie<'static> {
+    let same_site = match state.config().app().cookie_same_site() {
+        CookieSameSite::Lax => SameSite::Lax,
+        CookieSameSite::Strict => SameSite::Strict,
+        CookieSameSite::None => SameSite::None,
+    };
+
+    Cookie::build((name, value))
+        .same_site(same_site)
+        .secure(state.config().app().cookie_secure())
+        .http_only(true)
+        .path(path)
+        .build()
+}
+
+/// Issues a session token for `user` and sets it as the auth cookie.
+/// The JWT carries the user's role and expires together with the cookie after `app.token_ttl_secs`.
+/// A single-use refresh token living `app.refresh_token_ttl_secs` is stored and set alongside.
+async fn start_session(state: &AppState, cookies: &Cookies, user: &UserEntity) -> WebResult<()> {
+    let user_id = user.id();
+    let ttl = state.config().app().token_ttl_secs();
+    let claims = UserClaims {
+        sub: user_id.to_string(),
+        exp: (chrono::Utc::now() + Duration::seconds(ttl)).timestamp(),
+        role: Some(user.role().to_string()),
+        issued_at: chrono::Utc::now().timestamp_millis(),
+    };
+    let token = auth::generate_token(claims, state.config().app().jwt())
+        .map_err(|e| WebError::server_crypt_error(e.into()))?;
+
+    let mut cookie = auth_cookie(state, AUTH_TOKEN, token, "/");
+    cookie.set_max_age(time::Duration::seconds(ttl));
+    cookies.add(cookie);
+
+    let refresh_ttl = state.config().app().refresh_token_ttl_secs();
+    let refresh = RefreshTokenCreate {
+        token: crate::auth::token::generate_token(),
+        user_id,
+        expires_at: chrono::Utc::now() + Duration::seconds(refresh_ttl),
+    };
+    let refresh = RefreshToken::create(state.pool(), &AuthenticatedUser::system(), refresh)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(RefreshToken::get_resource_type(), e))?;
+
+    let mut cookie =
+        auth_cookie(state, REFRESH_TOKEN, refresh.token().to_string(), REFRESH_COOKIE_PATH);
+    cookie.set_max_age(time::Duration::seconds(refresh_ttl));
+    cookies.add(cookie);
+    Ok(())
+}
+
 #[utoipa::path(
     post,
     path = "/api/v1/account/signup",
     request_body = UserCreateUpdateBody,
     description = "Creates new user in database",
     responses(
-        (status = 200, description = "User created successfully", body = UserEntity),
+        (status = 201, description = "User created successfully", body = UserEntity,
+            headers(("Location" = String, description = "Path of the new user"))),
+        (status = 400, description = "Password too weak", body = ErrorResponse),
         (status = 409, description = "User already exists", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
     ),
     tag = "account"
 )]
 async fn user_signup_handler(
     State(state): State<AppState>,
     cookies: Cookies,
-    Json(payload): Json<UserCreateUpdateBody>,
+    JsonBody(payload): JsonBody<UserCreateUpdateBody>,
 ) -> WebResult<impl IntoResponse> {
-    let admin = AuthenticatedUser::admin();
-    let found = UserEntity::find_by_username(state.pool(), &admin, &payload.username)
+    validate_password(&payload.password, state.config().app().min_password_len())
+        .map_err(|e| WebError::user_bad_request(e.to_string()))?;
+
+    let system = AuthenticatedUser::system();
+    let found = UserEntity::find_by_username(state.pool(), &system, &payload.username)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
@@ -72,32 +175,19 @@ async fn user_signup_handler(
         return Err(WebError::registration_conflict());
     }
 
-    let hash = hash_password(&payload.password).map_err(|e| WebError::server_crypt_error(e))?;
+    let hash = hash_password(&payload.password).map_err(WebError::server_crypt_error)?;
     let payload = UserEntityCreateUpdate {
         username: payload.username,
         password_hash: hash,
     };
 
-    let created = UserEntity::create(state.pool(), &admin, payload)
+    let created = UserEntity::create(state.pool(), &system, payload)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
-    let timestamp = (chrono::Utc::now() + Duration::days(1)).timestamp();
-    let jwt_token = Config::get_or_init().await.app().jwt();
-
-    let claims = UserClaims {
-        sub: created.id().to_string(),
-        exp: timestamp,
-    };
-    let token = auth::generate_token(claims, jwt_token)
-        .map_err(|e| WebError::server_crypt_error(e.into()))?;
-    let mut cookie = Cookie::new(AUTH_TOKEN, token);
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_http_only(true);
-    cookie.set_path("/");
-    cookies.add(cookie);
+    start_session(&state, &cookies, &created).await?;
 
-    Ok((StatusCode::OK, Json(created)))
+    Ok(super::created(format!("/api/v1/account/{}", created.id()), created))
 }
 
 #[utoipa::path(
@@ -110,16 +200,17 @@ async fn user_signup_handler(
         (status = 401, description = "Credentials invalid", body = ErrorResponse),
         (status = 404, description = "Specified user is not found", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
     ),
     tag = "account",
 )]
 async fn user_signin_handler(
     State(state): State<AppState>,
     cookies: Cookies,
-    Json(payload): Json<UserCreateUpdateBody>,
+    JsonBody(payload): JsonBody<UserCreateUpdateBody>,
 ) -> WebResult<impl IntoResponse> {
-    let admin = AuthenticatedUser::admin();
-    let found = UserEntity::find_by_username(state.pool(), &admin, &payload.username)
+    let system = AuthenticatedUser::system();
+    let found = UserEntity::find_by_username(state.pool(), &system, &payload.username)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
@@ -130,31 +221,66 @@ async fn user_signin_handler(
     let found = found.unwrap();
 
     let is_verified = verify_password(found.hash(), &payload.password)
-        .map_err(|e| WebError::server_crypt_error(e))?;
+        .map_err(WebError::server_crypt_error)?;
 
     if !is_verified {
         return Err(WebError::auth_invalid_credentials());
     }
 
-    let timestamp = (chrono::Utc::now() + Duration::days(1)).timestamp();
-    let jwt_token = Config::get_or_init().await.app().jwt();
-    let claims = UserClaims {
-        sub: found.id().to_string(),
-        exp: timestamp,
-    };
+    start_session(&state, &cookies, &found).await?;
 
-    let token = auth::generate_token(claims, jwt_token)
-        .map_err(|e| WebError::server_crypt_error(e.into()))?;
+    Ok((StatusCode::OK, Json(found)))
+}
 
-    let mut cookie = Cookie::new(AUTH_TOKEN, token);
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_http_only(true);
-    cookie.set_path("/");
-    cookies.add(cookie);
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/refresh",
+    description = "Trades the refresh cookie for a new session. Each refresh token works only once",
+    responses(
+        (status = 200, description = "New session and refresh cookies set"),
+        (status = 401, description = "Refresh token missing, expired or already used", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "account",
+)]
+async fn user_refresh_handler(
+    State(state): State<AppState>,
+    cookies: Cookies,
+) -> WebResult<impl IntoResponse> {
+    let token = cookies
+        .get(REFRESH_TOKEN)
+        .map(|c| c.value().to_string())
+        .ok_or_else(WebError::auth_refresh_token_invalid)?;
 
-    Ok((StatusCode::OK, Json(found)))
+    let system = AuthenticatedUser::system();
+    let refresh = RefreshToken::consume(state.pool(), &system, &token)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(RefreshToken::get_resource_type(), e))?
+        .ok_or_else(WebError::auth_refresh_token_invalid)?;
+
+    let found = UserEntity::find_by_id(state.pool(), &system, refresh.user_id())
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or_else(WebError::auth_refresh_token_invalid)?;
+
+    start_session(&state, &cookies, &found).await?;
+
+    Ok(StatusCode::OK)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/account/verify",
+    description = "Checks whether the session cookie is still valid, without a body",
+    responses(
+        (status = 200, description = "Session is valid"),
+        (status = 401, description = "No valid session"),
+    ),
+    tag = "account",
+    security(
+        ("cookie" = [])
+    )
+)]
 async fn user_verify_handler(ctx: RequestContext) -> WebResult<impl IntoResponse> {
     let user = ctx.maybe_user();
 
@@ -165,6 +291,78 @@ async fn user_verify_handler(ctx: RequestContext) -> WebResult<impl IntoResponse
     Ok(StatusCode::OK)
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/account/me",
+    description = "Returns the signed in user",
+    responses(
+        (status = 200, description = "Current user", body = UserEntity),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "account",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn user_me_handler(
+    ctx: RequestContext,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+
+    // the session outlived the account
+    let found = UserEntity::find_by_id(state.pool(), user, user.user_id())
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or(WebError::auth_required())?;
+
+    Ok((StatusCode::OK, Json(found)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/account/lessons",
+    description = "Lists lessons of every module in course order with the signed in user's completion",
+    params(
+        ("status" = Option<crate::web::dto::lessons::LessonStatusFilter>, Query, description = "Only `completed` or only `incomplete` lessons, all when omitted"),
+        ("limit" = i64, Query, description = "Page size"),
+        ("offset" = i64, Query, description = "Page offset"),
+    ),
+    responses(
+        (status = 200, description = "Returns requested page", body = crate::model::Page<LessonWithModuleRow>),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "account",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn user_lessons_handler(
+    ctx: RequestContext,
+    Query(page): Query<PaginationQuery>,
+    Query(filter): Query<LessonStatusQuery>,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let user = ctx.user()?;
+    let completed = filter.status.map(|s| s.completed());
+
+    let lessons = LessonWithModuleRow::page_by_user(
+        state.pool(),
+        user,
+        user.user_id(),
+        completed,
+        page.limit,
+        page.offset,
+    )
+    .await
+    .map_err(|e| WebError::resource_fetch_error(UserProgress::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(lessons)))
+}
+
 #[utoipa::path(
     post,
     path = "/api/v1/account/logout",
@@ -181,6 +379,7 @@ async fn user_verify_handler(ctx: RequestContext) -> WebResult<impl IntoResponse
 )]
 async fn user_logout_handler(
     ctx: RequestContext,
+    State(state): State<AppState>,
     cookies: Cookies,
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.maybe_user();
@@ -188,21 +387,29 @@ async fn user_logout_handler(
         return Ok(StatusCode::UNAUTHORIZED);
     }
 
-    let cookie = Cookie::build(AUTH_TOKEN)
-        .same_site(SameSite::Lax)
-        .http_only(true)
-        .path("/")
-        .build();
-    cookies.remove(cookie);
+    cookies.remove(auth_cookie(&state, AUTH_TOKEN, String::new(), "/"));
+
+    if let Some(refresh) = cookies.get(REFRESH_TOKEN) {
+        RefreshToken::delete_by_token(state.pool(), &AuthenticatedUser::system(), refresh.value())
+            .await
+            .map_err(|e| WebError::resource_fetch_error(RefreshToken::get_resource_type(), e))?;
+
+        cookies.remove(auth_cookie(&state, REFRESH_TOKEN, String::new(), REFRESH_COOKIE_PATH));
+    }
     Ok(StatusCode::OK)
 }
 
 #[utoipa::path(
     get,
     path = "/api/v1/account/page",
-    request_body = PaginationQuery,
+    params(
+        ("limit" = i64, Query, description = "Page size"),
+        ("offset" = i64, Query, description = "Page offset"),
+        ("sort" = Option<String>, Query, description = "`username` or `role`, optionally followed by `:asc` or `:desc`"),
+    ),
     responses(
         (status = 200, description = "Returns requested page", body = crate::model::Page<UserEntity>),
+        (status = 400, description = "Unknown sort field or direction", body = ErrorResponse),
         (status = 401, description = "You're not an admin to do this", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
     ),
@@ -212,31 +419,73 @@ async fn user_logout_handler(
     )
 )]
 async fn user_list_handler(
-    ctx: RequestContext,
+    AdminUser(user): AdminUser,
     Query(page): Query<PaginationQuery>,
+    Query(sort): Query<SortQuery>,
     State(state): State<AppState>,
 ) -> WebResult<impl IntoResponse> {
-    let user = ctx.user()?;
-    if user.user_role() != UserRole::Admin {
-        return Err(WebError::resource_forbidden(UserEntity::get_resource_type()));
-    }
+    let sort = sort.parse(UserEntity::get_resource_type())?;
 
-    let users = UserEntity::page(state.pool(), &user, page.limit, page.offset)
+    let users = UserEntity::page(state.pool(), &user, page.limit, page.offset, sort)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
     Ok((StatusCode::OK, Json(users)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/account/{id}/progress",
+    description = "Lists progress of the given user with lesson titles. Admin only",
+    params(
+        ("id" = Uuid, Path, description = "ID of the user to inspect"),
+        ("limit" = i64, Query, description = "Page size"),
+        ("offset" = i64, Query, description = "Page offset"),
+    ),
+    responses(
+        (status = 200, description = "Returns requested page", body = crate::model::Page<UserProgressWithLessonRow>),
+        (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "account",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn user_progress_handler(
+    AdminUser(user): AdminUser,
+    Path(id): Path<Uuid>,
+    Query(page): Query<PaginationQuery>,
+    State(state): State<AppState>,
+) -> WebResult<impl IntoResponse> {
+    let found = UserEntity::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
+    if found.is_none() {
+        return Err(WebError::resource_not_found(UserEntity::get_resource_type()));
+    }
+
+    let progress =
+        UserProgressWithLessonRow::page_by_user(state.pool(), &user, id, page.limit, page.offset)
+            .await
+            .map_err(|e| WebError::resource_fetch_error(UserProgress::get_resource_type(), e))?;
+
+    Ok((StatusCode::OK, Json(progress)))
+}
+
 #[utoipa::path(
     put,
     path = "/api/v1/account/{id}",
-    request_body = UserCreateUpdateBody,
+    request_body = UserUpdateBody,
     responses(
         (status = 200, description = "User updated successfully", body = UserEntity),
+        (status = 400, description = "Password too weak", body = ErrorResponse),
         (status = 401, description = "You're not authorized to do this", body = ErrorResponse),
         (status = 403, description = "You doesn't have enough permissions to do this", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
     ),
     tag = "account",
     security(
@@ -247,11 +496,15 @@ async fn user_update_handler(
     ctx: RequestContext,
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-    Json(payload): Json<UserCreateUpdateBody>,
+    JsonBody(payload): JsonBody<UserUpdateBody>,
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.user()?;
+    if let Some(password) = &payload.password {
+        validate_password(password, state.config().app().min_password_len())
+            .map_err(|e| WebError::user_bad_request(e.to_string()))?;
+    }
 
-    let found = UserEntity::find_by_id(state.pool(), &user, id)
+    let found = UserEntity::find_by_id(state.pool(), user, id)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
@@ -259,7 +512,7 @@ async fn user_update_handler(
         return Err(WebError::resource_not_found(UserEntity::get_resource_type()));
     }
     let found = found.unwrap();
-    check_access(state.pool(), &user, &found, user.user_id())
+    check_access(state.pool(), user, &found)
         .await
         .map_err(|e| {
             if let DatabaseError::Forbidden = e {
@@ -269,21 +522,26 @@ async fn user_update_handler(
             }
         })?;
 
-    let conflict_found = UserEntity::find_by_username(state.pool(), &user, &payload.username)
+    let conflict_found = UserEntity::find_by_username(state.pool(), user, &payload.username)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
-    if conflict_found.is_some() {
+    // keeping your own username is not a conflict
+    if conflict_found.is_some_and(|u| u.id() != found.id()) {
         return Err(WebError::registration_conflict());
     }
 
+    let password_hash = match &payload.password {
+        Some(password) => hash_password(password).map_err(WebError::server_crypt_error)?,
+        None => String::new(), // keeps the current hash
+    };
     let payload = UserEntityCreateUpdate {
         username: payload.username,
-        password_hash: String::new(), // not in use
+        password_hash,
     };
 
     let updated = found
-        .update(state.pool(), &user, payload)
+        .update(state.pool(), user, payload)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
@@ -312,7 +570,7 @@ async fn user_delete_handler(
 ) -> WebResult<impl IntoResponse> {
     let user = ctx.user()?;
 
-    let found = UserEntity::find_by_id(state.pool(), &user, id)
+    let found = UserEntity::find_by_id(state.pool(), user, id)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
 
@@ -321,7 +579,7 @@ async fn user_delete_handler(
     }
 
     let found = found.unwrap();
-    check_access(state.pool(), &user, &found, user.user_id())
+    check_access(state.pool(), user, &found)
         .await
         .map_err(|e| {
             if let DatabaseError::Forbidden = e {
@@ -332,9 +590,120 @@ async fn user_delete_handler(
         })?;
 
     found
-        .delete(state.pool(), &user)
+        .delete(state.pool(), user)
         .await
         .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
+    state.module_cache().invalidate_user(id);
+    state.sessions().revoke(id);
 
     Ok(StatusCode::OK)
 }
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/account/{id}/merge",
+    description = "Moves the progress and attempts of `source_id` to the specified user and deletes `source_id`. Admin only",
+    params(
+        ("id" = Uuid, Path, description = "ID of the user that keeps the progress")
+    ),
+    request_body = UserMergeBody,
+    responses(
+        (status = 200, description = "Users merged", body = UserEntity),
+        (status = 401, description = "You're not authorized", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 404, description = "One of the users not found", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 422, description = "A user can't be merged into itself", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "account",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn user_merge_handler(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    JsonBody(payload): JsonBody<UserMergeBody>,
+) -> WebResult<impl IntoResponse> {
+    if payload.source_id == id {
+        return Err(WebError::resource_unprocessable(
+            UserEntity::get_resource_type(),
+            "source_id must be another user",
+        ));
+    }
+
+    let target = UserEntity::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(UserEntity::get_resource_type()))?;
+    let source = UserEntity::find_by_id(state.pool(), &user, payload.source_id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(UserEntity::get_resource_type()))?;
+
+    target
+        .merge_from(state.pool(), &user, source)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?;
+    state.module_cache().invalidate_user(id);
+    state.module_cache().invalidate_user(payload.source_id);
+    state.sessions().revoke(payload.source_id);
+
+    Ok((StatusCode::OK, Json(target)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/account/{id}/role",
+    description = "Sets the role of the specified user. Their sessions end right away, the new role applies from their next refresh or sign in. The last admin can't be demoted. Admin only",
+    params(
+        ("id" = Uuid, Path, description = "ID of the user")
+    ),
+    request_body = UserRoleBody,
+    responses(
+        (status = 200, description = "Role updated", body = UserEntity),
+        (status = 400, description = "The last admin can't be demoted", body = ErrorResponse),
+        (status = 401, description = "You're not authorized", body = ErrorResponse),
+        (status = 403, description = "You're not an admin to do this", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 415, description = "Body is not JSON", body = ErrorResponse),
+        (status = 422, description = "Unknown role", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+    ),
+    tag = "account",
+    security(
+        ("cookie" = [])
+    )
+)]
+async fn user_role_handler(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    JsonBody(payload): JsonBody<UserRoleBody>,
+) -> WebResult<impl IntoResponse> {
+    let role: UserRole = payload.role.parse().map_err(|e: UnknownUserRole| {
+        WebError::resource_unprocessable(UserEntity::get_resource_type(), e.to_string())
+    })?;
+
+    let target = UserEntity::find_by_id(state.pool(), &user, id)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or(WebError::resource_not_found(UserEntity::get_resource_type()))?;
+
+    let updated = target
+        .set_role(state.pool(), &user, &role)
+        .await
+        .map_err(|e| WebError::resource_fetch_error(UserEntity::get_resource_type(), e))?
+        .ok_or_else(|| {
+            WebError::resource_bad_request(
+                UserEntity::get_resource_type(),
+                "can't demote the last admin",
+            )
+        })?;
+    // sessions carry the role, a refresh picks up the new one
+    state.sessions().revoke(id);
+
+    Ok((StatusCode::OK, Json(updated)))
+}
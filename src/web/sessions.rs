@@ -0,0 +1,62 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+/// In-memory list of users whose sessions were revoked, e.g. after their role changed or
+/// the account was deleted.
+///
+/// Session tokens carry the role and are trusted without a database lookup, so a token
+/// issued before the revocation is rejected until it would have expired anyway.
+/// Entries older than the token TTL can't match a live token and are dropped.
+#[derive(Debug)]
+pub struct SessionRevocations {
+    ttl: Duration,
+    revoked: RwLock<HashMap<Uuid, DateTime<Utc>>>,
+}
+
+impl SessionRevocations {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            revoked: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Ends every session of `user_id` issued up to now
+    pub fn revoke(&self, user_id: Uuid) {
+        let now = Utc::now();
+        let mut revoked = self.revoked.write().unwrap();
+        revoked.retain(|_, revoked_at| now - *revoked_at < self.ttl);
+        revoked.insert(user_id, now);
+    }
+
+    /// Whether a token of `user_id` issued at `issued_at` (unix milliseconds) was revoked
+    pub fn is_revoked(&self, user_id: Uuid, issued_at: i64) -> bool {
+        self.revoked
+            .read()
+            .unwrap()
+            .get(&user_id)
+            .is_some_and(|revoked_at| issued_at <= revoked_at.timestamp_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_revocations_test() {
+        let sessions = SessionRevocations::new(Duration::hours(1));
+        let user_id = Uuid::new_v4();
+        let before = Utc::now().timestamp_millis();
+        assert!(!sessions.is_revoked(user_id, before));
+
+        sessions.revoke(user_id);
+        assert!(sessions.is_revoked(user_id, before));
+        assert!(!sessions.is_revoked(Uuid::new_v4(), before));
+        // sessions started afterwards are fine
+        let after = Utc::now().timestamp_millis() + 1;
+        assert!(!sessions.is_revoked(user_id, after));
+    }
+}
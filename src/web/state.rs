@@ -1,16 +1,61 @@
+use std::sync::Arc;
+
+use crate::Config;
 use crate::model::ModelManager;
+use crate::web::{ModuleCache, RateLimiter, SessionRevocations};
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     mm: ModelManager,
+    config: &'static Config,
+    modules: Arc<ModuleCache>,
+    rate_limiter: Arc<RateLimiter>,
+    auth_rate_limiter: Arc<RateLimiter>,
+    sessions: Arc<SessionRevocations>,
 }
 
 impl AppState {
-    pub fn new(mm: ModelManager) -> Self {
-        Self { mm }
+    pub fn new(mm: ModelManager, config: &'static Config) -> Self {
+        Self {
+            mm,
+            config,
+            modules: Arc::new(ModuleCache::new(config.app().modules_cache_ttl())),
+            rate_limiter: Arc::new(RateLimiter::new(
+                config.app().rate_limit_requests(),
+                config.app().rate_limit_window(),
+            )),
+            auth_rate_limiter: Arc::new(RateLimiter::new(
+                config.app().auth_rate_limit_requests(),
+                config.app().auth_rate_limit_window(),
+            )),
+            sessions: Arc::new(SessionRevocations::new(chrono::Duration::seconds(
+                config.app().token_ttl_secs(),
+            ))),
+        }
     }
 
     pub fn pool(&self) -> &ModelManager {
         &self.mm
     }
+
+    pub fn config(&self) -> &'static Config {
+        self.config
+    }
+
+    pub fn module_cache(&self) -> &ModuleCache {
+        &self.modules
+    }
+
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Separate, tighter budget for sign-in and sign-up
+    pub fn auth_rate_limiter(&self) -> &RateLimiter {
+        &self.auth_rate_limiter
+    }
+
+    pub fn sessions(&self) -> &SessionRevocations {
+        &self.sessions
+    }
 }
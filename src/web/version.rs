@@ -0,0 +1,79 @@
+use std::convert::Infallible;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+/// Media type prefix of versioned responses, `application/vnd.flern.v1+json` and so on
+const VENDOR_PREFIX: &str = "application/vnd.flern.v";
+const VENDOR_SUFFIX: &str = "+json";
+
+/// Response shape asked for with `Accept: application/vnd.flern.v{n}+json`.
+/// Clients that don't ask get the latest one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    V1,
+    /// Adds `code` to error bodies and timestamps to the module listing
+    V2,
+}
+
+impl ApiVersion {
+    pub const LATEST: Self = Self::V2;
+
+    pub fn number(self) -> u32 {
+        match self {
+            Self::V1 => 1,
+            Self::V2 => 2,
+        }
+    }
+
+    fn from_number(number: u32) -> Option<Self> {
+        match number {
+            1 => Some(Self::V1),
+            2 => Some(Self::V2),
+            _ => None,
+        }
+    }
+
+    /// Version of the first vendor media type in `accept`, the latest if there is none.
+    /// A vendor type of an unknown version is an error carrying that version.
+    pub fn negotiate(accept: &str) -> Result<Self, String> {
+        let requested = accept
+            .split(',')
+            .map(|media| media.split(';').next().unwrap_or_default().trim())
+            .find_map(|media| media.strip_prefix(VENDOR_PREFIX)?.strip_suffix(VENDOR_SUFFIX));
+
+        let Some(requested) = requested else {
+            return Ok(Self::LATEST);
+        };
+        requested
+            .parse()
+            .ok()
+            .and_then(Self::from_number)
+            .ok_or_else(|| requested.to_string())
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ApiVersion {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get().copied().unwrap_or(Self::LATEST))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn negotiate_test() {
+        assert_eq!(ApiVersion::negotiate(""), Ok(ApiVersion::LATEST));
+        assert_eq!(ApiVersion::negotiate("application/json, */*"), Ok(ApiVersion::LATEST));
+        assert_eq!(ApiVersion::negotiate("application/vnd.flern.v1+json"), Ok(ApiVersion::V1));
+        assert_eq!(
+            ApiVersion::negotiate("text/html, application/vnd.flern.v2+json; q=0.9"),
+            Ok(ApiVersion::V2)
+        );
+        assert_eq!(ApiVersion::negotiate("application/vnd.flern.v9+json"), Err("9".to_string()));
+        assert_eq!(ApiVersion::negotiate("application/vnd.flern.vx+json"), Err("x".to_string()));
+    }
+}
@@ -0,0 +1,121 @@
+mod common;
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::common::{
+    Action, Flow, seed_lesson, seed_module, seed_task, setup_server, setup_test_db,
+    signin_action, signin_admin_action, signup_action,
+};
+
+#[tokio::test]
+async fn route_admin_tasks_by_type_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    for task_type in ["string_cmp", "choice", "string_cmp", "fill_code", "string_cmp"] {
+        seed_task(&pool, lesson.id(), task_type).await;
+    }
+
+    Flow::new()
+        .step(signup_action("notadmin", "notadmin"))
+        .step(
+            Action::new("tasks_by_type", "GET", "/api/v1/admin/tasks-by-type")
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("tasks_by_type", "GET", "/api/v1/admin/tasks-by-type").assert_body(|body| {
+                let counts: Value = serde_json::from_str(body).unwrap();
+                assert_eq!(
+                    counts,
+                    json!([
+                        { "task_type": "string_cmp", "count": 3 },
+                        { "task_type": "choice", "count": 1 },
+                        { "task_type": "fill_code", "count": 1 },
+                    ])
+                );
+            }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_admin_rebuild_progress_cache_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let first = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    seed_lesson(&pool, module.id(), "Loops", 2).await;
+
+    Flow::new()
+        .step(signup_action("cacheduser", "cacheduser"))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", first)),
+        )
+        .step(
+            Action::new("rebuild", "POST", "/api/v1/admin/rebuild-progress-cache")
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    let cached = || async {
+        let (completed, total): (i32, i32) = sqlx::query_as(
+            r#"
+            SELECT ump.completed, ump.total FROM user_module_progress ump
+            JOIN users u ON u.id = ump.user_id
+            WHERE u.username = 'cacheduser' AND ump.module_id = $1
+            "#,
+        )
+        .bind(module.id())
+        .fetch_one(pool.mm().executor())
+        .await
+        .unwrap();
+        (completed, total)
+    };
+
+    // marking the lesson done updated the cache in place
+    assert_eq!(cached().await, (1, 2));
+
+    // new lessons are counted for users who already have a row
+    seed_lesson(&pool, module.id(), "Functions", 3).await;
+    assert_eq!(cached().await, (1, 3));
+
+    // a stale cache is repaired by the rebuild
+    sqlx::query("UPDATE user_module_progress SET completed = 0, total = 0")
+        .execute(pool.mm().executor())
+        .await
+        .unwrap();
+    let module_id: Uuid = module.id();
+
+    Flow::new()
+        .step(signin_admin_action())
+        .step(
+            Action::new("rebuild", "POST", "/api/v1/admin/rebuild-progress-cache").assert_body(
+                |body| {
+                    let res: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(res, json!({ "rows": 1 }));
+                },
+            ),
+        )
+        .step(signin_action("cacheduser", "cacheduser").with_clear_cookies(true))
+        .step(
+            Action::new("progress_batch", "POST", "/api/v1/modules/progress/batch")
+                .with_body(json!({ "ids": [module_id] }))
+                .assert_body(|body| {
+                    let res: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(res[0]["completed"], 1);
+                    assert_eq!(res[0]["total"], 3);
+                }),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    assert_eq!(cached().await, (1, 3));
+}
@@ -0,0 +1,74 @@
+#![cfg(feature = "client")]
+
+mod common;
+use std::net::SocketAddr;
+
+use axum::http::StatusCode;
+use flern::client::{ClientError, FlernClient};
+use flern::model::DbConnection;
+use flern::web::dto::tasks::TaskCheckRequest;
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+use crate::common::{seed_answer, seed_lesson, seed_module, seed_task, setup_test_db};
+
+/// The client talks real HTTP, so the app gets a listener instead of the in-memory `TestServer`
+async fn spawn_server(db: &common::FlowDatabase) -> String {
+    let pool = DbConnection::from_pool(db.mm().executor().clone());
+    let (_, app) = flern::build_server_with_pool(pool).await.unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .unwrap();
+    });
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn client_end_to_end_test() {
+    let db = setup_test_db().await;
+    let module = seed_module(&db, "Basics", 1).await;
+    let lesson = seed_lesson(&db, module.id(), "Variables", 1).await;
+    let task = seed_task(&db, lesson.id(), "choice").await;
+    let right = seed_answer(&db, task.id(), "let", true).await;
+    seed_answer(&db, task.id(), "var", false).await;
+
+    let client = FlernClient::new(spawn_server(&db).await).unwrap();
+
+    // nothing works before signing in
+    let err = client.list_modules().await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::UNAUTHORIZED));
+
+    let err = client.signin("admin", "wrong").await.unwrap_err();
+    assert!(matches!(err, ClientError::Api { status: StatusCode::UNAUTHORIZED, .. }));
+
+    let user = client.signin("admin", "admin").await.unwrap();
+    assert_eq!(user.username(), "admin");
+
+    let modules = client.list_modules().await.unwrap();
+    assert_eq!(modules.len(), 1);
+    assert_eq!(modules[0].id, module.id());
+    assert_eq!(modules[0].lessons[0].id, lesson.id());
+    assert!(!modules[0].lessons[0].completed);
+
+    let fetched = client.get_lesson(lesson.id()).await.unwrap();
+    assert_eq!(fetched.title(), "Variables");
+    assert!(!fetched.status());
+
+    let check = TaskCheckRequest {
+        answer_id: Some(right.id()),
+        task_id: None,
+        task_type: "choice".to_string(),
+        user_answer: None,
+        answer_ids: None,
+        served_token: None,
+    };
+    let result = client.check_task(&check).await.unwrap();
+    assert!(result.is_correct);
+    assert!(client.get_lesson(lesson.id()).await.unwrap().status());
+
+    let err = client.get_lesson(Uuid::new_v4()).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+}
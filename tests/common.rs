@@ -1,8 +1,21 @@
-use std::collections::HashMap;
+// Shared by every integration test binary; each one only uses part of the harness.
+#![allow(dead_code)]
+
+use std::{borrow::Borrow, collections::HashMap};
 
 use axum::http::StatusCode;
 use axum_test::TestServer;
-use flern::{build_server_with_pool, model::DbConnection};
+use flern::{
+    Config, build_server_with_config, build_server_with_pool,
+    model::{
+        CrudRepository, DbConnection, ModelManager,
+        entity::{
+            Answer, AnswerCreate, Lesson, LessonCreate, LessonTask, LessonTaskCreate, Module,
+            ModuleCreate,
+        },
+    },
+    web::{AppState, AuthenticatedUser},
+};
 use serde::de::DeserializeOwned;
 use serde_json::{Value, json};
 use sqlx::{Executor, PgPool, postgres::PgPoolOptions};
@@ -11,6 +24,20 @@ use url::Url;
 use uuid::Uuid;
 
 pub async fn setup_test_db() -> FlowDatabase {
+    let db = setup_unseeded_db().await;
+    seed_admin(&db.pool).await;
+    db
+}
+
+/// Migrated database without the `admin` account `setup_test_db` adds
+pub async fn setup_unseeded_db() -> FlowDatabase {
+    let db = setup_empty_db().await;
+    sqlx::migrate!().run(&db.pool).await.unwrap();
+    db
+}
+
+/// Fresh database without any tables, for tests of the migrations themselves
+pub async fn setup_empty_db() -> FlowDatabase {
     let _ = dotenvy::dotenv();
     let db_name = format!("test_db_{}", Uuid::new_v4());
     let admin_url = std::env::var("TEST_DATABASE_ADMIN_URL")
@@ -34,11 +61,21 @@ pub async fn setup_test_db() -> FlowDatabase {
     let test_db_url = url.to_string();
 
     let pool = PgPool::connect(&test_db_url).await.unwrap();
-    sqlx::migrate!().run(&pool).await.unwrap();
-
     FlowDatabase { db_name, pool }
 }
 
+/// Flows authenticate as `admin`/`admin` (see `signin_admin_action`), so every fresh database
+/// gets that account.
+async fn seed_admin(pool: &PgPool) {
+    let hash = flern::auth::hash_password("admin").unwrap();
+    sqlx::query("INSERT INTO users (id, username, password_hash, role) VALUES ($1, 'admin', $2, 'admin')")
+        .bind(Uuid::new_v4())
+        .bind(hash)
+        .execute(pool)
+        .await
+        .unwrap();
+}
+
 /// `FlowDatabase` represents temporary postgres database. This database deletes on `Drop`(when it
 /// comes out of scope)
 // FIXME: Drop database even if the test panics
@@ -47,6 +84,58 @@ pub struct FlowDatabase {
     pool: PgPool,
 }
 
+impl FlowDatabase {
+    /// `ModelManager` over this database, for seeding and checking rows directly
+    pub fn mm(&self) -> ModelManager {
+        ModelManager::new(DbConnection::from_pool(self.pool.clone()))
+    }
+}
+
+// Content seeding, the API has no endpoints for creating course content
+
+pub async fn seed_module(db: &FlowDatabase, title: &str, order_index: i32) -> Module {
+    let module = ModuleCreate {
+        title: title.to_string(),
+        description: format!("{title} description"),
+        order_index: Some(order_index),
+    };
+    Module::create(&db.mm(), &AuthenticatedUser::admin(), module).await.unwrap()
+}
+
+pub async fn seed_lesson(db: &FlowDatabase, module_id: Uuid, title: &str, order_index: i32) -> Lesson {
+    let lesson = LessonCreate {
+        module_id,
+        title: title.to_string(),
+        content: format!("{title} content"),
+        order_index: Some(order_index),
+    };
+    Lesson::create(&db.mm(), &AuthenticatedUser::admin(), lesson).await.unwrap()
+}
+
+pub async fn seed_task(db: &FlowDatabase, lesson_id: Uuid, task_type: &str) -> LessonTask {
+    let task = LessonTaskCreate {
+        lesson_id,
+        task_type: task_type.to_string(),
+        question: "question".to_string(),
+        explanation: "explanation".to_string(),
+        case_insensitive: false,
+        normalize_whitespace: false,
+        min_seconds_before_check: 0,
+        reveal_on_fail: true,
+    };
+    LessonTask::create(&db.mm(), &AuthenticatedUser::admin(), task).await.unwrap()
+}
+
+pub async fn seed_answer(db: &FlowDatabase, task_id: Uuid, text: &str, is_correct: bool) -> Answer {
+    let answer = AnswerCreate {
+        task_id,
+        answer_text: text.to_string(),
+        image: String::new(),
+        is_correct: Some(is_correct),
+    };
+    Answer::create(&db.mm(), &AuthenticatedUser::admin(), answer).await.unwrap()
+}
+
 impl Drop for FlowDatabase {
     fn drop(&mut self) {
         let db_name = self.db_name.clone();
@@ -70,12 +159,48 @@ impl Drop for FlowDatabase {
 }
 
 pub async fn setup_server(pool: &FlowDatabase) -> TestServer {
+    setup_server_with_state(pool).await.1
+}
+
+/// Test server running with its own config, see `test_config`
+pub async fn setup_server_with_config(pool: &FlowDatabase, config: &'static Config) -> TestServer {
     let pool = DbConnection::from_pool(pool.pool.clone());
-    let server = build_server_with_pool(pool).await.unwrap().1;
+    let (_, server) = build_server_with_config(pool, config).await.unwrap();
     TestServer::new(server).unwrap()
 }
 
-#[derive(Debug)]
+/// Local `config.toml` with some `[app]` keys replaced, e.g. `[("public_catalog", true.into())]`.
+/// Leaked, since `AppState` holds the config for the whole run.
+pub fn test_config(app_overrides: &[(&str, toml::Value)]) -> &'static Config {
+    test_config_section("app", app_overrides)
+}
+
+/// Same as `test_config`, for keys of any other section, e.g. `[cors]`. The section is created
+/// when `config.toml` doesn't have it
+pub fn test_config_section(section: &str, overrides: &[(&str, toml::Value)]) -> &'static Config {
+    let source = std::fs::read_to_string("config.toml").unwrap();
+    let mut table: toml::Table = toml::from_str(&source).unwrap();
+    let section = table
+        .entry(section)
+        .or_insert_with(|| toml::Table::new().into())
+        .as_table_mut()
+        .unwrap();
+    for (key, value) in overrides {
+        section.insert(key.to_string(), value.clone());
+    }
+
+    let config = Config::from_toml(&toml::to_string(&table).unwrap()).unwrap();
+    Box::leak(Box::new(config))
+}
+
+/// Same as `setup_server`, but also hands out the `AppState` for inspecting caches etc.
+pub async fn setup_server_with_state(pool: &FlowDatabase) -> (AppState, TestServer) {
+    let pool = DbConnection::from_pool(pool.pool.clone());
+    let (state, server) = build_server_with_pool(pool).await.unwrap();
+    (state, TestServer::new(server).unwrap())
+}
+
+#[derive(Debug, Default)]
 pub struct FlowContext {
     pub store: HashMap<&'static str, Value>, // a way to pass data between steps
 }
@@ -95,7 +220,7 @@ impl FlowContext {
         self.store.get(key).expect("missing store key")
     }
 
-    pub fn get_json<'de, T>(&self, key: &str) -> T
+    pub fn get_json<T>(&self, key: &str) -> T
     where
         T: DeserializeOwned,
     {
@@ -105,20 +230,26 @@ impl FlowContext {
     }
 }
 
+type DynPathFn = Box<dyn Fn(&FlowContext) -> String + Send + Sync>;
+type DynBodyFn = Box<dyn Fn(&FlowContext) -> Value + Send + Sync>;
+type CookieAssertFn = Box<dyn Fn(&Cookie) + Send + Sync>;
+type BodyAssertFn = Box<dyn Fn(&str) + Send + Sync>;
+
 pub struct Action {
     #[allow(unused)]
     pub name: &'static str,
     pub method: &'static str,
     pub path: String,
-    pub dyn_path: Option<Box<dyn Fn(&FlowContext) -> String + Send + Sync>>,
+    pub dyn_path: Option<DynPathFn>,
     pub body: Option<Value>,
-    pub dyn_body: Option<Box<dyn Fn(&FlowContext) -> Value + Send + Sync>>,
+    pub dyn_body: Option<DynBodyFn>,
     pub expect: StatusCode,
     pub clear_cookies: bool,
     pub save_cookies: bool,
     pub query_params: Vec<(String, String)>,
-    pub cookie_asserts: Vec<(&'static str, Box<dyn Fn(&Cookie) + Send + Sync>)>,
-    pub body_asserts: Vec<Box<dyn Fn(&str) + Send + Sync>>,
+    pub headers: Vec<(&'static str, String)>,
+    pub cookie_asserts: Vec<(&'static str, CookieAssertFn)>,
+    pub body_asserts: Vec<BodyAssertFn>,
     pub save_as: Option<&'static str>,
 }
 
@@ -135,6 +266,7 @@ impl Action {
             clear_cookies: false,
             save_cookies: true,
             query_params: vec![],
+            headers: vec![],
             cookie_asserts: vec![],
             body_asserts: vec![],
             save_as: None,
@@ -167,6 +299,11 @@ impl Action {
         self
     }
 
+    pub fn with_header(mut self, name: &'static str, val: &str) -> Self {
+        self.headers.push((name, String::from(val)));
+        self
+    }
+
     pub fn with_dyn_path<F>(mut self, f: F) -> Self
     where
         F: Fn(&FlowContext) -> String + Send + Sync + 'static,
@@ -206,6 +343,7 @@ impl Action {
     }
 }
 
+#[derive(Default)]
 pub struct Flow {
     actions: Vec<Action>,
 }
@@ -220,7 +358,9 @@ impl Flow {
         self
     }
 
-    pub async fn run(self, server: &mut TestServer, _db: FlowDatabase) {
+    /// Runs every step in order. The database is taken by value or by reference, either way
+    /// it's kept alive until the flow is done.
+    pub async fn run<D: Borrow<FlowDatabase>>(self, server: &mut TestServer, _db: D) {
         let mut ctx = FlowContext::new(); // create new context for this flow
         for action in self.actions {
             println!("==> Running test action `{}`", action.name);
@@ -262,6 +402,10 @@ impl Flow {
                 }
             }
 
+            for (name, val) in action.headers {
+                req = req.add_header(name, val);
+            }
+
             let resp = req.await;
             resp.assert_status(action.expect);
             let cookies = resp.cookies();
@@ -295,10 +439,12 @@ impl Flow {
 // Common actions builders
 
 pub fn signup_action(name: &str, password: &str) -> Action {
-    Action::new("signup", "POST", "/api/v1/account/signup").with_body(json!({
-        "username": name,
-        "password": password,
-    }))
+    Action::new("signup", "POST", "/api/v1/account/signup")
+        .with_body(json!({
+            "username": name,
+            "password": password,
+        }))
+        .with_expect(StatusCode::CREATED)
 }
 
 pub fn signin_action(name: &str, password: &str) -> Action {
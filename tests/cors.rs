@@ -0,0 +1,67 @@
+mod common;
+use axum::http::{HeaderValue, Method, header};
+use axum_test::TestServer;
+
+use crate::common::{setup_server, setup_server_with_config, setup_test_db, test_config_section};
+
+async fn allow_origin(server: &TestServer, origin: &'static str) -> Option<HeaderValue> {
+    server
+        .get("/api/v1/account/verify")
+        .add_header(header::ORIGIN, HeaderValue::from_static(origin))
+        .await
+        .maybe_header(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+}
+
+#[tokio::test]
+async fn cors_allowed_origins_test() {
+    let pool = setup_test_db().await;
+    let config = test_config_section(
+        "cors",
+        &[
+            ("allowed_origins", vec!["https://app.example"].into()),
+            ("allow_credentials", true.into()),
+        ],
+    );
+    let server = setup_server_with_config(&pool, config).await;
+
+    assert!(allow_origin(&server, "https://evil.example").await.is_none());
+
+    // credentialed responses name the origin, never `*`
+    let response = server
+        .get("/api/v1/account/verify")
+        .add_header(header::ORIGIN, HeaderValue::from_static("https://app.example"))
+        .await;
+    assert_eq!(response.header(header::ACCESS_CONTROL_ALLOW_ORIGIN), "https://app.example");
+    assert_eq!(response.header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS), "true");
+    let exposed = response.header(header::ACCESS_CONTROL_EXPOSE_HEADERS);
+    assert!(exposed.to_str().unwrap().contains("x-request-id"), "exposed: {exposed:?}");
+
+    let preflight = server
+        .method(Method::OPTIONS, "/api/v1/account/signin")
+        .add_header(header::ORIGIN, HeaderValue::from_static("https://app.example"))
+        .add_header(header::ACCESS_CONTROL_REQUEST_METHOD, HeaderValue::from_static("POST"))
+        .add_header(
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            HeaderValue::from_static("content-type"),
+        )
+        .await;
+    preflight.assert_status_ok();
+    assert_eq!(preflight.header(header::ACCESS_CONTROL_ALLOW_ORIGIN), "https://app.example");
+    assert!(preflight.header(header::ACCESS_CONTROL_ALLOW_METHODS).to_str().unwrap().contains("POST"));
+    assert_eq!(preflight.header(header::ACCESS_CONTROL_ALLOW_HEADERS), "content-type");
+}
+
+#[tokio::test]
+async fn cors_any_origin_test() {
+    let pool = setup_test_db().await;
+    let config = test_config_section("cors", &[("allowed_origins", vec!["*"].into())]);
+    let server = setup_server_with_config(&pool, config).await;
+    assert_eq!(allow_origin(&server, "https://anyone.example").await.unwrap(), "*");
+
+    // tests are debug builds, which allow every origin without a [cors] section
+    let server = setup_server(&pool).await;
+    assert_eq!(
+        allow_origin(&server, "https://anyone.example").await.unwrap(),
+        "https://anyone.example"
+    );
+}
@@ -0,0 +1,143 @@
+use flern::web::doc::ApiDoc;
+use serde_json::Value;
+use utoipa::OpenApi;
+
+#[test]
+fn openapi_paths_tags_test() {
+    let doc: Value = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+    assert_eq!(doc["info"]["title"], "flern-api");
+    assert_eq!(doc["info"]["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(doc["servers"][0]["url"], "/");
+
+    let expected = [
+        ("/api/v1/account/signup", "post", "account"),
+        ("/api/v1/account/signin", "post", "account"),
+        ("/api/v1/account/refresh", "post", "account"),
+        ("/api/v1/account/verify", "get", "account"),
+        ("/api/v1/account/me", "get", "account"),
+        ("/api/v1/account/lessons", "get", "account"),
+        ("/api/v1/account/page", "get", "account"),
+        ("/api/v1/account/logout", "post", "account"),
+        ("/api/v1/account/{id}", "put", "account"),
+        ("/api/v1/account/{id}", "delete", "account"),
+        ("/api/v1/account/{id}/progress", "get", "account"),
+        ("/api/v1/account/{id}/role", "put", "account"),
+        ("/api/v1/modules/", "get", "modules"),
+        ("/api/v1/modules/", "post", "modules"),
+        ("/api/v1/modules/{module_id}", "put", "modules"),
+        ("/api/v1/modules/{module_id}", "delete", "modules"),
+        ("/api/v1/modules/{module_id}/toc", "get", "modules"),
+        ("/api/v1/modules/{module_id}/gating", "get", "modules"),
+        ("/api/v1/modules/{module_id}/gating", "put", "modules"),
+        ("/api/v1/modules/progress/batch", "post", "modules"),
+        ("/api/v1/lessons/", "post", "lessons"),
+        ("/api/v1/lessons/search", "get", "lessons"),
+        ("/api/v1/lessons/{lesson_id}", "get", "lessons"),
+        ("/api/v1/lessons/{lesson_id}/html", "get", "lessons"),
+        ("/api/v1/lessons/{lesson_id}", "put", "lessons"),
+        ("/api/v1/lessons/{lesson_id}", "delete", "lessons"),
+        ("/api/v1/lessons/{lesson_id}/status", "get", "lessons"),
+        ("/api/v1/lessons/{lesson_id}/done", "post", "lessons"),
+        ("/api/v1/lessons/{lesson_id}/tasks", "get", "lessons"),
+        ("/api/v1/lessons/{lesson_id}/next", "get", "lessons"),
+        ("/api/v1/tasks/", "post", "tasks"),
+        ("/api/v1/tasks/{task_id}", "put", "tasks"),
+        ("/api/v1/tasks/{task_id}", "delete", "tasks"),
+        ("/api/v1/tasks/{task_id}/answers", "post", "tasks"),
+        ("/api/v1/tasks/{task_id}/answers/{answer_id}", "put", "tasks"),
+        ("/api/v1/tasks/{task_id}/answers/{answer_id}", "delete", "tasks"),
+        ("/api/v1/tasks/check", "post", "tasks"),
+        ("/api/v1/tasks/attempts/sync", "post", "tasks"),
+        ("/api/v1/tasks/{task_id}/recompute-attempts", "post", "tasks"),
+        ("/api/v1/tasks/{task_id}/answer-distribution", "get", "tasks"),
+        ("/api/v1/progress/{token}", "get", "progress"),
+        ("/api/v1/progress/share", "post", "progress"),
+        ("/api/v1/progress/resume-link", "post", "progress"),
+        ("/api/v1/progress/resume/{token}", "get", "progress"),
+        ("/api/v1/progress/leaderboard", "get", "progress"),
+        ("/api/v1/admin/tasks-by-type", "get", "admin"),
+        ("/api/v1/admin/rebuild-progress-cache", "post", "admin"),
+        ("/api/v1/admin/gc-uploads", "post", "admin"),
+    ];
+    for (path, method, tag) in expected {
+        let operation = &doc["paths"][path][method];
+        assert!(operation.is_object(), "{method} {path} is missing");
+        assert_eq!(operation["tags"][0], tag, "{method} {path} has the wrong tag");
+    }
+
+    // every operation is tagged with one of the declared tags
+    let declared: Vec<&str> = doc["tags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap())
+        .collect();
+    for (path, item) in doc["paths"].as_object().unwrap() {
+        for (method, operation) in item.as_object().unwrap() {
+            let tag = operation["tags"][0].as_str().unwrap_or_default();
+            assert!(declared.contains(&tag), "{method} {path} has undeclared tag `{tag}`");
+        }
+    }
+}
+
+#[test]
+fn openapi_error_code_test() {
+    let doc: Value = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    let schemas = &doc["components"]["schemas"];
+
+    // nullable, so the reference sits next to `null`
+    let code = &schemas["ErrorResponse"]["properties"]["code"];
+    assert_eq!(code["oneOf"][1]["$ref"], "#/components/schemas/ErrorCode", "{code}");
+    let codes: Vec<&str> = schemas["ErrorCode"]["enum"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap())
+        .collect();
+    for code in [
+        "ResourceNotFound",
+        "AuthenticationInvalidCredentials",
+        "UserTooManyRequests",
+        "ServerIoError",
+    ] {
+        assert!(codes.contains(&code), "{code} is missing from {codes:?}");
+    }
+}
+
+#[test]
+fn openapi_modules_list_schema_test() {
+    let doc: Value = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+    let response = &doc["paths"]["/api/v1/modules/"]["get"]["responses"]["200"];
+    let body = &response["content"]["application/json"]["schema"];
+    assert_eq!(body["type"], "array", "{body}");
+    assert_eq!(body["items"]["$ref"], "#/components/schemas/ModuleWithLessons", "{body}");
+
+    let module = &doc["components"]["schemas"]["ModuleWithLessons"];
+    let lessons = &module["properties"]["lessons"];
+    assert_eq!(lessons["type"], "array", "{module}");
+    assert_eq!(lessons["items"]["$ref"], "#/components/schemas/LessonShort", "{module}");
+}
+
+#[test]
+fn openapi_author_routes_test() {
+    let doc: Value = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    let operation = |path: &str, method: &str| doc["paths"][path][method].clone();
+
+    // instructors may author content, deleting it stays with admins
+    for (path, method) in [
+        ("/api/v1/modules/", "post"),
+        ("/api/v1/lessons/{lesson_id}", "put"),
+        ("/api/v1/tasks/{task_id}/answers/{answer_id}", "put"),
+    ] {
+        let op = operation(path, method);
+        let description = op["description"].as_str().unwrap_or_default();
+        assert!(description.ends_with("Admin or instructor"), "{method} {path}: {description}");
+        assert_eq!(op["responses"]["403"]["description"], "You're not allowed to author content");
+    }
+    for (path, method) in [("/api/v1/modules/{module_id}", "delete"), ("/api/v1/lessons/{lesson_id}", "delete")] {
+        let description = operation(path, method)["description"].as_str().unwrap_or_default().to_string();
+        assert!(description.ends_with("Admin only"), "{method} {path}: {description}");
+    }
+}
@@ -0,0 +1,630 @@
+mod common;
+use flern::model::{
+    ContentSort, CrudRepository, CursorPaginatableRepository, DatabaseError, HasOwner, ModelManager,
+    OrderPaginatableRepository, PaginatableRepository, ResourceType, ResourceTyped, Sort,
+    SortDirection, check_access,
+};
+use flern::model::entity::{
+    Answer, AnswerCreate, Lesson, LessonCreate, LessonTask, LessonTaskCreate, LessonWithStatusRow, Module, ModuleCreate, ModuleListOptions, ModuleSortField,
+    ModuleWithLessonsRow, ProgressStatus, ProgressToken, ProgressTokenCreate, RefreshToken, RefreshTokenCreate, ResumeToken, ResumeTokenCreate,
+    UserEntity, UserEntityCreateUpdate, UserModuleProgress, UserProgress, UserProgressCreate,
+    UserSortField, UserTaskAttempt, UserTaskAttemptCreate,
+};
+use flern::web::{AuthenticatedUser, UserRole};
+use uuid::Uuid;
+
+use crate::common::{seed_answer, seed_lesson, seed_module, seed_task, setup_test_db};
+
+#[tokio::test]
+async fn find_by_id_missing_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    assert!(UserEntity::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(UserEntity::find_by_username(&mm, &actor, "missing").await.unwrap().is_none());
+    assert!(Module::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(Lesson::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(LessonWithStatusRow::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(LessonTask::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(Answer::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(UserProgress::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(UserTaskAttempt::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(ProgressToken::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(ProgressToken::find_by_token(&mm, &actor, "missing").await.unwrap().is_none());
+    assert!(ResumeToken::find_by_id(&mm, &actor, Uuid::new_v4()).await.unwrap().is_none());
+    assert!(ResumeToken::find_by_token(&mm, &actor, "missing").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn lesson_page_by_order_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    let module = seed_module(&db, "Basics", 1).await;
+    for (title, order_index) in [("A", 10), ("B", 20), ("C", 30), ("D", 40)] {
+        seed_lesson(&db, module.id(), title, order_index).await;
+    }
+
+    let first = Lesson::page_by_order(&mm, &actor, None, 2).await.unwrap();
+    let titles: Vec<&str> = first.items.iter().map(Lesson::title).collect();
+    assert_eq!(titles, ["A", "B"]);
+
+    // lands before the cursor, offset pagination would now return "B" again
+    seed_lesson(&db, module.id(), "A2", 15).await;
+
+    let second = Lesson::page_by_order(&mm, &actor, first.next, 2).await.unwrap();
+    let titles: Vec<&str> = second.items.iter().map(Lesson::title).collect();
+    assert_eq!(titles, ["C", "D"]);
+
+    let last = Lesson::page_by_order(&mm, &actor, second.next, 2).await.unwrap();
+    assert!(last.items.is_empty());
+    assert!(last.next.is_none());
+}
+
+#[tokio::test]
+async fn task_type_constraint_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    let module = seed_module(&db, "Basics", 1).await;
+    let lesson = seed_lesson(&db, module.id(), "Variables", 1).await;
+    let task = LessonTaskCreate {
+        lesson_id: lesson.id(),
+        task_type: "essay".to_string(),
+        question: "question".to_string(),
+        explanation: "explanation".to_string(),
+        case_insensitive: false,
+        normalize_whitespace: false,
+        min_seconds_before_check: 0,
+        reveal_on_fail: true,
+    };
+
+    let err = LessonTask::create(&mm, &actor, task).await.unwrap_err();
+    assert!(
+        matches!(&err, DatabaseError::ConstraintViolation { constraint, .. } if constraint == "tasks_task_type_check"),
+        "{err}"
+    );
+}
+
+#[test]
+fn resource_type_round_trip_test() {
+    let types = [
+        UserEntity::get_resource_type(),
+        Module::get_resource_type(),
+        Lesson::get_resource_type(),
+        LessonTask::get_resource_type(),
+        Answer::get_resource_type(),
+        UserProgress::get_resource_type(),
+        UserModuleProgress::get_resource_type(),
+        UserTaskAttempt::get_resource_type(),
+        ProgressToken::get_resource_type(),
+        ResumeToken::get_resource_type(),
+        RefreshToken::get_resource_type(),
+    ];
+
+    // every entity has its own type, and every type belongs to an entity
+    assert_eq!(types, ResourceType::ALL);
+    for r#type in types {
+        assert_eq!(r#type.to_string().parse::<ResourceType>().unwrap(), r#type);
+    }
+
+    assert_eq!(UserTaskAttempt::get_resource_type().to_string(), "user_task_attempt");
+    assert!("nonsense".parse::<ResourceType>().is_err());
+}
+
+#[tokio::test]
+async fn model_manager_with_transaction_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+
+    let insert = |title: &'static str| {
+        sqlx::query("INSERT INTO modules (id, title, description, order_index) VALUES ($1, $2, '', 0)")
+            .bind(Uuid::new_v4())
+            .bind(title)
+    };
+    let count = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM modules")
+            .fetch_one(mm.executor())
+            .await
+            .unwrap()
+    };
+
+    // both inserts go away when the closure fails after them
+    let result: Result<(), DatabaseError> = mm
+        .with_transaction(|tx| {
+            Box::pin(async move {
+                insert("first").execute(&mut **tx).await?;
+                insert("second").execute(&mut **tx).await?;
+                Err(DatabaseError::Forbidden)
+            })
+        })
+        .await;
+    assert!(matches!(result, Err(DatabaseError::Forbidden)));
+    assert_eq!(count().await, 0);
+
+    let inserted = mm
+        .with_transaction(|tx| {
+            Box::pin(async move {
+                insert("first").execute(&mut **tx).await?;
+                insert("second").execute(&mut **tx).await?;
+                Ok(2)
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!(inserted, 2);
+    assert_eq!(count().await, 2);
+
+    // a plain `begin` rolls back when dropped
+    {
+        let mut tx = mm.begin().await.unwrap();
+        insert("third").execute(&mut *tx).await.unwrap();
+    }
+    assert_eq!(count().await, 2);
+}
+
+#[tokio::test]
+async fn task_create_with_answers_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    let module = seed_module(&db, "Basics", 1).await;
+    let lesson = seed_lesson(&db, module.id(), "Variables", 1).await;
+    let task = || LessonTaskCreate {
+        lesson_id: lesson.id(),
+        task_type: "choice".to_string(),
+        question: "Which keyword declares a variable?".to_string(),
+        explanation: "explanation".to_string(),
+        case_insensitive: false,
+        normalize_whitespace: false,
+        min_seconds_before_check: 0,
+        reveal_on_fail: true,
+    };
+    let answer = |text: &str, is_correct| AnswerCreate {
+        task_id: Uuid::nil(),
+        answer_text: text.to_string(),
+        image: String::new(),
+        is_correct: Some(is_correct),
+    };
+
+    // postgres rejects NUL in text, so the second answer fails after the task and first answer went in
+    let err = LessonTask::create_with_answers(
+        &mm,
+        &actor,
+        task(),
+        vec![answer("let", true), answer("m\0ut", false)],
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(err, DatabaseError::SqlxError(_)), "{err}");
+    assert_eq!(LessonTask::count(&mm, &actor).await.unwrap(), 0);
+    assert_eq!(Answer::count(&mm, &actor).await.unwrap(), 0);
+
+    let (task, answers) = LessonTask::create_with_answers(
+        &mm,
+        &actor,
+        task(),
+        vec![answer("let", true), answer("mut", false)],
+    )
+    .await
+    .unwrap();
+    assert_eq!(answers.len(), 2);
+    assert!(answers.iter().all(|a| a.task_id() == task.id()));
+
+    let stored = Answer::find_all_by_task(&mm, &actor, task.id()).await.unwrap();
+    assert_eq!(stored.len(), 2);
+}
+
+#[tokio::test]
+async fn update_touches_updated_at_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    let module = seed_module(&db, "Basics", 1).await;
+    let lesson = seed_lesson(&db, module.id(), "Variables", 1).await;
+    let task = seed_task(&db, lesson.id(), "choice").await;
+    let answer = seed_answer(&db, task.id(), "let", true).await;
+    let user = UserEntity::create(
+        &mm,
+        &actor,
+        UserEntityCreateUpdate {
+            username: "stamped".to_string(),
+            password_hash: String::new(),
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(module.created_at(), module.updated_at());
+    assert_eq!(user.created_at(), user.updated_at());
+
+    let module_updated_at = *module.updated_at();
+    let module = module
+        .update(
+            &mm,
+            &actor,
+            ModuleCreate {
+                title: "Basics 2".to_string(),
+                description: String::new(),
+                order_index: Some(1),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(*module.updated_at() > module_updated_at);
+    let stored = Module::find_by_id(&mm, &actor, module.id()).await.unwrap().unwrap();
+    assert_eq!(stored.updated_at(), module.updated_at());
+    assert_eq!(stored.created_at(), module.created_at());
+
+    let lesson_updated_at = *lesson.updated_at();
+    let data = LessonCreate {
+        module_id: module.id(),
+        title: "Constants".to_string(),
+        content: String::new(),
+        order_index: Some(1),
+    };
+    let lesson = lesson.update(&mm, &actor, data).await.unwrap();
+    assert!(*lesson.updated_at() > lesson_updated_at);
+
+    let task_updated_at = *task.updated_at();
+    let data = LessonTaskCreate {
+        lesson_id: lesson.id(),
+        task_type: "choice".to_string(),
+        question: "new question".to_string(),
+        explanation: String::new(),
+        case_insensitive: false,
+        normalize_whitespace: false,
+        min_seconds_before_check: 0,
+        reveal_on_fail: true,
+    };
+    let task = task.update(&mm, &actor, data).await.unwrap();
+    assert!(*task.updated_at() > task_updated_at);
+
+    let answer_updated_at = *answer.updated_at();
+    let data = AnswerCreate {
+        task_id: task.id(),
+        answer_text: "const".to_string(),
+        image: String::new(),
+        is_correct: Some(true),
+    };
+    let answer = answer.update(&mm, &actor, data).await.unwrap();
+    assert!(*answer.updated_at() > answer_updated_at);
+
+    let user_updated_at = *user.updated_at();
+    let data = UserEntityCreateUpdate {
+        username: "restamped".to_string(),
+        password_hash: String::new(),
+    };
+    let user = user.update(&mm, &actor, data).await.unwrap();
+    assert!(*user.updated_at() > user_updated_at);
+    assert!(user.updated_at() > user.created_at());
+}
+
+#[test]
+fn sort_parse_test() {
+    let sort: Sort<UserSortField> = "username:desc".parse().unwrap();
+    assert_eq!(sort, Sort::new(UserSortField::Username, SortDirection::Desc));
+    let sort: Sort<ModuleSortField> = "title".parse().unwrap();
+    assert_eq!(sort, Sort::new(ModuleSortField::Title, SortDirection::Asc));
+
+    // only allow-listed columns make it into the query
+    assert!("password_hash:asc".parse::<Sort<UserSortField>>().is_err());
+    assert!("title:asc".parse::<Sort<UserSortField>>().is_err());
+    assert!("username:up".parse::<Sort<UserSortField>>().is_err());
+    assert!("id; DROP TABLE users".parse::<Sort<ModuleSortField>>().is_err());
+}
+
+#[tokio::test]
+async fn user_list_sort_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    for username in ["carol", "alice", "bob"] {
+        let data = UserEntityCreateUpdate {
+            username: username.to_string(),
+            password_hash: String::new(),
+        };
+        UserEntity::create(&mm, &actor, data).await.unwrap();
+    }
+
+    let asc = Sort::new(UserSortField::Username, SortDirection::Asc);
+    let users = UserEntity::list(&mm, &actor, 10, 0, Some(asc)).await.unwrap();
+    let names: Vec<&str> = users.iter().map(UserEntity::username).collect();
+    assert_eq!(names, ["admin", "alice", "bob", "carol"]);
+
+    let desc = Sort::new(UserSortField::Username, SortDirection::Desc);
+    let page = UserEntity::page(&mm, &actor, 2, 1, Some(desc)).await.unwrap();
+    let names: Vec<&str> = page.items.iter().map(UserEntity::username).collect();
+    assert_eq!(names, ["bob", "alice"]);
+    assert_eq!(page.total, 4);
+}
+
+#[tokio::test]
+async fn user_create_with_role_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    for (username, role) in [("teacher", "instructor"), ("root", "admin"), ("student", "user")] {
+        let data = UserEntityCreateUpdate {
+            username: username.to_string(),
+            password_hash: String::new(),
+        };
+        let role: UserRole = role.parse().unwrap();
+        let created = UserEntity::create_with_role(&mm, &actor, data, &role).await.unwrap();
+        assert_eq!(created.role(), role);
+
+        let stored = UserEntity::find_by_username(&mm, &actor, username).await.unwrap().unwrap();
+        assert_eq!(stored.role(), role);
+    }
+
+    // `--role` is parsed strictly, unknown values never fall back to `user`
+    assert!("owner".parse::<UserRole>().is_err());
+    assert!("Admin".parse::<UserRole>().is_err());
+}
+
+#[tokio::test]
+async fn module_list_sort_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+
+    for (title, order_index) in [("Basics", 2), ("Advanced", 3), ("Intro", 1)] {
+        seed_module(&db, title, order_index).await;
+    }
+
+    let modules = Module::list(&mm, &actor, 10, 0, None).await.unwrap();
+    let titles: Vec<&str> = modules.iter().map(Module::title).collect();
+    assert_eq!(titles, ["Intro", "Basics", "Advanced"]);
+
+    let desc = Sort::new(ModuleSortField::OrderIndex, SortDirection::Desc);
+    let modules = Module::list(&mm, &actor, 10, 0, Some(desc)).await.unwrap();
+    let titles: Vec<&str> = modules.iter().map(Module::title).collect();
+    assert_eq!(titles, ["Advanced", "Basics", "Intro"]);
+
+    let by_title = Sort::new(ModuleSortField::Title, SortDirection::Asc);
+    let page = Module::page(&mm, &actor, 10, 0, Some(by_title)).await.unwrap();
+    let titles: Vec<&str> = page.items.iter().map(Module::title).collect();
+    assert_eq!(titles, ["Advanced", "Basics", "Intro"]);
+}
+
+#[tokio::test]
+async fn attempt_page_after_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+
+    let module = seed_module(&db, "Basics", 1).await;
+    let lesson = seed_lesson(&db, module.id(), "Variables", 1).await;
+    let task = seed_task(&db, lesson.id(), "choice").await;
+    let answer = seed_answer(&db, task.id(), "let", true).await;
+    let data = UserEntityCreateUpdate {
+        username: "attempter".to_string(),
+        password_hash: String::new(),
+    };
+    let user = UserEntity::create(&mm, &AuthenticatedUser::admin(), data).await.unwrap();
+    let actor = AuthenticatedUser::new(user.id(), UserRole::User);
+
+    for _ in 0..7 {
+        let attempt = UserTaskAttemptCreate::new(user.id(), task.id(), Some(answer.id()), true);
+        UserTaskAttempt::create(&mm, &actor, attempt).await.unwrap();
+    }
+
+    let mut by_offset = Vec::new();
+    for offset in (0..7).step_by(3) {
+        let page = UserTaskAttempt::page(&mm, &actor, 3, offset, None).await.unwrap();
+        by_offset.extend(page.items.iter().map(UserTaskAttempt::id));
+    }
+
+    let mut by_cursor = Vec::new();
+    let mut page_sizes = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = UserTaskAttempt::page_after(&mm, &actor, cursor, 3).await.unwrap();
+        assert_eq!(page.total, 7);
+        page_sizes.push(page.items.len());
+        by_cursor.extend(page.items.iter().map(UserTaskAttempt::id));
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+
+    assert_eq!(page_sizes, [3, 3, 1]);
+    assert_eq!(by_cursor.len(), 7);
+    assert_eq!(by_cursor, by_offset);
+}
+
+#[tokio::test]
+async fn check_access_owner_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let admin = AuthenticatedUser::admin();
+
+    let create_user = |username: &'static str| {
+        let mm = mm.clone();
+        async move {
+            let data = UserEntityCreateUpdate {
+                username: username.to_string(),
+                password_hash: "hash".to_string(),
+            };
+            let user = UserEntity::create(&mm, &AuthenticatedUser::admin(), data).await.unwrap();
+            (AuthenticatedUser::new(user.id(), UserRole::User), user)
+        }
+    };
+    let (owner, owner_entity) = create_user("owner").await;
+    let (stranger, _) = create_user("stranger").await;
+
+    let module = seed_module(&db, "Basics", 1).await;
+    let lesson = seed_lesson(&db, module.id(), "Variables", 1).await;
+    let task = seed_task(&db, lesson.id(), "choice").await;
+    let answer = seed_answer(&db, task.id(), "let", true).await;
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+    let progress =
+        UserProgress::create(&mm, &owner, UserProgressCreate::new(owner.user_id(), lesson.id(), ProgressStatus::Done))
+            .await
+            .unwrap();
+    let attempt = UserTaskAttempt::create(
+        &mm,
+        &owner,
+        UserTaskAttemptCreate {
+            user_id: owner.user_id(),
+            task_id: task.id(),
+            selected_answer_id: Some(answer.id()),
+            is_correct: true,
+        },
+    )
+    .await
+    .unwrap();
+    let progress_token = ProgressToken::create(
+        &mm,
+        &owner,
+        ProgressTokenCreate { token: "progress".to_string(), user_id: owner.user_id(), expires_at, single_use: false },
+    )
+    .await
+    .unwrap();
+    let resume_token = ResumeToken::create(
+        &mm,
+        &owner,
+        ResumeTokenCreate {
+            token: "resume".to_string(),
+            user_id: owner.user_id(),
+            lesson_id: lesson.id(),
+            expires_at,
+        },
+    )
+    .await
+    .unwrap();
+    let refresh_token = RefreshToken::create(
+        &mm,
+        &owner,
+        RefreshTokenCreate { token: "refresh".to_string(), user_id: owner.user_id(), expires_at },
+    )
+    .await
+    .unwrap();
+
+    async fn allowed<T: HasOwner + Sync>(
+        mm: &ModelManager,
+        actor: &AuthenticatedUser,
+        resource: &T,
+    ) -> bool {
+        match check_access(mm, actor, resource).await {
+            Ok(()) => true,
+            Err(DatabaseError::Forbidden) => false,
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    // user-owned: the owner and admins get in, anyone else is forbidden
+    macro_rules! assert_user_owned {
+        ($($resource:expr),+ $(,)?) => {$(
+            assert!(allowed(&mm, &owner, $resource).await, "owner denied {}", stringify!($resource));
+            assert!(allowed(&mm, &admin, $resource).await, "admin denied {}", stringify!($resource));
+            assert!(!allowed(&mm, &stranger, $resource).await, "stranger allowed {}", stringify!($resource));
+        )+};
+    }
+    assert_user_owned!(
+        &owner_entity,
+        &progress,
+        &attempt,
+        &progress_token,
+        &resume_token,
+        &refresh_token,
+    );
+
+    // admin-owned content: no user may change it, whoever they are
+    macro_rules! assert_admin_owned {
+        ($($resource:expr),+ $(,)?) => {$(
+            assert!(allowed(&mm, &admin, $resource).await, "admin denied {}", stringify!($resource));
+            assert!(!allowed(&mm, &owner, $resource).await, "user allowed {}", stringify!($resource));
+            assert!(!allowed(&mm, &stranger, $resource).await, "user allowed {}", stringify!($resource));
+        )+};
+    }
+    assert_admin_owned!(&module, &lesson, &task, &answer);
+}
+
+#[tokio::test]
+async fn token_update_unsupported_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let actor = AuthenticatedUser::admin();
+    let user = UserEntity::create(
+        &mm,
+        &actor,
+        UserEntityCreateUpdate { username: "tokens".to_string(), password_hash: "hash".to_string() },
+    )
+    .await
+    .unwrap();
+    let lesson = seed_lesson(&db, seed_module(&db, "Basics", 1).await.id(), "Variables", 1).await;
+    let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+
+    let data = || ResumeTokenCreate {
+        token: "resume".to_string(),
+        user_id: user.id(),
+        lesson_id: lesson.id(),
+        expires_at,
+    };
+    let resume_token = ResumeToken::create(&mm, &actor, data()).await.unwrap();
+    let result = resume_token.update(&mm, &actor, data()).await;
+    assert!(matches!(result, Err(DatabaseError::Unsupported(_))));
+
+    let data = || RefreshTokenCreate { token: "refresh".to_string(), user_id: user.id(), expires_at };
+    let refresh_token = RefreshToken::create(&mm, &actor, data()).await.unwrap();
+    let result = refresh_token.update(&mm, &actor, data()).await;
+    assert!(matches!(result, Err(DatabaseError::Unsupported(_))));
+}
+
+#[tokio::test]
+async fn progress_status_completion_test() {
+    let db = setup_test_db().await;
+    let mm = db.mm();
+    let data = UserEntityCreateUpdate {
+        username: "learner".to_string(),
+        password_hash: "hash".to_string(),
+    };
+    let user = UserEntity::create(&mm, &AuthenticatedUser::admin(), data).await.unwrap();
+    let actor = AuthenticatedUser::new(user.id(), UserRole::User);
+
+    let module = seed_module(&db, "Basics", 1).await;
+    let not_started = seed_lesson(&db, module.id(), "Variables", 1).await;
+    let in_progress = seed_lesson(&db, module.id(), "Loops", 2).await;
+    let done = seed_lesson(&db, module.id(), "Functions", 3).await;
+
+    for (lesson, status) in [
+        (&not_started, ProgressStatus::NotStarted),
+        (&in_progress, ProgressStatus::InProgress),
+        (&done, ProgressStatus::Done),
+    ] {
+        let progress = UserProgress::create(
+            &mm,
+            &actor,
+            UserProgressCreate::new(actor.user_id(), lesson.id(), status),
+        )
+        .await
+        .unwrap();
+        assert_eq!(progress.status(), status);
+    }
+
+    // only done lessons count as completed
+    for (lesson, completed) in [(&not_started, false), (&in_progress, false), (&done, true)] {
+        let row = LessonWithStatusRow::find_by_id(&mm, &actor, lesson.id()).await.unwrap().unwrap();
+        assert_eq!(row.status, completed, "{}", lesson.title());
+        assert_eq!(Lesson::is_completed_by(&mm, &actor, lesson.id()).await.unwrap(), Some(completed));
+    }
+
+    let modules = ModuleWithLessonsRow::fetch_all(&mm, &actor, ContentSort::OrderIndex, ModuleListOptions::default())
+        .await
+        .unwrap();
+    let completed: Vec<bool> = modules[0].lessons.as_array().unwrap()
+        .iter()
+        .map(|lesson| lesson["completed"].as_bool().unwrap())
+        .collect();
+    assert_eq!(completed, [false, false, true]);
+
+    assert_eq!(UserProgress::count_completed(&mm, &actor).await.unwrap(), 1);
+}
@@ -0,0 +1,150 @@
+mod common;
+use std::path::Path;
+
+use flern::model::{CourseImport, CourseImportError, ImportSummary};
+use flern::web::AuthenticatedUser;
+use serde_json::{Value, json};
+
+use crate::common::{seed_module, setup_test_db};
+
+const FIXTURE: &str = "tests/fixtures/course/course.yaml";
+
+async fn count(db: &common::FlowDatabase, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+        .fetch_one(db.mm().executor())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn course_import_test() {
+    let db = setup_test_db().await;
+    seed_module(&db, "Existing", 4).await;
+
+    let course = CourseImport::from_path(Path::new(FIXTURE)).unwrap();
+    let summary = course.import(&db.mm(), &AuthenticatedUser::admin()).await.unwrap();
+
+    assert_eq!(summary, ImportSummary { modules: 2, lessons: 3, tasks: 3, answers: 6 });
+    assert_eq!(count(&db, "modules").await, 3);
+    assert_eq!(count(&db, "lessons").await, 3);
+    assert_eq!(count(&db, "tasks").await, 3);
+    assert_eq!(count(&db, "task_answers").await, 6);
+
+    // modules go after the existing ones, lessons follow the file
+    let order: Vec<(String, i32)> =
+        sqlx::query_as("SELECT title, order_index FROM modules ORDER BY order_index")
+            .fetch_all(db.mm().executor())
+            .await
+            .unwrap();
+    assert_eq!(
+        order,
+        vec![("Existing".into(), 4), ("Rust basics".into(), 5), ("Ownership".into(), 6)]
+    );
+    let content: String = sqlx::query_scalar("SELECT content FROM lessons WHERE title = 'Variables' AND order_index = 1")
+        .fetch_one(db.mm().executor())
+        .await
+        .unwrap();
+    assert!(content.starts_with("# Variables"));
+
+    // importing the same course again would duplicate the module titles
+    let again = CourseImport::from_path(Path::new(FIXTURE)).unwrap();
+    let error = again.import(&db.mm(), &AuthenticatedUser::admin()).await.unwrap_err();
+    assert!(matches!(error, CourseImportError::Invalid(_)), "{error}");
+    assert_eq!(count(&db, "modules").await, 3);
+}
+
+#[tokio::test]
+async fn course_import_invalid_test() {
+    let db = setup_test_db().await;
+
+    let invalid = [
+        ("modules: []", "no modules"),
+        (
+            "modules:\n  - title: A\n    lessons:\n      - title: L\n        content: x\n        tasks:\n          - task_type: essay\n            question: Q\n            answers: [{ answer_text: a, is_correct: true }]",
+            "modules[0].lessons[0].tasks[0]: unsupported task_type",
+        ),
+        (
+            "modules:\n  - title: A\n    lessons:\n      - title: L\n        content: x\n        tasks:\n          - task_type: choice\n            question: ' '",
+            "modules[0].lessons[0].tasks[0]: question is empty",
+        ),
+        (
+            "modules:\n  - title: A\n  - title: A",
+            "modules[1]: title is used by another module",
+        ),
+        (
+            "modules:\n  - title: A\n    lessons:\n      - title: L",
+            "modules[0].lessons[0]: content or file is required",
+        ),
+    ];
+    let dir = tempfile::tempdir().unwrap();
+    for (yaml, expected) in invalid {
+        let path = dir.path().join("course.yaml");
+        std::fs::write(&path, yaml).unwrap();
+        let course = CourseImport::from_path(&path).unwrap();
+        let error = course.import(&db.mm(), &AuthenticatedUser::admin()).await.unwrap_err();
+        assert!(error.to_string().contains(expected), "{error}");
+    }
+
+    // broken courses are refused before anything is written
+    assert_eq!(count(&db, "modules").await, 0);
+
+    let path = dir.path().join("course.yaml");
+    std::fs::write(&path, "modules:\n  - title: A\n    lessons:\n      - title: L\n        file: missing.md").unwrap();
+    assert!(matches!(CourseImport::from_path(&path), Err(CourseImportError::Read { .. })));
+    std::fs::write(&path, "modules:\n  - title: A\n    colour: red").unwrap();
+    assert!(matches!(CourseImport::from_path(&path), Err(CourseImportError::Parse { .. })));
+}
+
+/// Tasks and answers have no position, so their order isn't part of the comparison
+fn normalized(course: &CourseImport) -> Value {
+    let mut value = serde_json::to_value(course).unwrap();
+    for module in value["modules"].as_array_mut().unwrap() {
+        for lesson in module["lessons"].as_array_mut().unwrap() {
+            let tasks = lesson["tasks"].as_array_mut().unwrap();
+            for task in tasks.iter_mut() {
+                task["answers"].as_array_mut().unwrap().sort_by_key(|a| a.to_string());
+            }
+            tasks.sort_by_key(|t| t.to_string());
+        }
+    }
+    value
+}
+
+#[tokio::test]
+async fn course_export_round_trip_test() {
+    let source = setup_test_db().await;
+    let actor = AuthenticatedUser::admin();
+    let course = CourseImport::from_path(Path::new(FIXTURE)).unwrap();
+    course.import(&source.mm(), &actor).await.unwrap();
+    sqlx::query("INSERT INTO user_progress (id, user_id, lesson_id, status) SELECT gen_random_uuid(), u.id, l.id, 'done' FROM users u, lessons l")
+        .execute(source.mm().executor())
+        .await
+        .unwrap();
+
+    let exported = CourseImport::from_database(&source.mm(), &actor).await.unwrap();
+    let json = serde_json::to_string_pretty(&exported).unwrap();
+    assert!(!json.contains("status") && !json.contains("user_id"), "progress leaked into {json}");
+
+    let expected = json!({
+        "title": "Functions",
+        "order_index": 2,
+        "content": "# Functions\n\nDeclared with `fn`, the last expression is the return value.\n",
+    });
+    let functions = &serde_json::from_str::<Value>(&json).unwrap()["modules"][0]["lessons"][1];
+    for (key, value) in expected.as_object().unwrap() {
+        assert_eq!(&functions[key], value);
+    }
+
+    // the export imports into an empty database as the same course
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("course.json");
+    std::fs::write(&path, &json).unwrap();
+    let target = setup_test_db().await;
+    let summary = CourseImport::from_path(&path).unwrap().import(&target.mm(), &actor).await.unwrap();
+    assert_eq!(summary, ImportSummary { modules: 2, lessons: 3, tasks: 3, answers: 6 });
+
+    let reexported = CourseImport::from_database(&target.mm(), &actor).await.unwrap();
+    assert_eq!(normalized(&reexported), normalized(&exported));
+    assert_eq!(reexported.modules[0].title, "Rust basics");
+    assert_eq!(reexported.modules[1].order_index, Some(2));
+}
@@ -0,0 +1,578 @@
+mod common;
+use axum::http::{HeaderValue, StatusCode, header};
+use flern::model::entity::{UserEntity, UserProgress};
+use flern::web::{AuthenticatedUser, UserRole};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::common::{
+    Action, Flow, FlowContext, seed_answer, seed_lesson, seed_module, seed_task, setup_server,
+    setup_server_with_config, setup_test_db, signin_action, signin_admin_action, signup_action,
+    test_config,
+};
+
+#[tokio::test]
+async fn route_lesson_missing_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    let missing = Uuid::new_v4();
+
+    Flow::new()
+        .step(signup_action("lessonmissing", "lessonmissing"))
+        .step(
+            Action::new("lesson_get", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}", missing))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", missing))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(
+            Action::new("lesson_tasks", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/tasks", missing))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(
+            Action::new("lesson_next", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/next", missing))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_module_gating_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let first = seed_module(&pool, "Basics", 1).await;
+    let first_a = seed_lesson(&pool, first.id(), "Variables", 1).await.id();
+    seed_lesson(&pool, first.id(), "Loops", 2).await;
+    let second = seed_module(&pool, "Advanced", 2).await.id();
+    let second_a = seed_lesson(&pool, second, "Traits", 1).await.id();
+    seed_lesson(&pool, second, "Generics", 2).await;
+    let task = seed_task(&pool, second_a, "choice").await;
+    let answer = seed_answer(&pool, task.id(), "impl", true).await.id();
+
+    let gating_path = move |_: &FlowContext| format!("/api/v1/modules/{}/gating", second);
+    let get_locked = move |_: &FlowContext| format!("/api/v1/lessons/{}", second_a);
+
+    Flow::new()
+        .step(signup_action("gated", "gatedpass"))
+        .step(
+            Action::new("gating_forbidden", "PUT", "")
+                .with_dyn_path(gating_path)
+                .with_body(json!({ "min_prerequisite_percent": 50 }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("gating_out_of_range", "PUT", "")
+                .with_dyn_path(gating_path)
+                .with_body(json!({ "min_prerequisite_percent": 101 }))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY),
+        )
+        .step(
+            Action::new("gating_set", "PUT", "")
+                .with_dyn_path(gating_path)
+                .with_body(json!({ "min_prerequisite_percent": 50 }))
+                .assert_body(|body| assert!(body.contains(r#""min_prerequisite_percent":50"#))),
+        )
+        // admins bypass the gate
+        .step(Action::new("admin_get", "GET", "").with_dyn_path(get_locked))
+        .step(signin_action("gated", "gatedpass").with_clear_cookies(true))
+        .step(
+            Action::new("gating_get", "GET", "")
+                .with_dyn_path(gating_path)
+                .assert_body(|body| assert!(body.contains(r#""min_prerequisite_percent":50"#))),
+        )
+        .step(
+            Action::new("locked_get", "GET", "")
+                .with_dyn_path(get_locked)
+                .with_expect(StatusCode::FORBIDDEN)
+                .assert_body(|body| {
+                    assert!(body.contains("complete at least 50% of the previous module first, 0% done"))
+                }),
+        )
+        // nothing else of the locked lesson leaks out, nor can it be completed
+        .step(
+            Action::new("locked_tasks", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/tasks", second_a))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("locked_next", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/next", second_a))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("locked_check", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "task_type": "choice", "answer_id": answer }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("locked_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", second_a))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        // the first module is never gated
+        .step(
+            Action::new("first_get", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}", first_a)),
+        )
+        .step(
+            Action::new("first_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", first_a)),
+        )
+        .step(Action::new("unlocked_get", "GET", "").with_dyn_path(get_locked))
+        .step(
+            Action::new("unlocked_tasks", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/tasks", second_a)),
+        )
+        .step(
+            Action::new("unlocked_next", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/next", second_a))
+                .assert_body(|body| assert!(body.contains("Generics"))),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_crud_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let other_id = seed_module(&pool, "Advanced", 2).await.id();
+    let lesson_path = |ctx: &FlowContext| {
+        format!("/api/v1/lessons/{}", ctx.get("lesson")["id"].as_str().unwrap())
+    };
+
+    Flow::new()
+        .step(signin_admin_action())
+        .step(
+            Action::new("lesson_create", "POST", "/api/v1/lessons/")
+                .with_expect(StatusCode::CREATED)
+                .with_body(json!({
+                    "module_id": module_id,
+                    "title": "Variables",
+                    "content": "let x = 1;",
+                    "order_index": 1,
+                }))
+                .with_save_as("lesson"),
+        )
+        .step(
+            Action::new("lesson_get", "GET", "")
+                .with_dyn_path(lesson_path)
+                .assert_body(move |body| {
+                    let lesson: serde_json::Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(lesson["module_id"], json!(module_id));
+                    assert_eq!(lesson["title"], "Variables");
+                    assert_eq!(lesson["content"], "let x = 1;");
+                    assert_eq!(lesson["status"], false);
+                }),
+        )
+        .step(
+            Action::new("lesson_update", "PUT", "")
+                .with_dyn_path(lesson_path)
+                .with_body(json!({
+                    "module_id": other_id,
+                    "title": "Constants",
+                    "content": "const X: i32 = 1;",
+                })),
+        )
+        .step(
+            Action::new("lesson_get_updated", "GET", "")
+                .with_dyn_path(lesson_path)
+                .assert_body(move |body| {
+                    let lesson: serde_json::Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(lesson["module_id"], json!(other_id));
+                    assert_eq!(lesson["title"], "Constants");
+                }),
+        )
+        .step(
+            Action::new("lesson_create_orphan", "POST", "/api/v1/lessons/")
+                .with_body(json!({
+                    "module_id": Uuid::new_v4(),
+                    "title": "Nowhere",
+                    "content": "",
+                }))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(Action::new("lesson_delete", "DELETE", "").with_dyn_path(lesson_path))
+        .step(
+            Action::new("lesson_get_deleted", "GET", "")
+                .with_dyn_path(lesson_path)
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_crud_forbidden_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let lesson_id = seed_lesson(&pool, module_id, "Variables", 1).await.id();
+
+    Flow::new()
+        .step(signup_action("lessonwriter", "lessonwriter"))
+        .step(
+            Action::new("lesson_create", "POST", "/api/v1/lessons/")
+                .with_body(json!({ "module_id": module_id, "title": "Sneaky", "content": "" }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("lesson_update", "PUT", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}", lesson_id))
+                .with_body(json!({ "module_id": module_id, "title": "Sneaky", "content": "" }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("lesson_delete", "DELETE", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}", lesson_id))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_status_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let done = seed_lesson(&pool, module_id, "Variables", 1).await.id();
+    let open = seed_lesson(&pool, module_id, "Loops", 2).await.id();
+    let missing = Uuid::new_v4();
+
+    Flow::new()
+        .step(signup_action("lessonstatus", "lessonstatus"))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", done)),
+        )
+        .step(
+            Action::new("status_done", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/status", done))
+                .assert_body(|body| assert_eq!(body, r#"{"completed":true}"#)),
+        )
+        .step(
+            Action::new("status_open", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/status", open))
+                .assert_body(|body| assert_eq!(body, r#"{"completed":false}"#)),
+        )
+        .step(
+            Action::new("status_missing", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/status", missing))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_done_idempotent_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let lesson = seed_lesson(&pool, module_id, "Variables", 1).await.id();
+    let task = seed_task(&pool, lesson, "choice").await.id();
+    let right = seed_answer(&pool, task, "let", true).await.id();
+
+    let done = |name: &'static str| {
+        Action::new(name, "POST", "")
+            .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson))
+    };
+
+    Flow::new()
+        .step(signup_action("donetwice", "donetwice"))
+        .step(done("lesson_done"))
+        .step(done("lesson_done_again"))
+        // a correct answer marks the same lesson done too
+        .step(
+            Action::new("task_check", "POST", "/api/v1/tasks/check").with_body(json!({
+                "answer_id": right,
+                "task_type": "choice",
+            })),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    let mm = pool.mm();
+    let user = UserEntity::find_by_username(&mm, &AuthenticatedUser::admin(), "donetwice")
+        .await
+        .unwrap()
+        .unwrap();
+    let actor = AuthenticatedUser::new(user.id(), UserRole::User);
+
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_progress WHERE user_id = $1")
+        .bind(user.id())
+        .fetch_one(mm.executor())
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+    assert_eq!(UserProgress::count_completed(&mm, &actor).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn route_lesson_tasks_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let lesson_id = seed_lesson(&pool, module_id, "Variables", 1).await.id();
+
+    // task id -> answer ids in the expected order
+    let mut expected: Vec<(Uuid, Vec<Uuid>)> = Vec::new();
+    for (task_type, answers) in [
+        ("choice", &["let", "mut", "const"][..]),
+        ("string_cmp", &["let"][..]),
+        ("choice", &[][..]),
+        ("fill_code", &["fn", "let"][..]),
+    ] {
+        let task = seed_task(&pool, lesson_id, task_type).await;
+        let mut ids = Vec::new();
+        for (i, text) in answers.iter().enumerate() {
+            ids.push(seed_answer(&pool, task.id(), text, i == 0).await.id());
+        }
+        ids.sort();
+        expected.push((task.id(), ids));
+    }
+    // answers of another lesson stay out
+    let other = seed_lesson(&pool, module_id, "Loops", 2).await.id();
+    let other_task = seed_task(&pool, other, "choice").await.id();
+    seed_answer(&pool, other_task, "loop", true).await;
+
+    Flow::new()
+        .step(signup_action("lessontasks", "lessontasks"))
+        .step(
+            Action::new("lesson_tasks", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/tasks", lesson_id))
+                .assert_body(move |body| {
+                    let tasks: Vec<Value> = serde_json::from_str(body).unwrap();
+                    assert_eq!(tasks.len(), expected.len());
+
+                    for task in &tasks {
+                        let id: Uuid = task["id"].as_str().unwrap().parse().unwrap();
+                        let (_, ids) = expected.iter().find(|(t, _)| *t == id).unwrap();
+                        let answers = task["answers"].as_array().unwrap();
+                        let got: Vec<Uuid> = answers
+                            .iter()
+                            .map(|a| a["id"].as_str().unwrap().parse().unwrap())
+                            .collect();
+                        assert_eq!(&got, ids);
+
+                        let hidden = task["task_type"] == "string_cmp";
+                        assert!(answers.iter().all(|a| a.get("answer_text").is_none() == hidden));
+                    }
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_compression_test() {
+    let pool = setup_test_db().await;
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson_id = seed_lesson(&pool, module.id(), "Long read", 1).await.id();
+    sqlx::query("UPDATE lessons SET content = $1 WHERE id = $2")
+        .bind("Markdown all the way down. ".repeat(1000))
+        .bind(lesson_id)
+        .execute(pool.mm().executor())
+        .await
+        .unwrap();
+
+    let path = format!("/api/v1/lessons/{lesson_id}");
+    for (config, encoding) in [
+        (test_config(&[]), Some("gzip")),
+        (test_config(&[("compression", false.into())]), None),
+    ] {
+        let mut server = setup_server_with_config(&pool, config).await;
+        Flow::new().step(signin_admin_action()).run(&mut server, &pool).await;
+
+        let response = server
+            .get(&path)
+            .add_header(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"))
+            .await;
+        response.assert_status_ok();
+        assert_eq!(
+            response.maybe_header(header::CONTENT_ENCODING),
+            encoding.map(HeaderValue::from_static)
+        );
+        if encoding.is_some() {
+            assert!(response.as_bytes().len() < 27_000 / 10, "barely compressed");
+        }
+    }
+}
+
+#[tokio::test]
+async fn route_lesson_instructor_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let lesson_path = |ctx: &FlowContext| {
+        format!("/api/v1/lessons/{}", ctx.get("lesson")["id"].as_str().unwrap())
+    };
+
+    Flow::new()
+        .step(signup_action("teacher", "teacherpass"))
+        .run(&mut server, &pool)
+        .await;
+    sqlx::query("UPDATE users SET role = 'instructor' WHERE username = 'teacher'")
+        .execute(pool.mm().executor())
+        .await
+        .unwrap();
+
+    // the role is read at sign-in
+    Flow::new()
+        .step(signin_action("teacher", "teacherpass"))
+        .step(
+            Action::new("lesson_create", "POST", "/api/v1/lessons/")
+                .with_expect(StatusCode::CREATED)
+                .with_body(json!({ "module_id": module_id, "title": "Variables", "content": "let x = 1;" }))
+                .with_save_as("lesson"),
+        )
+        .step(
+            Action::new("lesson_update", "PUT", "")
+                .with_dyn_path(lesson_path)
+                .with_body(json!({ "module_id": module_id, "title": "Constants", "content": "const X: i32 = 1;" })),
+        )
+        // managing users and deleting content stays with admins
+        .step(
+            Action::new("user_list", "GET", "/api/v1/account/page")
+                .with_param("limit", "5")
+                .with_param("offset", "0")
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("lesson_delete", "DELETE", "")
+                .with_dyn_path(lesson_path)
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("me", "GET", "/api/v1/account/me")
+                .assert_body(|body| assert!(body.contains("\"role\":\"instructor\""), "{body}")),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_search_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    let module = seed_module(&pool, "Basics", 1).await;
+    let contents = [
+        ("Ownership", "# Ownership\n\nThe **borrow** checker enforces borrowing rules, every borrow ends before its owner is dropped."),
+        ("Closures", "Closures capture their environment, sometimes by borrow."),
+        ("Macros", "Macros write code that writes code."),
+    ];
+    for (index, (title, content)) in contents.into_iter().enumerate() {
+        let id = seed_lesson(&pool, module.id(), title, index as i32 + 1).await.id();
+        sqlx::query("UPDATE lessons SET content = $1 WHERE id = $2")
+            .bind(content)
+            .bind(id)
+            .execute(pool.mm().executor())
+            .await
+            .unwrap();
+    }
+
+    let search = |name: &'static str, q: &'static str, limit: &'static str| {
+        Action::new(name, "GET", "/api/v1/lessons/search")
+            .with_param("q", q)
+            .with_param("limit", limit)
+            .with_param("offset", "0")
+    };
+
+    Flow::new()
+        .step(search("search_anonymous", "borrow", "10").with_expect(StatusCode::UNAUTHORIZED))
+        .step(signup_action("reader", "readerpass"))
+        .step(search("search", "borrowing", "10").assert_body(|body| {
+            let page: Value = serde_json::from_str(body).unwrap();
+            assert_eq!(page["total"], 2);
+            // stemming matches `borrow` too, the lesson mentioning it most comes first
+            assert_eq!(page["items"][0]["title"], "Ownership");
+            assert_eq!(page["items"][1]["title"], "Closures");
+            let snippet = page["items"][0]["snippet"].as_str().unwrap();
+            assert!(snippet.contains("borrow checker"), "{snippet}");
+            assert!(!snippet.contains("**") && !snippet.contains('#'), "{snippet}");
+        }))
+        .step(search("search_paged", "borrow", "1").assert_body(|body| {
+            let page: Value = serde_json::from_str(body).unwrap();
+            assert_eq!(page["total"], 2);
+            assert_eq!(page["items"].as_array().unwrap().len(), 1);
+        }))
+        .step(search("search_none", "lifetimes", "10").assert_body(|body| {
+            let page: Value = serde_json::from_str(body).unwrap();
+            assert_eq!(page["total"], 0);
+        }))
+        .step(
+            search("search_blank", "  ", "10")
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| assert!(body.contains("q must not be empty"))),
+        )
+        .step(
+            search("search_too_many", "borrow", "51")
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| assert!(body.contains("limit must be between 1 and 50"))),
+        )
+        .run(&mut server, &pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_lesson_html_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson_id = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let content = "## Bindings\n\nSee [the book](https://doc.rust-lang.org/book/).\n\n<script>alert('xss')</script>\n\n<a href=\"#\" onclick=\"steal()\">click</a>";
+    sqlx::query("UPDATE lessons SET content = $1 WHERE id = $2")
+        .bind(content)
+        .bind(lesson_id)
+        .execute(pool.mm().executor())
+        .await
+        .unwrap();
+
+    Flow::new()
+        .step(signup_action("reader", "readerpass"))
+        .step(
+            Action::new("lesson_html", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{lesson_id}/html"))
+                .assert_body(|body| {
+                    let lesson: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(lesson["title"], "Variables");
+                    let html = lesson["html"].as_str().unwrap();
+                    assert!(html.contains("<h2>Bindings</h2>"), "{html}");
+                    assert!(html.contains("<a href=\"https://doc.rust-lang.org/book/\""), "{html}");
+                    assert!(!html.contains("<script") && !html.contains("alert"), "{html}");
+                    assert!(!html.contains("onclick") && !html.contains("steal"), "{html}");
+                }),
+        )
+        // the markdown endpoint is left as it was
+        .step(
+            Action::new("lesson_raw", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{lesson_id}"))
+                .assert_body(move |body| {
+                    let lesson: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(lesson["content"], content);
+                }),
+        )
+        .step(
+            Action::new("lesson_html_missing", "GET", "")
+                .with_dyn_path(|_| format!("/api/v1/lessons/{}/html", Uuid::new_v4()))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, &pool)
+        .await;
+}
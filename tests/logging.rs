@@ -0,0 +1,115 @@
+mod common;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::common::{setup_server_with_config, setup_test_db, test_config};
+
+/// Collects everything the subscriber writes, so the test can read the log back
+#[derive(Clone, Default)]
+struct CapturedLog(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLog {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for CapturedLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CapturedLog {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[tokio::test]
+async fn request_log_exclude_paths_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[("log_exclude_paths", vec!["/api/v1/modules"].into())]);
+    let server = setup_server_with_config(&pool, config).await;
+
+    let log = CapturedLog::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(log.clone())
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    server.get("/api/v1/modules/").await;
+    server.get("/api/v1/account/verify").await;
+
+    let log = log.contents();
+    assert!(!log.contains("/api/v1/modules"), "excluded path was logged:\n{log}");
+    assert!(log.contains("path=\"/api/v1/account/verify\" status=401"), "request not logged:\n{log}");
+}
+
+#[tokio::test]
+async fn error_request_id_test() {
+    let pool = setup_test_db().await;
+    let server = setup_server_with_config(&pool, test_config(&[])).await;
+
+    let log = CapturedLog::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(log.clone())
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let response = server.get("/api/v1/account/me").await;
+    response.assert_status_unauthorized();
+    let header = response.header("x-request-id").to_str().unwrap().to_string();
+    let body: Value = response.json();
+    assert_eq!(body["request_id"], header.as_str());
+
+    let log = log.contents();
+    assert!(log.contains(&format!("request_id=\"{header}\"")), "request id not logged:\n{log}");
+
+    // a client supplied id is kept, so both sides can search for the same one
+    let response = server
+        .get("/api/v1/lessons/00000000-0000-0000-0000-000000000000")
+        .add_header("x-request-id", "support-42")
+        .await;
+    assert_eq!(response.header("x-request-id"), "support-42");
+    let body: Value = response.json();
+    assert_eq!(body["request_id"], "support-42");
+}
+
+#[tokio::test]
+async fn request_id_span_test() {
+    let pool = setup_test_db().await;
+    let server = setup_server_with_config(&pool, test_config(&[])).await;
+
+    let log = CapturedLog::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(log.clone())
+        .with_ansi(false)
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let generated = server.get("/api/v1/account/verify").await;
+    let generated = generated.header("x-request-id").to_str().unwrap().to_string();
+    assert!(uuid::Uuid::parse_str(&generated).is_ok(), "not a uuid: {generated}");
+
+    let response = server.get("/api/v1/account/verify").add_header("x-request-id", "support-7").await;
+    assert_eq!(response.header("x-request-id"), "support-7");
+
+    // lines logged while handling a request sit in its span, so they can be grepped by id
+    let log = log.contents();
+    for id in [generated.as_str(), "support-7"] {
+        assert!(log.contains(&format!("request{{request_id={id}}}")), "no span for {id}:\n{log}");
+    }
+}
@@ -0,0 +1,33 @@
+mod common;
+use std::path::Path;
+
+use flern::model::{DatabaseError, DbConnection};
+
+use crate::common::setup_empty_db;
+
+// Changes the working directory of the whole process, so it gets a test binary of its own
+#[tokio::test]
+async fn migrate_outside_of_repo_test() {
+    let db = setup_empty_db().await;
+    let conn = DbConnection::from_pool(db.mm().executor().clone());
+
+    let elsewhere = tempfile::tempdir().unwrap();
+    std::env::set_current_dir(elsewhere.path()).unwrap();
+
+    // a relative directory resolves against the new working directory, where it doesn't exist
+    let err = conn.migrate(Some(Path::new("./migrations"))).await.unwrap_err();
+    assert!(matches!(err, DatabaseError::MigrationsDirNotFound(_)));
+    assert_eq!(err.to_string(), "migrations directory `./migrations` not found");
+
+    // the built-in migrations don't care where the server runs from
+    conn.migrate(None).await.unwrap();
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(conn.pool())
+        .await
+        .unwrap();
+    assert_eq!(users, 0);
+
+    // an explicit directory with the same migrations finds them all applied
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations");
+    conn.migrate(Some(&dir)).await.unwrap();
+}
@@ -0,0 +1,619 @@
+mod common;
+use axum::http::StatusCode;
+use flern::web::dto::modules::ModuleWithLessons;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::common::{
+    Action, Flow, seed_answer, seed_lesson, seed_module, seed_task, setup_server,
+    setup_server_with_config, setup_server_with_state, setup_test_db, signin_admin_action,
+    signup_action, test_config,
+};
+
+#[tokio::test]
+async fn route_modules_cache_test() {
+    let pool = setup_test_db().await;
+    let (state, mut server) = setup_server_with_state(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson_id = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+
+    let loads = |expected: u64| {
+        let state = state.clone();
+        move |_: &str| assert_eq!(state.module_cache().loads(), expected)
+    };
+
+    Flow::new()
+        .step(signup_action("modulescache", "modulescache"))
+        .step(Action::new("modules_list", "GET", "/api/v1/modules/").assert_body(loads(1)))
+        // served from cache
+        .step(Action::new("modules_list_cached", "GET", "/api/v1/modules/").assert_body(loads(1)))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson_id)),
+        )
+        // progress changed, so the listing is loaded again
+        .step(
+            Action::new("modules_list_invalidated", "GET", "/api/v1/modules/")
+                .assert_body(loads(2))
+                .assert_body(|body| {
+                    let modules: Vec<ModuleWithLessons> = serde_json::from_str(body).unwrap();
+                    assert!(modules[0].lessons[0].completed);
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_toc_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    // seeded out of order on purpose
+    seed_lesson(&pool, module.id(), "Third", 3).await;
+    seed_lesson(&pool, module.id(), "First", 1).await;
+    seed_lesson(&pool, module.id(), "Second", 2).await;
+    let module_id = module.id();
+    let missing = Uuid::new_v4();
+
+    Flow::new()
+        .step(signup_action("modulestoc", "modulestoc"))
+        .step(
+            Action::new("modules_toc", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}/toc", module_id))
+                .assert_body(|body| {
+                    let toc: Vec<Value> = serde_json::from_str(body).unwrap();
+                    let titles: Vec<&str> = toc.iter().map(|l| l["title"].as_str().unwrap()).collect();
+                    assert_eq!(titles, ["First", "Second", "Third"]);
+                    for lesson in &toc {
+                        let keys: Vec<&str> = lesson.as_object().unwrap().keys().map(String::as_str).collect();
+                        assert_eq!(keys.len(), 3);
+                        assert!(lesson.get("content").is_none());
+                        assert!(lesson.get("id").is_some() && lesson.get("order_index").is_some());
+                    }
+                }),
+        )
+        .step(
+            Action::new("modules_toc_missing", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}/toc", missing))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_public_catalog_test() {
+    let pool = setup_test_db().await;
+    let mut private = setup_server(&pool).await;
+    let config = test_config(&[("public_catalog", true.into())]);
+    let mut public = setup_server_with_config(&pool, config).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson_id = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let module_id = module.id();
+
+    Flow::new()
+        .step(
+            Action::new("modules_list", "GET", "/api/v1/modules/")
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .run(&mut private, &pool)
+        .await;
+
+    Flow::new()
+        .step(Action::new("modules_list", "GET", "/api/v1/modules/").assert_body(|body| {
+            let modules: Vec<ModuleWithLessons> = serde_json::from_str(body).unwrap();
+            assert_eq!(modules[0].lessons[0].title, "Variables");
+            assert!(!modules[0].lessons[0].completed);
+        }))
+        .step(
+            Action::new("modules_toc", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}/toc", module_id)),
+        )
+        // content stays behind authentication
+        .step(
+            Action::new("lesson_get", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}", lesson_id))
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .run(&mut public, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_progress_batch_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let basics = seed_module(&pool, "Basics", 1).await.id();
+    let done = seed_lesson(&pool, basics, "Variables", 1).await.id();
+    seed_lesson(&pool, basics, "Loops", 2).await;
+    seed_lesson(&pool, basics, "Functions", 3).await;
+    let empty = seed_module(&pool, "Empty", 2).await.id();
+    let untouched = seed_module(&pool, "Untouched", 3).await.id();
+    seed_lesson(&pool, untouched, "Traits", 1).await;
+    let missing = Uuid::new_v4();
+
+    Flow::new()
+        .step(signup_action("dashboard", "dashboard"))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", done)),
+        )
+        .step(
+            Action::new("progress_batch", "POST", "/api/v1/modules/progress/batch")
+                .with_body(json!({ "ids": [untouched, missing, basics, empty] }))
+                .assert_body(move |body| {
+                    let rows: Vec<Value> = serde_json::from_str(body).unwrap();
+                    let summary: Vec<(String, i64, i64, f64)> = rows
+                        .iter()
+                        .map(|r| {
+                            (
+                                r["module_id"].as_str().unwrap().to_string(),
+                                r["completed"].as_i64().unwrap(),
+                                r["total"].as_i64().unwrap(),
+                                r["percent"].as_f64().unwrap(),
+                            )
+                        })
+                        .collect();
+                    assert_eq!(
+                        summary,
+                        [
+                            (basics.to_string(), 1, 3, 100.0 / 3.0),
+                            (empty.to_string(), 0, 0, 0.0),
+                            (untouched.to_string(), 0, 1, 0.0),
+                        ]
+                    );
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_crud_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    Flow::new()
+        .step(signin_admin_action())
+        .step(
+            Action::new("module_create", "POST", "/api/v1/modules/")
+                .with_expect(StatusCode::CREATED)
+                .with_body(json!({ "title": "Basics", "description": "First steps", "order_index": 1 }))
+                .with_save_as("module")
+                .assert_body(|body| {
+                    let module: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(module["title"], "Basics");
+                    assert_eq!(module["order_index"], 1);
+                }),
+        )
+        .step(
+            Action::new("module_update", "PUT", "")
+                .with_dyn_path(|ctx| format!("/api/v1/modules/{}", ctx.get("module")["id"].as_str().unwrap()))
+                .with_body(json!({ "title": "Basics 2", "description": "Second take" }))
+                .assert_body(|body| {
+                    let module: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(module["title"], "Basics 2");
+                    assert_eq!(module["order_index"], 0);
+                }),
+        )
+        .step(Action::new("modules_list", "GET", "/api/v1/modules/").assert_body(|body| {
+            let modules: Vec<ModuleWithLessons> = serde_json::from_str(body).unwrap();
+            assert_eq!(modules.len(), 1);
+            assert_eq!(modules[0].title, "Basics 2");
+        }))
+        .step(
+            Action::new("module_delete", "DELETE", "")
+                .with_dyn_path(|ctx| format!("/api/v1/modules/{}", ctx.get("module")["id"].as_str().unwrap())),
+        )
+        .step(
+            Action::new("module_delete_again", "DELETE", "")
+                .with_dyn_path(|ctx| format!("/api/v1/modules/{}", ctx.get("module")["id"].as_str().unwrap()))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_crud_forbidden_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+
+    Flow::new()
+        .step(signup_action("notadmin", "notadmin"))
+        .step(
+            Action::new("module_create", "POST", "/api/v1/modules/")
+                .with_body(json!({ "title": "Sneaky", "description": "Not allowed" }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("module_update", "PUT", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}", module_id))
+                .with_body(json!({ "title": "Sneaky", "description": "Not allowed" }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("module_delete", "DELETE", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}", module_id))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM modules")
+        .fetch_one(pool.mm().executor())
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn route_modules_delete_cascade_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let kept_module_id = seed_module(&pool, "Advanced", 2).await.id();
+    let lesson_id = seed_lesson(&pool, module_id, "Variables", 1).await.id();
+    // left open, so the resume link points into the deleted module
+    seed_lesson(&pool, module_id, "Loops", 2).await;
+    let kept_lesson_id = seed_lesson(&pool, kept_module_id, "Traits", 1).await.id();
+    let task_id = seed_task(&pool, lesson_id, "choice").await.id();
+    let answer_id = seed_answer(&pool, task_id, "let", true).await.id();
+    seed_answer(&pool, task_id, "var", false).await;
+
+    Flow::new()
+        .step(signup_action("cascader", "cascader"))
+        .step(
+            Action::new("task_check", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": answer_id, "task_type": "choice" })),
+        )
+        .step(
+            Action::new("kept_lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", kept_lesson_id)),
+        )
+        .step(
+            Action::new("resume_link", "POST", "/api/v1/progress/resume-link")
+                .assert_body(|body| assert!(body.contains("token"))),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("module_delete", "DELETE", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}", module_id)),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    let count = |sql: &'static str| {
+        let pool = &pool;
+        async move {
+            sqlx::query_scalar::<_, i64>(sql)
+                .fetch_one(pool.mm().executor())
+                .await
+                .unwrap()
+        }
+    };
+    assert_eq!(count("SELECT COUNT(*) FROM modules").await, 1);
+    assert_eq!(count("SELECT COUNT(*) FROM lessons").await, 1);
+    assert_eq!(count("SELECT COUNT(*) FROM tasks").await, 0);
+    assert_eq!(count("SELECT COUNT(*) FROM task_answers").await, 0);
+    assert_eq!(count("SELECT COUNT(*) FROM user_task_attempts").await, 0);
+    assert_eq!(count("SELECT COUNT(*) FROM resume_tokens").await, 0);
+    // only the other module's progress survives
+    assert_eq!(count("SELECT COUNT(*) FROM user_progress").await, 1);
+    assert_eq!(count("SELECT COUNT(*) FROM user_module_progress").await, 1);
+}
+
+#[tokio::test]
+async fn route_modules_create_location_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    Flow::new().step(signin_admin_action()).run(&mut server, &pool).await;
+
+    let response = server
+        .post("/api/v1/modules/")
+        .json(&json!({ "title": "Basics", "description": "First steps" }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+
+    let module: Value = response.json();
+    let location = response.header("location");
+    assert_eq!(location, format!("/api/v1/modules/{}", module["id"].as_str().unwrap()));
+
+    // the header points at a route that knows the module
+    server
+        .get(&format!("{}/toc", location.to_str().unwrap()))
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+async fn route_modules_default_sort_test() {
+    let pool = setup_test_db().await;
+
+    let zebra = seed_module(&pool, "Zebra", 1).await.id();
+    seed_lesson(&pool, zebra, "Loops", 1).await;
+    seed_lesson(&pool, zebra, "Arrays", 2).await;
+    seed_module(&pool, "Alpha", 2).await;
+
+    let listing = |catalog: Vec<ModuleWithLessons>| {
+        catalog
+            .into_iter()
+            .map(|m| {
+                let lessons: Vec<String> = m.lessons.into_iter().map(|l| l.title).collect();
+                (m.title, lessons)
+            })
+            .collect::<Vec<_>>()
+    };
+    let toc_titles = |toc: Vec<Value>| {
+        toc.iter().map(|l| l["title"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+    };
+    let toc_path = format!("/api/v1/modules/{zebra}/toc");
+
+    let config = test_config(&[("public_catalog", true.into())]);
+    let server = setup_server_with_config(&pool, config).await;
+    assert_eq!(
+        listing(server.get("/api/v1/modules/").await.json()),
+        [
+            ("Zebra".to_string(), vec!["Loops".to_string(), "Arrays".to_string()]),
+            ("Alpha".to_string(), vec![]),
+        ]
+    );
+    assert_eq!(toc_titles(server.get(&toc_path).await.json()), ["Loops", "Arrays"]);
+
+    let config = test_config(&[
+        ("public_catalog", true.into()),
+        ("default_content_sort", "title".into()),
+    ]);
+    let server = setup_server_with_config(&pool, config).await;
+    assert_eq!(
+        listing(server.get("/api/v1/modules/").await.json()),
+        [
+            ("Alpha".to_string(), vec![]),
+            ("Zebra".to_string(), vec!["Arrays".to_string(), "Loops".to_string()]),
+        ]
+    );
+    assert_eq!(toc_titles(server.get(&toc_path).await.json()), ["Arrays", "Loops"]);
+}
+
+#[tokio::test]
+async fn route_modules_recommended_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let basics = seed_module(&pool, "Basics", 1).await.id();
+    let variables = seed_lesson(&pool, basics, "Variables", 1).await.id();
+    let loops = seed_lesson(&pool, basics, "Loops", 2).await.id();
+    let traits = seed_module(&pool, "Traits", 2).await.id();
+    let generics = seed_lesson(&pool, traits, "Generics", 1).await.id();
+    seed_module(&pool, "Empty", 3).await;
+
+    let done = |name: &'static str, lesson: Uuid| {
+        Action::new(name, "POST", "")
+            .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson))
+    };
+    let recommended = |name: &'static str, expected: Uuid| {
+        Action::new(name, "GET", "/api/v1/modules/recommended").assert_body(move |body| {
+            let module: ModuleWithLessons = serde_json::from_str(body).unwrap();
+            assert_eq!(module.id, expected);
+        })
+    };
+
+    Flow::new()
+        .step(
+            Action::new("recommended_unauthorized", "GET", "/api/v1/modules/recommended")
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .step(signup_action("recommended", "recommended"))
+        .step(recommended("recommended_fresh", basics))
+        .step(done("variables_done", variables))
+        // an untouched module goes before the partially completed one
+        .step(recommended("recommended_untouched", traits))
+        .step(done("generics_done", generics))
+        .step(
+            Action::new("recommended_partial", "GET", "/api/v1/modules/recommended")
+                .assert_body(move |body| {
+                    let module: ModuleWithLessons = serde_json::from_str(body).unwrap();
+                    assert_eq!(module.id, basics);
+                    let completed: Vec<bool> = module.lessons.iter().map(|l| l.completed).collect();
+                    assert_eq!(completed, [true, false]);
+                }),
+        )
+        .step(done("loops_done", loops))
+        // the lessonless module has nothing to take
+        .step(
+            Action::new("recommended_all_done", "GET", "/api/v1/modules/recommended")
+                .with_expect(StatusCode::NO_CONTENT),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_api_version_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let missing = Uuid::new_v4();
+
+    let list = |name: &'static str, accept: Option<&str>, timestamps: bool| {
+        let action = Action::new(name, "GET", "/api/v1/modules/").assert_body(move |body| {
+            let modules: Vec<Value> = serde_json::from_str(body).unwrap();
+            assert_eq!(modules[0]["lessons"][0]["title"], "Variables");
+            assert_eq!(modules[0].get("created_at").is_some(), timestamps);
+            assert_eq!(modules[0].get("updated_at").is_some(), timestamps);
+        });
+        match accept {
+            Some(accept) => action.with_header("accept", accept),
+            None => action,
+        }
+    };
+    let not_found = |name: &'static str, accept: &str, code: Option<&'static str>| {
+        Action::new(name, "GET", "")
+            .with_dyn_path(move |_| format!("/api/v1/modules/{}/toc", missing))
+            .with_header("accept", accept)
+            .with_expect(StatusCode::NOT_FOUND)
+            .assert_body(move |body| {
+                let error: Value = serde_json::from_str(body).unwrap();
+                assert_eq!(error["status_code"], "404");
+                assert_eq!(error.get("code").and_then(Value::as_str), code);
+            })
+    };
+
+    Flow::new()
+        .step(signup_action("apiversion", "apiversion"))
+        .step(list("modules_list_default", None, true))
+        .step(list("modules_list_v1", Some("application/vnd.flern.v1+json"), false))
+        .step(list("modules_list_v2", Some("application/vnd.flern.v2+json"), true))
+        .step(
+            Action::new("modules_list_unknown", "GET", "/api/v1/modules/")
+                .with_header("accept", "application/vnd.flern.v9+json")
+                .with_expect(StatusCode::NOT_ACCEPTABLE),
+        )
+        .step(not_found("error_v1", "application/vnd.flern.v1+json", None))
+        .step(not_found("error_v2", "application/vnd.flern.v2+json", Some("ResourceNotFound")))
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_lesson_order_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await.id();
+    let first = seed_lesson(&pool, module, "First", 1).await.id();
+    let second = seed_lesson(&pool, module, "Second", 2).await.id();
+    let third = seed_lesson(&pool, module, "Third", 3).await.id();
+    let other = seed_module(&pool, "Traits", 2).await.id();
+    let foreign = seed_lesson(&pool, other, "Generics", 1).await.id();
+
+    let reorder = |name: &'static str, module: Uuid, ids: Vec<Uuid>, expect: StatusCode| {
+        Action::new(name, "PUT", "")
+            .with_dyn_path(move |_| format!("/api/v1/modules/{}/lesson-order", module))
+            .with_body(json!({ "lesson_ids": ids }))
+            .with_expect(expect)
+    };
+    let next = |name: &'static str, lesson: Uuid, expected: Option<Uuid>| {
+        let action = Action::new(name, "GET", "")
+            .with_dyn_path(move |_| format!("/api/v1/lessons/{}/next", lesson));
+        match expected {
+            Some(expected) => action.assert_body(move |body| {
+                let next: Value = serde_json::from_str(body).unwrap();
+                assert_eq!(next["id"], expected.to_string());
+            }),
+            None => action.with_expect(StatusCode::NOT_FOUND),
+        }
+    };
+
+    Flow::new()
+        .step(signin_admin_action())
+        .step(reorder("order_partial", module, vec![third, first], StatusCode::BAD_REQUEST))
+        .step(reorder(
+            "order_foreign",
+            module,
+            vec![third, first, second, foreign],
+            StatusCode::BAD_REQUEST,
+        ))
+        .step(reorder("order_duplicate", module, vec![third, first, first], StatusCode::BAD_REQUEST))
+        .step(reorder("order_missing_module", Uuid::new_v4(), vec![], StatusCode::NOT_FOUND))
+        // rejected orders left everything as seeded
+        .step(next("next_unchanged", first, Some(second)))
+        .step(reorder("order_shuffle", module, vec![third, first, second], StatusCode::NO_CONTENT))
+        .step(next("next_after_third", third, Some(first)))
+        .step(next("next_after_first", first, Some(second)))
+        .step(next("next_after_second", second, None))
+        .step(signup_action("lessonorder", "lessonorder").with_clear_cookies(true))
+        .step(reorder("order_forbidden", module, vec![first, second, third], StatusCode::FORBIDDEN))
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_modules_list_filter_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let basics = seed_module(&pool, "Basics", 1).await.id();
+    let variables = seed_lesson(&pool, basics, "Variables", 1).await.id();
+    seed_lesson(&pool, basics, "Loops", 2).await;
+    let traits = seed_module(&pool, "Traits", 2).await.id();
+    let generics = seed_lesson(&pool, traits, "Generics", 1).await.id();
+    seed_module(&pool, "Macros", 3).await;
+
+    let lessons = |modules: &[ModuleWithLessons]| -> Vec<(String, Vec<String>)> {
+        modules
+            .iter()
+            .map(|m| (m.title.clone(), m.lessons.iter().map(|l| l.title.clone()).collect()))
+            .collect()
+    };
+    let done = |name: &'static str, lesson: Uuid| {
+        Action::new(name, "POST", "")
+            .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson))
+    };
+
+    Flow::new()
+        .step(signup_action("modulesfilter", "modulesfilter"))
+        .step(done("variables_done", variables))
+        .step(done("generics_done", generics))
+        // without params everything is listed, completed or not
+        .step(Action::new("modules_list_default", "GET", "/api/v1/modules/").assert_body(move |body| {
+            let modules: Vec<ModuleWithLessons> = serde_json::from_str(body).unwrap();
+            assert_eq!(
+                lessons(&modules),
+                [
+                    ("Basics".into(), vec!["Variables".into(), "Loops".into()]),
+                    ("Traits".into(), vec!["Generics".into()]),
+                    ("Macros".into(), vec![]),
+                ]
+            );
+            assert!(modules[0].lessons[0].completed);
+        }))
+        .step(
+            Action::new("modules_list_incomplete", "GET", "/api/v1/modules/")
+                .with_param("only_incomplete", "true")
+                .assert_body(move |body| {
+                    let modules: Vec<ModuleWithLessons> = serde_json::from_str(body).unwrap();
+                    assert_eq!(
+                        lessons(&modules),
+                        [
+                            ("Basics".into(), vec!["Loops".into()]),
+                            ("Traits".into(), vec![]),
+                            ("Macros".into(), vec![]),
+                        ]
+                    );
+                }),
+        )
+        .step(
+            Action::new("modules_list_page", "GET", "/api/v1/modules/")
+                .with_param("limit", "1")
+                .with_param("offset", "1")
+                .assert_body(move |body| {
+                    let modules: Vec<ModuleWithLessons> = serde_json::from_str(body).unwrap();
+                    assert_eq!(lessons(&modules), [("Traits".into(), vec!["Generics".into()])]);
+                }),
+        )
+        // the filtered responses didn't end up in the cache
+        .step(Action::new("modules_list_default_again", "GET", "/api/v1/modules/").assert_body(|body| {
+            let modules: Vec<ModuleWithLessons> = serde_json::from_str(body).unwrap();
+            assert_eq!(modules.len(), 3);
+            assert_eq!(modules[0].lessons.len(), 2);
+        }))
+        .step(
+            Action::new("modules_list_negative", "GET", "/api/v1/modules/")
+                .with_param("offset", "-1")
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .run(&mut server, pool)
+        .await;
+}
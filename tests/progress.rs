@@ -0,0 +1,526 @@
+mod common;
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use flern::model::CrudRepository;
+use flern::model::entity::{
+    ProgressToken, ProgressTokenCreate, RefreshToken, RefreshTokenCreate, ResumeToken,
+    ResumeTokenCreate, UserEntity, UserEntityCreateUpdate, UserProgress,
+};
+use flern::utils::cleanup::progress_token_cleanup;
+use flern::web::{AuthenticatedUser, UserRole};
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::common::{
+    Action, Flow, seed_answer, seed_lesson, seed_module, seed_task, setup_server,
+    setup_server_with_config, setup_test_db, signin_action, signin_admin_action, signup_action,
+    test_config,
+};
+
+#[tokio::test]
+async fn route_progress_resume_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let first = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let second = seed_lesson(&pool, module.id(), "Functions", 2).await.id();
+
+    Flow::new()
+        .step(signup_action("resumer", "resumerpass"))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", first)),
+        )
+        .step(Action::new("resume_link", "POST", "/api/v1/progress/resume-link").with_save_as("resume"))
+        // links are meant to be shared, no session needed to open them
+        .step(
+            Action::new("resume", "GET", "")
+                .with_dyn_path(|ctx| {
+                    let token = ctx.get("resume")["token"].as_str().unwrap();
+                    format!("/api/v1/progress/resume/{}", token)
+                })
+                .with_clear_cookies(true)
+                .assert_body(move |body| {
+                    let lesson: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(lesson["id"], second.to_string());
+                    assert_eq!(lesson["title"], "Functions");
+                    assert_eq!(lesson["status"], false);
+                }),
+        )
+        .step(
+            Action::new("resume_missing", "GET", "/api/v1/progress/resume/nonexisting")
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_progress_resume_gated_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let gated = seed_module(&pool, "Advanced", 2).await.id();
+    let lesson = seed_lesson(&pool, gated, "Traits", 1).await.id();
+
+    let admin = AuthenticatedUser::admin();
+    let data = UserEntityCreateUpdate { username: "resumegated".to_string(), password_hash: "hash".to_string() };
+    let user = UserEntity::create(&pool.mm(), &admin, data).await.unwrap();
+    let data = ResumeTokenCreate {
+        token: "gated-resume".to_string(),
+        user_id: user.id(),
+        lesson_id: lesson,
+        expires_at: chrono::Utc::now() + chrono::Duration::minutes(30),
+    };
+    ResumeToken::create(&pool.mm(), &admin, data).await.unwrap();
+
+    Flow::new()
+        .step(Action::new("resume_open", "GET", "/api/v1/progress/resume/gated-resume"))
+        .step(signin_admin_action())
+        .step(
+            Action::new("gating_set", "PUT", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}/gating", gated))
+                .with_body(json!({ "min_prerequisite_percent": 50 })),
+        )
+        // the link was created before the module was gated
+        .step(
+            Action::new("resume_locked", "GET", "/api/v1/progress/resume/gated-resume")
+                .with_clear_cookies(true)
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_progress_share_read_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let first = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    seed_lesson(&pool, module.id(), "Functions", 2).await;
+    let task = seed_task(&pool, first, "choice").await;
+    let right = seed_answer(&pool, task.id(), "let", true).await.id();
+    let wrong = seed_answer(&pool, task.id(), "var", false).await.id();
+
+    Flow::new()
+        .step(signup_action("sharer", "sharerpass"))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", first)),
+        )
+        .step(
+            Action::new("check_wrong", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": wrong, "task_type": "choice" })),
+        )
+        .step(
+            Action::new("check_right", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": right, "task_type": "choice" })),
+        )
+        .step(Action::new("share", "POST", "/api/v1/progress/share").with_save_as("share"))
+        // whoever gets the link reads the progress without an account
+        .step(
+            Action::new("read", "GET", "")
+                .with_dyn_path(|ctx| {
+                    let token = ctx.get("share")["token"].as_str().unwrap();
+                    format!("/api/v1/progress/{}", token)
+                })
+                .with_clear_cookies(true)
+                .assert_body(|body| {
+                    let progress: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(progress["username"], "sharer");
+                    assert_eq!(progress["total_lessons"], 2);
+                    assert_eq!(progress["completed_lessons"], 1);
+                    assert_eq!(progress["total_answers"], 2);
+                    assert_eq!(progress["correct_answers"], 1);
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_progress_share_limit_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[("max_active_share_tokens", 2.into())]);
+    let mut server = setup_server_with_config(&pool, config).await;
+
+    let share = || Action::new("share", "POST", "/api/v1/progress/share");
+
+    Flow::new()
+        .step(signup_action("sharer", "sharerpass"))
+        .step(share())
+        .step(share())
+        .step(
+            share()
+                .with_expect(StatusCode::TOO_MANY_REQUESTS)
+                .assert_body(|body| assert!(body.contains("at most 2 active share tokens"))),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    // expired tokens don't count
+    sqlx::query(
+        "UPDATE progress_tokens SET expires_at = now() - interval '1 minute' \
+         WHERE id = (SELECT id FROM progress_tokens LIMIT 1)",
+    )
+    .execute(pool.mm().executor())
+    .await
+    .unwrap();
+
+    Flow::new()
+        .step(share())
+        .step(share().with_expect(StatusCode::TOO_MANY_REQUESTS))
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_progress_share_ttl_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[("max_share_ttl_minutes", 60.into()), ("max_active_share_tokens", 10.into())]);
+    let mut server = setup_server_with_config(&pool, config).await;
+
+    let expires_in = |minutes: i64| {
+        move |body: &str| {
+            let token: Value = serde_json::from_str(body).unwrap();
+            let expires_at: chrono::DateTime<chrono::Utc> =
+                token["expires_at"].as_str().unwrap().parse().unwrap();
+            let left = expires_at - chrono::Utc::now();
+            let expected = chrono::Duration::minutes(minutes);
+            assert!(left <= expected, "expires in {left}, expected {expected}");
+            assert!(left > expected - chrono::Duration::minutes(1), "expires in {left}, expected {expected}");
+        }
+    };
+    let share = || Action::new("share", "POST", "/api/v1/progress/share");
+
+    Flow::new()
+        .step(signup_action("ttler", "ttlerpass"))
+        .step(share().assert_body(expires_in(30)))
+        .step(share().with_body(json!({})).assert_body(expires_in(30)))
+        .step(share().with_body(json!({ "ttl_minutes": 45 })).assert_body(expires_in(45)))
+        // longer than the configured maximum is cut down to it
+        .step(share().with_body(json!({ "ttl_minutes": 500 })).assert_body(expires_in(60)))
+        .step(
+            share()
+                .with_body(json!({ "ttl_minutes": 0 }))
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| assert!(body.contains("ttl_minutes must be positive"))),
+        )
+        .step(
+            share()
+                .with_body(json!({ "ttl_minutes": -5 }))
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_progress_share_single_use_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let read = |key: &'static str, expect: StatusCode| {
+        Action::new("read", "GET", "")
+            .with_dyn_path(move |ctx| {
+                let token = ctx.get(key)["token"].as_str().unwrap();
+                format!("/api/v1/progress/{}", token)
+            })
+            .with_clear_cookies(true)
+            .with_expect(expect)
+    };
+
+    Flow::new()
+        .step(signup_action("oncer", "oncerpass"))
+        .step(
+            Action::new("share_once", "POST", "/api/v1/progress/share")
+                .with_body(json!({ "single_use": true }))
+                .with_save_as("once"),
+        )
+        .step(Action::new("share", "POST", "/api/v1/progress/share").with_save_as("many"))
+        .step(read("once", StatusCode::OK).assert_body(|body| assert!(body.contains("oncer"))))
+        .step(read("once", StatusCode::NOT_FOUND))
+        // multi use tokens keep working until they expire
+        .step(read("many", StatusCode::OK))
+        .step(read("many", StatusCode::OK))
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn progress_token_cleanup_worker_test() {
+    let pool = setup_test_db().await;
+    let mm = pool.mm();
+    let admin = AuthenticatedUser::admin();
+    let owner: Uuid = sqlx::query_scalar("SELECT id FROM users WHERE username = 'admin'")
+        .fetch_one(mm.executor())
+        .await
+        .unwrap();
+
+    let lesson = seed_lesson(&pool, seed_module(&pool, "Basics", 1).await.id(), "Variables", 1).await.id();
+    for (token, expires_in) in [("expired", -1), ("active", 30)] {
+        let expires_at = chrono::Utc::now() + chrono::Duration::minutes(expires_in);
+        ProgressToken::create(
+            &mm,
+            &admin,
+            ProgressTokenCreate { token: token.to_string(), user_id: owner, expires_at, single_use: false },
+        )
+        .await
+        .unwrap();
+        ResumeToken::create(
+            &mm,
+            &admin,
+            ResumeTokenCreate { token: token.to_string(), user_id: owner, lesson_id: lesson, expires_at },
+        )
+        .await
+        .unwrap();
+        RefreshToken::create(
+            &mm,
+            &admin,
+            RefreshTokenCreate { token: token.to_string(), user_id: owner, expires_at },
+        )
+        .await
+        .unwrap();
+    }
+
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(progress_token_cleanup(
+        mm.clone(),
+        Duration::from_millis(50),
+        shutdown.clone(),
+    ));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(ProgressToken::find_by_token(&mm, &admin, "expired").await.unwrap().is_none());
+    assert!(ProgressToken::find_by_token(&mm, &admin, "active").await.unwrap().is_some());
+    assert!(ResumeToken::find_by_token(&mm, &admin, "expired").await.unwrap().is_none());
+    assert!(ResumeToken::find_by_token(&mm, &admin, "active").await.unwrap().is_some());
+    assert!(RefreshToken::find_by_token(&mm, &admin, "expired").await.unwrap().is_none());
+    assert!(RefreshToken::find_by_token(&mm, &admin, "active").await.unwrap().is_some());
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(1), worker)
+        .await
+        .expect("cleanup worker didn't stop on shutdown")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn route_progress_modules_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let basics = seed_module(&pool, "Basics", 1).await.id();
+    let variables = seed_lesson(&pool, basics, "Variables", 1).await.id();
+    let loops = seed_lesson(&pool, basics, "Loops", 2).await.id();
+    seed_lesson(&pool, basics, "Functions", 3).await;
+    let traits = seed_module(&pool, "Traits", 2).await.id();
+    seed_lesson(&pool, traits, "Generics", 1).await;
+    seed_module(&pool, "Empty", 3).await;
+
+    let done = |name: &'static str, lesson: Uuid| {
+        Action::new(name, "POST", "")
+            .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson))
+    };
+
+    Flow::new()
+        .step(
+            Action::new("progress_modules_unauthorized", "GET", "/api/v1/progress/modules")
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .step(signup_action("moduleprogress", "moduleprogress"))
+        .step(done("variables_done", variables))
+        .step(done("loops_done", loops))
+        .step(
+            Action::new("progress_modules", "GET", "/api/v1/progress/modules").assert_body(move |body| {
+                let progress: Vec<Value> = serde_json::from_str(body).unwrap();
+                let counts: Vec<(&str, i64, i64)> = progress
+                    .iter()
+                    .map(|m| {
+                        (
+                            m["title"].as_str().unwrap(),
+                            m["completed"].as_i64().unwrap(),
+                            m["total"].as_i64().unwrap(),
+                        )
+                    })
+                    .collect();
+                assert_eq!(counts, [("Basics", 2, 3), ("Traits", 0, 1), ("Empty", 0, 0)]);
+                assert_eq!(progress[0]["module_id"], basics.to_string());
+                assert!(progress[0].get("lessons").is_none());
+            }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_progress_reset_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let basics = seed_module(&pool, "Basics", 1).await.id();
+    let variables = seed_lesson(&pool, basics, "Variables", 1).await.id();
+    let loops = seed_lesson(&pool, basics, "Loops", 2).await.id();
+    let task = seed_task(&pool, variables, "choice").await.id();
+    let right = seed_answer(&pool, task, "let", true).await.id();
+    let traits = seed_module(&pool, "Traits", 2).await.id();
+    let generics = seed_lesson(&pool, traits, "Generics", 1).await.id();
+
+    let done = |name: &'static str, lesson: Uuid| {
+        Action::new(name, "POST", "")
+            .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson))
+    };
+    let reset = |name: &'static str, path: &'static str, lessons: u64, attempts: u64| {
+        Action::new(name, "DELETE", path).assert_body(move |body| {
+            let reset: Value = serde_json::from_str(body).unwrap();
+            assert_eq!(reset["lessons"], lessons);
+            assert_eq!(reset["attempts"], attempts);
+        })
+    };
+
+    Flow::new()
+        .step(
+            Action::new("reset_unauthorized", "DELETE", "/api/v1/progress/")
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .step(signup_action("resetter", "resetterpass"))
+        .step(
+            Action::new("check_right", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": right, "task_type": "choice" })),
+        )
+        .step(done("loops_done", loops))
+        .step(done("generics_done", generics))
+        .step(
+            reset("reset_traits", "", 1, 0)
+                .with_dyn_path(move |_| format!("/api/v1/progress/?module_id={}", traits)),
+        )
+        .step(
+            Action::new("reset_missing_module", "DELETE", "")
+                .with_dyn_path(|_| format!("/api/v1/progress/?module_id={}", Uuid::new_v4()))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(Action::new("progress_modules", "GET", "/api/v1/progress/modules").assert_body(|body| {
+            let progress: Vec<Value> = serde_json::from_str(body).unwrap();
+            let completed: Vec<i64> = progress.iter().map(|m| m["completed"].as_i64().unwrap()).collect();
+            assert_eq!(completed, [2, 0]);
+        }))
+        .step(reset("reset_all", "/api/v1/progress/", 2, 1))
+        .run(&mut server, &pool)
+        .await;
+
+    let mm = pool.mm();
+    let admin = AuthenticatedUser::admin();
+    let user = UserEntity::find_by_username(&mm, &admin, "resetter").await.unwrap().unwrap();
+    let actor = AuthenticatedUser::new(user.id(), UserRole::User);
+    assert_eq!(UserProgress::count_completed(&mm, &actor).await.unwrap(), 0);
+    let attempts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_task_attempts WHERE user_id = $1")
+        .bind(user.id())
+        .fetch_one(mm.executor())
+        .await
+        .unwrap();
+    assert_eq!(attempts, 0);
+
+    // resetting someone else takes the admin route
+    let user_id = user.id();
+    Flow::new()
+        .step(signin_action("resetter", "resetterpass").with_clear_cookies(true))
+        .step(done("variables_done", variables))
+        .step(
+            Action::new("admin_reset_forbidden", "DELETE", "")
+                .with_dyn_path(move |_| format!("/api/v1/admin/users/{}/progress", user_id))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            reset("admin_reset", "", 1, 0)
+                .with_dyn_path(move |_| format!("/api/v1/admin/users/{}/progress", user_id)),
+        )
+        .step(
+            Action::new("admin_reset_missing", "DELETE", "")
+                .with_dyn_path(|_| format!("/api/v1/admin/users/{}/progress", Uuid::new_v4()))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    assert_eq!(UserProgress::count_completed(&mm, &actor).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn route_progress_leaderboard_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await.id();
+    let variables = seed_lesson(&pool, module, "Variables", 1).await.id();
+    let first = seed_task(&pool, variables, "choice").await.id();
+    let first_right = seed_answer(&pool, first, "let", true).await.id();
+    let first_wrong = seed_answer(&pool, first, "var", false).await.id();
+    let loops = seed_lesson(&pool, module, "Loops", 2).await.id();
+    let second = seed_task(&pool, loops, "choice").await.id();
+    let second_right = seed_answer(&pool, second, "for", true).await.id();
+
+    let check = |name: &'static str, answer: Uuid| {
+        Action::new(name, "POST", "/api/v1/tasks/check")
+            .with_body(json!({ "answer_id": answer, "task_type": "choice" }))
+    };
+    let leaderboard = |name: &'static str, limit: &str, expected: &'static [(&str, i64, i64)]| {
+        Action::new(name, "GET", "/api/v1/progress/leaderboard")
+            .with_param("limit", limit)
+            .assert_body(move |body| {
+                let entries: Vec<Value> = serde_json::from_str(body).unwrap();
+                let ranked: Vec<(&str, i64, i64)> = entries
+                    .iter()
+                    .map(|e| {
+                        // nothing but the username identifies another user
+                        assert_eq!(e.as_object().unwrap().len(), 3);
+                        (
+                            e["username"].as_str().unwrap(),
+                            e["correct_answers"].as_i64().unwrap(),
+                            e["completed_lessons"].as_i64().unwrap(),
+                        )
+                    })
+                    .collect();
+                assert_eq!(ranked, expected);
+            })
+    };
+
+    Flow::new()
+        .step(
+            Action::new("leaderboard_unauthorized", "GET", "/api/v1/progress/leaderboard")
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .step(signup_action("alpha", "alphapass"))
+        .step(check("alpha_1", first_right))
+        .step(check("alpha_2", first_right))
+        .step(check("alpha_3", first_right))
+        .step(signup_action("bravo", "bravopass").with_clear_cookies(true))
+        .step(check("bravo_1", first_right))
+        .step(check("bravo_2", second_right))
+        .step(signup_action("delta", "deltapass").with_clear_cookies(true))
+        .step(check("delta_1", first_right))
+        .step(check("delta_2", first_right))
+        // only wrong answers, so not ranked
+        .step(signup_action("echo", "echopass").with_clear_cookies(true))
+        .step(check("echo_1", first_wrong))
+        .step(leaderboard(
+            "leaderboard",
+            "10",
+            &[("alpha", 3, 1), ("bravo", 2, 2), ("delta", 2, 1)],
+        ))
+        .step(leaderboard("leaderboard_top", "2", &[("alpha", 3, 1), ("bravo", 2, 2)]))
+        .step(
+            Action::new("leaderboard_zero", "GET", "/api/v1/progress/leaderboard")
+                .with_param("limit", "0")
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .run(&mut server, pool)
+        .await;
+}
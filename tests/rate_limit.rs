@@ -0,0 +1,80 @@
+mod common;
+use axum::http::StatusCode;
+use axum_test::TestResponse;
+use serde_json::json;
+
+use crate::common::{setup_server, setup_server_with_config, setup_test_db, test_config};
+
+fn header_u64(response: &TestResponse, name: &str) -> u64 {
+    response.header(name).to_str().unwrap().parse().unwrap()
+}
+
+#[tokio::test]
+async fn rate_limit_headers_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[("rate_limit_requests", 3.into())]);
+    let server = setup_server_with_config(&pool, config).await;
+
+    let mut remaining = Vec::new();
+    for _ in 0..3 {
+        let response = server.get("/api/v1/modules/").await;
+        assert_eq!(header_u64(&response, "x-ratelimit-limit"), 3);
+        assert!(header_u64(&response, "x-ratelimit-reset") <= 60);
+        remaining.push(header_u64(&response, "x-ratelimit-remaining"));
+    }
+    assert_eq!(remaining, [2, 1, 0]);
+
+    // failed requests count too, the budget is per client rather than per route
+    let response = server.get("/api/v1/account/verify").expect_failure().await;
+    response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(header_u64(&response, "x-ratelimit-remaining"), 0);
+    assert!(header_u64(&response, "retry-after") <= 60);
+    assert!(response.text().contains("Too many requests"));
+}
+
+#[tokio::test]
+async fn rate_limit_disabled_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[("rate_limit_requests", 0.into())]);
+    let server = setup_server_with_config(&pool, config).await;
+
+    let response = server.get("/api/v1/modules/").await;
+    assert!(response.maybe_header("x-ratelimit-limit").is_none());
+
+    // the default budget is far above what a test needs
+    let server = setup_server(&pool).await;
+    let response = server.get("/api/v1/modules/").await;
+    assert_eq!(header_u64(&response, "x-ratelimit-limit"), 300);
+}
+
+#[tokio::test]
+async fn rate_limit_auth_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[("auth_rate_limit_requests", 3.into())]);
+    let server = setup_server_with_config(&pool, config).await;
+
+    let signin = || {
+        server
+            .post("/api/v1/account/signin")
+            .json(&json!({ "username": "admin", "password": "wrong" }))
+            .expect_failure()
+    };
+
+    // wrong passwords count like any other attempt
+    for _ in 0..3 {
+        signin().await.assert_status(StatusCode::UNAUTHORIZED);
+    }
+    let response = signin().await;
+    response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    assert!(header_u64(&response, "retry-after") <= 60);
+
+    // sign-up shares the budget, the rest of the API keeps its own
+    server
+        .post("/api/v1/account/signup")
+        .json(&json!({ "username": "stuffer", "password": "stufferpass" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::TOO_MANY_REQUESTS);
+    let response = server.get("/api/v1/modules/").expect_failure().await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
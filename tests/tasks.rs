@@ -0,0 +1,1024 @@
+mod common;
+use std::sync::{Arc, Mutex};
+
+use axum::http::StatusCode;
+use axum_test::multipart::{MultipartForm, Part};
+use flern::model::CrudRepository;
+use flern::model::entity::{AnswerCreate, LessonTask, LessonTaskCreate, UserTaskAttempt};
+use flern::web::AuthenticatedUser;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::common::{
+    Action, Flow, FlowContext, seed_answer, seed_lesson, seed_module, seed_task, setup_server,
+    setup_server_with_config, setup_test_db, signin_action, signin_admin_action, signup_action,
+    test_config,
+};
+
+#[tokio::test]
+async fn route_task_check_type_mismatch_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "choice").await;
+    let answer = seed_answer(&pool, task.id(), "42", true).await;
+
+    Flow::new()
+        .step(signup_action("taskcheck", "taskcheck"))
+        // well-formed, but the task is a `choice` one
+        .step(
+            Action::new("task_check_mismatch", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "answer_id": answer.id(),
+                    "task_type": "string_cmp",
+                    "user_answer": "42",
+                }))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY)
+                .assert_body(|body| {
+                    assert!(body.contains("task_type `string_cmp` doesn't match the task"));
+                }),
+        )
+        .step(
+            Action::new("task_check", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "answer_id": answer.id(),
+                    "task_type": "choice",
+                }))
+                .assert_body(|body| assert!(body.contains("\"is_correct\":true"))),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_check_missing_user_answer_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "string_cmp").await;
+    let answer = seed_answer(&pool, task.id(), "secret", true).await;
+
+    Flow::new()
+        .step(signup_action("typedcheck", "typedcheck"))
+        .step(
+            Action::new("task_check_no_answer", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "answer_id": answer.id(),
+                    "task_type": "string_cmp",
+                }))
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| {
+                    let body: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(
+                        body["message"],
+                        "Bad request: invalid user_answer field passed. You should pass some value in it if you're checking string_cmp task"
+                    );
+                    assert_eq!(body["code"], "UserBadRequest");
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_recompute_attempts_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "choice").await;
+    let right = seed_answer(&pool, task.id(), "let", true).await;
+    let wrong = seed_answer(&pool, task.id(), "var", false).await;
+    let task_id = task.id();
+    let right_id = right.id();
+
+    Flow::new()
+        .step(signup_action("recompute", "recompute"))
+        .step(
+            Action::new("task_check_right", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": right.id(), "task_type": "choice" })),
+        )
+        .step(
+            Action::new("task_check_wrong", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": wrong.id(), "task_type": "choice" })),
+        )
+        .step(
+            Action::new("recompute_forbidden", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/tasks/{}/recompute-attempts", task_id))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    // the author got it the other way around
+    let mm = pool.mm();
+    let actor = AuthenticatedUser::admin();
+    for (answer, is_correct) in [(right, false), (wrong, true)] {
+        let data = AnswerCreate {
+            task_id,
+            answer_text: answer.answer_text().to_string(),
+            image: answer.image().to_string(),
+            is_correct: Some(is_correct),
+        };
+        answer.update(&mm, &actor, data).await.unwrap();
+    }
+
+    Flow::new()
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("recompute", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/tasks/{}/recompute-attempts", task_id))
+                .assert_body(|body| assert!(body.contains("\"flipped\":2"))),
+        )
+        // nothing left to flip
+        .step(
+            Action::new("recompute_again", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/tasks/{}/recompute-attempts", task_id))
+                .assert_body(|body| assert!(body.contains("\"flipped\":0"))),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    let attempts = UserTaskAttempt::list(&mm, &actor, 10, 0, None).await.unwrap();
+    assert_eq!(attempts.len(), 2);
+    for attempt in attempts {
+        let expected = attempt.selected_answer_id() != Some(right_id);
+        assert_eq!(attempt.is_correct(), expected);
+    }
+}
+
+#[tokio::test]
+async fn route_task_answer_distribution_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "choice").await;
+    let right = seed_answer(&pool, task.id(), "let", true).await.id();
+    let wrong = seed_answer(&pool, task.id(), "var", false).await.id();
+    seed_answer(&pool, task.id(), "const", false).await;
+    let task_id = task.id();
+
+    let check = |name: &'static str, answer_id| {
+        Action::new(name, "POST", "/api/v1/tasks/check")
+            .with_body(json!({ "answer_id": answer_id, "task_type": "choice" }))
+    };
+
+    Flow::new()
+        .step(signup_action("first", "firstpass"))
+        .step(check("check_right", right))
+        .step(signup_action("second", "secondpass").with_clear_cookies(true))
+        .step(check("check_wrong", wrong))
+        .step(check("check_right", right))
+        .step(check("check_right", right))
+        .step(
+            Action::new("distribution_forbidden", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/tasks/{}/answer-distribution", task_id))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("distribution", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/tasks/{}/answer-distribution", task_id))
+                .assert_body(|body| {
+                    let rows: Vec<serde_json::Value> = serde_json::from_str(body).unwrap();
+                    let summary: Vec<(&str, i64, f64)> = rows
+                        .iter()
+                        .map(|r| {
+                            let text = r["answer_text"].as_str().unwrap();
+                            (text, r["count"].as_i64().unwrap(), r["percent"].as_f64().unwrap())
+                        })
+                        .collect();
+                    assert_eq!(summary, [("let", 3, 75.0), ("var", 1, 25.0), ("const", 0, 0.0)]);
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_attempts_sync_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "choice").await;
+    let right = seed_answer(&pool, task.id(), "let", true).await.id();
+    let wrong = seed_answer(&pool, task.id(), "var", false).await.id();
+    let other = seed_module(&pool, "Other", 2).await;
+    let other_lesson = seed_lesson(&pool, other.id(), "Other", 1).await;
+    let other_task = seed_task(&pool, other_lesson.id(), "choice").await.id();
+    let multi = seed_task(&pool, other_lesson.id(), "multi_select").await.id();
+    let multi_right = seed_answer(&pool, multi, "let", true).await.id();
+    seed_answer(&pool, multi, "const", true).await;
+    let typed = seed_task(&pool, other_lesson.id(), "string_cmp").await.id();
+    let typed_answer = seed_answer(&pool, typed, "let", true).await.id();
+    let (task_id, lesson_id) = (task.id(), lesson.id());
+
+    let attempt = |client_id: Uuid, task_id: Uuid, answer_id: Uuid| {
+        json!({
+            "client_id": client_id,
+            "task_id": task_id,
+            "selected_answer_id": answer_id,
+            "attempted_at": "2025-11-01T10:00:00Z",
+        })
+    };
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    let batch = json!([
+        attempt(first, task_id, wrong),
+        attempt(second, task_id, right),
+        // replayed within the same batch
+        attempt(first, task_id, wrong),
+    ]);
+
+    Flow::new()
+        .step(signup_action("offline", "offlinepass"))
+        // the answer belongs to a different task
+        .step(
+            Action::new("sync_unknown", "POST", "/api/v1/tasks/attempts/sync")
+                .with_body(json!([
+                    attempt(Uuid::new_v4(), task_id, right),
+                    attempt(Uuid::new_v4(), other_task, right),
+                ]))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY)
+                .assert_body(|body| assert!(body.contains("references unknown task"))),
+        )
+        // one of two right answers would otherwise count as a correct attempt
+        .step(
+            Action::new("sync_multi_select", "POST", "/api/v1/tasks/attempts/sync")
+                .with_body(json!([attempt(Uuid::new_v4(), multi, multi_right)]))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY)
+                .assert_body(|body| assert!(body.contains("multi_select task"))),
+        )
+        // the stored answer text is served with the task, the typed text has to be checked online
+        .step(
+            Action::new("sync_string_cmp", "POST", "/api/v1/tasks/attempts/sync")
+                .with_body(json!([attempt(Uuid::new_v4(), typed, typed_answer)]))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY)
+                .assert_body(|body| assert!(body.contains("string_cmp task"))),
+        )
+        .step(
+            Action::new("sync", "POST", "/api/v1/tasks/attempts/sync")
+                .with_body(batch.clone())
+                .assert_body(|body| {
+                    let synced: serde_json::Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(synced, json!({ "inserted": 2, "duplicates": 1 }));
+                }),
+        )
+        .step(
+            Action::new("sync_again", "POST", "/api/v1/tasks/attempts/sync")
+                .with_body(batch)
+                .assert_body(|body| {
+                    let synced: serde_json::Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(synced, json!({ "inserted": 0, "duplicates": 3 }));
+                }),
+        )
+        .step(
+            Action::new("lesson_get", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}", lesson_id))
+                .assert_body(|body| assert!(body.contains(r#""status":true"#))),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    // the rejected batch left nothing behind, and correctness came from the answers
+    let stored: Vec<bool> = sqlx::query_scalar(
+        "SELECT is_correct FROM user_task_attempts ORDER BY is_correct",
+    )
+    .fetch_all(pool.mm().executor())
+    .await
+    .unwrap();
+    assert_eq!(stored, [false, true]);
+}
+
+#[tokio::test]
+async fn route_task_check_correct_answer_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let choice = seed_task(&pool, lesson.id(), "choice").await;
+    let right = seed_answer(&pool, choice.id(), "true", true).await.id();
+    let wrong = seed_answer(&pool, choice.id(), "false", false).await.id();
+    let typed = seed_task(&pool, lesson.id(), "string_cmp").await;
+    let typed_answer = seed_answer(&pool, typed.id(), "secret", true).await.id();
+
+    let correct_id = |body: &str| {
+        let res: serde_json::Value = serde_json::from_str(body).unwrap();
+        res.get("correct_answer_id").map(|id| id.as_str().unwrap().parse::<Uuid>().unwrap())
+    };
+
+    Flow::new()
+        .step(signup_action("highlight", "highlight"))
+        .step(
+            Action::new("check_wrong", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": wrong, "task_type": "choice" }))
+                .assert_body(move |body| assert_eq!(correct_id(body), Some(right))),
+        )
+        .step(
+            Action::new("check_right", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": right, "task_type": "choice" }))
+                .assert_body(move |body| assert_eq!(correct_id(body), Some(right))),
+        )
+        .step(
+            Action::new("check_string_cmp", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "answer_id": typed_answer,
+                    "task_type": "string_cmp",
+                    "user_answer": "guess",
+                }))
+                .assert_body(move |body| assert_eq!(correct_id(body), None)),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_check_reveal_on_fail_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let choice = seed_task(&pool, lesson, "choice").await;
+    let right = seed_answer(&pool, choice.id(), "let", true).await.id();
+    let wrong = seed_answer(&pool, choice.id(), "var", false).await.id();
+    let typed = seed_task(&pool, lesson, "string_cmp").await;
+    let typed_answer = seed_answer(&pool, typed.id(), "secret", true).await.id();
+
+    let data = LessonTaskCreate {
+        lesson_id: lesson,
+        task_type: "choice".to_string(),
+        question: "question".to_string(),
+        explanation: "explanation".to_string(),
+        case_insensitive: false,
+        normalize_whitespace: false,
+        min_seconds_before_check: 0,
+        reveal_on_fail: false,
+    };
+    let secretive = LessonTask::create(&pool.mm(), &AuthenticatedUser::admin(), data).await.unwrap();
+    seed_answer(&pool, secretive.id(), "fn", true).await;
+    let secretive_wrong = seed_answer(&pool, secretive.id(), "def", false).await.id();
+
+    let correct_ids = |body: &str| {
+        let res: Value = serde_json::from_str(body).unwrap();
+        res.get("correct_answer_ids").map(|ids| serde_json::from_value::<Vec<Uuid>>(ids.clone()).unwrap())
+    };
+
+    Flow::new()
+        .step(signup_action("revealer", "revealer"))
+        .step(
+            Action::new("check_wrong", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": wrong, "task_type": "choice" }))
+                .assert_body(move |body| assert_eq!(correct_ids(body), Some(vec![right]))),
+        )
+        .step(
+            Action::new("check_right", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": right, "task_type": "choice" }))
+                .assert_body(move |body| assert_eq!(correct_ids(body), None)),
+        )
+        .step(
+            Action::new("check_string_cmp_wrong", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "answer_id": typed_answer,
+                    "task_type": "string_cmp",
+                    "user_answer": "guess",
+                }))
+                .assert_body(move |body| assert_eq!(correct_ids(body), None)),
+        )
+        .step(
+            Action::new("check_not_revealed", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": secretive_wrong, "task_type": "choice" }))
+                .assert_body(move |body| {
+                    assert_eq!(correct_ids(body), None);
+                    assert!(!body.contains("correct_answer_id"));
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_check_multi_select_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "multi_select").await;
+    let let_ = seed_answer(&pool, task.id(), "let", true).await.id();
+    let const_ = seed_answer(&pool, task.id(), "const", true).await.id();
+    let var = seed_answer(&pool, task.id(), "var", false).await.id();
+    let other_task = seed_task(&pool, lesson.id(), "choice").await;
+    let foreign = seed_answer(&pool, other_task.id(), "mut", true).await.id();
+    let task_id = task.id();
+
+    let check = |name: &'static str, answer_ids: Vec<Uuid>, expected: bool| {
+        Action::new(name, "POST", "/api/v1/tasks/check")
+            .with_body(json!({
+                "task_id": task_id,
+                "task_type": "multi_select",
+                "answer_ids": answer_ids,
+            }))
+            .assert_body(move |body| {
+                assert!(body.contains(&format!("\"is_correct\":{expected}")), "{body}");
+                assert!(!body.contains("\"correct_answer_id\""));
+            })
+    };
+
+    Flow::new()
+        .step(signup_action("multiselect", "multiselect"))
+        .step(check("check_subset", vec![let_], false))
+        .step(check("check_superset", vec![let_, const_, var], false))
+        .step(check("check_exact", vec![const_, let_], true))
+        // order and repeats don't matter
+        .step(check("check_exact_repeated", vec![let_, const_, let_], true))
+        // an answer of the task still identifies it, but isn't stored as the pick
+        .step(
+            Action::new("check_by_answer", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "answer_id": var,
+                    "task_type": "multi_select",
+                    "answer_ids": [let_, const_],
+                }))
+                .assert_body(|body| assert!(body.contains("\"is_correct\":true"), "{body}")),
+        )
+        .step(
+            Action::new("check_missing_ids", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "task_id": task_id, "task_type": "multi_select" }))
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .step(
+            Action::new("check_missing_task", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "task_type": "multi_select", "answer_ids": [let_] }))
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .step(
+            Action::new("check_answer_of_other_task", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "answer_id": foreign,
+                    "task_id": task_id,
+                    "task_type": "multi_select",
+                    "answer_ids": [let_, const_],
+                }))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY),
+        )
+        .step(
+            Action::new("check_foreign_answer", "POST", "/api/v1/tasks/check")
+                .with_body(json!({
+                    "task_id": task_id,
+                    "task_type": "multi_select",
+                    "answer_ids": [let_, const_, foreign],
+                }))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY),
+        )
+        // the other types still need the picked answer
+        .step(
+            Action::new("check_choice_without_answer", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "task_id": other_task.id(), "task_type": "choice" }))
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    let mm = pool.mm();
+    let actor = AuthenticatedUser::admin();
+    let attempts = UserTaskAttempt::list(&mm, &actor, 10, 0, None).await.unwrap();
+    let mut recorded = Vec::new();
+    for attempt in &attempts {
+        assert_eq!(attempt.selected_answer_id(), None);
+        recorded.push((attempt.selected_answer_ids(&mm).await.unwrap().len(), attempt.is_correct()));
+    }
+    assert_eq!(recorded, [(1, false), (3, false), (2, true), (2, true), (2, true)]);
+}
+
+#[tokio::test]
+async fn route_task_multi_select_stats_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "multi_select").await;
+    let task_id = task.id();
+    let let_ = seed_answer(&pool, task_id, "let", true).await;
+    let const_ = seed_answer(&pool, task_id, "const", true).await;
+    let var = seed_answer(&pool, task_id, "var", false).await;
+    let (let_id, const_id, var_id) = (let_.id(), const_.id(), var.id());
+
+    let check = |name: &'static str, answer_ids: [Uuid; 2]| {
+        Action::new(name, "POST", "/api/v1/tasks/check").with_body(json!({
+            "task_id": task_id,
+            "task_type": "multi_select",
+            "answer_ids": answer_ids,
+        }))
+    };
+    let distribution = |name: &'static str, expected: [(&'static str, i64, f64); 3]| {
+        Action::new(name, "GET", "")
+            .with_dyn_path(move |_| format!("/api/v1/tasks/{}/answer-distribution", task_id))
+            .assert_body(move |body| {
+                let rows: Vec<Value> = serde_json::from_str(body).unwrap();
+                let summary: Vec<(&str, i64, f64)> = rows
+                    .iter()
+                    .map(|r| {
+                        let text = r["answer_text"].as_str().unwrap();
+                        (text, r["count"].as_i64().unwrap(), r["percent"].as_f64().unwrap())
+                    })
+                    .collect();
+                assert_eq!(summary, expected);
+            })
+    };
+    let recompute = |name: &'static str, flipped: u64| {
+        Action::new(name, "POST", "")
+            .with_dyn_path(move |_| format!("/api/v1/tasks/{}/recompute-attempts", task_id))
+            .assert_body(move |body| assert!(body.contains(&format!("\"flipped\":{flipped}")), "{body}"))
+    };
+
+    Flow::new()
+        .step(signup_action("picker", "pickerpass"))
+        .step(check("check_right", [let_id, const_id]))
+        .step(signup_action("guesser", "guesserpass").with_clear_cookies(true))
+        .step(check("check_wrong", [let_id, var_id]))
+        .step(signin_admin_action().with_clear_cookies(true))
+        // every pick counts, so the shares add up to more than 100
+        .step(distribution("distribution", [("let", 2, 100.0), ("const", 1, 50.0), ("var", 1, 50.0)]))
+        .step(recompute("recompute_unchanged", 0))
+        .run(&mut server, &pool)
+        .await;
+
+    // the right set becomes let and var
+    let mm = pool.mm();
+    let actor = AuthenticatedUser::admin();
+    for (answer, is_correct) in [(const_, false), (var, true)] {
+        let data = AnswerCreate {
+            task_id,
+            answer_text: answer.answer_text().to_string(),
+            image: answer.image().to_string(),
+            is_correct: Some(is_correct),
+        };
+        answer.update(&mm, &actor, data).await.unwrap();
+    }
+
+    Flow::new()
+        .step(recompute("recompute", 2))
+        .step(recompute("recompute_again", 0))
+        .run(&mut server, &pool)
+        .await;
+
+    let attempts = UserTaskAttempt::list(&mm, &actor, 10, 0, None).await.unwrap();
+    let verdicts: Vec<bool> = attempts.iter().map(UserTaskAttempt::is_correct).collect();
+    assert_eq!(verdicts, [false, true]);
+}
+
+#[tokio::test]
+async fn route_task_check_delay_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let data = LessonTaskCreate {
+        lesson_id: lesson,
+        task_type: "choice".to_string(),
+        question: "question".to_string(),
+        explanation: "explanation".to_string(),
+        case_insensitive: false,
+        normalize_whitespace: false,
+        min_seconds_before_check: 1,
+        reveal_on_fail: true,
+    };
+    let task = LessonTask::create(&pool.mm(), &AuthenticatedUser::admin(), data).await.unwrap();
+    let right = seed_answer(&pool, task.id(), "let", true).await.id();
+    let other = seed_task(&pool, lesson, "choice").await.id();
+
+    let served_token = Arc::new(Mutex::new(String::new()));
+    let check = |name: &'static str, served_token: Arc<Mutex<String>>| {
+        Action::new(name, "POST", "/api/v1/tasks/check").with_dyn_body(move |_| {
+            json!({
+                "answer_id": right,
+                "task_type": "choice",
+                "served_token": *served_token.lock().unwrap(),
+            })
+        })
+    };
+
+    let saved = served_token.clone();
+    Flow::new()
+        .step(signup_action("checkdelay", "checkdelay"))
+        .step(
+            Action::new("lesson_tasks", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/tasks", lesson))
+                .assert_body(move |body| {
+                    let tasks: Vec<Value> = serde_json::from_str(body).unwrap();
+                    for task in tasks {
+                        // tasks without a delay don't need a token
+                        if task["id"] == other.to_string() {
+                            assert!(task.get("served_token").is_none());
+                        } else {
+                            *saved.lock().unwrap() = task["served_token"].as_str().unwrap().to_string();
+                        }
+                    }
+                }),
+        )
+        .step(
+            Action::new("check_without_token", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": right, "task_type": "choice" }))
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .step(
+            check("check_forged_token", Arc::new(Mutex::new("forged".to_string())))
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .step(check("check_too_soon", served_token.clone()).with_expect(StatusCode::TOO_MANY_REQUESTS))
+        .run(&mut server, &pool)
+        .await;
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    Flow::new()
+        .step(
+            check("check_after_delay", served_token.clone())
+                .assert_body(|body| assert!(body.contains("\"is_correct\":true"))),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    // the served token carries the user id as well, but must not work as a session
+    server.clear_cookies();
+    let token = served_token.lock().unwrap().clone();
+    server
+        .get("/api/v1/account/me")
+        .authorization_bearer(token)
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn route_task_attempts_sync_rules_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let data = LessonTaskCreate {
+        lesson_id: lesson,
+        task_type: "choice".to_string(),
+        question: "question".to_string(),
+        explanation: "explanation".to_string(),
+        case_insensitive: false,
+        normalize_whitespace: false,
+        min_seconds_before_check: 1,
+        reveal_on_fail: true,
+    };
+    let delayed = LessonTask::create(&pool.mm(), &AuthenticatedUser::admin(), data).await.unwrap().id();
+    let delayed_answer = seed_answer(&pool, delayed, "let", true).await.id();
+    let gated = seed_module(&pool, "Advanced", 2).await.id();
+    let gated_lesson = seed_lesson(&pool, gated, "Traits", 1).await.id();
+    let gated_task = seed_task(&pool, gated_lesson, "choice").await.id();
+    let gated_answer = seed_answer(&pool, gated_task, "impl", true).await.id();
+
+    let served_token = Arc::new(Mutex::new(String::new()));
+    let sync = |name: &'static str, task_id: Uuid, answer_id: Uuid, served_token: Option<Arc<Mutex<String>>>| {
+        Action::new(name, "POST", "/api/v1/tasks/attempts/sync").with_dyn_body(move |_| {
+            json!([{
+                "client_id": Uuid::new_v4(),
+                "task_id": task_id,
+                "selected_answer_id": answer_id,
+                "attempted_at": chrono::Utc::now(),
+                "served_token": served_token.as_ref().map(|t| t.lock().unwrap().clone()),
+            }])
+        })
+    };
+
+    let saved = served_token.clone();
+    Flow::new()
+        .step(signup_action("syncrules", "syncrules"))
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("gating_set", "PUT", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}/gating", gated))
+                .with_body(json!({ "min_prerequisite_percent": 50 })),
+        )
+        .step(signin_action("syncrules", "syncrules").with_clear_cookies(true))
+        .step(
+            Action::new("lesson_tasks", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/tasks", lesson))
+                .assert_body(move |body| {
+                    let tasks: Vec<Value> = serde_json::from_str(body).unwrap();
+                    *saved.lock().unwrap() = tasks[0]["served_token"].as_str().unwrap().to_string();
+                }),
+        )
+        .step(sync("sync_locked", gated_task, gated_answer, None).with_expect(StatusCode::FORBIDDEN))
+        .step(sync("sync_without_token", delayed, delayed_answer, None).with_expect(StatusCode::BAD_REQUEST))
+        .step(
+            sync("sync_too_soon", delayed, delayed_answer, Some(served_token.clone()))
+                .with_expect(StatusCode::TOO_MANY_REQUESTS),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+
+    Flow::new()
+        .step(
+            sync("sync_after_delay", delayed, delayed_answer, Some(served_token.clone()))
+                .assert_body(|body| assert!(body.contains(r#""inserted":1"#))),
+        )
+        .run(&mut server, &pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_authoring_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module_id = seed_module(&pool, "Basics", 1).await.id();
+    let id_of = |key: &'static str| {
+        move |ctx: &FlowContext| ctx.get(key)["id"].as_str().unwrap().to_string()
+    };
+
+    Flow::new()
+        .step(signin_admin_action())
+        .step(
+            Action::new("lesson_create", "POST", "/api/v1/lessons/")
+                .with_expect(StatusCode::CREATED)
+                .with_body(json!({ "module_id": module_id, "title": "Variables", "content": "" }))
+                .with_save_as("lesson"),
+        )
+        .step(
+            Action::new("task_create_bad_type", "POST", "/api/v1/tasks/")
+                .with_dyn_body(move |ctx| json!({
+                    "lesson_id": id_of("lesson")(ctx),
+                    "task_type": "essay",
+                    "question": "Write a poem",
+                    "explanation": "",
+                }))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY),
+        )
+        .step(
+            Action::new("task_create", "POST", "/api/v1/tasks/")
+                .with_expect(StatusCode::CREATED)
+                .with_dyn_body(move |ctx| json!({
+                    "lesson_id": id_of("lesson")(ctx),
+                    "task_type": "choice",
+                    "question": "Which keyword declares a variable?",
+                    "explanation": "Rust uses let",
+                }))
+                .with_save_as("task"),
+        )
+        .step(
+            Action::new("answer_create_right", "POST", "")
+                .with_expect(StatusCode::CREATED)
+                .with_dyn_path(move |ctx| format!("/api/v1/tasks/{}/answers", id_of("task")(ctx)))
+                .with_body(json!({ "answer_text": "let", "is_correct": true }))
+                .with_save_as("right"),
+        )
+        .step(
+            Action::new("answer_create_wrong", "POST", "")
+                .with_expect(StatusCode::CREATED)
+                .with_dyn_path(move |ctx| format!("/api/v1/tasks/{}/answers", id_of("task")(ctx)))
+                .with_body(json!({ "answer_text": "var" }))
+                .with_save_as("wrong"),
+        )
+        .step(
+            Action::new("answer_create_extra", "POST", "")
+                .with_expect(StatusCode::CREATED)
+                .with_dyn_path(move |ctx| format!("/api/v1/tasks/{}/answers", id_of("task")(ctx)))
+                .with_body(json!({ "answer_text": "dim" }))
+                .with_save_as("extra"),
+        )
+        .step(
+            Action::new("answer_update", "PUT", "")
+                .with_dyn_path(move |ctx| {
+                    format!("/api/v1/tasks/{}/answers/{}", id_of("task")(ctx), id_of("wrong")(ctx))
+                })
+                .with_body(json!({ "answer_text": "const" })),
+        )
+        .step(
+            Action::new("answer_delete", "DELETE", "")
+                .with_dyn_path(move |ctx| {
+                    format!("/api/v1/tasks/{}/answers/{}", id_of("task")(ctx), id_of("extra")(ctx))
+                }),
+        )
+        // answers are only reachable through their own task
+        .step(
+            Action::new("answer_delete_foreign", "DELETE", "")
+                .with_dyn_path(move |ctx| {
+                    format!("/api/v1/tasks/{}/answers/{}", Uuid::new_v4(), id_of("right")(ctx))
+                })
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(
+            Action::new("lesson_tasks", "GET", "")
+                .with_dyn_path(move |ctx| format!("/api/v1/lessons/{}/tasks", id_of("lesson")(ctx)))
+                .assert_body(|body| {
+                    let tasks: serde_json::Value = serde_json::from_str(body).unwrap();
+                    let tasks = tasks.as_array().unwrap();
+                    assert_eq!(tasks.len(), 1);
+                    assert_eq!(tasks[0]["task_type"], "choice");
+                    assert_eq!(tasks[0]["question"], "Which keyword declares a variable?");
+
+                    let mut texts: Vec<&str> = tasks[0]["answers"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|a| a["answer_text"].as_str().unwrap())
+                        .collect();
+                    texts.sort();
+                    assert_eq!(texts, ["const", "let"]);
+                }),
+        )
+        .step(
+            Action::new("task_delete", "DELETE", "")
+                .with_dyn_path(move |ctx| format!("/api/v1/tasks/{}", id_of("task")(ctx))),
+        )
+        .step(
+            Action::new("lesson_tasks_empty", "GET", "")
+                .with_dyn_path(move |ctx| format!("/api/v1/lessons/{}/tasks", id_of("lesson")(ctx)))
+                .assert_body(|body| assert_eq!(body, "[]")),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_authoring_forbidden_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson_id = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let task_id = seed_task(&pool, lesson_id, "choice").await.id();
+
+    Flow::new()
+        .step(signup_action("taskwriter", "taskwriter"))
+        .step(
+            Action::new("task_create", "POST", "/api/v1/tasks/")
+                .with_body(json!({
+                    "lesson_id": lesson_id,
+                    "task_type": "choice",
+                    "question": "Sneaky",
+                    "explanation": "",
+                }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("answer_create", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/tasks/{}/answers", task_id))
+                .with_body(json!({ "answer_text": "sneaky", "is_correct": true }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("task_delete", "DELETE", "")
+                .with_dyn_path(move |_| format!("/api/v1/tasks/{}", task_id))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_tasks_for_lessons_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let variables = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let loops = seed_lesson(&pool, module.id(), "Loops", 2).await.id();
+    let untouched = seed_lesson(&pool, module.id(), "Traits", 3).await.id();
+    let choice = seed_task(&pool, variables, "choice").await.id();
+    seed_answer(&pool, choice, "let", true).await;
+    let typed = seed_task(&pool, variables, "string_cmp").await.id();
+    seed_answer(&pool, typed, "secret", true).await;
+    let loop_task = seed_task(&pool, loops, "choice").await.id();
+    seed_answer(&pool, loop_task, "for", true).await;
+    seed_task(&pool, untouched, "choice").await;
+    let gated = seed_module(&pool, "Advanced", 2).await.id();
+    let locked = seed_lesson(&pool, gated, "Generics", 1).await.id();
+    seed_task(&pool, locked, "choice").await;
+
+    let too_many: Vec<Uuid> = (0..51).map(|_| Uuid::new_v4()).collect();
+
+    Flow::new()
+        .step(signin_admin_action())
+        .step(
+            Action::new("gating_set", "PUT", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{}/gating", gated))
+                .with_body(json!({ "min_prerequisite_percent": 50 })),
+        )
+        .step(signup_action("forlessons", "forlessons").with_clear_cookies(true))
+        .step(
+            Action::new("tasks_for_lessons", "POST", "/api/v1/tasks/for-lessons")
+                .with_body(json!({ "lesson_ids": [variables, loops, Uuid::new_v4()] }))
+                .assert_body(move |body| {
+                    let by_lesson: Value = serde_json::from_str(body).unwrap();
+                    let by_lesson = by_lesson.as_object().unwrap();
+                    assert_eq!(by_lesson.len(), 2);
+
+                    let ids = |lesson: Uuid| {
+                        let mut ids: Vec<String> = by_lesson[&lesson.to_string()]
+                            .as_array()
+                            .unwrap()
+                            .iter()
+                            .map(|t| t["id"].as_str().unwrap().to_string())
+                            .collect();
+                        ids.sort();
+                        ids
+                    };
+                    let mut expected = vec![choice.to_string(), typed.to_string()];
+                    expected.sort();
+                    assert_eq!(ids(variables), expected);
+                    assert_eq!(ids(loops), [loop_task.to_string()]);
+
+                    for task in by_lesson[&variables.to_string()].as_array().unwrap() {
+                        let text = &task["answers"][0]["answer_text"];
+                        if task["task_type"] == "string_cmp" {
+                            assert!(text.is_null());
+                        } else {
+                            assert_eq!(text, "let");
+                        }
+                    }
+                }),
+        )
+        // a single locked lesson gives nothing away
+        .step(
+            Action::new("tasks_for_locked_lesson", "POST", "/api/v1/tasks/for-lessons")
+                .with_body(json!({ "lesson_ids": [variables, locked] }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(
+            Action::new("tasks_for_too_many_lessons", "POST", "/api/v1/tasks/for-lessons")
+                .with_body(json!({ "lesson_ids": too_many }))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_task_answer_image_upload_test() {
+    let pool = setup_test_db().await;
+    let uploads = tempfile::tempdir().unwrap();
+    let config = test_config(&[
+        ("max_upload_bytes", 64.into()),
+        ("uploads_dir", uploads.path().to_str().unwrap().into()),
+    ]);
+    let mut server = setup_server_with_config(&pool, config).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson = seed_lesson(&pool, module.id(), "Variables", 1).await;
+    let task = seed_task(&pool, lesson.id(), "choice").await.id();
+    let answer = seed_answer(&pool, task, "let", true).await.id();
+    let path = format!("/api/v1/tasks/{task}/answers/{answer}/image");
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0".to_vec();
+    let form = |bytes: Vec<u8>, mime: &str| {
+        MultipartForm::new().add_part("image", Part::bytes(bytes).file_name("image").mime_type(mime))
+    };
+
+    Flow::new().step(signup_action("uploader", "uploader")).run(&mut server, &pool).await;
+    server.post(&path).multipart(form(png.clone(), "image/png")).await.assert_status_forbidden();
+
+    Flow::new()
+        .step(signin_admin_action().with_clear_cookies(true))
+        .run(&mut server, &pool)
+        .await;
+    // not an image, a mislabeled one, one over the limit, and no file at all
+    let mut large = png.clone();
+    large.resize(65, 0);
+    let rejected = [
+        form(b"hello".to_vec(), "text/plain"),
+        form(png.clone(), "image/jpeg"),
+        form(large, "image/png"),
+        MultipartForm::new().add_text("note", "x"),
+    ];
+    for form in rejected {
+        server.post(&path).multipart(form).await.assert_status_bad_request();
+    }
+    server
+        .post(&format!("/api/v1/tasks/{task}/answers/{}/image", Uuid::new_v4()))
+        .multipart(form(png.clone(), "image/png"))
+        .await
+        .assert_status_not_found();
+
+    let response = server.post(&path).multipart(form(png.clone(), "image/png")).await;
+    response.assert_status_ok();
+    let stored: Value = response.json();
+    let image = stored["image"].as_str().unwrap().to_string();
+    assert!(image.starts_with("answers/") && image.ends_with(".png"), "{image}");
+
+    let served = server.get(&format!("/api/v1/static/{image}")).await;
+    served.assert_status_ok();
+    assert_eq!(served.as_bytes().as_ref(), png.as_slice());
+    assert!(uploads.path().join(&image).is_file());
+}
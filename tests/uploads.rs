@@ -0,0 +1,36 @@
+mod common;
+use axum::http::{StatusCode, header};
+
+use crate::common::{setup_server_with_config, setup_test_db, test_config};
+
+#[tokio::test]
+async fn static_uploads_headers_test() {
+    let pool = setup_test_db().await;
+    let uploads = tempfile::tempdir().unwrap();
+    std::fs::create_dir(uploads.path().join("answers")).unwrap();
+    std::fs::write(uploads.path().join("answers/cat.webp"), b"RIFF\0\0\0\0WEBPVP8 ").unwrap();
+    let svg = "<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+    std::fs::write(uploads.path().join("logo.svg"), svg).unwrap();
+    let config = test_config(&[
+        ("uploads_dir", uploads.path().to_str().unwrap().into()),
+        ("uploads_max_age_secs", 120.into()),
+    ]);
+    let server = setup_server_with_config(&pool, config).await;
+
+    let response = server.get("/api/v1/static/answers/cat.webp").await;
+    response.assert_status_ok();
+    assert_eq!(response.header(header::CONTENT_TYPE), "image/webp");
+    assert_eq!(response.header(header::CACHE_CONTROL), "public, max-age=120");
+
+    let response = server.get("/api/v1/static/logo.svg").await;
+    response.assert_status_ok();
+    assert_eq!(response.header(header::CONTENT_TYPE), "image/svg+xml");
+
+    // neither missing files nor directories are served, and the miss isn't cached
+    let missing = ["/api/v1/static/answers/dog.webp", "/api/v1/static/answers", "/api/v1/static/answers/"];
+    for path in missing {
+        let response = server.get(path).await;
+        assert_eq!(response.status_code(), StatusCode::NOT_FOUND, "{path}");
+        assert!(response.maybe_header(header::CACHE_CONTROL).is_none(), "{path}");
+    }
+}
@@ -1,12 +1,18 @@
 mod common;
 use axum::http::StatusCode;
-use flern::model::entity::UserEntity;
-use flern::web::middlewares::AUTH_TOKEN;
-use serde_json::json;
-use tower_cookies::cookie::SameSite;
+use flern::model::entity::{UserEntity, UserEntityCreateUpdate};
+use flern::model::{CrudRepository, DatabaseError};
+use flern::web::AuthenticatedUser;
+use flern::web::middlewares::{AUTH_TOKEN, REFRESH_TOKEN};
+use serde_json::{Value, json};
+use tower_cookies::Cookie;
+use tower_cookies::cookie::{SameSite, time::Duration};
+use uuid::Uuid;
 
 use crate::common::{
-    Action, Flow, setup_server, setup_test_db, signin_action, signin_admin_action, signup_action,
+    Action, Flow, FlowContext, seed_answer, seed_lesson, seed_module, seed_task, setup_server,
+    setup_server_with_config, setup_test_db, setup_unseeded_db, signin_action, signin_admin_action,
+    signup_action, test_config,
 };
 
 #[tokio::test]
@@ -16,20 +22,21 @@ async fn route_signup_test() {
 
     Flow::new()
         .step(
-            signup_action("foobar", "foobaz")
+            signup_action("foobar", "foobazpass")
                 .assert_cookie(AUTH_TOKEN, |cookie| {
                     assert_eq!(cookie.same_site(), Some(SameSite::Lax));
                     assert_eq!(cookie.path(), Some("/"));
                     assert_eq!(cookie.http_only(), Some(true));
+                    assert_eq!(cookie.max_age(), Some(Duration::seconds(86400)));
                 })
                 .assert_body(|body| {
                     let ent: UserEntity = serde_json::from_str(body).expect("Invalid body format");
                     assert_eq!(ent.username(), "foobar");
                 })
-                .with_expect(StatusCode::OK),
+                .with_expect(StatusCode::CREATED),
         )
         // try to signup twice
-        .step(signup_action("foobar", "foobaz").with_expect(StatusCode::CONFLICT))
+        .step(signup_action("foobar", "foobazpass").with_expect(StatusCode::CONFLICT))
         .run(&mut server, pool)
         .await;
 }
@@ -75,13 +82,43 @@ async fn route_signin_test() {
         .await;
 }
 
+#[tokio::test]
+async fn route_me_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    Flow::new()
+        .step(
+            Action::new("me_anonymous", "GET", "/api/v1/account/me")
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .step(signup_action("WHOAMI", "WHOAMIPASS").with_save_as("whoami"))
+        .step(
+            Action::new("me", "GET", "/api/v1/account/me").assert_body(|body| {
+                let ent: UserEntity = serde_json::from_str(body).expect("Invalid body format");
+                assert_eq!(ent.username(), "WHOAMI");
+                assert!(!body.contains("password"));
+            }),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("me_admin", "GET", "/api/v1/account/me").assert_body(|body| {
+                let ent: UserEntity = serde_json::from_str(body).expect("Invalid body format");
+                assert_eq!(ent.username(), "admin");
+                assert!(body.contains(r#""role":"admin""#));
+            }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
 #[tokio::test]
 async fn route_user_list_test() {
     let pool = setup_test_db().await;
     let mut server = setup_server(&pool).await;
 
     Flow::new()
-        .step(signup_action("FOOBAR", "FOOBAZ").with_save_cookies(true))
+        .step(signup_action("FOOBAR", "FOOBAZPASS").with_save_cookies(true))
         // try to request without admin perms
         .step(
             Action::new("user_list", "GET", "/api/v1/account/page")
@@ -105,6 +142,51 @@ async fn route_user_list_test() {
                 })
                 .with_expect(StatusCode::OK),
         )
+        .step(
+            Action::new("user_list_sorted", "GET", "/api/v1/account/page")
+                .with_param("limit", "5")
+                .with_param("offset", "0")
+                .with_param("sort", "role:desc")
+                .assert_body(|body| {
+                    let page: Value = serde_json::from_str(body).unwrap();
+                    let names: Vec<&str> = page["items"]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .map(|u| u["username"].as_str().unwrap())
+                        .collect();
+                    assert_eq!(names, ["FOOBAR", "admin"]);
+                })
+                .with_expect(StatusCode::OK),
+        )
+        .step(
+            Action::new("user_list_last", "GET", "/api/v1/account/page")
+                .with_param("limit", "5")
+                .with_param("offset", "1")
+                .assert_body(|body| {
+                    let page: Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+                    assert_eq!(page["out_of_range"], false);
+                }),
+        )
+        .step(
+            Action::new("user_list_past_end", "GET", "/api/v1/account/page")
+                .with_param("limit", "5")
+                .with_param("offset", "10")
+                .assert_body(|body| {
+                    let page: Value = serde_json::from_str(body).unwrap();
+                    assert!(page["items"].as_array().unwrap().is_empty());
+                    assert_eq!(page["total"], 2);
+                    assert_eq!(page["out_of_range"], true);
+                }),
+        )
+        .step(
+            Action::new("user_list_bad_sort", "GET", "/api/v1/account/page")
+                .with_param("limit", "5")
+                .with_param("offset", "0")
+                .with_param("sort", "password_hash:asc")
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
         .run(&mut server, pool)
         .await;
 }
@@ -117,12 +199,12 @@ async fn route_user_update_test() {
     Flow::new()
         // create a pair of users and save their data to `foobar_user` and `foobar2_user`
         .step(
-            signup_action("FOOBAR", "FOOBAZ")
+            signup_action("FOOBAR", "FOOBAZPASS")
                 .with_save_cookies(false)
                 .with_save_as("foobar_user"),
         )
         .step(
-            signup_action("FOOBAR2", "FOOBAZ2")
+            signup_action("FOOBAR2", "FOOBAZPASS2")
                 .with_save_cookies(true)
                 .with_save_as("foobar2_user"),
         )
@@ -205,14 +287,59 @@ async fn route_user_update_test() {
         .await;
 }
 
+#[tokio::test]
+async fn route_user_update_password_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let update_path = |ctx: &FlowContext| {
+        let user = ctx.get_json::<UserEntity>("rotator");
+        format!("/api/v1/account/{}", user.id())
+    };
+
+    Flow::new()
+        .step(signup_action("ROTATOR", "first password").with_save_as("rotator"))
+        .step(
+            Action::new("user_update_weak", "PUT", "dynamic")
+                .with_dyn_path(update_path)
+                .with_body(json!({ "username": "ROTATOR", "password": "short" }))
+                .with_expect(StatusCode::BAD_REQUEST),
+        )
+        .step(
+            Action::new("user_update_password", "PUT", "dynamic")
+                .with_dyn_path(update_path)
+                .with_body(json!({ "username": "ROTATOR", "password": "second password" })),
+        )
+        .step(
+            signin_action("ROTATOR", "first password")
+                .with_clear_cookies(true)
+                .with_expect(StatusCode::UNAUTHORIZED),
+        )
+        .step(signin_action("ROTATOR", "second password"))
+        // omitting the password keeps the current one
+        .step(
+            Action::new("user_update_username", "PUT", "dynamic")
+                .with_dyn_path(update_path)
+                .with_body(json!({ "username": "ROTATED" }))
+                .assert_body(|body| assert!(body.contains("ROTATED"))),
+        )
+        .step(
+            signin_action("ROTATED", "second password")
+                .with_clear_cookies(true)
+                .with_expect(StatusCode::OK),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
 #[tokio::test]
 async fn route_user_delete_test() {
     let pool = setup_test_db().await;
     let mut server = setup_server(&pool).await; 
 
     Flow::new()
-        .step(signup_action("FOOBAR", "FOOBAZ").with_save_cookies(false).with_save_as("foobar"))
-        .step(signup_action("FOOBAZ", "FOOBAR").with_save_cookies(true).with_save_as("foobaz"))
+        .step(signup_action("FOOBAR", "FOOBAZPASS").with_save_cookies(false).with_save_as("foobar"))
+        .step(signup_action("FOOBAZ", "FOOBARPASS").with_save_cookies(true).with_save_as("foobaz"))
         // we can't allow everybody to delete anybody ;D
         .step(
             Action::new("user_delete", "DELETE", "dynamic")
@@ -256,3 +383,778 @@ async fn route_user_delete_test() {
         .run(&mut server, pool)
         .await;
 }
+
+#[tokio::test]
+async fn route_user_merge_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let shared = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    let only_dup = seed_lesson(&pool, module.id(), "Functions", 2).await.id();
+    seed_lesson(&pool, module.id(), "Traits", 3).await;
+    let task = seed_task(&pool, only_dup, "choice").await;
+    let right = seed_answer(&pool, task.id(), "fn", true).await.id();
+
+    let done = |name: &'static str, lesson: Uuid| {
+        Action::new(name, "POST", "").with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson))
+    };
+    let merge_path = |ctx: &FlowContext| {
+        let main: UserEntity = ctx.get_json("main");
+        format!("/api/v1/account/{}/merge", main.id())
+    };
+    let dup_body = |ctx: &FlowContext| {
+        let dup: UserEntity = ctx.get_json("dup");
+        json!({ "source_id": dup.id() })
+    };
+
+    Flow::new()
+        .step(signup_action("MAIN", "MAINPASSWORD").with_save_as("main"))
+        .step(done("main_done", shared))
+        .step(signup_action("DUP", "DUPPASSWORD").with_save_as("dup"))
+        .step(done("dup_done_shared", shared))
+        .step(
+            Action::new("dup_task_check", "POST", "/api/v1/tasks/check")
+                .with_body(json!({ "answer_id": right, "task_type": "choice" })),
+        )
+        .step(
+            Action::new("merge_forbidden", "POST", "")
+                .with_dyn_path(merge_path)
+                .with_dyn_body(dup_body)
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("merge_self", "POST", "")
+                .with_dyn_path(merge_path)
+                .with_dyn_body(|ctx| {
+                    let main: UserEntity = ctx.get_json("main");
+                    json!({ "source_id": main.id() })
+                })
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY),
+        )
+        .step(
+            Action::new("merge_missing", "POST", "")
+                .with_dyn_path(merge_path)
+                .with_body(json!({ "source_id": Uuid::new_v4() }))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(
+            Action::new("merge", "POST", "")
+                .with_dyn_path(merge_path)
+                .with_dyn_body(dup_body)
+                .assert_body(|body| assert!(body.contains("\"username\":\"MAIN\""))),
+        )
+        .step(
+            Action::new("merged_progress", "GET", "")
+                .with_dyn_path(|ctx| {
+                    let main: UserEntity = ctx.get_json("main");
+                    format!("/api/v1/account/{}/progress", main.id())
+                })
+                .with_param("limit", "5")
+                .with_param("offset", "0")
+                .assert_body(|body| {
+                    let page: Value = serde_json::from_str(body).unwrap();
+                    // the lesson both completed is there once
+                    assert_eq!(page["total"], 2);
+                }),
+        )
+        .step(
+            Action::new("merge_again", "POST", "")
+                .with_dyn_path(merge_path)
+                .with_dyn_body(dup_body)
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    let mm = pool.mm();
+    let admin = AuthenticatedUser::admin();
+    assert!(UserEntity::find_by_username(&mm, &admin, "DUP").await.unwrap().is_none());
+
+    let main = UserEntity::find_by_username(&mm, &admin, "MAIN").await.unwrap().unwrap();
+    let attempts: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_task_attempts WHERE user_id = $1")
+        .bind(main.id())
+        .fetch_one(mm.executor())
+        .await
+        .unwrap();
+    assert_eq!(attempts, 1);
+
+    let (completed, total): (i32, i32) =
+        sqlx::query_as("SELECT completed, total FROM user_module_progress WHERE user_id = $1")
+            .bind(main.id())
+            .fetch_one(mm.executor())
+            .await
+            .unwrap();
+    assert_eq!((completed, total), (2, 3));
+}
+
+#[tokio::test]
+async fn route_user_progress_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let module = seed_module(&pool, "Basics", 1).await;
+    let lesson_id = seed_lesson(&pool, module.id(), "Variables", 1).await.id();
+    seed_lesson(&pool, module.id(), "Functions", 2).await;
+    let missing = Uuid::new_v4();
+
+    let progress_path = |ctx: &FlowContext| {
+        let learner: UserEntity = ctx.get_json("learner");
+        format!("/api/v1/account/{}/progress", learner.id())
+    };
+
+    Flow::new()
+        .step(signup_action("LEARNER", "LEARNERPASS").with_save_as("learner"))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", lesson_id)),
+        )
+        // learners can't inspect anyone
+        .step(
+            Action::new("user_progress", "GET", "")
+                .with_dyn_path(progress_path)
+                .with_param("limit", "5")
+                .with_param("offset", "0")
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(
+            Action::new("user_progress", "GET", "")
+                .with_dyn_path(progress_path)
+                .with_param("limit", "5")
+                .with_param("offset", "0")
+                .assert_body(move |body| {
+                    let page: serde_json::Value = serde_json::from_str(body).unwrap();
+                    assert_eq!(page["total"], 1);
+                    assert_eq!(page["items"][0]["lesson_id"], lesson_id.to_string());
+                    assert_eq!(page["items"][0]["lesson_title"], "Variables");
+                    assert_eq!(page["items"][0]["status"], "done");
+                }),
+        )
+        .step(
+            Action::new("user_progress_missing", "GET", "")
+                .with_dyn_path(move |_| format!("/api/v1/account/{}/progress", missing))
+                .with_param("limit", "5")
+                .with_param("offset", "0")
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_signup_content_type_test() {
+    let pool = setup_test_db().await;
+    let server = setup_server(&pool).await;
+
+    let response = server
+        .post("/api/v1/account/signup")
+        .text(r#"{"username": "plain", "password": "plain"}"#)
+        .expect_failure()
+        .await;
+
+    response.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(response.text().contains("Unsupported media type"));
+
+    // malformed JSON is still a plain bad request
+    let response = server
+        .post("/api/v1/account/signup")
+        .content_type("application/json")
+        .bytes("{".into())
+        .expect_failure()
+        .await;
+
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert!(response.text().contains("Bad request"));
+}
+
+#[tokio::test]
+async fn route_session_ttl_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[("token_ttl_secs", 120.into())]);
+    let mut server = setup_server_with_config(&pool, config).await;
+
+    let check = move |cookie: &Cookie| {
+        assert_eq!(cookie.max_age(), Some(Duration::seconds(120)));
+        let claims = flern::auth::process_token(cookie.value(), config.app().jwt()).unwrap();
+        let left = claims.claims.exp - chrono::Utc::now().timestamp();
+        assert!((110..=120).contains(&left), "token expires in {left}s");
+    };
+
+    Flow::new()
+        .step(signup_action("SHORTSESSION", "SHORTSESSION").assert_cookie(AUTH_TOKEN, check))
+        .step(signin_action("SHORTSESSION", "SHORTSESSION").assert_cookie(AUTH_TOKEN, check))
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_signup_constraint_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    Flow::new()
+        .step(
+            signup_action("   ", "blankname")
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| {
+                    assert!(body.contains("Resource error, bad request."));
+                    // debug builds carry the violated constraint
+                    assert!(body.contains("users_username_not_empty"));
+                }),
+        )
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_signup_weak_password_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    Flow::new()
+        .step(
+            signup_action("weakling", "short")
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| {
+                    assert!(body.contains("password must be at least 8 characters long"))
+                }),
+        )
+        .step(
+            signup_action("weakling", "          ")
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| assert!(body.contains("password can't be only whitespace"))),
+        )
+        .step(signup_action("weakling", "long enough"))
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_signup_race_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    server.do_not_save_cookies();
+
+    let signup = || {
+        server
+            .post("/api/v1/account/signup")
+            .json(&json!({ "username": "RACER", "password": "RACERPASS" }))
+    };
+    let (first, second) = tokio::join!(signup(), signup());
+    let mut statuses = [first.status_code(), second.status_code()];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::CREATED, StatusCode::CONFLICT]);
+
+    // the database has the last word when both pass the username lookup
+    let duplicate = UserEntityCreateUpdate {
+        username: String::from("RACER"),
+        password_hash: String::from("hash"),
+    };
+    let err = UserEntity::create(&pool.mm(), &AuthenticatedUser::admin(), duplicate)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DatabaseError::UniqueViolation { .. }));
+}
+
+#[tokio::test]
+async fn route_verify_bearer_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    server.do_not_save_cookies();
+
+    let response = server
+        .post("/api/v1/account/signup")
+        .json(&json!({ "username": "BEARER", "password": "BEARERPASS" }))
+        .await;
+    let token = response.cookie(AUTH_TOKEN).value().to_string();
+
+    server
+        .get("/api/v1/account/verify")
+        .authorization_bearer(&token)
+        .await
+        .assert_status_ok();
+
+    server
+        .get("/api/v1/account/verify")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    let response = server
+        .get("/api/v1/account/verify")
+        .authorization_bearer("not-a-jwt")
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert!(response.text().contains("Authentication error, cookie invalid."));
+}
+
+#[tokio::test]
+async fn route_refresh_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    server.do_not_save_cookies();
+
+    let response = server
+        .post("/api/v1/account/signup")
+        .json(&json!({ "username": "REFRESH", "password": "REFRESHPASS" }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let user: Value = response.json();
+    assert_eq!(
+        response.header("location"),
+        format!("/api/v1/account/{}", user["id"].as_str().unwrap())
+    );
+    let refresh = response.cookie(REFRESH_TOKEN);
+    assert_eq!(refresh.path(), Some("/api/v1/account"));
+    assert_eq!(refresh.http_only(), Some(true));
+    assert_eq!(refresh.max_age(), Some(Duration::days(30)));
+    let refresh = refresh.value().to_string();
+
+    let response = server
+        .post("/api/v1/account/refresh")
+        .add_cookie(Cookie::new(REFRESH_TOKEN, refresh.clone()))
+        .await;
+    response.assert_status_ok();
+    let session = response.cookie(AUTH_TOKEN).value().to_string();
+    let rotated = response.cookie(REFRESH_TOKEN).value().to_string();
+    assert_ne!(rotated, refresh);
+
+    server
+        .get("/api/v1/account/verify")
+        .add_cookie(Cookie::new(AUTH_TOKEN, session))
+        .await
+        .assert_status_ok();
+
+    // refresh tokens are single-use
+    let response = server
+        .post("/api/v1/account/refresh")
+        .add_cookie(Cookie::new(REFRESH_TOKEN, refresh))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    assert!(response.text().contains("refresh token expired or already used"));
+
+    server
+        .post("/api/v1/account/refresh")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    sqlx::query("UPDATE refresh_tokens SET expires_at = now() - interval '1 minute' WHERE token = $1")
+        .bind(&rotated)
+        .execute(pool.mm().executor())
+        .await
+        .unwrap();
+    server
+        .post("/api/v1/account/refresh")
+        .add_cookie(Cookie::new(REFRESH_TOKEN, rotated))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn route_role_claim_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[]);
+    let mut server = setup_server_with_config(&pool, config).await;
+    server.do_not_save_cookies();
+
+    let response = server
+        .post("/api/v1/account/signup")
+        .json(&json!({ "username": "ROLECLAIM", "password": "ROLECLAIM" }))
+        .await;
+    let claims = flern::auth::process_token(response.cookie(AUTH_TOKEN).value(), config.app().jwt())
+        .unwrap()
+        .claims;
+    assert_eq!(claims.role.as_deref(), Some("user"));
+
+    let token = |sub: Uuid, role: Option<&str>| {
+        let claims = flern::auth::UserClaims {
+            sub: sub.to_string(),
+            exp: (chrono::Utc::now() + chrono::Duration::minutes(5)).timestamp(),
+            role: role.map(String::from),
+            issued_at: chrono::Utc::now().timestamp_millis(),
+        };
+        flern::auth::generate_token(claims, config.app().jwt()).unwrap()
+    };
+
+    // nobody with this id exists, so passing proves the role came from the token alone
+    let ghost = Uuid::new_v4();
+    server
+        .get("/api/v1/account/page?limit=10&offset=0")
+        .authorization_bearer(token(ghost, Some("admin")))
+        .await
+        .assert_status_ok();
+
+    // tokens without the claim still go through the database
+    server
+        .get("/api/v1/account/verify")
+        .authorization_bearer(token(ghost, None))
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    let user_id: Uuid = claims.sub.parse().unwrap();
+    server
+        .get("/api/v1/account/verify")
+        .authorization_bearer(token(user_id, None))
+        .await
+        .assert_status_ok();
+    server
+        .get("/api/v1/account/page?limit=10&offset=0")
+        .authorization_bearer(token(user_id, None))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn route_malformed_token_subject_test() {
+    let pool = setup_test_db().await;
+    let config = test_config(&[]);
+    let server = setup_server_with_config(&pool, config).await;
+
+    let claims = flern::auth::UserClaims {
+        sub: String::from("not-a-uuid"),
+        exp: (chrono::Utc::now() + chrono::Duration::minutes(5)).timestamp(),
+        role: Some(String::from("admin")),
+        issued_at: chrono::Utc::now().timestamp_millis(),
+    };
+    let token = flern::auth::generate_token(claims, config.app().jwt()).unwrap();
+
+    let response = server
+        .get("/api/v1/account/me")
+        .authorization_bearer(&token)
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: Value = response.json();
+    assert_eq!(body["message"], "Authentication error, token malformed.", "got {body}");
+
+    // same for the cookie, and the server keeps answering afterwards
+    server
+        .get("/api/v1/account/me")
+        .add_cookie(Cookie::new(AUTH_TOKEN, token))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/api/v1/account/me")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn route_cookie_flags_test() {
+    let pool = setup_test_db().await;
+
+    // defaults stay usable over plain HTTP
+    let mut server = setup_server(&pool).await;
+    Flow::new()
+        .step(signup_action("PLAINCOOKIE", "PLAINCOOKIE").assert_cookie(AUTH_TOKEN, |cookie| {
+            assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+            assert_ne!(cookie.secure(), Some(true));
+        }))
+        .run(&mut server, &pool)
+        .await;
+
+    for (same_site, expected) in [("strict", SameSite::Strict), ("none", SameSite::None)] {
+        let config = test_config(&[
+            ("cookie_secure", true.into()),
+            ("cookie_same_site", same_site.into()),
+        ]);
+        let mut server = setup_server_with_config(&pool, config).await;
+        let username = format!("COOKIE{}", same_site.to_uppercase());
+        let check = move |cookie: &Cookie| {
+            assert_eq!(cookie.same_site(), Some(expected));
+            assert_eq!(cookie.secure(), Some(true));
+            assert_eq!(cookie.http_only(), Some(true));
+        };
+
+        Flow::new()
+            .step(
+                signup_action(&username, "cookiepass")
+                    .assert_cookie(AUTH_TOKEN, check)
+                    .assert_cookie(REFRESH_TOKEN, check),
+            )
+            .step(signin_action(&username, "cookiepass").assert_cookie(AUTH_TOKEN, check))
+            .run(&mut server, &pool)
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn route_user_lessons_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    // created out of course order on purpose
+    let advanced = seed_module(&pool, "Advanced", 2).await.id();
+    let traits = seed_lesson(&pool, advanced, "Traits", 1).await.id();
+    let basics = seed_module(&pool, "Basics", 1).await.id();
+    let loops = seed_lesson(&pool, basics, "Loops", 2).await.id();
+    let variables = seed_lesson(&pool, basics, "Variables", 1).await.id();
+
+    let titles = |body: &str| {
+        let page: Value = serde_json::from_str(body).unwrap();
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| format!("{}/{}", l["module_title"], l["title"]).replace('"', ""))
+            .collect::<Vec<_>>()
+    };
+    let lessons = |status: &'static str| {
+        let action = Action::new("user_lessons", "GET", "/api/v1/account/lessons")
+            .with_param("limit", "10")
+            .with_param("offset", "0");
+        if status.is_empty() {
+            action
+        } else {
+            action.with_param("status", status)
+        }
+    };
+
+    Flow::new()
+        .step(signup_action("continuer", "continuer"))
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", variables)),
+        )
+        .step(
+            Action::new("lesson_done", "POST", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{}/done", traits)),
+        )
+        .step(lessons("").assert_body(move |body| {
+            assert_eq!(titles(body), ["Basics/Variables", "Basics/Loops", "Advanced/Traits"]);
+        }))
+        .step(lessons("incomplete").assert_body(move |body| {
+            let page: Value = serde_json::from_str(body).unwrap();
+            assert_eq!(page["total"], 1);
+            assert_eq!(page["items"][0]["id"], loops.to_string());
+            assert_eq!(page["items"][0]["status"], false);
+        }))
+        .step(lessons("completed").assert_body(move |body| {
+            assert_eq!(titles(body), ["Basics/Variables", "Advanced/Traits"]);
+        }))
+        .step(lessons("sometimes").with_expect(StatusCode::BAD_REQUEST))
+        .run(&mut server, pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_admin_user_extractor_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    let module = seed_module(&pool, "Basics", 1).await.id();
+    let lesson = seed_lesson(&pool, module, "Variables", 1).await.id();
+
+    let admin_routes = move || {
+        [
+            Action::new("user_list", "GET", "/api/v1/account/page")
+                .with_param("limit", "5")
+                .with_param("offset", "0"),
+            Action::new("module_create", "POST", "/api/v1/modules/")
+                .with_body(json!({ "title": "Hijacked", "description": "", "order_index": 9 })),
+            Action::new("lesson_delete", "DELETE", "")
+                .with_dyn_path(move |_| format!("/api/v1/lessons/{lesson}")),
+            Action::new("lesson_order", "PUT", "")
+                .with_dyn_path(move |_| format!("/api/v1/modules/{module}/lesson-order"))
+                .with_body(json!({ "lesson_ids": [lesson] })),
+        ]
+    };
+
+    // no session is a 401, checked before the role
+    let mut flow = Flow::new();
+    for action in admin_routes() {
+        flow = flow.step(action.with_expect(StatusCode::UNAUTHORIZED));
+    }
+    flow.run(&mut server, &pool).await;
+
+    let mut flow = Flow::new().step(signup_action("notadmin", "notadminpass"));
+    for action in admin_routes() {
+        flow = flow.step(
+            action
+                .with_expect(StatusCode::FORBIDDEN)
+                .assert_body(|body| assert!(body.contains("resource forbidden"))),
+        );
+    }
+    flow.run(&mut server, &pool).await;
+
+    let lessons: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM lessons")
+        .fetch_one(pool.mm().executor())
+        .await
+        .unwrap();
+    assert_eq!(lessons, 1);
+}
+
+#[tokio::test]
+async fn route_user_role_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+
+    let missing = Uuid::new_v4();
+    let role_path = |name: &'static str| {
+        move |ctx: &FlowContext| {
+            let user: UserEntity = ctx.get_json(name);
+            format!("/api/v1/account/{}/role", user.id())
+        }
+    };
+
+    Flow::new()
+        .step(signup_action("author", "authorpass").with_save_as("author"))
+        .step(
+            Action::new("role_forbidden", "PUT", "")
+                .with_dyn_path(role_path("author"))
+                .with_body(json!({ "role": "admin" }))
+                .with_expect(StatusCode::FORBIDDEN),
+        )
+        .step(signin_admin_action().with_clear_cookies(true))
+        .step(Action::new("admin_me", "GET", "/api/v1/account/me").with_save_as("admin"))
+        .step(
+            Action::new("role_unknown", "PUT", "")
+                .with_dyn_path(role_path("author"))
+                .with_body(json!({ "role": "owner" }))
+                .with_expect(StatusCode::UNPROCESSABLE_ENTITY),
+        )
+        .step(
+            Action::new("role_missing", "PUT", "")
+                .with_dyn_path(move |_| format!("/api/v1/account/{missing}/role"))
+                .with_body(json!({ "role": "user" }))
+                .with_expect(StatusCode::NOT_FOUND),
+        )
+        .step(
+            Action::new("promote", "PUT", "")
+                .with_dyn_path(role_path("author"))
+                .with_body(json!({ "role": "instructor" }))
+                .assert_body(|body| assert!(body.contains("\"role\":\"instructor\""))),
+        )
+        .step(
+            Action::new("demote_last_admin", "PUT", "")
+                .with_dyn_path(role_path("admin"))
+                .with_body(json!({ "role": "user" }))
+                .with_expect(StatusCode::BAD_REQUEST)
+                .assert_body(|body| assert!(body.contains("can't demote the last admin"))),
+        )
+        .step(
+            Action::new("promote_admin", "PUT", "")
+                .with_dyn_path(role_path("author"))
+                .with_body(json!({ "role": "admin" })),
+        )
+        // with a second admin around the first one can step down
+        .step(
+            Action::new("demote_admin", "PUT", "")
+                .with_dyn_path(role_path("admin"))
+                .with_body(json!({ "role": "user" }))
+                .assert_body(|body| assert!(body.contains("\"role\":\"user\""))),
+        )
+        .step(signin_action("author", "authorpass").with_clear_cookies(true))
+        .step(
+            Action::new("author_me", "GET", "/api/v1/account/me")
+                .assert_body(|body| assert!(body.contains("\"role\":\"admin\""))),
+        )
+        .run(&mut server, &pool)
+        .await;
+}
+
+#[tokio::test]
+async fn route_session_revoked_test() {
+    let pool = setup_test_db().await;
+    let mut server = setup_server(&pool).await;
+    server.do_not_save_cookies();
+
+    let signin = async |server: &axum_test::TestServer, username: &str, password: &str| {
+        let response = server
+            .post("/api/v1/account/signin")
+            .json(&json!({ "username": username, "password": password }))
+            .await;
+        response.assert_status_ok();
+        (response.cookie(AUTH_TOKEN).value().to_string(), response.cookie(REFRESH_TOKEN).value().to_string())
+    };
+    let me = |session: &str| server.get("/api/v1/account/me").add_cookie(Cookie::new(AUTH_TOKEN, session.to_string()));
+
+    let (admin, _) = signin(&server, "admin", "admin").await;
+    let response = server
+        .post("/api/v1/account/signup")
+        .json(&json!({ "username": "REVOKED", "password": "REVOKEDPASS" }))
+        .await;
+    let id = response.json::<Value>()["id"].as_str().unwrap().to_string();
+    let set_role = |role: &'static str| {
+        server
+            .put(&format!("/api/v1/account/{id}/role"))
+            .add_cookie(Cookie::new(AUTH_TOKEN, admin.clone()))
+            .json(&json!({ "role": role }))
+    };
+
+    set_role("admin").await.assert_status_ok();
+    let (session, refresh) = signin(&server, "REVOKED", "REVOKEDPASS").await;
+    me(&session).await.assert_status_ok();
+
+    // the demoted admin's token still says admin, it must not be accepted anymore
+    set_role("user").await.assert_status_ok();
+    let response = me(&session).expect_failure().await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    assert!(response.text().contains("session revoked"));
+
+    // a refresh picks up the new role
+    let response = server
+        .post("/api/v1/account/refresh")
+        .add_cookie(Cookie::new(REFRESH_TOKEN, refresh))
+        .await;
+    response.assert_status_ok();
+    let session = response.cookie(AUTH_TOKEN).value().to_string();
+    let body: Value = me(&session).await.json();
+    assert_eq!(body["role"], "user", "got {body}");
+
+    // a deleted user is gone right away, not once the token expires
+    server
+        .delete(&format!("/api/v1/account/{id}"))
+        .add_cookie(Cookie::new(AUTH_TOKEN, admin.clone()))
+        .await
+        .assert_status_ok();
+    me(&session).expect_failure().await.assert_status(StatusCode::UNAUTHORIZED);
+    me(&admin).await.assert_status_ok();
+}
+
+#[tokio::test]
+async fn startup_admin_seed_test() {
+    let pool = setup_unseeded_db().await;
+    let config = test_config(&[
+        ("admin_username", "root".into()),
+        ("admin_password", "rootpassword".into()),
+    ]);
+    let admins = async || -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = 'admin'")
+            .fetch_one(pool.mm().executor())
+            .await
+            .unwrap()
+    };
+
+    // without a password nothing is seeded
+    setup_server_with_config(&pool, test_config(&[])).await;
+    assert_eq!(admins().await, 0);
+
+    let mut server = setup_server_with_config(&pool, config).await;
+    assert_eq!(admins().await, 1);
+
+    Flow::new()
+        .step(signin_action("root", "rootpassword"))
+        .step(
+            Action::new("me", "GET", "/api/v1/account/me")
+                .assert_body(|body| assert!(body.contains("\"role\":\"admin\""))),
+        )
+        .run(&mut server, &pool)
+        .await;
+
+    // later starts leave the existing admin alone, even with other credentials configured
+    setup_server_with_config(&pool, config).await;
+    let other = test_config(&[
+        ("admin_username", "other".into()),
+        ("admin_password", "otherpassword".into()),
+    ]);
+    setup_server_with_config(&pool, other).await;
+    assert_eq!(admins().await, 1);
+}